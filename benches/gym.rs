@@ -0,0 +1,36 @@
+use std::time::{Duration, Instant};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tetrust::gym::{self, Action, Env};
+
+// Measures how fast the training environment runs with random actions. Run
+// with `cargo bench --bench gym`.
+
+const RUN: Duration = Duration::from_secs(3);
+
+fn run(name: &str, mut choose: impl FnMut(&mut Env, &mut StdRng) -> Action) {
+    let mut env = Env::new(10, 20).unwrap();
+    let mut rng = StdRng::seed_from_u64(0);
+    let (mut steps, mut episodes) = (0u64, 0u64);
+    let start = Instant::now();
+    while start.elapsed() < RUN {
+        for _ in 0..1000 {
+            let action = choose(&mut env, &mut rng);
+            if env.step(action).2 {
+                env.reset(episodes);
+                episodes += 1;
+            }
+            steps += 1;
+        }
+    }
+    let per_minute = steps as f64 * 60.0 / start.elapsed().as_secs_f64();
+    println!("{:10} {:10.0} steps/minute, {} episodes", name, per_minute, episodes);
+}
+
+fn main() {
+    run("inputs", |_, rng| Action::from_bits(rng.gen_range(0..gym::INPUT_ACTIONS) as u8));
+    run("placements", |env, rng| {
+        let count = env.placements().len().max(1);
+        Action::Placement(rng.gen_range(0..count))
+    });
+}
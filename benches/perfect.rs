@@ -0,0 +1,63 @@
+use std::time::{Duration, Instant};
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use tetrust::perfect::{self, Search};
+use tetrust::tetrust::*;
+
+// Times the perfect clear solver on openers from shuffled bags and on clears
+// from the middle of a game. Run with `cargo bench --bench perfect`.
+
+const OPENERS: u64 = 20;
+
+fn bags(seed: u64) -> Vec<PieceEnum> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut pieces = Vec::new();
+    for _ in 0..2 {
+        let mut bag = vec![PieceEnum::O, PieceEnum::S, PieceEnum::Z, PieceEnum::T, PieceEnum::L, PieceEnum::J, PieceEnum::I];
+        bag.shuffle(&mut rng);
+        pieces.extend(bag);
+    }
+    pieces
+}
+
+fn report(name: &str, times: &[(Duration, Search)]) {
+    let total: Duration = times.iter().map(|t| t.0).sum();
+    let max = times.iter().map(|t| t.0).max().unwrap_or_default();
+    let found = times.iter().filter(|t| t.1.solution().is_some()).count();
+    let gave_up = times.iter().filter(|t| t.1 == Search::GaveUp).count();
+    println!("{:10} {:3} runs, {:3} found, {:3} gave up, mean {:?}, max {:?}",
+             name, times.len(), found, gave_up, total / times.len().max(1) as u32, max);
+}
+
+fn time(tetris: &Tetris, queue: &[PieceEnum], lines: u16) -> (Duration, Search) {
+    let start = Instant::now();
+    let search = perfect::solve(tetris, queue, lines);
+    (start.elapsed(), search)
+}
+
+fn main() {
+    // Four lines from an empty board, knowing both bags.
+    let openers: Vec<_> = (0..OPENERS).map(|seed| {
+        let pieces = bags(seed);
+        time(&Tetris::with_queue(TileCanvas::new(10, 20), PieceQueue::fixed(pieces.clone())), &pieces[1..], 4)
+    }).collect();
+    report("opener", &openers);
+
+    // The end of each opener found, from halfway through with only the preview
+    // to go on, the way the in-game hint sees it.
+    let previews: Vec<_> = (0..OPENERS).zip(openers.iter()).filter_map(|(seed, (_, search))| {
+        let moves = search.solution()?;
+        let mut tetris = Tetris::with_queue(TileCanvas::new(10, 20), PieceQueue::fixed(bags(seed)));
+        for m in &moves[..moves.len() / 2] {
+            if m.hold {
+                tetris.hold_piece();
+            }
+            tetris.place_piece(m.placement.piece, m.placement.kick);
+            tetris.disable_piece();
+            tetris.spawn_next();
+        }
+        let queue: Vec<PieceEnum> = tetris.queue.peek().copied().collect();
+        Some(time(&tetris, &queue, 4))
+    }).collect();
+    report("preview", &previews);
+}
@@ -0,0 +1,80 @@
+"""Smoke test for the Python bindings in src/python.rs.
+
+Build the extension and run this with the directory holding tetrust.so:
+
+    cargo rustc --release --lib --features python --crate-type cdylib
+    cp target/release/libtetrust.so python/tetrust.so
+    python3 python/smoke.py
+"""
+
+import json
+import os
+import tempfile
+
+import tetrust
+
+HARD_DROP = 1 << 3
+
+
+def refused(make):
+    try:
+        make()
+    except ValueError:
+        return True
+    return False
+
+
+def replay_file(width, height, seed, inputs):
+    replay = {
+        "version": 2, "width": width, "height": height, "seed": seed, "gravity": 0,
+        "timings": {"are": 0, "line_are": 0, "das": 10, "arr": 2, "lock_delay": 30, "line_clear": 0},
+        "inputs": inputs,
+    }
+    handle, path = tempfile.mkstemp(suffix=".json")
+    with os.fdopen(handle, "w") as f:
+        json.dump(replay, f)
+    return path
+
+
+def main():
+    assert refused(lambda: tetrust.Game(width=3))
+    assert refused(lambda: tetrust.Game(height=0))
+    assert refused(lambda: tetrust.Env(width=65))
+    assert refused(lambda: tetrust.Game().step(["jump"]))
+
+    # Hard drop a few pieces, letting go in between.
+    game = tetrust.Game(seed=7)
+    inputs = [HARD_DROP, 0] * 5
+    for bits in inputs:
+        game.step_bits(bits)
+    assert game.falling and not game.over
+    assert len(game.board) == 10
+
+    path = replay_file(10, 20, 7, inputs)
+    try:
+        replay = tetrust.load_replay(path)
+    finally:
+        os.remove(path)
+    assert (replay.width, replay.height, replay.seed, len(replay)) == (10, 20, 7, len(inputs))
+    played = replay.play()
+    assert (played.board, played.score, played.frames) == (game.board, game.score, game.frames)
+    assert replay.game(2).frames == 2
+
+    path = replay_file(1, 20, 7, inputs)
+    try:
+        assert refused(lambda: tetrust.load_replay(path))
+    finally:
+        os.remove(path)
+
+    env = tetrust.Env(max_pieces=3)
+    obs = env.reset(1)
+    assert len(obs["board"]) == 20
+    done = False
+    while not done:
+        _, _, done, info = env.step_placement(0)
+    assert info["pieces"] == 3
+    print("ok")
+
+
+if __name__ == "__main__":
+    main()
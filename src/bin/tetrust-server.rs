@@ -0,0 +1,33 @@
+use std::net::TcpListener;
+
+use tetrust::{net, room};
+
+// Usage: tetrust-server [--room SIZE] [ADDR] [INPUT_DELAY]
+//
+// Without --room the server pairs up players for 1v1 matches, with it it hosts
+// battle royale rooms of SIZE players.
+fn main() -> Result<(), String> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut room_size = None;
+    if args.first().map(|s| s.as_str()) == Some("--room") {
+        let size = args.get(1).ok_or("--room needs a size")?;
+        room_size = Some(size.parse::<usize>().map_err(|e| format!("invalid room size '{}': {}", size, e))?);
+        args.drain(..2);
+    }
+    let addr = args.first().cloned().unwrap_or(format!("0.0.0.0:{}", net::DEFAULT_PORT));
+    let delay = match args.get(1) {
+        Some(s) => s.parse::<u32>().map_err(|e| format!("invalid input delay '{}': {}", s, e))?,
+        None => net::DEFAULT_INPUT_DELAY
+    };
+    let listener = TcpListener::bind(&addr).map_err(|e| format!("{}: {}", addr, e))?;
+    match room_size {
+        Some(size) => {
+            println!("Hosting rooms of {} players on {}", size, addr);
+            room::serve_rooms(listener, size)
+        }
+        None => {
+            println!("Listening on {} with {} frames of input delay", addr, delay);
+            net::serve(listener, delay)
+        }
+    }
+}
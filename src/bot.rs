@@ -0,0 +1,269 @@
+use std::collections::VecDeque;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::game::*;
+use crate::movegen::{self, Action, Placement};
+use crate::tetrust::*;
+
+// A computer player. It scores every placement it can reach, with and without
+// hold, picks the best one and then presses buttons to get the piece there, so
+// it plays through the same `Input` a keyboard produces.
+
+// How much each board feature counts towards a placement's score.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Weights {
+    // Sum of the column heights.
+    pub height: f64,
+    pub holes: f64,
+    // Sum of the height differences between neighbouring columns.
+    pub bumpiness: f64,
+    // Depth of every well but the deepest, which is kept open for I pieces.
+    pub wells: f64,
+    // Indexed by lines cleared.
+    pub clears: [f64; 5]
+}
+
+impl Default for Weights {
+    fn default() -> Weights {
+        Weights { height: -0.51, holes: -0.36, bumpiness: -0.18, wells: -0.1, clears: [0.0, 0.76, 1.52, 2.28, 3.8] }
+    }
+}
+
+impl Weights {
+    pub fn evaluate(&self, canvas: &TileCanvas, clear: &Clear) -> f64 {
+        let height = canvas.height();
+        let columns = canvas.column_heights();
+        let holes = canvas.holes();
+        let bumpiness: u16 = columns.windows(2).map(|w| w[0].abs_diff(w[1])).sum();
+        let mut wells: Vec<u16> = (0..columns.len())
+            .map(|i| {
+                let left = if i == 0 { height } else { columns[i - 1] };
+                let right = columns.get(i + 1).copied().unwrap_or(height);
+                left.min(right).saturating_sub(columns[i])
+            })
+            .collect();
+        wells.sort_unstable();
+        wells.pop();
+        self.height * columns.iter().map(|&h| h as f64).sum::<f64>()
+            + self.holes * holes as f64
+            + self.bumpiness * bumpiness as f64
+            + self.wells * wells.iter().map(|&w| w as f64).sum::<f64>()
+            + self.clears.get(clear.lines as usize).copied().unwrap_or(0.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy, Medium, Hard
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard"
+        }
+    }
+    pub fn parse(name: &str) -> Result<Difficulty, String> {
+        Difficulty::ALL.iter().copied().find(|d| d.name() == name).ok_or(format!("unknown difficulty '{}'", name))
+    }
+    // Frames spent idle between two button presses.
+    fn delay(&self) -> u32 {
+        match self {
+            Difficulty::Easy => 8,
+            Difficulty::Medium => 3,
+            Difficulty::Hard => 0
+        }
+    }
+    // Chance of settling for one of the runner-up placements.
+    fn mistakes(&self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.3,
+            Difficulty::Medium => 0.1,
+            Difficulty::Hard => 0.0
+        }
+    }
+    fn holds(&self) -> bool {
+        *self != Difficulty::Easy
+    }
+}
+
+// Times the bot adjusts its plan for one piece before giving up and dropping it
+// where it is, so a piece kicked up and pulled down again can't stall it.
+const MAX_REPLANS: u32 = 10;
+
+// A placement the bot can make. With `hold` the held piece is played instead,
+// starting from where it spawns.
+#[derive(Clone, Debug)]
+pub struct Plan {
+    pub hold: bool,
+    pub start: TetrisPiece,
+    pub placement: Placement,
+    pub score: f64
+}
+
+// Anything that can play in place of the keyboard.
+pub trait Controller {
+    // Decides which buttons are down this frame.
+    fn input(&mut self, game: &Game) -> Result<Input, String>;
+}
+
+pub struct Bot {
+    pub weights: Weights,
+    pub difficulty: Difficulty,
+    rng: StdRng,
+    driver: Driver
+}
+
+impl Bot {
+    pub fn new(difficulty: Difficulty, seed: u64) -> Bot {
+        Bot { weights: Weights::default(), difficulty, rng: StdRng::seed_from_u64(seed), driver: Driver::new(difficulty.delay()) }
+    }
+
+    // Every placement of the active piece, and of the other piece if holding is
+    // allowed, best first.
+    pub fn plans(&self, tetris: &Tetris) -> Vec<Plan> {
+        let mut plans = self.plans_for(tetris, false);
+        if self.difficulty.holds() {
+            let mut held = tetris.clone();
+            if held.hold_piece() && !held.check_loss() {
+                plans.extend(self.plans_for(&held, true));
+            }
+        }
+        plans.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+        plans
+    }
+
+    fn plans_for(&self, tetris: &Tetris, hold: bool) -> Vec<Plan> {
+        let start = tetris.active_piece();
+        movegen::placements(tetris).into_iter().map(|placement| {
+            let mut after = tetris.clone();
+            after.place_piece(placement.piece, placement.kick);
+            let clear = after.disable_piece();
+            let score = if after.check_loss() { f64::NEG_INFINITY } else { self.weights.evaluate(&after.tile_canvas, &clear) };
+            Plan { hold, start, placement, score }
+        }).collect()
+    }
+
+    fn choose(&mut self, tetris: &Tetris) -> Option<Plan> {
+        let plans = self.plans(tetris);
+        let mut choice = 0;
+        if plans.len() > 1 && self.rng.gen_bool(self.difficulty.mistakes()) {
+            choice = self.rng.gen_range(1..plans.len().min(4));
+        }
+        plans.into_iter().nth(choice)
+    }
+
+    pub fn input(&mut self, game: &Game) -> Input {
+        let mut driver = std::mem::take(&mut self.driver);
+        let input = driver.input(game, |tetris| self.choose(tetris));
+        self.driver = driver;
+        input
+    }
+}
+
+impl Controller for Bot {
+    fn input(&mut self, game: &Game) -> Result<Input, String> {
+        Ok(Bot::input(self, game))
+    }
+}
+
+// Presses buttons to get each piece to the placement picked for it, one button
+// per frame with `delay` idle frames in between.
+#[derive(Default)]
+pub struct Driver {
+    pub delay: u32,
+    // The rest of the current plan, and where the piece should be before the next action.
+    plan: VecDeque<(Action, TetrisPiece)>,
+    expected: Option<TetrisPiece>,
+    target: Option<TetrisPiece>,
+    replans: u32,
+    last: Input,
+    wait: u32
+}
+
+impl Driver {
+    pub fn new(delay: u32) -> Driver {
+        Driver { delay, ..Driver::default() }
+    }
+
+    // Breaks `plan` down into single steps the game will take one by one.
+    fn follow(&mut self, tetris: &Tetris, plan: Option<Plan>) {
+        self.plan.clear();
+        self.expected = Some(tetris.active_piece());
+        self.target = None;
+        if let Some(plan) = plan {
+            self.target = Some(plan.placement.piece);
+            if plan.hold {
+                self.plan.push_back((Action::Hold, plan.start));
+            }
+            self.plan.extend(movegen::steps(tetris, plan.start, &plan.placement.inputs));
+        }
+    }
+
+    // `choose` picks a placement for a new piece. Until it comes up with one the
+    // piece is left alone and it is asked again on the next frame.
+    pub fn input(&mut self, game: &Game, choose: impl FnOnce(&Tetris) -> Option<Plan>) -> Input {
+        let input = self.next_input(game, choose);
+        self.last = input;
+        input
+    }
+
+    fn next_input(&mut self, game: &Game, choose: impl FnOnce(&Tetris) -> Option<Plan>) -> Input {
+        if !game.is_falling() {
+            self.plan.clear();
+            self.expected = None;
+            return Input::default();
+        }
+        let tetris = &game.tetris;
+        let piece = tetris.active_piece();
+        if self.expected != Some(piece) {
+            // Gravity got in the way, or this is a new piece. Head for the same
+            // spot from here if it is still reachable.
+            let replanning = self.expected.is_some();
+            let placement = self.target.filter(|_| replanning)
+                .and_then(|target| movegen::placements(tetris).into_iter().find(|p| p.piece == target));
+            self.replans = if replanning { self.replans + 1 } else { 0 };
+            match placement {
+                _ if self.replans > MAX_REPLANS => {
+                    self.follow(tetris, None);
+                    self.plan.push_back((Action::HardDrop, piece));
+                }
+                Some(placement) => self.follow(tetris, Some(Plan { hold: false, start: piece, placement, score: 0.0 })),
+                None => match choose(tetris) {
+                    Some(plan) => self.follow(tetris, Some(plan)),
+                    None => {
+                        self.expected = None;
+                        return Input::default();
+                    }
+                }
+            }
+        }
+        // Buttons have to be let go of before they register again, except soft drop.
+        if self.last != Input::default() && self.last != Action::SoftDrop.input() {
+            return Input::default();
+        }
+        if self.wait > 0 {
+            self.wait -= 1;
+            return Input::default();
+        }
+        match self.plan.pop_front() {
+            Some((Action::HardDrop, _)) => {
+                // The next piece gets a plan of its own.
+                self.expected = None;
+                self.target = None;
+                Action::HardDrop.input()
+            }
+            Some((action, after)) => {
+                self.expected = Some(after);
+                self.wait = self.delay;
+                action.input()
+            }
+            None => Input::default()
+        }
+    }
+}
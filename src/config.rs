@@ -0,0 +1,595 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::*;
+use crate::tetrust::*;
+
+// Player settings, read from a TOML file. Every section may be left out to
+// keep its defaults, but a section that is there replaces the whole default
+// one. Key names are SDL scancode names and gamepad ones SDL game controller
+// names; the frontend checks them, since this crate doesn't link SDL.
+
+pub const CONFIG_FILE: &str = "config.toml";
+
+// Written on first run, so it doubles as the documentation of the format.
+pub const DEFAULT_CONFIG: &str = r#"# tetrust settings. Delete this file to get the defaults back.
+
+# Frames are 1/60 of a second.
+[handling]
+# Delayed auto shift: frames a direction is held before the piece slides.
+das = 10
+# Auto repeat rate: frames between moves while sliding, 0 to slide at once.
+arr = 2
+# Soft drop speed in rows per frame.
+sdf = 1.0
+# Seconds of 3-2-1 before each solo game, 0 to start at once. Holding a
+# direction during it charges DAS.
+countdown = 3
+
+[display]
+ghost = true
+# Width and height of a tile in pixels, which sets the window size.
+tile_size = 32
+# Size the font is rendered at before it is scaled to a tile.
+font_size = 128
+# A sidebar of live statistics next to the solo games.
+stats = true
+
+# The board of the solo modes that don't have one of their own.
+[board]
+width = 16
+height = 19
+
+[theme]
+# Directory with a .bmp per tile colour and font.ttf.
+assets = "assets"
+background = [102, 102, 153]
+
+[sound]
+# From 0 to 100.
+volume = 80
+
+# Each action takes a list of keys, any of which triggers it. Actions left out
+# get their default keys, unless those are bound to something else.
+[keys]
+left = ["Left"]
+right = ["Right"]
+soft_drop = ["Down"]
+hard_drop = ["Space"]
+hold = ["C", "Left Shift"]
+rotate_cw = ["Up", "X"]
+rotate_ccw = ["Z"]
+# Pauses, and goes back in menus.
+quit = ["Escape"]
+# Starts the solo game over with new pieces, or the puzzle or opener being
+# played over on the same ones.
+restart = ["R"]
+# Takes back the last piece in zen.
+undo = ["Backspace"]
+# Skips to the next puzzle or opener.
+next = ["N"]
+# Prints the zen board as a fumen link.
+export = ["F"]
+# Pick the next piece in zen, O, S, Z, T, L, J and I in order, and the first
+# four the targeting strategy in battle royale.
+pick_1 = ["1"]
+pick_2 = ["2"]
+pick_3 = ["3"]
+pick_4 = ["4"]
+pick_5 = ["5"]
+pick_6 = ["6"]
+pick_7 = ["7"]
+
+# Local versus; quit comes from [keys].
+[versus.player1]
+left = ["A"]
+right = ["D"]
+soft_drop = ["S"]
+hard_drop = ["W"]
+hold = ["Left Shift"]
+rotate_cw = ["G"]
+rotate_ccw = ["F"]
+
+[versus.player2]
+left = ["Left"]
+right = ["Right"]
+soft_drop = ["Down"]
+hard_drop = ["Up"]
+hold = ["Right Shift"]
+rotate_cw = ["/"]
+rotate_ccw = ["."]
+
+# Gamepads work in every mode; in versus the first one plugged in plays for
+# player 1 and the second for player 2.
+[gamepad]
+# How far a stick or trigger has to be pushed, from 0 to 1.
+deadzone = 0.5
+# SDL mapping strings for controllers SDL doesn't know, as made by tools like
+# SDL2 Gamepad Tool.
+mappings = []
+
+# Buttons are named as in SDL mappings: a, b, x, y, back, start, leftshoulder,
+# rightshoulder, leftstick, dpup, dpleft and so on. Sticks and triggers are
+# axes with the way they are pushed: leftx-, leftx+, lefty-, lefty+, rightx-,
+# lefttrigger+ and so on.
+[gamepad.buttons]
+left = ["dpleft", "leftx-"]
+right = ["dpright", "leftx+"]
+soft_drop = ["dpdown", "lefty+"]
+hard_drop = ["dpup"]
+hold = ["leftshoulder", "rightshoulder"]
+rotate_cw = ["a"]
+rotate_ccw = ["b"]
+quit = ["back", "start"]
+restart = ["y"]
+
+# Buttons for one kind of controller, by the name SDL gives it, in place of
+# the ones above:
+# [gamepad.controllers."PS4 Controller"]
+# left = ["dpleft"]
+# ...
+"#;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Left,
+    Right,
+    SoftDrop,
+    HardDrop,
+    Hold,
+    RotateCw,
+    RotateCcw,
+    Quit,
+    Restart,
+    Undo,
+    Next,
+    Export,
+    #[serde(rename = "pick_1")]
+    Pick1,
+    #[serde(rename = "pick_2")]
+    Pick2,
+    #[serde(rename = "pick_3")]
+    Pick3,
+    #[serde(rename = "pick_4")]
+    Pick4,
+    #[serde(rename = "pick_5")]
+    Pick5,
+    #[serde(rename = "pick_6")]
+    Pick6,
+    #[serde(rename = "pick_7")]
+    Pick7
+}
+
+impl Action {
+    pub const ALL: [Action; 19] = [Action::Left, Action::Right, Action::SoftDrop, Action::HardDrop, Action::Hold, Action::RotateCw, Action::RotateCcw,
+                                   Action::Quit, Action::Restart, Action::Undo, Action::Next, Action::Export, Action::Pick1, Action::Pick2,
+                                   Action::Pick3, Action::Pick4, Action::Pick5, Action::Pick6, Action::Pick7];
+    // Choices by number, whatever the mode offers to pick from.
+    pub const PICKS: [Action; 7] = [Action::Pick1, Action::Pick2, Action::Pick3, Action::Pick4, Action::Pick5, Action::Pick6, Action::Pick7];
+
+    // The name used in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::SoftDrop => "soft_drop",
+            Action::HardDrop => "hard_drop",
+            Action::Hold => "hold",
+            Action::RotateCw => "rotate_cw",
+            Action::RotateCcw => "rotate_ccw",
+            Action::Quit => "quit",
+            Action::Restart => "restart",
+            Action::Undo => "undo",
+            Action::Next => "next",
+            Action::Export => "export",
+            Action::Pick1 => "pick_1",
+            Action::Pick2 => "pick_2",
+            Action::Pick3 => "pick_3",
+            Action::Pick4 => "pick_4",
+            Action::Pick5 => "pick_5",
+            Action::Pick6 => "pick_6",
+            Action::Pick7 => "pick_7"
+        }
+    }
+    // Whether the action is a game button rather than something for the frontend.
+    pub fn is_button(self) -> bool {
+        Action::ALL[..7].contains(&self)
+    }
+    // Whether the action can be left without keys. Only the game buttons and
+    // quit, which also leaves menus, are needed to play.
+    pub fn is_optional(self) -> bool {
+        !self.is_button() && self != Action::Quit
+    }
+    // Sets the button in `input`; does nothing for other actions.
+    pub fn set(self, input: &mut Input, down: bool) {
+        let button = match self {
+            Action::Left => &mut input.left,
+            Action::Right => &mut input.right,
+            Action::SoftDrop => &mut input.soft_drop,
+            Action::HardDrop => &mut input.hard_drop,
+            Action::Hold => &mut input.hold,
+            Action::RotateCw => &mut input.rotate_cw,
+            Action::RotateCcw => &mut input.rotate_ccw,
+            _ => return
+        };
+        *button = down;
+    }
+}
+
+// Key names by action.
+pub type Bindings = BTreeMap<Action, Vec<String>>;
+
+// The sets of bindings in the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeySection {
+    Solo,
+    Player1,
+    Player2
+}
+
+impl KeySection {
+    pub const ALL: [KeySection; 3] = [KeySection::Solo, KeySection::Player1, KeySection::Player2];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            KeySection::Solo => "keys",
+            KeySection::Player1 => "versus.player1",
+            KeySection::Player2 => "versus.player2"
+        }
+    }
+    pub fn actions(self) -> &'static [Action] {
+        match self {
+            KeySection::Solo => &Action::ALL,
+            KeySection::Player1 | KeySection::Player2 => &Action::ALL[..7]
+        }
+    }
+    // Whether the two bindings are in use at the same time, so can't share a
+    // key. Quit works in versus too.
+    fn overlaps(a: (KeySection, Action), b: (KeySection, Action)) -> bool {
+        let versus = |(section, action)| section != KeySection::Solo || action == Action::Quit;
+        a.0 == b.0 || (versus(a) && versus(b))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Handling {
+    pub das: u32,
+    pub arr: u32,
+    pub sdf: f64,
+    // In seconds.
+    pub countdown: u32
+}
+
+impl Default for Handling {
+    fn default() -> Handling {
+        let timings = Timings::default();
+        Handling { das: timings.das, arr: timings.arr, sdf: 1.0, countdown: 3 }
+    }
+}
+
+impl Handling {
+    // Sets up a local game; modes with timings of their own override them later.
+    pub fn apply(&self, game: &mut Game) {
+        game.timings.das = self.das;
+        game.timings.arr = self.arr;
+        game.soft_drop_gravity = (self.sdf * GRAVITY_UNIT as f64).round() as u32;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    pub ghost: bool,
+    pub tile_size: u32,
+    pub font_size: u16,
+    pub stats: bool
+}
+
+impl Default for DisplayConfig {
+    fn default() -> DisplayConfig {
+        DisplayConfig { ghost: true, tile_size: 32, font_size: 128, stats: true }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BoardConfig {
+    pub width: u16,
+    pub height: u16
+}
+
+impl Default for BoardConfig {
+    fn default() -> BoardConfig {
+        BoardConfig { width: TILE_CANVAS_WIDTH, height: TILE_CANVAS_HEIGHT }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub assets: PathBuf,
+    pub background: [u8; 3]
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme { assets: PathBuf::from("assets"), background: [102, 102, 153] }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sound {
+    pub volume: u32
+}
+
+impl Default for Sound {
+    fn default() -> Sound {
+        Sound { volume: 80 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VersusKeys {
+    pub player1: Bindings,
+    pub player2: Bindings
+}
+
+impl Default for VersusKeys {
+    fn default() -> VersusKeys {
+        VersusKeys {
+            player1: bindings(&[&["A"], &["D"], &["S"], &["W"], &["Left Shift"], &["G"], &["F"]]),
+            player2: bindings(&[&["Left"], &["Right"], &["Down"], &["Up"], &["Right Shift"], &["/"], &["."]])
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GamepadConfig {
+    pub deadzone: f64,
+    pub mappings: Vec<String>,
+    pub buttons: Bindings,
+    // By controller name.
+    pub controllers: BTreeMap<String, Bindings>
+}
+
+impl Default for GamepadConfig {
+    fn default() -> GamepadConfig {
+        GamepadConfig {
+            deadzone: 0.5,
+            mappings: Vec::new(),
+            buttons: bindings(&[&["dpleft", "leftx-"], &["dpright", "leftx+"], &["dpdown", "lefty+"], &["dpup"], &["leftshoulder", "rightshoulder"],
+                                &["a"], &["b"], &["back", "start"], &["y"]]),
+            controllers: BTreeMap::new()
+        }
+    }
+}
+
+impl GamepadConfig {
+    // The buttons for a controller called `name`.
+    pub fn buttons_for(&self, name: &str) -> &Bindings {
+        self.controllers.get(name).unwrap_or(&self.buttons)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub handling: Handling,
+    pub display: DisplayConfig,
+    pub board: BoardConfig,
+    pub theme: Theme,
+    pub sound: Sound,
+    pub keys: Bindings,
+    pub versus: VersusKeys,
+    pub gamepad: GamepadConfig
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config { handling: Handling::default(), display: DisplayConfig::default(), board: BoardConfig::default(), theme: Theme::default(),
+                 sound: Sound::default(), keys: default_keys(), versus: VersusKeys::default(),
+                 gamepad: GamepadConfig::default() }
+    }
+}
+
+// Keys in `Action::ALL` order.
+fn bindings(keys: &[&[&str]]) -> Bindings {
+    Action::ALL.iter().zip(keys).map(|(&action, keys)| (action, keys.iter().map(|k| k.to_string()).collect())).collect()
+}
+
+pub fn default_keys() -> Bindings {
+    bindings(&[&["Left"], &["Right"], &["Down"], &["Space"], &["C", "Left Shift"], &["Up", "X"], &["Z"], &["Escape"], &["R"], &["Backspace"],
+               &["N"], &["F"], &["1"], &["2"], &["3"], &["4"], &["5"], &["6"], &["7"]])
+}
+
+fn check_range<T: PartialOrd + Display + Copy>(name: &str, value: T, min: T, max: T) -> Result<(), String> {
+    if !(min..=max).contains(&value) {
+        return Err(format!("{} = {} is out of range, expected {} to {}", name, value, min, max));
+    }
+    Ok(())
+}
+
+// Sections written before an action existed don't bind it, so it gets the
+// default keys that nothing else in the section uses.
+fn fill_missing(bindings: &mut Bindings, actions: &[Action], defaults: &Bindings) {
+    for action in actions {
+        if bindings.contains_key(action) {
+            continue;
+        }
+        let used: Vec<String> = bindings.values().flatten().cloned().collect();
+        let keys: Vec<String> = defaults.get(action).into_iter().flatten()
+            .filter(|key| !used.iter().any(|u| u.eq_ignore_ascii_case(key)))
+            .cloned().collect();
+        if !keys.is_empty() {
+            bindings.insert(*action, keys);
+        }
+    }
+}
+
+// Every action in `actions` needs at least one key, and no others can be bound.
+// Optional ones can go without, like when their default keys were already
+// taken for something else.
+fn check_bindings(section_name: &str, actions: &[Action], bindings: &Bindings) -> Result<(), String> {
+    for action in actions {
+        match bindings.get(action) {
+            None if action.is_optional() => {}
+            None => return Err(format!("{}: nothing is bound to {}", section_name, action.name())),
+            Some(keys) if keys.is_empty() => return Err(format!("{}.{}: needs at least one key", section_name, action.name())),
+            Some(keys) => {
+                if let Some(i) = keys.iter().position(|k| k.trim().is_empty()) {
+                    return Err(format!("{}.{}: key {} is empty", section_name, action.name(), i + 1));
+                }
+            }
+        }
+    }
+    if let Some(action) = bindings.keys().find(|a| !actions.contains(a)) {
+        return Err(format!("{}: {} can only be bound in [keys]", section_name, action.name()));
+    }
+    Ok(())
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config: Config = toml::from_str(text).map_err(|e| e.to_string())?;
+        config.fill_missing();
+        config.validate()?;
+        Ok(config)
+    }
+    fn fill_missing(&mut self) {
+        let defaults = Config::default();
+        for &section in KeySection::ALL.iter() {
+            fill_missing(self.bindings_mut(section), section.actions(), defaults.bindings(section));
+        }
+        fill_missing(&mut self.gamepad.buttons, &Action::ALL, &defaults.gamepad.buttons);
+        for buttons in self.gamepad.controllers.values_mut() {
+            fill_missing(buttons, &Action::ALL, &defaults.gamepad.buttons);
+        }
+    }
+
+    // Ranges are kept to what the game can draw and play sensibly.
+    pub fn validate(&self) -> Result<(), String> {
+        check_range("handling.das", self.handling.das, 0, 60)?;
+        check_range("handling.arr", self.handling.arr, 0, 60)?;
+        check_range("handling.sdf", self.handling.sdf, 0.1, 40.0)?;
+        check_range("handling.countdown", self.handling.countdown, 0, 10)?;
+        check_range("display.tile_size", self.display.tile_size, 8, 128)?;
+        check_range("display.font_size", self.display.font_size, 8, 512)?;
+        check_range("board.width", self.board.width, MIN_BOARD_SIZE, 40)?;
+        check_range("board.height", self.board.height, MIN_BOARD_SIZE, 40)?;
+        check_range("sound.volume", self.sound.volume, 0, 100)?;
+        if !self.theme.assets.is_dir() {
+            return Err(format!("theme.assets: {} is not a directory", self.theme.assets.display()));
+        }
+        for &section in KeySection::ALL.iter() {
+            check_bindings(section.name(), section.actions(), self.bindings(section))?;
+        }
+        check_range("gamepad.deadzone", self.gamepad.deadzone, 0.05, 0.95)?;
+        for (i, mapping) in self.gamepad.mappings.iter().enumerate() {
+            let fields: Vec<&str> = mapping.split(',').collect();
+            if fields.len() < 3 || fields[0].len() != 32 || !fields[0].chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("gamepad.mappings: entry {} doesn't start with a 32 digit GUID and a name", i + 1));
+            }
+        }
+        check_bindings("gamepad.buttons", &Action::ALL, &self.gamepad.buttons)?;
+        for (name, buttons) in &self.gamepad.controllers {
+            check_bindings(&format!("gamepad.controllers.\"{}\"", name), &Action::ALL, buttons)?;
+        }
+        Ok(())
+    }
+
+    pub fn bindings(&self, section: KeySection) -> &Bindings {
+        match section {
+            KeySection::Solo => &self.keys,
+            KeySection::Player1 => &self.versus.player1,
+            KeySection::Player2 => &self.versus.player2
+        }
+    }
+    pub fn bindings_mut(&mut self, section: KeySection) -> &mut Bindings {
+        match section {
+            KeySection::Solo => &mut self.keys,
+            KeySection::Player1 => &mut self.versus.player1,
+            KeySection::Player2 => &mut self.versus.player2
+        }
+    }
+
+    // Binds `key` to `action`, in place of its keys or as one more. A key does
+    // one thing at a time, so it comes off any binding it clashes with. When
+    // that leaves the other action without keys, it gets the ones `action` had
+    // instead, or nothing changes if there are none to swap. Returns the
+    // bindings the key came off.
+    pub fn bind(&mut self, section: KeySection, action: Action, key: &str, add: bool) -> Result<Vec<(KeySection, Action)>, String> {
+        if !section.actions().contains(&action) {
+            return Err(format!("{} can only be bound in [keys]", action.name()));
+        }
+        let swap: Vec<String> = self.bindings(section).get(&action).into_iter().flatten().filter(|k| k.as_str() != key).cloned().collect();
+        let clashes: Vec<(KeySection, Action)> = KeySection::ALL.iter().flat_map(|&other| {
+            self.bindings(other).iter().filter(move |(_, keys)| keys.iter().any(|k| k == key)).map(move |(&a, _)| (other, a))
+        }).filter(|&binding| binding != (section, action) && KeySection::overlaps(binding, (section, action))).collect();
+        for &(other, other_action) in &clashes {
+            if self.bindings(other)[&other_action].len() == 1 && (add || swap.is_empty()) {
+                return Err(format!("{} is the only key for {}.{}", key, other.name(), other_action.name()));
+            }
+        }
+        for &(other, other_action) in &clashes {
+            let keys = self.bindings_mut(other).get_mut(&other_action).unwrap();
+            keys.retain(|k| k != key);
+            if keys.is_empty() {
+                keys.extend(swap.iter().cloned());
+            }
+        }
+        let keys = self.bindings_mut(section).entry(action).or_default();
+        if !add {
+            keys.clear();
+        }
+        if !keys.iter().any(|k| k == key) {
+            keys.push(key.to_string());
+        }
+        Ok(clashes)
+    }
+
+    pub fn reset_keys(&mut self) {
+        let defaults = Config::default();
+        self.keys = defaults.keys;
+        self.versus = defaults.versus;
+    }
+
+    // Comments in the file are lost.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        self.validate()?;
+        let text = toml::to_string(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, format!("# tetrust settings. Delete this file to get the defaults back.\n\n{}", text))
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Config::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // Loads the config, first writing the default one if there is none.
+    pub fn load_or_create(path: &Path) -> Result<Config, String> {
+        if !path.exists() {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+            }
+            std::fs::write(path, DEFAULT_CONFIG).map_err(|e| format!("{}: {}", path.display(), e))?;
+            println!("Wrote the default settings to {}", path.display());
+        }
+        Config::load(path)
+    }
+}
+
+// $XDG_CONFIG_HOME/tetrust/config.toml, or under ~/.config without it.
+pub fn default_path() -> Option<PathBuf> {
+    let non_empty = |var| std::env::var_os(var).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let dir = non_empty("XDG_CONFIG_HOME").or_else(|| non_empty("HOME").map(|home| home.join(".config")))?;
+    Some(dir.join("tetrust").join(CONFIG_FILE))
+}
@@ -0,0 +1,198 @@
+use crate::game::*;
+use crate::movegen::{self, Action};
+use crate::tetrust::*;
+
+// Finesse: placing each piece with as few key presses as possible. The trainer
+// logs what the player pressed for every piece and compares it with the
+// shortest input sequence the move finder knows for the same placement.
+
+// How long the fault indicator stays up.
+pub const FAULT_FRAMES: u64 = 60;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PieceLog {
+    pub piece: PieceEnum,
+    // Presses in order. A shift held long enough to auto repeat counts as one
+    // press into the wall.
+    pub inputs: Vec<Action>,
+    pub optimal: Vec<Action>,
+    // Frame the piece locked on.
+    pub frame: u64
+}
+
+impl PieceLog {
+    pub fn is_fault(&self) -> bool {
+        self.inputs.len() > self.optimal.len()
+    }
+    pub fn extra(&self) -> usize {
+        self.inputs.len().saturating_sub(self.optimal.len())
+    }
+}
+
+pub fn format_inputs(inputs: &[Action]) -> String {
+    let names: Vec<&str> = inputs.iter().map(|action| match action {
+        Action::Left => "L",
+        Action::Right => "R",
+        Action::DasLeft => "DL",
+        Action::DasRight => "DR",
+        Action::RotateCw => "CW",
+        Action::RotateCcw => "CCW",
+        Action::SoftDrop => "SD",
+        Action::SonicDrop => "SD*",
+        Action::HardDrop => "HD",
+        Action::Hold => "H"
+    }).collect();
+    names.join(" ")
+}
+
+#[derive(Clone, Debug)]
+pub struct Finesse {
+    log: Vec<PieceLog>,
+    // The board as the current piece spawned into it.
+    board: Option<Tetris>,
+    inputs: Vec<Action>,
+    last: Input,
+    // Index in `inputs` of the shift being held and for how many frames.
+    shift: Option<(usize, u32)>
+}
+
+impl Default for Finesse {
+    fn default() -> Finesse {
+        Finesse::new()
+    }
+}
+
+impl Finesse {
+    pub fn new() -> Finesse {
+        Finesse { log: Vec::new(), board: None, inputs: Vec::new(), last: Input::default(), shift: None }
+    }
+    pub fn log(&self) -> &[PieceLog] {
+        &self.log
+    }
+    pub fn pieces(&self) -> usize {
+        self.log.len()
+    }
+    pub fn faults(&self) -> usize {
+        self.log.iter().filter(|l| l.is_fault()).count()
+    }
+    pub fn extra_presses(&self) -> usize {
+        self.log.iter().map(|l| l.extra()).sum()
+    }
+    // Percentage of pieces placed without a fault.
+    pub fn accuracy(&self) -> f64 {
+        if self.log.is_empty() {
+            return 100.0;
+        }
+        100.0 * (self.pieces() - self.faults()) as f64 / self.pieces() as f64
+    }
+    // The last fault if it happened within `FAULT_FRAMES` of `now`.
+    pub fn recent_fault(&self, now: u64) -> Option<&PieceLog> {
+        self.log.last().filter(|l| l.is_fault() && now.saturating_sub(l.frame) < FAULT_FRAMES)
+    }
+
+    // Call after every `Game::step` with the input that was played. Returns the
+    // log of the piece that locked this frame.
+    pub fn update(&mut self, game: &Game, input: Input) -> Option<&PieceLog> {
+        let start = self.inputs.len();
+        self.record(input, game.timings.das);
+        let mut locked = false;
+        for event in game.events() {
+            match *event {
+                Event::Locked(piece, clear) => {
+                    self.finish(piece, clear.tspin, game.frames());
+                    locked = true;
+                }
+                // The held piece starts over from its spawn.
+                Event::Held(_) => {
+                    self.board = None;
+                    self.inputs.clear();
+                }
+                Event::Spawned(_) => self.spawned(start),
+                _ => {}
+            }
+        }
+        // Spawns, and boards swapped in by undo or retry.
+        let active = game.tetris.active_piece().piece;
+        if self.board.as_ref().map(|b| b.active_piece().piece) != Some(active) && game.is_falling() {
+            let mut board = game.tetris.clone();
+            board.place_piece(board.spawn_position(active), None);
+            self.board = Some(board);
+        }
+        if locked { self.log.last() } else { None }
+    }
+
+    fn record(&mut self, input: Input, das: u32) {
+        let last = self.last;
+        self.last = input;
+        let presses = [
+            (input.left && !last.left, Action::Left),
+            (input.right && !last.right, Action::Right),
+            (input.rotate_cw && !last.rotate_cw, Action::RotateCw),
+            (input.rotate_ccw && !last.rotate_ccw, Action::RotateCcw),
+            (input.soft_drop && !last.soft_drop, Action::SoftDrop),
+            (input.hard_drop && !last.hard_drop, Action::HardDrop),
+        ];
+        let shifting = input.left != input.right;
+        match self.shift.as_mut() {
+            Some((i, frames)) if shifting && input.left == last.left && input.right == last.right => {
+                *frames += 1;
+                if *frames == das {
+                    if let Some(action) = self.inputs.get_mut(*i) {
+                        *action = match action { Action::Left => Action::DasLeft, _ => Action::DasRight };
+                    }
+                }
+            }
+            _ => self.shift = None
+        }
+        for &(pressed, action) in presses.iter() {
+            if pressed {
+                if action == Action::Left || action == Action::Right {
+                    self.shift = Some((self.inputs.len(), 0));
+                }
+                self.inputs.push(action);
+            }
+        }
+    }
+
+    // Presses made before the piece spawned, during ARE, aren't charged to it.
+    // A shift still held moves it as it spawns, so that counts as one press.
+    fn spawned(&mut self, start: usize) {
+        let start = start.min(self.inputs.len());
+        let held = self.shift.filter(|&(i, _)| i < start).map(|(i, frames)| (self.inputs[i], frames));
+        self.inputs.drain(..start);
+        match held {
+            Some((action, frames)) => {
+                self.inputs.insert(0, action);
+                self.shift = Some((0, frames));
+            }
+            None => {
+                if let Some((i, _)) = self.shift.as_mut() {
+                    *i -= start;
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self, piece: TetrisPiece, tspin: TSpin, frame: u64) {
+        let inputs = std::mem::take(&mut self.inputs);
+        self.shift = None;
+        let board = match self.board.take() {
+            Some(board) => board,
+            None => return
+        };
+        let mut cells = piece.cells();
+        cells.sort_unstable();
+        let same_cells = |p: &movegen::Placement| {
+            let mut found = p.piece.cells();
+            found.sort_unstable();
+            found == cells
+        };
+        let placements = movegen::placements(&board);
+        let optimal = placements.iter().find(|p| same_cells(p) && p.tspin == tspin)
+            .or_else(|| placements.iter().find(|p| same_cells(p)))
+            .map(|p| p.inputs.clone())
+            // Garbage moved the stack under the piece; give the player the benefit of the doubt.
+            .unwrap_or_else(|| inputs.clone());
+        self.log.push(PieceLog { piece: piece.piece, inputs, optimal, frame });
+    }
+}
@@ -0,0 +1,427 @@
+use crate::tetrust::*;
+
+// Fumen v115, the board editor format used to share setups as URLs. Each page
+// stores its field as a run length encoded diff against the field the previous
+// page left behind, followed by the piece placed on that page and an optional
+// comment. Numbers are written in base 64, least significant digit first.
+
+pub const FUMEN_WIDTH: u16 = 10;
+const FIELD_ROWS: usize = 23;
+// The visible field plus the garbage row below the floor.
+const FIELD_BLOCKS: usize = (FIELD_ROWS + 1) * FUMEN_WIDTH as usize;
+const UNCHANGED_FIELD: u32 = 8 * FIELD_BLOCKS as u32 + FIELD_BLOCKS as u32 - 1;
+const MAX_REPEAT: u32 = 63;
+const MAX_COMMENT: usize = 4095;
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const PREFIXES: [&str; 3] = ["v115@", "m115@", "d115@"];
+
+type Field = [u8; FIELD_BLOCKS];
+
+#[derive(Clone, Debug)]
+pub struct Page {
+    pub board: TileCanvas,
+    // Placed and locked on this page, so the next page starts from the board
+    // with the piece added and full rows cleared.
+    pub piece: Option<TetrisPiece>,
+    pub comment: String
+}
+
+impl Page {
+    pub fn new(board: TileCanvas) -> Page {
+        Page { board, piece: None, comment: String::new() }
+    }
+}
+
+fn tile_code(color: TileColor) -> u8 {
+    match color {
+        TileColor::Empty => 0,
+        TileColor::Cyan => 1,
+        TileColor::Orange => 2,
+        TileColor::Yellow => 3,
+        TileColor::Red => 4,
+        TileColor::Purple => 5,
+        TileColor::Blue => 6,
+        TileColor::Green => 7,
+        TileColor::Gray => 8,
+    }
+}
+
+fn tile_color(code: u8) -> TileColor {
+    match code {
+        1 => TileColor::Cyan,
+        2 => TileColor::Orange,
+        3 => TileColor::Yellow,
+        4 => TileColor::Red,
+        5 => TileColor::Purple,
+        6 => TileColor::Blue,
+        7 => TileColor::Green,
+        8 => TileColor::Gray,
+        _ => TileColor::Empty
+    }
+}
+
+fn piece_code(piece: PieceEnum) -> u32 {
+    tile_code(piece.color()) as u32
+}
+
+fn code_piece(code: u32) -> Option<PieceEnum> {
+    [PieceEnum::I, PieceEnum::L, PieceEnum::O, PieceEnum::Z, PieceEnum::T, PieceEnum::J, PieceEnum::S]
+        .get((code as usize).wrapping_sub(1)).copied()
+}
+
+// Rotation states are stored as 0 = 180, 1 = right, 2 = spawn, 3 = left, which
+// maps onto ours both ways.
+fn rotation_code(state: u8) -> u32 {
+    [2, 1, 0, 3][state as usize % 4]
+}
+
+// Blocks around the SRS rotation center, with y pointing up.
+fn blocks(piece: PieceEnum, state: u8) -> [(i16, i16); 4] {
+    let mut blocks = match piece {
+        PieceEnum::I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+        PieceEnum::T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        PieceEnum::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        PieceEnum::L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+        PieceEnum::J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        PieceEnum::S => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+        PieceEnum::Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+    };
+    for _ in 0..state % 4 {
+        for block in blocks.iter_mut() {
+            *block = (block.1, -block.0);
+        }
+    }
+    blocks
+}
+
+// Fumen anchors O, I, S and Z on a different cell than the SRS center for some
+// rotations; this is the offset from the stored position to the center.
+fn center_offset(piece: PieceEnum, state: u8) -> (i16, i16) {
+    match (piece, state) {
+        (PieceEnum::O, 0) => (0, -1),
+        (PieceEnum::O, 2) => (1, 0),
+        (PieceEnum::O, 3) => (1, -1),
+        (PieceEnum::I, 2) => (1, 0),
+        (PieceEnum::I, 3) => (0, -1),
+        (PieceEnum::S, 0) => (0, -1),
+        (PieceEnum::S, 1) => (-1, 0),
+        (PieceEnum::Z, 0) => (0, -1),
+        (PieceEnum::Z, 3) => (1, 0),
+        _ => (0, 0)
+    }
+}
+
+// Field cells are indexed from the top left, with row 0 the highest of the 23
+// visible rows; board rows are bottom aligned onto them.
+fn field_index(x: i16, row_from_bottom: i16) -> Option<usize> {
+    if x < 0 || x >= FUMEN_WIDTH as i16 || row_from_bottom < -1 || row_from_bottom >= FIELD_ROWS as i16 {
+        return None;
+    }
+    Some((FIELD_ROWS as i16 - 1 - row_from_bottom) as usize * FUMEN_WIDTH as usize + x as usize)
+}
+
+fn field_from_board(board: &TileCanvas) -> Result<Field, String> {
+    if board.width() != FUMEN_WIDTH {
+        return Err(format!("fumen boards are {} cells wide, not {}", FUMEN_WIDTH, board.width()));
+    }
+    let mut field = [0; FIELD_BLOCKS];
+    for y in 0..board.height() {
+        for x in 0..board.width() {
+            let color = board.get_tile(x, y);
+            if color == TileColor::Empty {
+                continue;
+            }
+            let index = field_index(x as i16, (board.height() - 1 - y) as i16)
+                .ok_or(format!("fumen boards are at most {} rows high", FIELD_ROWS))?;
+            field[index] = tile_code(color);
+        }
+    }
+    Ok(field)
+}
+
+fn board_from_field(field: &Field, height: u16) -> Result<TileCanvas, String> {
+    let mut board = TileCanvas::new(FUMEN_WIDTH, height);
+    for (index, &code) in field.iter().enumerate().take(FIELD_ROWS * FUMEN_WIDTH as usize) {
+        if code == 0 {
+            continue;
+        }
+        let row = FIELD_ROWS - 1 - index / FUMEN_WIDTH as usize;
+        if row >= height as usize {
+            return Err(format!("fumen board does not fit in {} rows", height));
+        }
+        board.set_tile((index % FUMEN_WIDTH as usize) as u16, (height as usize - 1 - row) as u16, tile_color(code));
+    }
+    Ok(board)
+}
+
+// Locks `piece` into the field and clears full rows the way the editor does
+// before moving on to the next page.
+fn lock_piece(field: &mut Field, piece: PieceEnum, state: u8, x: i16, row: i16) {
+    for &(dx, dy) in blocks(piece, state).iter() {
+        if let Some(index) = field_index(x + dx, row + dy) {
+            field[index] = piece_code(piece) as u8;
+        }
+    }
+    let width = FUMEN_WIDTH as usize;
+    let mut rows: Vec<Vec<u8>> = field[..FIELD_ROWS * width].chunks(width)
+        .filter(|row| row.contains(&0))
+        .map(|row| row.to_vec())
+        .collect();
+    while rows.len() < FIELD_ROWS {
+        rows.insert(0, vec![0; width]);
+    }
+    for (index, code) in rows.concat().into_iter().enumerate() {
+        field[index] = code;
+    }
+}
+
+// The position of `piece` on a board of `height` rows as (code, rotation, x,
+// row from bottom) with x and row at the fumen anchor.
+fn piece_position(piece: &TetrisPiece, height: u16) -> Result<(u32, u32, i16, i16), String> {
+    let cells: Vec<(i16, i16)> = piece.cells().iter().map(|&(x, y)| (x, height as i16 - 1 - y)).collect();
+    let shape = blocks(piece.piece, piece.state);
+    let center = cells.iter().copied().find(|&(cx, cy)| {
+        shape.iter().all(|&(dx, dy)| cells.contains(&(cx + dx, cy + dy)))
+    }).ok_or(format!("{:?} piece has no rotation center", piece.piece))?;
+    let (ox, oy) = center_offset(piece.piece, piece.state);
+    Ok((piece_code(piece.piece), rotation_code(piece.state), center.0 - ox, center.1 - oy))
+}
+
+// Finds our piece covering the cells of the fumen piece anchored at (x, row).
+fn piece_at(piece: PieceEnum, state: u8, x: i16, row: i16, height: u16) -> TetrisPiece {
+    let (ox, oy) = center_offset(piece, state);
+    let mut cells: Vec<(i16, i16)> = blocks(piece, state).iter()
+        .map(|&(dx, dy)| (x + ox + dx, height as i16 - 1 - (row + oy + dy)))
+        .collect();
+    let mut shape = piece.cells(state).to_vec();
+    cells.sort_by_key(|&(x, y)| (y, x));
+    shape.sort_by_key(|&(x, y)| (y, x));
+    TetrisPiece { x: cells[0].0 - shape[0].0, y: cells[0].1 - shape[0].1, piece, state }
+}
+
+fn push(out: &mut Vec<u8>, mut value: u32, digits: usize) {
+    for _ in 0..digits {
+        out.push(BASE64[(value % 64) as usize]);
+        value /= 64;
+    }
+}
+
+// JavaScript's `escape`, which fumen applies to comments before packing them.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for unit in text.encode_utf16() {
+        match char::from_u32(unit as u32) {
+            Some(c) if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) => escaped.push(c),
+            _ if unit < 256 => escaped.push_str(&format!("%{:02X}", unit)),
+            _ => escaped.push_str(&format!("%u{:04X}", unit))
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut units = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let hex = |from: usize, len: usize| -> Option<u16> {
+            let digits: String = chars.get(from..from + len)?.iter().collect();
+            u16::from_str_radix(&digits, 16).ok()
+        };
+        if chars[i] == '%' && chars.get(i + 1) == Some(&'u') {
+            if let Some(unit) = hex(i + 2, 4) {
+                units.push(unit);
+                i += 6;
+                continue;
+            }
+        } else if chars[i] == '%' {
+            if let Some(unit) = hex(i + 1, 2) {
+                units.push(unit);
+                i += 3;
+                continue;
+            }
+        }
+        let mut buf = [0; 2];
+        units.extend_from_slice(chars[i].encode_utf16(&mut buf));
+        i += 1;
+    }
+    String::from_utf16_lossy(&units)
+}
+
+pub fn encode(pages: &[Page]) -> Result<String, String> {
+    let mut out = Vec::new();
+    let mut prev = [0; FIELD_BLOCKS];
+    let mut repeat_at: Option<(usize, u32)> = None;
+    let mut comment = String::new();
+    for (i, page) in pages.iter().enumerate() {
+        let height = page.board.height();
+        let field = field_from_board(&page.board)?;
+
+        let mut runs = Vec::new();
+        let mut index = 0;
+        while index < FIELD_BLOCKS {
+            let diff = field[index] as u32 + 8 - prev[index] as u32;
+            let start = index;
+            while index < FIELD_BLOCKS && field[index] as u32 + 8 - prev[index] as u32 == diff {
+                index += 1;
+            }
+            runs.push(diff * FIELD_BLOCKS as u32 + (index - start - 1) as u32);
+        }
+        // Pages that leave the field alone share one run and a repeat counter.
+        if runs == [UNCHANGED_FIELD] {
+            match repeat_at {
+                Some((at, count)) if count < MAX_REPEAT => {
+                    out[at] = BASE64[count as usize + 1];
+                    repeat_at = Some((at, count + 1));
+                }
+                _ => {
+                    push(&mut out, UNCHANGED_FIELD, 2);
+                    repeat_at = Some((out.len(), 0));
+                    push(&mut out, 0, 1);
+                }
+            }
+        } else {
+            repeat_at = None;
+            for run in runs {
+                push(&mut out, run, 2);
+            }
+        }
+
+        let (code, rotation, x, row) = match &page.piece {
+            Some(piece) => piece_position(piece, height)?,
+            None => (0, 0, 0, FIELD_ROWS as i16 - 1)
+        };
+        let position = field_index(x, row).filter(|&index| index < FIELD_BLOCKS - FUMEN_WIDTH as usize)
+            .ok_or(format!("piece at ({}, {}) is outside the fumen field", x, row))?;
+        let comment_changed = page.comment != comment;
+        let colorize = i == 0;
+        let flags = (colorize as u32) << 2 | (comment_changed as u32) << 3;
+        push(&mut out, code + 8 * (rotation + 4 * (position as u32 + FIELD_BLOCKS as u32 * flags)), 3);
+
+        if comment_changed {
+            comment = page.comment.clone();
+            let escaped: Vec<u32> = escape(&comment).chars().take(MAX_COMMENT).map(|c| c as u32 - 32).collect();
+            push(&mut out, escaped.len() as u32, 2);
+            for chunk in escaped.chunks(4) {
+                push(&mut out, chunk.iter().rev().fold(0, |value, &c| value * 96 + c), 5);
+            }
+        }
+
+        prev = field;
+        if let Some(piece) = &page.piece {
+            let (ox, oy) = center_offset(piece.piece, piece.state);
+            lock_piece(&mut prev, piece.piece, piece.state, x + ox, row + oy);
+        }
+    }
+
+    // The editor breaks the data with a `?` after 42 characters and then every 47.
+    let mut text = String::from(PREFIXES[0]);
+    for (i, &c) in out.iter().enumerate() {
+        if i >= 42 && (i - 42) % 47 == 0 {
+            text.push('?');
+        }
+        text.push(c as char);
+    }
+    Ok(text)
+}
+
+struct Reader {
+    data: Vec<u32>,
+    pos: usize
+}
+
+impl Reader {
+    fn read(&mut self, digits: usize) -> Result<u32, String> {
+        let values = self.data.get(self.pos..self.pos + digits).ok_or("fumen data ends unexpectedly")?;
+        self.pos += digits;
+        Ok(values.iter().rev().fold(0, |value, &v| value * 64 + v))
+    }
+    fn is_done(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+}
+
+// Decodes every page of a fumen, given either as the bare data or as a URL
+// containing it, onto boards `height` rows high.
+pub fn decode(text: &str, height: u16) -> Result<Vec<Page>, String> {
+    let start = PREFIXES.iter().filter_map(|p| text.find(p)).min().ok_or("not a v115 fumen")?;
+    let data = text[start + PREFIXES[0].len()..].trim().chars()
+        .filter(|&c| c != '?')
+        .map(|c| BASE64.iter().position(|&b| b as char == c).map(|v| v as u32).ok_or(format!("invalid fumen character '{}'", c)))
+        .collect::<Result<Vec<u32>, String>>()?;
+    let mut reader = Reader { data, pos: 0 };
+
+    let mut pages = Vec::new();
+    let mut field = [0; FIELD_BLOCKS];
+    let mut repeat = 0;
+    let mut comment = String::new();
+    while !reader.is_done() {
+        if repeat > 0 {
+            repeat -= 1;
+        } else {
+            let mut index = 0;
+            while index < FIELD_BLOCKS {
+                let run = reader.read(2)?;
+                let (diff, len) = (run / FIELD_BLOCKS as u32, run as usize % FIELD_BLOCKS + 1);
+                if diff > 16 || index + len > FIELD_BLOCKS {
+                    return Err(String::from("invalid fumen field data"));
+                }
+                for cell in &mut field[index..index + len] {
+                    *cell = (*cell as u32 + diff).checked_sub(8).filter(|&c| c <= 8)
+                        .ok_or("invalid fumen field data")? as u8;
+                }
+                index += len;
+                if run == UNCHANGED_FIELD {
+                    repeat = reader.read(1)?;
+                }
+            }
+        }
+
+        let mut action = reader.read(3)?;
+        let code = action % 8;
+        action /= 8;
+        let state = rotation_code((action % 4) as u8) as u8;
+        action /= 4;
+        let position = (action % FIELD_BLOCKS as u32) as i16;
+        action /= FIELD_BLOCKS as u32;
+        let (rise, mirror, comment_changed, lock) = (action & 1 != 0, action & 2 != 0, action & 8 != 0, action & 16 == 0);
+
+        if comment_changed {
+            let len = reader.read(2)? as usize;
+            let mut escaped = String::new();
+            while escaped.len() < len {
+                let mut value = reader.read(5)?;
+                for _ in 0..4 {
+                    if escaped.len() < len {
+                        escaped.push(char::from_u32(value % 96 + 32).unwrap_or(' '));
+                    }
+                    value /= 96;
+                }
+            }
+            comment = unescape(&escaped);
+        }
+
+        let x = position % FUMEN_WIDTH as i16;
+        let row = FIELD_ROWS as i16 - 1 - position / FUMEN_WIDTH as i16;
+        let piece = code_piece(code).map(|piece| piece_at(piece, state, x, row, height));
+        pages.push(Page { board: board_from_field(&field, height)?, piece, comment: comment.clone() });
+
+        if let (Some(piece), true) = (piece, lock) {
+            let (ox, oy) = center_offset(piece.piece, state);
+            lock_piece(&mut field, piece.piece, state, x + ox, row + oy);
+        }
+        if rise {
+            let width = FUMEN_WIDTH as usize;
+            field.copy_within(width.., 0);
+            for cell in &mut field[FIELD_BLOCKS - width..] {
+                *cell = 0;
+            }
+        }
+        if mirror {
+            for row in field[..FIELD_ROWS * FUMEN_WIDTH as usize].chunks_mut(FUMEN_WIDTH as usize) {
+                row.reverse();
+            }
+        }
+    }
+    Ok(pages)
+}
@@ -0,0 +1,307 @@
+use serde::{Deserialize, Serialize};
+
+use crate::tetrust::*;
+
+// Gravity is measured in 1/256ths of a row per frame, so 256 is 1G.
+pub const GRAVITY_UNIT: u32 = 256;
+pub const GRAVITY_20G: u32 = 20 * GRAVITY_UNIT;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Input {
+    pub left: bool,
+    pub right: bool,
+    pub soft_drop: bool,
+    pub hard_drop: bool,
+    pub hold: bool,
+    pub rotate_cw: bool,
+    pub rotate_ccw: bool
+}
+
+impl Input {
+    // One bit per button, for storing and sending inputs compactly.
+    pub fn bits(&self) -> u8 {
+        [self.left, self.right, self.soft_drop, self.hard_drop, self.hold, self.rotate_cw, self.rotate_ccw]
+            .iter().enumerate().fold(0, |bits, (i, &down)| bits | (down as u8) << i)
+    }
+    pub fn from_bits(bits: u8) -> Input {
+        let down = |i: u8| bits & 1 << i != 0;
+        Input { left: down(0), right: down(1), soft_drop: down(2), hard_drop: down(3), hold: down(4), rotate_cw: down(5), rotate_ccw: down(6) }
+    }
+}
+
+// All values are in frames.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Timings {
+    pub are: u32,
+    pub line_are: u32,
+    pub das: u32,
+    pub arr: u32,
+    pub lock_delay: u32,
+    pub line_clear: u32
+}
+
+impl Default for Timings {
+    fn default() -> Timings {
+        Timings { are: 0, line_are: 0, das: 10, arr: 2, lock_delay: 30, line_clear: 0 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Phase {
+    Falling, LineClear(u32), Are(u32), GameOver
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Lock {
+    pub clear: Clear,
+    pub soft_drop: u32
+}
+
+// What happened during a frame, in order, for anything that follows a game
+// from the outside.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    // Buttons pressed this frame that weren't down on the last one.
+    Pressed(Input),
+    Spawned(PieceEnum),
+    // The piece that went into hold.
+    Held(PieceEnum),
+    Locked(TetrisPiece, Clear),
+    Garbage(u16),
+    ToppedOut
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Step {
+    pub spawned: Option<PieceEnum>,
+    pub locked: Option<Lock>
+}
+
+#[derive(Clone, Debug)]
+pub struct Game {
+    pub tetris: Tetris,
+    pub gravity: u32,
+    pub soft_drop_gravity: u32,
+    pub timings: Timings,
+    phase: Phase,
+    gravity_acc: u32,
+    lock_frames: u32,
+    soft_drop_frames: u32,
+    das_dir: i16,
+    das_frames: u32,
+    // Set when a direction was held before the first frame.
+    das_charged: bool,
+    last_input: Input,
+    frames: u64,
+    events: Vec<Event>
+}
+
+impl Game {
+    pub fn new(tetris: Tetris, gravity: u32, timings: Timings) -> Game {
+        Game { tetris, gravity, soft_drop_gravity: GRAVITY_UNIT, timings, phase: Phase::Falling, gravity_acc: 0, lock_frames: 0,
+               soft_drop_frames: 0, das_dir: 0, das_frames: 0, das_charged: false, last_input: Input::default(), frames: 0, events: Vec::new() }
+    }
+    // Replaces the engine state, e.g. to restore a snapshot, and continues with
+    // its active piece.
+    pub fn set_tetris(&mut self, tetris: Tetris) {
+        self.tetris = tetris;
+        self.phase = if self.tetris.check_loss() { Phase::GameOver } else { Phase::Falling };
+        self.gravity_acc = 0;
+        self.lock_frames = 0;
+        self.soft_drop_frames = 0;
+    }
+    // Pushes garbage in under the stack, topping out if it pushes tiles off the top.
+    pub fn add_garbage(&mut self, rows: Vec<Vec<TileColor>>) {
+        self.events.push(Event::Garbage(rows.len() as u16));
+        self.tetris.add_garbage(rows);
+        if self.tetris.check_loss() {
+            self.top_out();
+        }
+    }
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+    pub fn is_falling(&self) -> bool {
+        self.phase == Phase::Falling
+    }
+    pub fn is_over(&self) -> bool {
+        self.phase == Phase::GameOver
+    }
+    // The events of the last frame, and of any garbage added since.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    // Holds `input` on a frame before the game starts, as during a countdown.
+    // A direction held charges DAS and nothing else happens; buttons still held
+    // on the first frame don't count as pressed then.
+    pub fn charge(&mut self, input: Input) {
+        if self.frames > 0 {
+            return;
+        }
+        self.update_das(input);
+        self.das_charged = self.das_dir != 0;
+        self.last_input = input;
+    }
+
+    // Advances the game by one frame with `input` holding the buttons that are
+    // currently down.
+    pub fn step(&mut self, input: Input) -> Step {
+        let mut step = Step::default();
+        self.events.clear();
+        if self.phase == Phase::GameOver {
+            return step;
+        }
+        self.frames += 1;
+        self.tetris.clock = self.frames;
+        let pressed = Input::from_bits(input.bits() & !self.last_input.bits());
+        if pressed != Input::default() {
+            self.events.push(Event::Pressed(pressed));
+        }
+        self.update_das(input);
+        match self.phase {
+            Phase::LineClear(n) if n > 1 => self.phase = Phase::LineClear(n - 1),
+            Phase::LineClear(_) => self.start_are(self.timings.line_are, &mut step),
+            Phase::Are(n) if n > 1 => self.phase = Phase::Are(n - 1),
+            Phase::Are(_) => {
+                self.spawn(&mut step);
+                if self.phase == Phase::Falling {
+                    self.fall(input, &mut step);
+                }
+            }
+            Phase::Falling => self.fall(input, &mut step),
+            Phase::GameOver => {}
+        }
+        self.das_charged = false;
+        self.last_input = input;
+        step
+    }
+
+    fn update_das(&mut self, input: Input) {
+        let dir = match (input.left, input.right) {
+            (true, false) => -1,
+            (false, true) => 1,
+            _ => 0
+        };
+        if dir != self.das_dir {
+            self.das_dir = dir;
+            self.das_frames = 0;
+        } else if dir != 0 {
+            self.das_frames += 1;
+        }
+    }
+
+    fn shift(&mut self) {
+        if self.das_dir == 0 {
+            return;
+        }
+        let t = self.timings;
+        // A direction held since before the start moves the piece on the first
+        // frame, all the way if DAS charged up and ARR is 0.
+        let charged = self.das_charged;
+        if self.das_frames == 0 || (charged && self.das_frames < t.das) {
+            self.tetris.move_piece(self.das_dir, 0);
+        } else if self.das_frames >= t.das {
+            if t.arr == 0 {
+                while self.tetris.move_piece(self.das_dir, 0) {}
+            } else if charged || (self.das_frames - t.das).is_multiple_of(t.arr) {
+                self.tetris.move_piece(self.das_dir, 0);
+            }
+        }
+    }
+
+    // Without ARE the next piece appears right away, but only starts falling on the
+    // following frame.
+    fn start_are(&mut self, frames: u32, step: &mut Step) {
+        if frames == 0 {
+            self.spawn(step);
+        } else {
+            self.phase = Phase::Are(frames);
+        }
+    }
+
+    fn spawn(&mut self, step: &mut Step) {
+        self.gravity_acc = 0;
+        self.lock_frames = 0;
+        self.soft_drop_frames = 0;
+        let spawned = self.tetris.spawn_next();
+        step.spawned = Some(self.tetris.active_piece().piece);
+        self.events.push(Event::Spawned(self.tetris.active_piece().piece));
+        if !spawned {
+            self.top_out();
+            return;
+        }
+        self.phase = Phase::Falling;
+    }
+
+    fn top_out(&mut self) {
+        self.phase = Phase::GameOver;
+        self.events.push(Event::ToppedOut);
+    }
+
+    fn lock(&mut self, step: &mut Step) {
+        let piece = self.tetris.active_piece();
+        let clear = self.tetris.disable_piece();
+        let lines = clear.lines;
+        step.locked = Some(Lock { clear, soft_drop: self.soft_drop_frames });
+        self.events.push(Event::Locked(piece, clear));
+        if self.tetris.check_loss() {
+            self.top_out();
+        } else if lines == 0 {
+            self.start_are(self.timings.are, step);
+        } else if self.timings.line_clear > 0 {
+            self.phase = Phase::LineClear(self.timings.line_clear);
+        } else {
+            self.start_are(self.timings.line_are, step);
+        }
+    }
+
+    fn fall(&mut self, input: Input, step: &mut Step) {
+        let last = self.last_input;
+        if input.rotate_cw && !last.rotate_cw {
+            self.tetris.rotate_piece(true);
+        }
+        if input.rotate_ccw && !last.rotate_ccw {
+            self.tetris.rotate_piece(false);
+        }
+        if input.hold && !last.hold && self.tetris.hold_piece() {
+            self.gravity_acc = 0;
+            self.lock_frames = 0;
+            if let Some(held) = self.tetris.hold() {
+                self.events.push(Event::Held(held));
+            }
+            if self.tetris.check_loss() {
+                self.top_out();
+                return;
+            }
+        }
+        self.shift();
+        if input.hard_drop && !last.hard_drop {
+            self.tetris.hard_drop();
+            self.lock(step);
+            return;
+        }
+
+        let mut gravity = self.gravity;
+        if input.soft_drop {
+            gravity = gravity.max(self.soft_drop_gravity);
+            self.soft_drop_frames += 1;
+        }
+        self.gravity_acc += gravity;
+        while self.gravity_acc >= GRAVITY_UNIT {
+            self.gravity_acc -= GRAVITY_UNIT;
+            if self.tetris.move_piece(0, 1) {
+                self.lock_frames = 0;
+            } else {
+                self.gravity_acc = 0;
+            }
+        }
+
+        if self.tetris.on_ground() {
+            self.lock_frames += 1;
+            if self.lock_frames >= self.timings.lock_delay {
+                self.lock(step);
+            }
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::tetrust::TileColor;
+
+pub struct GarbageGenerator {
+    rng: StdRng,
+    width: u16,
+    messiness: f64,
+    hole: Option<u16>
+}
+
+impl GarbageGenerator {
+    // `messiness` is the chance (0.0 to 1.0) that a row's hole moves away from the
+    // column of the previous row's hole.
+    pub fn new(width: u16, messiness: f64, seed: u64) -> GarbageGenerator {
+        GarbageGenerator { rng: StdRng::seed_from_u64(seed), width, messiness: if messiness.is_nan() { 0.0 } else { messiness.clamp(0.0, 1.0) }, hole: None }
+    }
+    pub fn next_hole(&mut self) -> u16 {
+        let hole = match self.hole {
+            Some(hole) if self.width < 2 || !self.rng.gen_bool(self.messiness) => hole,
+            Some(hole) => (hole + self.rng.gen_range(1..self.width)) % self.width,
+            None => self.rng.gen_range(0..self.width),
+        };
+        self.hole = Some(hole);
+        hole
+    }
+    pub fn next_row(&mut self) -> Vec<TileColor> {
+        let hole = self.next_hole() as usize;
+        let mut row = vec![TileColor::Gray; self.width as usize];
+        row[hole] = TileColor::Empty;
+        row
+    }
+    pub fn rows(&mut self, count: u16) -> Vec<Vec<TileColor>> {
+        (0..count).map(|_| self.next_row()).collect()
+    }
+    // `count` rows sharing one hole, as sent by a single attack.
+    pub fn chunk(&mut self, count: u16) -> Vec<Vec<TileColor>> {
+        let hole = self.next_hole() as usize;
+        let mut row = vec![TileColor::Gray; self.width as usize];
+        row[hole] = TileColor::Empty;
+        vec![row; count as usize]
+    }
+}
@@ -0,0 +1,208 @@
+use crate::game::*;
+use crate::movegen::{Action as Button, MoveFinder};
+use crate::perfect::Move;
+use crate::tetrust::*;
+
+// A headless environment for training agents, shaped like an OpenAI Gym one:
+// `reset` starts a game and `step` plays one action, returning the observation,
+// the reward, whether the episode is over and some extra info. Agents either
+// press buttons frame by frame or pick one of the placements the move finder
+// knows, which puts the piece there at once and runs the game on to the next
+// piece.
+
+// Piece index in observations for an empty hold.
+pub const NO_PIECE: u8 = 7;
+// Button combinations in the low level action space, see `Input::from_bits`.
+pub const INPUT_ACTIONS: usize = 128;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    // Buttons held down for one frame.
+    Input(Input),
+    // Index into `Env::placements`.
+    Placement(usize)
+}
+
+impl Action {
+    pub fn from_bits(bits: u8) -> Action {
+        Action::Input(Input::from_bits(bits))
+    }
+}
+
+// Pieces are numbered in `PieceEnum` order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation {
+    // One entry per row from the top, with bit x set if column x is filled.
+    pub board: Vec<u64>,
+    pub piece: u8,
+    pub x: i16,
+    pub y: i16,
+    pub rotation: u8,
+    pub hold: u8,
+    pub queue: [u8; PREVIEW_LENGTH]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rewards {
+    // Indexed by lines cleared.
+    pub lines: [f64; 5],
+    pub top_out: f64
+}
+
+impl Default for Rewards {
+    fn default() -> Rewards {
+        Rewards { lines: [0.0, 1.0, 3.0, 5.0, 8.0], top_out: -10.0 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Info {
+    pub frames: u64,
+    pub pieces: u32,
+    pub lines: u32,
+    // The clear of the piece that locked during the step.
+    pub clear: Option<Clear>,
+    // The placement index was out of range; nothing happened.
+    pub invalid: bool
+}
+
+pub struct Env {
+    // Applied on the next `reset`.
+    pub gravity: u32,
+    pub timings: Timings,
+    pub rewards: Rewards,
+    // Ends episodes after this many pieces.
+    pub max_pieces: Option<u32>,
+    width: u16,
+    height: u16,
+    game: Game,
+    pieces: u32,
+    lines: u32,
+    finder: MoveFinder,
+    // Cached until a piece locks, along with the piece and hold they are for.
+    placements: Option<(Option<TetrisPiece>, Option<PieceEnum>, Vec<Move>)>
+}
+
+impl Env {
+    pub fn new(width: u16, height: u16) -> Result<Env, String> {
+        check_board_size(width, height)?;
+        if width > 64 {
+            return Err(format!("unsupported board size {}x{}: must be at most 64 wide", width, height));
+        }
+        let mut env = Env { gravity: GRAVITY_UNIT / 19, timings: Timings::default(), rewards: Rewards::default(), max_pieces: None, width, height,
+                            game: Game::new(Tetris::new(TileCanvas::new(width, height), 0), 0, Timings::default()), pieces: 0, lines: 0,
+                            finder: MoveFinder::new(), placements: None };
+        env.reset(0);
+        Ok(env)
+    }
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.game = Game::new(Tetris::new(TileCanvas::new(self.width, self.height), seed), self.gravity, self.timings);
+        self.pieces = 0;
+        self.lines = 0;
+        self.placements = None;
+        self.observation()
+    }
+
+    pub fn step(&mut self, action: Action) -> (Observation, f64, bool, Info) {
+        let mut info = Info::default();
+        let mut reward = 0.0;
+        if !self.is_done() {
+            let over = self.game.is_over();
+            let lock = match action {
+                Action::Input(input) => self.game.step(input).locked,
+                Action::Placement(i) => match self.placements().get(i).cloned() {
+                    Some(m) => place(&mut self.game, &m),
+                    None => {
+                        info.invalid = true;
+                        None
+                    }
+                }
+            };
+            if let Some(lock) = lock {
+                self.placements = None;
+                self.pieces += 1;
+                self.lines += lock.clear.lines as u32;
+                reward += self.rewards.lines.get(lock.clear.lines as usize).copied().unwrap_or(0.0);
+                info.clear = Some(lock.clear);
+            }
+            if self.game.is_over() && !over {
+                reward += self.rewards.top_out;
+            }
+        }
+        info.frames = self.game.frames();
+        info.pieces = self.pieces;
+        info.lines = self.lines;
+        (self.observation(), reward, self.is_done(), info)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.game.is_over() || self.max_pieces.is_some_and(|max| self.pieces >= max)
+    }
+
+    // The placement action space for this step.
+    pub fn placements(&mut self) -> &[Move] {
+        let tetris = &self.game.tetris;
+        let key = (Some(tetris.active_piece()).filter(|_| self.game.is_falling()), tetris.hold());
+        match &self.placements {
+            Some((piece, hold, _)) if (*piece, *hold) == key => {}
+            _ => self.placements = Some((key.0, key.1, moves_with(&mut self.finder, &self.game)))
+        }
+        self.placements.as_ref().map(|(_, _, moves)| moves.as_slice()).unwrap_or_default()
+    }
+
+    pub fn observation(&self) -> Observation {
+        let tetris = &self.game.tetris;
+        let canvas = &tetris.tile_canvas;
+        let board = (0..canvas.height()).map(|y| {
+            (0..canvas.width()).filter(|&x| canvas.get_tile(x, y) != TileColor::Empty).fold(0, |row, x| row | 1 << x)
+        }).collect();
+        let piece = tetris.active_piece();
+        let mut queue = [NO_PIECE; PREVIEW_LENGTH];
+        for (slot, next) in queue.iter_mut().zip(tetris.queue.peek()) {
+            *slot = *next as u8;
+        }
+        Observation { board, piece: piece.piece as u8, x: piece.x, y: piece.y, rotation: piece.state, hold: tetris.hold().map(|p| p as u8).unwrap_or(NO_PIECE),
+                      queue }
+    }
+}
+
+// Every placement of the active piece and of the piece hold would bring out.
+pub fn moves(game: &Game) -> Vec<Move> {
+    moves_with(&mut MoveFinder::new(), game)
+}
+
+pub fn moves_with(finder: &mut MoveFinder, game: &Game) -> Vec<Move> {
+    if !game.is_falling() {
+        return Vec::new();
+    }
+    let tetris = &game.tetris;
+    let mut moves: Vec<Move> = finder.placements(tetris).into_iter().map(|placement| Move { hold: false, placement }).collect();
+    let mut held = tetris.clone();
+    if held.hold_piece() && !held.check_loss() {
+        moves.extend(finder.placements(&held).into_iter().map(|placement| Move { hold: true, placement }));
+    }
+    moves
+}
+
+// Puts the piece in place and drops it, then runs the game until the next
+// piece can move. Returns None without locking if `m` isn't for this piece.
+pub fn place(game: &mut Game, m: &Move) -> Option<Lock> {
+    // Let go of everything so the presses below register.
+    game.step(Input::default());
+    if m.hold {
+        game.step(Button::Hold.input());
+    }
+    if !game.is_falling() || game.tetris.active_piece().piece != m.placement.piece.piece {
+        return None;
+    }
+    game.tetris.place_piece(m.placement.piece, m.placement.kick);
+    let locked = game.step(Button::HardDrop.input()).locked;
+    while !game.is_falling() && !game.is_over() {
+        game.step(Input::default());
+    }
+    locked
+}
@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::keyboard::{KeyboardState, Scancode};
+use sdl2::GameControllerSubsystem;
+
+use tetrust::config::{Action, Bindings, Config, GamepadConfig};
+use tetrust::game::Input;
+use tetrust::menu::Nav;
+
+// Turns the keyboard and gamepads into game inputs and frontend actions,
+// following the bindings in the config.
+
+// The keys for each action; pressing any of them triggers it.
+#[derive(Default)]
+pub struct KeyMap {
+    keys: BTreeMap<Action, Vec<Scancode>>
+}
+
+impl KeyMap {
+    // `section` names the bindings in errors.
+    fn new(bindings: &Bindings, section: &str) -> Result<KeyMap, String> {
+        let mut keys = BTreeMap::new();
+        for (&action, names) in bindings {
+            let scancodes = names.iter().map(|name| {
+                Scancode::from_name(name).ok_or_else(|| format!("{}.{}: unknown key '{}'", section, action.name(), name))
+            }).collect::<Result<Vec<_>, _>>()?;
+            keys.insert(action, scancodes);
+        }
+        Ok(KeyMap { keys })
+    }
+
+    fn read(&self, keyboard: &KeyboardState) -> Input {
+        let mut input = Input::default();
+        for (action, keys) in &self.keys {
+            action.set(&mut input, keys.iter().any(|&key| keyboard.is_scancode_pressed(key)));
+        }
+        input
+    }
+
+    fn is_bound(&self, action: Action, scancode: Scancode) -> bool {
+        self.keys.get(&action).is_some_and(|keys| keys.contains(&scancode))
+    }
+}
+
+// A button, or a stick or trigger pushed past the deadzone one way.
+#[derive(Clone, Copy, PartialEq)]
+enum PadInput {
+    Button(Button),
+    Axis(Axis, bool)
+}
+
+fn parse_pad_input(name: &str) -> Option<PadInput> {
+    if let Some(axis) = name.strip_suffix('+') {
+        return Axis::from_string(axis).map(|axis| PadInput::Axis(axis, true));
+    }
+    if let Some(axis) = name.strip_suffix('-') {
+        return Axis::from_string(axis).map(|axis| PadInput::Axis(axis, false));
+    }
+    Button::from_string(name).map(PadInput::Button)
+}
+
+#[derive(Clone, Default)]
+struct PadMap {
+    inputs: BTreeMap<Action, Vec<PadInput>>
+}
+
+impl PadMap {
+    fn new(bindings: &Bindings, section: &str) -> Result<PadMap, String> {
+        let mut inputs = BTreeMap::new();
+        for (&action, names) in bindings {
+            let pad_inputs = names.iter().map(|name| {
+                parse_pad_input(name).ok_or_else(|| format!("{}.{}: unknown button or axis '{}'", section, action.name(), name))
+            }).collect::<Result<Vec<_>, _>>()?;
+            inputs.insert(action, pad_inputs);
+        }
+        Ok(PadMap { inputs })
+    }
+}
+
+struct Gamepad {
+    controller: GameController,
+    map: PadMap
+}
+
+impl Gamepad {
+    fn is_down(&self, input: PadInput, deadzone: i32) -> bool {
+        match input {
+            PadInput::Button(button) => self.controller.button(button),
+            PadInput::Axis(axis, true) => self.controller.axis(axis) as i32 > deadzone,
+            PadInput::Axis(axis, false) => (self.controller.axis(axis) as i32) < -deadzone
+        }
+    }
+
+    fn read(&self, deadzone: i32) -> Input {
+        let mut input = Input::default();
+        for (action, inputs) in &self.map.inputs {
+            action.set(&mut input, inputs.iter().any(|&i| self.is_down(i, deadzone)));
+        }
+        input
+    }
+}
+
+fn merge(a: Input, b: Input) -> Input {
+    Input::from_bits(a.bits() | b.bits())
+}
+
+// The solo keys also quit in versus. Gamepads are opened as SDL reports them
+// plugged in, which it also does for the ones there at the start.
+#[derive(Default)]
+pub struct Controls {
+    solo: KeyMap,
+    // The solo key names, for showing them.
+    solo_names: Bindings,
+    versus: [KeyMap; 2],
+    pad_buttons: PadMap,
+    // By controller name.
+    pad_controllers: BTreeMap<String, PadMap>,
+    deadzone: i32,
+    subsystem: Option<GameControllerSubsystem>,
+    // In the order they were plugged in.
+    gamepads: Vec<Gamepad>
+}
+
+impl Controls {
+    pub fn new(config: &Config) -> Result<Controls, String> {
+        let gamepad = &config.gamepad;
+        let mut pad_controllers = BTreeMap::new();
+        for (name, buttons) in &gamepad.controllers {
+            pad_controllers.insert(name.clone(), PadMap::new(buttons, &format!("gamepad.controllers.\"{}\"", name))?);
+        }
+        Ok(Controls {
+            solo: KeyMap::new(&config.keys, "keys")?,
+            solo_names: config.keys.clone(),
+            versus: [KeyMap::new(&config.versus.player1, "versus.player1")?, KeyMap::new(&config.versus.player2, "versus.player2")?],
+            pad_buttons: PadMap::new(&gamepad.buttons, "gamepad.buttons")?,
+            pad_controllers,
+            deadzone: (gamepad.deadzone * i16::MAX as f64) as i32,
+            subsystem: None,
+            gamepads: Vec::new()
+        })
+    }
+
+    // Picks up changed bindings, keeping the gamepads that are open.
+    pub fn reload(&mut self, config: &Config) -> Result<(), String> {
+        let mut controls = Controls::new(config)?;
+        controls.subsystem = self.subsystem.take();
+        controls.gamepads = std::mem::take(&mut self.gamepads);
+        for pad in &mut controls.gamepads {
+            pad.map = controls.pad_controllers.get(&pad.controller.name()).unwrap_or(&controls.pad_buttons).clone();
+        }
+        *self = controls;
+        Ok(())
+    }
+
+    pub fn open_gamepads(&mut self, subsystem: GameControllerSubsystem, config: &GamepadConfig) -> Result<(), String> {
+        for (i, mapping) in config.mappings.iter().enumerate() {
+            subsystem.add_mapping(mapping).map_err(|e| format!("gamepad.mappings: entry {}: {}", i + 1, e))?;
+        }
+        self.subsystem = Some(subsystem);
+        Ok(())
+    }
+
+    // Opens and closes gamepads as they come and go.
+    pub fn update(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                let controller = match self.subsystem.as_ref().map(|s| s.open(which)) {
+                    Some(Ok(controller)) => controller,
+                    Some(Err(e)) => {
+                        println!("Couldn't open gamepad {}: {}", which, e);
+                        return;
+                    }
+                    None => return
+                };
+                if self.gamepads.iter().any(|pad| pad.controller.instance_id() == controller.instance_id()) {
+                    return;
+                }
+                let name = controller.name();
+                let map = self.pad_controllers.get(&name).unwrap_or(&self.pad_buttons).clone();
+                println!("Gamepad connected: {}", name);
+                self.gamepads.push(Gamepad { controller, map });
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.gamepads.retain(|pad| pad.controller.instance_id() != which);
+            }
+            _ => {}
+        }
+    }
+
+    // Whether `event` presses a key or button bound to `action`.
+    pub fn is_pressed(&self, event: &Event, action: Action) -> bool {
+        match *event {
+            Event::KeyDown { scancode: Some(scancode), repeat: false, .. } => self.solo.is_bound(action, scancode),
+            Event::ControllerButtonDown { which, button, .. } => self.gamepads.iter().any(|pad| {
+                pad.controller.instance_id() == which && pad.map.inputs.get(&action).is_some_and(|inputs| inputs.contains(&PadInput::Button(button)))
+            }),
+            _ => false
+        }
+    }
+    pub fn is_quit(&self, event: &Event) -> bool {
+        self.is_pressed(event, Action::Quit)
+    }
+    pub fn is_restart(&self, event: &Event) -> bool {
+        self.is_pressed(event, Action::Restart)
+    }
+    // Which of `Action::PICKS` `event` presses, from 0.
+    pub fn pick(&self, event: &Event) -> Option<usize> {
+        Action::PICKS.iter().position(|&action| self.is_pressed(event, action))
+    }
+    // The first solo key for `action`, to tell players what to press.
+    pub fn key_name(&self, action: Action) -> &str {
+        self.solo_names.get(&action).and_then(|keys| keys.first()).map_or("-", String::as_str)
+    }
+
+    // Menus always work with the arrow keys, Enter and Escape and with a
+    // gamepad's d-pad, A and B, so a bad binding can't lock anyone out. The
+    // quit bindings go back too.
+    pub fn nav(&self, event: &Event) -> Option<Nav> {
+        match *event {
+            Event::KeyDown { scancode: Some(scancode), .. } => match scancode {
+                Scancode::Up => Some(Nav::Up),
+                Scancode::Down => Some(Nav::Down),
+                Scancode::Left => Some(Nav::Left),
+                Scancode::Right => Some(Nav::Right),
+                Scancode::Return | Scancode::KpEnter => Some(Nav::Select),
+                Scancode::Escape => Some(Nav::Back),
+                _ if self.solo.is_bound(Action::Quit, scancode) => Some(Nav::Back),
+                _ => None
+            },
+            Event::ControllerButtonDown { button, .. } => match button {
+                Button::DPadUp => Some(Nav::Up),
+                Button::DPadDown => Some(Nav::Down),
+                Button::DPadLeft => Some(Nav::Left),
+                Button::DPadRight => Some(Nav::Right),
+                Button::A => Some(Nav::Select),
+                Button::B => Some(Nav::Back),
+                _ if self.is_quit(event) => Some(Nav::Back),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    // Everything held on the solo keys and any gamepad.
+    pub fn read(&self, keyboard: &KeyboardState) -> Input {
+        self.gamepads.iter().fold(self.solo.read(keyboard), |input, pad| merge(input, pad.read(self.deadzone)))
+    }
+    // A versus player's keys and their gamepad.
+    pub fn read_player(&self, player: usize, keyboard: &KeyboardState) -> Input {
+        let keys = self.versus[player].read(keyboard);
+        match self.gamepads.get(player) {
+            Some(pad) => merge(keys, pad.read(self.deadzone)),
+            None => keys
+        }
+    }
+}
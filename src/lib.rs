@@ -0,0 +1,24 @@
+pub mod bot;
+pub mod config;
+pub mod finesse;
+pub mod fumen;
+pub mod game;
+pub mod garbage;
+pub mod gym;
+pub mod menu;
+pub mod modes;
+pub mod movegen;
+pub mod net;
+pub mod notation;
+pub mod perfect;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod records;
+pub mod replay;
+pub mod report;
+pub mod room;
+pub mod spectate;
+pub mod stats;
+pub mod tbp;
+pub mod tetrust;
+pub mod visibility;
@@ -1,107 +1,1395 @@
 extern crate sdl2;
 
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use sdl2::event::Event;
-use rand::distributions::{Distribution, Standard};
-use rand::Rng;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-
-mod tetrust;
-use crate::tetrust::tetrust::*;
-
-fn tetris_collision(tetris: &mut Tetris, x: u16) -> Result<(), String> {
-    println!("Collision!");
-    tetris.disable_piece()?;
-    tetris.set_piece(x, 0, rand::random())?;
+use sdl2::keyboard::{KeyboardState, Scancode};
+use sdl2::EventPump;
+
+mod input;
+mod render;
+use crate::input::*;
+use crate::render::*;
+use tetrust::bot::{Bot, Controller, Difficulty};
+use tetrust::config::{self, Action, Config, KeySection};
+use tetrust::finesse::{self, Finesse};
+use tetrust::fumen::{self, Page};
+use tetrust::game::*;
+use tetrust::menu::{Item, Menu, Nav};
+use tetrust::modes::dig::Dig;
+use tetrust::modes::master::{self, Master};
+use tetrust::modes::opener::{self, Opener};
+use tetrust::modes::puzzle::{self, Puzzle, PuzzleMode};
+use tetrust::modes::versus::{self, Outcome, Versus};
+use tetrust::modes::zen::Zen;
+use tetrust::net::{self, Client};
+use tetrust::notation;
+use tetrust::perfect::{self, Planner, Search};
+use tetrust::records::{self, Record, Records};
+use tetrust::replay::{Replay, ReplayMode};
+use tetrust::report::Report;
+use tetrust::room::{RoomClient, Targeting};
+use tetrust::spectate::{self, Broadcaster, Spectator};
+use tetrust::stats::Stats;
+use tetrust::tbp::{self, TbpBot};
+use tetrust::tetrust::*;
+use tetrust::visibility::{self, StackVisibility};
+
+const FRAME: Duration = Duration::from_micros(16_667);
+const MARATHON_GRAVITY: u32 = GRAVITY_UNIT / 19;
+
+const DIG_LINES: u16 = 18;
+const DIG_VISIBLE: u16 = 9;
+const DIG_MESSINESS: f64 = 0.3;
+
+const ZEN_BOARD_HEIGHT: u16 = 20;
+
+// How long "GO" stays up after the countdown.
+const GO_FRAMES: u64 = 30;
+
+enum Mode {
+    Marathon,
+    Dig(Box<Dig>),
+    Master(Box<Master>),
+    Zen(Box<Zen>, Option<TileCanvas>),
+    Puzzle(Box<PuzzleMode>),
+    Opener(Box<Opener>),
+    Versus(Box<Versus>),
+    Online(Box<Client>),
+    Royale(Box<RoomClient>),
+    Spectate(Box<Spectator>),
+    Options
+}
+
+struct Options {
+    visibility: StackVisibility,
+    broadcaster: Option<Broadcaster>,
+    // Computer players by player number; the solo modes use whichever is set.
+    bots: [Option<Box<dyn Controller>>; 2],
+    training: Training,
+    // Where to save a replay of the game.
+    record: Option<PathBuf>,
+    config: Config,
+    controls: Controls,
+    // Where changes to the config are saved, if anywhere.
+    config_path: Option<PathBuf>,
+    records: Records,
+    // Where results are saved, if anywhere.
+    records_path: Option<PathBuf>
+}
+
+// Helpers for the solo modes.
+#[derive(Default)]
+struct Training {
+    finesse: Option<Finesse>,
+    planner: Option<Planner>
+}
+
+// Usage: tetrust [--config FILE] [--invisible | --fade [SECONDS]] [--broadcast [ADDR]] [--bot [1|2] [DIFFICULTY]] [--tbp [1|2] COMMAND] [--finesse] [--pc [LINES]] [--record FILE] [--records FILE] [marathon | dig [LINES] [MESSINESS] | master | zen [BOARD] | puzzle [DIR] | opener [NAME] | versus | connect ADDR | royale ADDR | options]
+//        tetrust [--config FILE] --spectate ADDR
+//
+// Without a mode, the title screen lets you pick one. In the solo modes the
+// quit key pauses the game and the restart key starts it over on new pieces.
+// Settings are read from FILE, or from tetrust/config.toml in the XDG config
+// directory, and a default one is written there if it doesn't exist yet.
+// options shows the key bindings and lets you change them.
+// A zen BOARD is either a fumen or the path of a text board. --broadcast lets
+// others watch with --spectate; it listens on localhost unless given an ADDR.
+// --bot lets the computer play, as the second player in versus unless told
+// otherwise. --tbp does the same with an external bot speaking the Tetris Bot
+// Protocol, started with COMMAND; quote it to pass arguments. It needs a board
+// 10 wide and 20 to 40 tall. --finesse
+// counts wasted key presses in the solo modes. --pc shows where to put each
+// piece for a perfect clear within LINES lines when the preview allows one.
+// --record saves a replay of each marathon, dig or master game to FILE.
+// Results of marathon, dig and master games are kept in FILE, or in
+// tetrust/records.json in the XDG data directory, along with replays of the
+// games that make the records.
+fn parse_args() -> Result<(Option<Mode>, Vec<String>, Options), String> {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut options = Options { visibility: StackVisibility::Visible, broadcaster: None, bots: [None, None], training: Training::default(), record: None,
+                                config: Config::default(), controls: Controls::default(), config_path: None, records: Records::default(),
+                                records_path: None };
+    let visibility = &mut options.visibility;
+    let mut config_path = None;
+    let mut tbp_bot = false;
+    loop {
+        match args.first().map(|s| s.as_str()) {
+            Some("--config") => {
+                let path = args.get(1).ok_or("--config needs a file")?;
+                config_path = Some(PathBuf::from(path));
+                args.drain(..2);
+            }
+            Some("--invisible") => {
+                *visibility = StackVisibility::Invisible;
+                args.remove(0);
+            }
+            Some("--fade") => {
+                args.remove(0);
+                *visibility = StackVisibility::Fade(visibility::DEFAULT_FADE_FRAMES);
+                if let Some(Ok(seconds)) = args.first().map(|s| s.parse::<f64>()) {
+                    *visibility = StackVisibility::Fade((seconds * 60.0) as u32);
+                    args.remove(0);
+                }
+            }
+            Some("--broadcast") => {
+                args.remove(0);
+                let mut addr = format!("127.0.0.1:{}", spectate::DEFAULT_SPECTATE_PORT);
+                if args.first().is_some_and(|s| s.contains(':')) {
+                    addr = args.remove(0);
+                }
+                let b = Broadcaster::bind(&addr)?;
+                println!("Broadcasting on {}", b.local_addr()?);
+                options.broadcaster = Some(b);
+            }
+            Some("--bot") => {
+                args.remove(0);
+                let player = player_number(&mut args);
+                let mut difficulty = Difficulty::Hard;
+                if let Some(Ok(d)) = args.first().map(|s| Difficulty::parse(s)) {
+                    difficulty = d;
+                    args.remove(0);
+                }
+                options.bots[player] = Some(Box::new(Bot::new(difficulty, rand::random())));
+            }
+            Some("--tbp") => {
+                args.remove(0);
+                let player = player_number(&mut args);
+                let command = args.first().ok_or("--tbp needs a bot command")?;
+                let bot = TbpBot::spawn(command)?;
+                println!("Playing with {} by {}", bot.name, bot.author);
+                options.bots[player] = Some(Box::new(bot));
+                tbp_bot = true;
+                args.remove(0);
+            }
+            Some("--finesse") => {
+                args.remove(0);
+                options.training.finesse = Some(Finesse::new());
+            }
+            Some("--pc") => {
+                args.remove(0);
+                let mut lines = perfect::DEFAULT_PC_LINES;
+                if let Some(Ok(l)) = args.first().map(|s| s.parse::<u16>()) {
+                    lines = l;
+                    args.remove(0);
+                }
+                options.training.planner = Some(Planner::new(lines));
+            }
+            Some("--records") => {
+                let path = args.get(1).ok_or("--records needs a file")?;
+                options.records_path = Some(PathBuf::from(path));
+                args.drain(..2);
+            }
+            Some("--record") => {
+                let path = args.get(1).ok_or("--record needs a file")?;
+                options.record = Some(PathBuf::from(path));
+                args.drain(..2);
+            }
+            Some("--spectate") => {
+                let addr = args.get(1).ok_or("--spectate needs an address")?;
+                let spectator = Spectator::connect(&with_port(addr, spectate::DEFAULT_SPECTATE_PORT))?;
+                let (config, controls, config_path) = load_config(config_path)?;
+                return Ok((Some(Mode::Spectate(Box::new(spectator))), Vec::new(), Options { visibility: StackVisibility::Visible, broadcaster: None, bots: [None, None],
+                                                                          training: Training::default(), record: None, config, controls, config_path,
+                                                                          records: Records::default(), records_path: None }));
+            }
+            _ => break
+        }
+    }
+    (options.config, options.controls, options.config_path) = load_config(config_path)?;
+    options.records_path = options.records_path.take().or_else(records::default_path);
+    if let Some(path) = &options.records_path {
+        options.records = Records::load(path)?;
+    }
+    let mode = if args.is_empty() { None } else { Some(parse_mode(&args, &options.config)?) };
+    if options.record.is_some() && mode.as_ref().is_some_and(|mode| !is_timed(mode)) {
+        return Err(String::from("--record only works in marathon, dig and master"));
+    }
+    if let Some(mode) = mode.as_ref().filter(|_| tbp_bot) {
+        tbp::check_board(&board_for(mode, &options.config))?;
+    }
+    Ok((mode, args, options))
+}
+
+// The player a bot takes over, the second one unless a number is given.
+fn player_number(args: &mut Vec<String>) -> usize {
+    match args.first().map(|s| s.as_str()) {
+        Some("1") => {
+            args.remove(0);
+            0
+        }
+        Some("2") => {
+            args.remove(0);
+            1
+        }
+        _ => 1
+    }
+}
+
+// The settings from `path` or the default location. Without a home directory
+// to find that in, the defaults are used as they are.
+fn load_config(path: Option<PathBuf>) -> Result<(Config, Controls, Option<PathBuf>), String> {
+    let path = path.or_else(config::default_path);
+    let config = match &path {
+        Some(path) => Config::load_or_create(path)?,
+        None => Config::default()
+    };
+    let controls = Controls::new(&config).map_err(|e| match &path {
+        Some(path) => format!("{}: {}", path.display(), e),
+        None => e
+    })?;
+    Ok((config, controls, path))
+}
+
+fn with_port(addr: &str, port: u16) -> String {
+    if addr.contains(':') { addr.to_string() } else { format!("{}:{}", addr, port) }
+}
+
+fn parse_mode(args: &[String], config: &Config) -> Result<Mode, String> {
+    match args.first().map(|s| s.as_str()) {
+        None | Some("marathon") => Ok(Mode::Marathon),
+        Some("dig") => {
+            let lines = match args.get(1) {
+                Some(s) => s.parse::<u16>().map_err(|e| format!("invalid dig line count '{}': {}", s, e))?,
+                None => DIG_LINES
+            };
+            let messiness = match args.get(2) {
+                Some(s) => s.parse::<f64>().map_err(|e| format!("invalid dig messiness '{}': {}", s, e))?,
+                None => DIG_MESSINESS
+            };
+            if !(0.0..=1.0).contains(&messiness) {
+                return Err(format!("invalid dig messiness '{}': must be between 0 and 1", messiness));
+            }
+            Ok(Mode::Dig(Box::new(Dig::new(config.board.width, lines, DIG_VISIBLE, messiness, rand::random()))))
+        }
+        Some("master") => Ok(Mode::Master(Box::new(Master::new()))),
+        Some("zen") => {
+            let board = args.get(1).map(|s| load_board(s)).transpose()?;
+            Ok(Mode::Zen(Box::new(Zen::new()), board))
+        }
+        Some("puzzle") => {
+            let dir = args.get(1).map(|s| s.as_str()).unwrap_or(puzzle::PUZZLE_DIR);
+            let puzzles = Puzzle::load_pack(std::path::Path::new(dir))?;
+            if puzzles.is_empty() {
+                return Err(format!("no puzzles found in {}", dir));
+            }
+            Ok(Mode::Puzzle(Box::new(PuzzleMode::new(puzzles))))
+        }
+        Some("opener") => {
+            let setup = args.get(1).map(|s| opener::find_setup(s)).transpose()?.unwrap_or(0);
+            Ok(Mode::Opener(Box::new(Opener::new(opener::OPENER_WIDTH, opener::OPENER_HEIGHT, rand::random(), setup))))
+        }
+        Some("versus") => {
+            let tetris = Tetris::new(TileCanvas::new(versus::VERSUS_WIDTH, versus::VERSUS_HEIGHT), rand::random());
+            let mut game = Game::new(tetris, MARATHON_GRAVITY, Timings::default());
+            config.handling.apply(&mut game);
+            Ok(Mode::Versus(Box::new(Versus::new(game, versus::VERSUS_MESSINESS, rand::random()))))
+        }
+        Some("connect") => {
+            let addr = args.get(1).ok_or("connect needs a server address")?;
+            let addr = with_port(addr, net::DEFAULT_PORT);
+            let tetris = Tetris::new(TileCanvas::new(versus::VERSUS_WIDTH, versus::VERSUS_HEIGHT), 0);
+            println!("Waiting for an opponent on {}", addr);
+            Ok(Mode::Online(Box::new(Client::connect(&addr, Game::new(tetris, MARATHON_GRAVITY, Timings::default()))?)))
+        }
+        Some("royale") => {
+            let addr = args.get(1).ok_or("royale needs a server address")?;
+            let addr = with_port(addr, net::DEFAULT_PORT);
+            let tetris = Tetris::new(TileCanvas::new(versus::VERSUS_WIDTH, versus::VERSUS_HEIGHT), 0);
+            println!("Waiting for the room on {} to fill up", addr);
+            Ok(Mode::Royale(Box::new(RoomClient::join(&addr, Game::new(tetris, MARATHON_GRAVITY, Timings::default()))?)))
+        }
+        Some("options") => Ok(Mode::Options),
+        Some(mode) => Err(format!("unknown mode '{}'", mode))
+    }
+}
+
+fn load_board(arg: &str) -> Result<TileCanvas, String> {
+    if arg.contains("115@") {
+        let pages = fumen::decode(arg, ZEN_BOARD_HEIGHT)?;
+        return pages.into_iter().next().map(|page| page.board).ok_or(String::from("fumen has no pages"));
+    }
+    let text = std::fs::read_to_string(arg).map_err(|e| format!("{}: {}", arg, e))?;
+    let width = text.lines().map(|l| l.trim()).find(|l| !l.is_empty()).map(|l| l.chars().count()).unwrap_or(fumen::FUMEN_WIDTH as usize);
+    notation::parse_board(&text, width as u16, ZEN_BOARD_HEIGHT).map_err(|e| format!("{}: {}", arg, e))
+}
+
+// Prints the current board as text and the undo history as a fumen, one page per placement.
+fn export_zen(zen: &Zen, game: &Game) {
+    print!("{}", notation::format_board(&game.tetris.tile_canvas));
+    let pages: Vec<Page> = zen.snapshots().iter().map(|t| Page::new(t.tile_canvas.clone())).collect();
+    match fumen::encode(&pages) {
+        Ok(code) => println!("{}", code),
+        Err(e) => println!("No fumen: {}", e)
+    }
+}
+
+// The bot plays instead of the keyboard and gamepads if there is one.
+fn local_input(bot: &mut Option<Box<dyn Controller>>, game: &Game, controls: &Controls, keyboard: &KeyboardState) -> Result<Input, String> {
+    match bot {
+        Some(bot) => bot.input(game),
+        None => Ok(controls.read(keyboard))
+    }
+}
+
+fn status(game: &Game, mode: &Mode, training: &Training, controls: &Controls) -> Vec<String> {
+    let mut lines = mode_status(game, mode, controls);
+    if let Some(planner) = &training.planner {
+        lines.push(planner_status(planner));
+    }
+    if let Some(finesse) = &training.finesse {
+        lines.push(format!("Faults: {}", finesse.faults()));
+        if let Some(fault) = finesse.recent_fault(game.frames()) {
+            lines.push(format!("FAULT: {} keys, {} needed", fault.inputs.len(), fault.optimal.len()));
+        }
+    }
+    lines
+}
+
+fn mode_status(game: &Game, mode: &Mode, controls: &Controls) -> Vec<String> {
+    let retry = controls.key_name(Action::Restart);
+    let next = controls.key_name(Action::Next);
+    match mode {
+        Mode::Marathon => vec![String::from("Score: ") + &game.tetris.score().to_string()],
+        Mode::Dig(dig) => vec![
+            String::from("Score: ") + &game.tetris.score().to_string(),
+            String::from("Garbage: ") + &dig.remaining().to_string(),
+        ],
+        Mode::Master(master) => vec![
+            format!("Level: {}/{}", master.level(), master.level_stop()),
+            String::from("Grade: ") + master.grade(),
+            String::from("Time: ") + &master::format_time(game.frames()),
+        ],
+        Mode::Zen(zen, _) => vec![
+            String::from("Score: ") + &game.tetris.score().to_string(),
+            String::from("Undo: ") + &zen.undo_count().to_string(),
+        ],
+        Mode::Puzzle(puzzles) => vec![
+            format!("{}/{}: {}", puzzles.index() + 1, puzzles.len(), puzzles.current().name),
+            puzzles.current().describe(),
+            match puzzles.solved() {
+                Some(true) => format!("Solved! {}: next", next),
+                Some(false) => format!("Failed. {}: retry", retry),
+                None => format!("{}: retry, {}: next", retry, next)
+            },
+        ],
+        Mode::Opener(opener) => vec![
+            format!("Opener: {}", opener.name()),
+            format!("Perfect clears: {}/{}", opener.perfects(), opener.rounds()),
+            if opener.failed() {
+                format!("Round lost. {}: retry, {}: next", retry, next)
+            } else if opener.is_searching() {
+                String::from("Searching...")
+            } else {
+                format!("{}: retry, {}: next", retry, next)
+            },
+        ],
+        Mode::Versus(_) | Mode::Online(_) | Mode::Royale(_) | Mode::Spectate(_) | Mode::Options => Vec::new()
+    }
+}
+
+fn planner_status(planner: &Planner) -> String {
+    match planner.status() {
+        _ if planner.is_searching() => String::from("PC: searching"),
+        Some(Search::Found(_)) => format!("PC: {} pieces left", planner.remaining()),
+        Some(Search::Impossible) => String::from("PC: none"),
+        Some(Search::GaveUp) => String::from("PC: gave up"),
+        None => String::from("PC: -")
+    }
+}
+
+fn versus_status(versus: &Versus, player: usize) -> Vec<String> {
+    let p = &versus.players[player];
+    let mut status = vec![String::from("Sent: ") + &p.sent().to_string()];
+    match p.combo() {
+        Some(combo) if combo > 0 => status.push(format!("{} combo", combo)),
+        _ => {}
+    }
+    if p.back_to_back() {
+        status.push(String::from("Back-to-back"));
+    }
+    match versus.outcome() {
+        Some(Outcome::Winner(winner)) if winner == player => status.push(String::from("You win!")),
+        Some(Outcome::Winner(_)) => status.push(String::from("You lose")),
+        Some(Outcome::Draw) => status.push(String::from("Draw")),
+        None => {}
+    }
+    status
+}
+
+fn draw_versus(renderer: &mut Renderer, versus: &Versus, visibility: StackVisibility) -> Result<(), String> {
+    renderer.clear();
+    for (i, player) in versus.players.iter().enumerate() {
+        let visibility = if player.game.is_over() { StackVisibility::Visible } else { visibility };
+        renderer.draw_board(i as i32, &player.game.tetris, visibility, player.game.frames(), player.incoming(), &versus_status(versus, i))?;
+    }
+    renderer.present();
     Ok(())
 }
 
+// Runs a local match until the quit key goes back to the title screen; the
+// result stays on screen until then.
+fn play_versus(versus: &mut Versus, renderer: &mut Renderer, event_pump: &mut EventPump, visibility: StackVisibility,
+               bots: &mut [Option<Box<dyn Controller>>; 2], controls: &mut Controls) -> Result<Screen, String> {
+    let next = 'running: loop {
+        let frame_start = Instant::now();
+        for event in event_pump.poll_iter() {
+            controls.update(&event);
+            match event {
+                Event::Quit {..} => break 'running Screen::Quit,
+                _ if controls.is_quit(&event) => break 'running Screen::title(),
+                _ => {}
+            }
+        }
 
-fn main() -> Result<(), String> {
-    let mut rng = rand::thread_rng();
-    let sdl_context = sdl2::init()?;
-    let video_subsystem = sdl_context.video()?;
-    let ttf_context = sdl2::ttf::init().map_err(|e| e.to_string())?;
+        let keyboard = event_pump.keyboard_state();
+        let mut inputs: Vec<Input> = (0..2).map(|player| controls.read_player(player, &keyboard)).collect();
+        for (i, bot) in bots.iter_mut().enumerate() {
+            if let Some(bot) = bot {
+                inputs[i] = bot.input(&versus.players[i].game)?;
+            }
+        }
+        versus.step(&inputs);
+        draw_versus(renderer, versus, visibility)?;
 
-    let mut font = ttf_context.load_font("assets/font.ttf", 128)?;
-    font.set_style(sdl2::ttf::FontStyle::NORMAL);
-    let font_surface = font.render("Score: 0").blended(Color::RGB(255, 255, 255)).map_err(|e| e.to_string())?;
+        if let Some(remaining) = FRAME.checked_sub(frame_start.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    };
+    Ok(next)
+}
 
-    let game_window = video_subsystem.window("rust-sdl2 demo", 640, 768)
-        .position_centered()
-        .build().map_err(|e| e.to_string())?;
+// Like a local match, but the opponent's inputs come from the server. The local
+// player always uses the single player keys.
+fn play_online(mut client: Client, renderer: &mut Renderer, event_pump: &mut EventPump, visibility: StackVisibility,
+               broadcaster: &mut Option<Broadcaster>, bot: &mut Option<Box<dyn Controller>>, controls: &mut Controls) -> Result<Screen, String> {
+    let next = 'running: loop {
+        let frame_start = Instant::now();
+        for event in event_pump.poll_iter() {
+            controls.update(&event);
+            match event {
+                Event::Quit {..} => break 'running Screen::Quit,
+                _ if controls.is_quit(&event) => break 'running Screen::title(),
+                _ => {}
+            }
+        }
+
+        let input = local_input(bot, &client.versus.players[client.player()].game, controls, &event_pump.keyboard_state())?;
+        if let Err(e) = client.step(input) {
+            println!("Match ended: {}", e);
+            break 'running Screen::title()
+        }
+        if let Some(broadcaster) = broadcaster {
+            broadcaster.update(&client.versus.players[client.player()].game)?;
+        }
+        draw_versus(renderer, &client.versus, visibility)?;
 
-    let mut game_canvas = game_window.into_canvas().software().build().map_err(|e| e.to_string())?;
-    game_canvas.set_draw_color(BACKGROUND_COLOR);
-    game_canvas.fill_rect(None)?;
+        if let Some(remaining) = FRAME.checked_sub(frame_start.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    };
+    client.close();
+    Ok(next)
+}
 
-    let texture_creator = game_canvas.texture_creator();
-    let textures = TileTexture::new(&texture_creator)?;
-    let font_texture = texture_creator.create_texture_from_surface(font_surface).map_err(|e| e.to_string())?;
+fn royale_status(client: &RoomClient, controls: &Controls) -> Vec<String> {
+    let standing = match (client.winner(), client.place()) {
+        (_, Some(1)) => String::from("You win!"),
+        (_, Some(place)) => format!("Knocked out, #{}", place),
+        (Some(_), None) => String::from("Game over"),
+        (None, None) => format!("Alive: {}/{}", client.alive_count(), client.players())
+    };
+    vec![
+        standing,
+        format!("Target: {} ({}-{})", client.targeting().name(), controls.key_name(Action::Pick1), controls.key_name(Action::Pick4)),
+        String::from("Badges: ") + &client.badges(client.id()).to_string(),
+    ]
+}
+
+// Plays in a battle royale room; the first four pick actions choose the
+// targeting strategy.
+fn play_royale(mut client: RoomClient, renderer: &mut Renderer, event_pump: &mut EventPump, visibility: StackVisibility,
+               broadcaster: &mut Option<Broadcaster>, bot: &mut Option<Box<dyn Controller>>, controls: &mut Controls) -> Result<Screen, String> {
+    let next = 'running: loop {
+        let frame_start = Instant::now();
+        for event in event_pump.poll_iter() {
+            controls.update(&event);
+            match event {
+                Event::Quit {..} => break 'running Screen::Quit,
+                _ if controls.is_quit(&event) => break 'running Screen::title(),
+                _ => {
+                    if let Some(&targeting) = controls.pick(&event).and_then(|i| Targeting::ALL.get(i)) {
+                        client.set_targeting(targeting)?;
+                    }
+                }
+            }
+        }
 
-    game_canvas.copy(&font_texture, None, Rect::new(64, 640, 192, 64))?;
-    game_canvas.present();
+        let input = local_input(bot, &client.player.game, controls, &event_pump.keyboard_state())?;
+        if let Err(e) = client.step(input) {
+            println!("Room closed: {}", e);
+            break 'running Screen::title()
+        }
+        if let Some(broadcaster) = broadcaster {
+            broadcaster.update(&client.player.game)?;
+        }
 
-    let pieces = Pieces::new();
-    let mut tetris = Tetris::new(game_canvas, textures, TetrisPiece { x: 13, y: 0, piece: PieceEnum::I, buf: PieceEnum::buf(0, &PieceEnum::I), state: 0}, pieces, font);
+        let player = &client.player;
+        let visibility = if player.game.is_over() { StackVisibility::Visible } else { visibility };
+        renderer.clear();
+        renderer.draw_board(0, &player.game.tetris, visibility, player.game.frames(), player.incoming(), &royale_status(&client, controls))?;
+        let opponents: Vec<(Option<&TileCanvas>, bool)> = (0..client.players()).filter(|&i| i != client.id())
+            .map(|i| (client.board(i), client.alive(i)))
+            .collect();
+        renderer.draw_mini_boards(&player.game.tetris.tile_canvas, &opponents)?;
+        renderer.present();
 
-    for i in 2..TILE_CANVAS_WIDTH - 2 {
-        tetris.tile_canvas.set_tile(i, TILE_CANVAS_HEIGHT - 1, TileColor::Gray)?;
-        tetris.tile_canvas.set_tile_state(i, TILE_CANVAS_HEIGHT - 1, false);
+        if let Some(remaining) = FRAME.checked_sub(frame_start.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    };
+    client.close();
+    Ok(next)
+}
+
+fn spectate_status(spectator: &Spectator) -> Vec<String> {
+    let mut status = vec![String::from("Spectating"), String::from("Score: ") + &spectator.score().to_string()];
+    let last_clear = spectator.events().iter().rev().find_map(|event| match event {
+        tetrust::game::Event::Locked(_, clear) if clear.lines > 0 => Some(clear),
+        _ => None
+    });
+    if let Some(clear) = last_clear {
+        status.push(clear.name());
     }
-    for i in 0..TILE_CANVAS_HEIGHT { 
-        tetris.tile_canvas.set_tile(1, i, TileColor::Gray)?;
-        tetris.tile_canvas.set_tile(TILE_CANVAS_WIDTH - 1, i, TileColor::Gray)?;
+    if spectator.is_over() {
+        status.push(String::from("Game over"));
     }
-    
-    let mut ticks = 0;
-    
-    'running: loop {
-        for event in sdl_context.event_pump()?.poll_iter() {
+    status
+}
+
+// Shows a broadcast game until it ends or the player leaves.
+fn play_spectate(mut spectator: Spectator, renderer: &mut Renderer, event_pump: &mut EventPump, controls: &mut Controls) -> Result<Screen, String> {
+    let next = 'running: loop {
+        let frame_start = Instant::now();
+        for event in event_pump.poll_iter() {
+            controls.update(&event);
             match event {
-                Event::KeyDown{keycode: Some(Keycode::Up), ..} => { 
-                    if tetris.rotate_piece()? == true {
-                        tetris_collision(&mut tetris, rng.gen_range(2..TILE_CANVAS_WIDTH - 5))?;
+                Event::Quit {..} => break 'running Screen::Quit,
+                _ if controls.is_quit(&event) => break 'running Screen::title(),
+                _ => {}
+            }
+        }
+
+        if let Err(e) = spectator.update() {
+            println!("Stopped spectating: {}", e);
+            break 'running Screen::title()
+        }
+        renderer.draw(&spectator.tetris, StackVisibility::Visible, 0, &spectate_status(&spectator))?;
+
+        if let Some(remaining) = FRAME.checked_sub(frame_start.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    };
+    Ok(next)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum KeyItem {
+    Bind(Action),
+    Reset,
+    Back
+}
+
+fn action_label(action: Action) -> &'static str {
+    match action {
+        Action::Left => "Move left",
+        Action::Right => "Move right",
+        Action::SoftDrop => "Soft drop",
+        Action::HardDrop => "Hard drop",
+        Action::Hold => "Hold",
+        Action::RotateCw => "Rotate right",
+        Action::RotateCcw => "Rotate left",
+        Action::Quit => "Pause / back",
+        Action::Restart => "Restart / retry",
+        Action::Undo => "Undo (zen)",
+        Action::Next => "Next puzzle / opener",
+        Action::Export => "Export (zen)",
+        Action::Pick1 => "Pick O / target 1",
+        Action::Pick2 => "Pick S / target 2",
+        Action::Pick3 => "Pick Z / target 3",
+        Action::Pick4 => "Pick T / target 4",
+        Action::Pick5 => "Pick L",
+        Action::Pick6 => "Pick J",
+        Action::Pick7 => "Pick I"
+    }
+}
+
+fn key_items(config: &Config, section: KeySection) -> Vec<Item<KeyItem>> {
+    let mut items: Vec<Item<KeyItem>> = section.actions().iter().map(|&action| {
+        Item::with_value(KeyItem::Bind(action), action_label(action), config.bindings(section).get(&action).map(|keys| keys.join(", ")).unwrap_or_default())
+    }).collect();
+    items.push(Item::new(KeyItem::Reset, "Reset all keys to defaults"));
+    items.push(Item::new(KeyItem::Back, "Back"));
+    items
+}
+
+fn key_menu_title(section: KeySection) -> &'static str {
+    match section {
+        KeySection::Solo => "Keys < Solo >",
+        KeySection::Player1 => "Keys < Versus player 1 >",
+        KeySection::Player2 => "Keys < Versus player 2 >"
+    }
+}
+
+// Saves the config if it came from a file.
+fn save_config(config: &Config, path: Option<&Path>) -> String {
+    match path.map(|path| config.save(path)) {
+        Some(Ok(())) => String::from("Saved"),
+        Some(Err(e)) => format!("Not saved: {}", e),
+        None => String::from("Not saved: no config file")
+    }
+}
+
+// The key binding screen. The menu itself always works with the arrow keys,
+// Enter and Escape, so a bad binding can't lock anyone out of it; Escape
+// cancels waiting for a key, which is why it can't be bound here. Every change
+// is saved straight away.
+fn play_options(config: &mut Config, path: Option<&Path>, renderer: &mut Renderer, event_pump: &mut EventPump) -> Result<Screen, String> {
+    let mut section = 0;
+    let mut menu = Menu::new(key_menu_title(KeySection::ALL[section]), key_items(config, KeySection::ALL[section]));
+    // The action waiting for a key, and whether the key is added to its others.
+    let mut waiting: Option<(Action, bool)> = None;
+    let mut message = String::new();
+    loop {
+        let frame_start = Instant::now();
+        for event in event_pump.poll_iter() {
+            let scancode = match event {
+                Event::Quit {..} => return Ok(Screen::Quit),
+                Event::KeyDown { scancode: Some(scancode), repeat: false, .. } => scancode,
+                _ => continue
+            };
+            let current = KeySection::ALL[section];
+            if let Some((action, add)) = waiting.take() {
+                if scancode == Scancode::Escape {
+                    message.clear();
+                    continue
+                }
+                message = match config.bind(current, action, scancode.name(), add) {
+                    Ok(clashes) => {
+                        let moved: Vec<String> = clashes.iter().map(|&(s, a)| if s == current { action_label(a).to_string() } else {
+                            format!("{} ({})", action_label(a), s.name())
+                        }).collect();
+                        let saved = save_config(config, path);
+                        if moved.is_empty() { saved } else { format!("{}, taken from {}", saved, moved.join(", ")) }
                     }
-                },
-                Event::KeyDown{keycode: Some(Keycode::Down), ..} => {
-                    if tetris.update_screen()? == true {
-                        tetris_collision(&mut tetris, rng.gen_range(2..TILE_CANVAS_WIDTH - 5))?;
-                    };
-                },
-                Event::KeyDown{keycode: Some(Keycode::Right), ..} => {
-                    if tetris.move_piece(1, 0)? == true {
-                        tetris_collision(&mut tetris, rng.gen_range(2..TILE_CANVAS_WIDTH - 5))?;
-                    };
-
-                },
-                Event::KeyDown{keycode: Some(Keycode::Left), ..} => {
-                    if tetris.move_piece(-1, 0)? == true {
-                        tetris_collision(&mut tetris, rng.gen_range(2..TILE_CANVAS_WIDTH - 5))?;
-                    };
-
-                },
-                Event::Quit {..} |
-                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                    break 'running
-                },
+                    Err(e) => e
+                };
+                continue
+            }
+            match (scancode, menu.selected()) {
+                (Scancode::Up, _) => menu.up(),
+                (Scancode::Down, _) => menu.down(),
+                (Scancode::Left, _) | (Scancode::Right, _) => {
+                    let step = if scancode == Scancode::Left { KeySection::ALL.len() - 1 } else { 1 };
+                    section = (section + step) % KeySection::ALL.len();
+                    menu = Menu::new(key_menu_title(KeySection::ALL[section]), key_items(config, KeySection::ALL[section]));
+                    message.clear();
+                }
+                (Scancode::Return, Some(KeyItem::Bind(action))) => waiting = Some((action, false)),
+                (Scancode::Tab, Some(KeyItem::Bind(action))) => waiting = Some((action, true)),
+                (Scancode::Return, Some(KeyItem::Reset)) => {
+                    config.reset_keys();
+                    message = save_config(config, path);
+                }
+                (Scancode::Return, Some(KeyItem::Back)) | (Scancode::Escape, _) => return Ok(Screen::title()),
+                _ => {}
+            }
+        }
+
+        menu.set_items(key_items(config, KeySection::ALL[section]));
+        let mut footer = vec![String::from(match waiting {
+            Some(_) => "Press a key, or Escape to cancel",
+            None => "Enter: set key  Tab: add key  Left/Right: players"
+        })];
+        if !message.is_empty() {
+            footer.push(message.clone());
+        }
+        // The title and a blank line above the items and one below.
+        let rows = renderer.text_rows()?.saturating_sub(footer.len() + 3);
+        renderer.draw_menu(&menu.title, &menu.visible_lines(rows), &footer)?;
+
+        if let Some(remaining) = FRAME.checked_sub(frame_start.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    }
+}
+
+fn close_broadcast(broadcaster: Option<Broadcaster>) {
+    if let Some(broadcaster) = broadcaster {
+        broadcaster.close();
+    }
+}
+
+// The pieces zen's pick actions choose, in `Action::PICKS` order.
+const PICK_PIECES: [PieceEnum; 7] = [PieceEnum::O, PieceEnum::S, PieceEnum::Z, PieceEnum::T, PieceEnum::L, PieceEnum::J, PieceEnum::I];
+
+#[derive(Clone, Copy, PartialEq)]
+enum TitleItem {
+    // Starts the mode with these arguments.
+    Play(&'static str),
+    Options,
+    Records,
+    Quit
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum GameItem {
+    Resume,
+    Restart,
+    SaveReport,
+    MainMenu,
+    Quit
+}
+
+// What the window shows. The solo game screens carry their game along, so
+// leaving the pause menu picks it up where it stopped.
+enum Screen {
+    Title(Menu<TitleItem>),
+    Options,
+    // Showing the table of the mode at this index.
+    Records(usize),
+    Playing(Box<Session>),
+    Paused(Box<Session>, Menu<GameItem>),
+    GameOver(Box<Session>, Menu<GameItem>),
+    Quit
+}
+
+impl Screen {
+    fn title() -> Screen {
+        Screen::Title(Menu::new("tetrust", vec![
+            Item::new(TitleItem::Play("marathon"), "Marathon"),
+            Item::new(TitleItem::Play("dig"), "Dig"),
+            Item::new(TitleItem::Play("master"), "Master"),
+            Item::new(TitleItem::Play("zen"), "Zen"),
+            Item::new(TitleItem::Play("puzzle"), "Puzzle"),
+            Item::new(TitleItem::Play("opener"), "Opener"),
+            Item::new(TitleItem::Play("versus"), "Versus"),
+            Item::new(TitleItem::Options, "Options"),
+            Item::new(TitleItem::Records, "Records"),
+            Item::new(TitleItem::Quit, "Quit"),
+        ]))
+    }
+    fn paused(session: Box<Session>) -> Screen {
+        Screen::Paused(session, Menu::new("Paused", vec![
+            Item::new(GameItem::Resume, "Resume"),
+            Item::new(GameItem::Restart, "Restart"),
+            Item::new(GameItem::MainMenu, "Main menu"),
+            Item::new(GameItem::Quit, "Quit"),
+        ]))
+    }
+    fn game_over(session: Box<Session>) -> Screen {
+        let title = match &session.mode {
+            Mode::Dig(dig) if dig.is_cleared() => "Cleared",
+            Mode::Master(master) if master.is_finished() => "Finished",
+            _ => "Game over"
+        };
+        Screen::GameOver(session, Menu::new(title, vec![
+            Item::new(GameItem::Restart, "Retry"),
+            Item::new(GameItem::SaveReport, "Save report"),
+            Item::new(GameItem::MainMenu, "Main menu"),
+            Item::new(GameItem::Quit, "Quit"),
+        ]))
+    }
+}
+
+// A solo game and what it takes to start it over.
+struct Session {
+    // The mode arguments it was started with.
+    args: Vec<String>,
+    mode: Mode,
+    game: Game,
+    seed: u64,
+    replay: Option<Replay>,
+    // Frames of the countdown left before the first one.
+    countdown: u32,
+    counted_down: bool,
+    // Frames the whole stack has been shown since the game ended.
+    reveal_frames: u32,
+    stats: Stats,
+    report: Report,
+    // Where the report was saved, once it was.
+    report_path: Option<PathBuf>,
+    // Its place in the records once it is over, if it made them.
+    rank: Option<usize>
+}
+
+// The modes played against the clock, which count down and keep records.
+fn is_timed(mode: &Mode) -> bool {
+    matches!(mode, Mode::Marathon | Mode::Dig(_) | Mode::Master(_))
+}
+
+// Puzzles and openers restart the one being played rather than the session.
+fn retries_in_place(mode: &Mode) -> bool {
+    matches!(mode, Mode::Puzzle(_) | Mode::Opener(_))
+}
+
+// Whether the mode's goal was reached, which ends the game like topping out.
+fn is_finished(mode: &Mode) -> bool {
+    match mode {
+        Mode::Dig(dig) => dig.is_cleared(),
+        Mode::Master(master) => master.is_finished(),
+        _ => false
+    }
+}
+
+// The modes the records screen always has a table for.
+const RECORD_MODES: [&str; 3] = ["marathon", "dig", "master"];
+
+// The stats shown when a game ends.
+fn results(session: &Session) -> Vec<String> {
+    let game = &session.game;
+    let mut lines = match &session.mode {
+        Mode::Master(master) => vec![String::from("Grade: ") + master.grade(), String::from("Level: ") + &master.level().to_string()],
+        Mode::Puzzle(puzzles) => vec![format!("Puzzle: {}/{}", puzzles.index() + 1, puzzles.len())],
+        Mode::Opener(opener) => vec![format!("Opener: {}", opener.name()), format!("Perfect clears: {}/{}", opener.perfects(), opener.rounds())],
+        _ => vec![String::from("Score: ") + &game.tetris.score().to_string()]
+    };
+    if let Mode::Dig(dig) = &session.mode {
+        lines.push(String::from("Garbage left: ") + &dig.remaining().to_string());
+    }
+    let stats = &session.stats;
+    lines.push(String::from("Lines: ") + &stats.lines.to_string());
+    lines.push(String::from("Pieces: ") + &stats.pieces.to_string());
+    lines.push(String::from("Time: ") + &master::format_time(game.frames()));
+    lines.push(format!("PPS: {:.2}  APM: {:.1}  KPP: {:.2}", stats.pps(), stats.apm(), stats.kpp()));
+    match session.rank {
+        Some(1) => lines.push(String::from("New personal best!")),
+        Some(rank) => lines.push(format!("#{} in the records", rank)),
+        None => {}
+    }
+    if let Some(name) = session.report_path.as_ref().and_then(|path| path.file_name()) {
+        lines.push(format!("Saved report {}", name.to_string_lossy()));
+    }
+    lines
+}
+
+// The stats drawn next to a solo game as it is played.
+fn stats_sidebar(stats: &Stats) -> Vec<String> {
+    let tspin_minis: u32 = stats.tspin_minis.iter().sum();
+    vec![
+        String::from("Pieces: ") + &stats.pieces.to_string(),
+        format!("PPS: {:.2}", stats.pps()),
+        format!("APM: {:.1}", stats.apm()),
+        format!("KPP: {:.2}", stats.kpp()),
+        String::from("Singles: ") + &stats.clears[1].to_string(),
+        String::from("Doubles: ") + &stats.clears[2].to_string(),
+        String::from("Triples: ") + &stats.clears[3].to_string(),
+        String::from("Tetrises: ") + &stats.clears[4].to_string(),
+        String::from("TSS: ") + &stats.tspins[1].to_string(),
+        String::from("TSD: ") + &stats.tspins[2].to_string(),
+        String::from("TST: ") + &stats.tspins[3].to_string(),
+        String::from("Minis: ") + &tspin_minis.to_string(),
+        String::from("Max combo: ") + &stats.max_combo.to_string(),
+        String::from("Perfect: ") + &stats.perfect_clears.to_string(),
+    ]
+}
+
+fn board_for(mode: &Mode, config: &Config) -> TileCanvas {
+    match mode {
+        Mode::Master(_) => TileCanvas::new(master::MASTER_WIDTH, master::MASTER_HEIGHT),
+        Mode::Puzzle(puzzles) => puzzles.current().board.clone(),
+        Mode::Zen(_, Some(board)) => board.clone(),
+        Mode::Opener(_) => TileCanvas::new(opener::OPENER_WIDTH, opener::OPENER_HEIGHT),
+        _ => TileCanvas::new(config.board.width, config.board.height)
+    }
+}
+
+// What every screen works with.
+struct App<'t> {
+    renderer: Renderer<'t>,
+    event_pump: EventPump,
+    options: Options
+}
+
+impl<'t> App<'t> {
+    // This frame's events, with gamepads already opened or closed.
+    fn poll(&mut self) -> Vec<Event> {
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        for event in &events {
+            self.options.controls.update(event);
+        }
+        events
+    }
+
+    fn resize(&mut self, tile_canvas: &TileCanvas, boards: u32, extra_width: u32) -> Result<(), String> {
+        let (width, height) = window_size(tile_canvas, boards, self.options.config.display.tile_size);
+        self.renderer.set_window_size((width + extra_width, height))
+    }
+    fn resize_for_menus(&mut self) -> Result<(), String> {
+        let board = &self.options.config.board;
+        self.resize(&TileCanvas::new(board.width, board.height), 1, 0)
+    }
+
+    // Solo modes get a session; the others run until they are left.
+    fn start(&mut self, mode: Mode, args: Vec<String>) -> Result<Screen, String> {
+        let Options { visibility, broadcaster, bots, controls, config, config_path, .. } = &mut self.options;
+        let visibility = *visibility;
+        let next = match mode {
+            Mode::Versus(mut versus) => {
+                let tile_canvas = versus.players[0].game.tetris.tile_canvas.clone();
+                self.renderer.set_window_size(window_size(&tile_canvas, 2, config.display.tile_size))?;
+                play_versus(&mut versus, &mut self.renderer, &mut self.event_pump, visibility, bots, controls)?
+            }
+            Mode::Online(client) => {
+                let tile_canvas = client.versus.players[0].game.tetris.tile_canvas.clone();
+                self.renderer.set_window_size(window_size(&tile_canvas, 2, config.display.tile_size))?;
+                play_online(*client, &mut self.renderer, &mut self.event_pump, visibility, broadcaster, solo_bot(bots), controls)?
+            }
+            Mode::Royale(client) => {
+                let tile_canvas = client.player.game.tetris.tile_canvas.clone();
+                let (width, height) = window_size(&tile_canvas, 1, config.display.tile_size);
+                self.renderer.set_window_size((width + mini_boards_width(&tile_canvas, client.players() - 1), height))?;
+                play_royale(*client, &mut self.renderer, &mut self.event_pump, visibility, broadcaster, solo_bot(bots), controls)?
+            }
+            Mode::Spectate(spectator) => {
+                self.renderer.set_window_size(window_size(&spectator.tetris.tile_canvas, 1, config.display.tile_size))?;
+                play_spectate(*spectator, &mut self.renderer, &mut self.event_pump, controls)?
+            }
+            Mode::Options => {
+                let next = play_options(config, config_path.as_deref(), &mut self.renderer, &mut self.event_pump)?;
+                controls.reload(config)?;
+                next
+            }
+            mode => return Ok(Screen::Playing(Box::new(self.new_session(mode, args)?)))
+        };
+        self.resize_for_menus()?;
+        Ok(next)
+    }
+
+    fn new_session(&mut self, mut mode: Mode, args: Vec<String>) -> Result<Session, String> {
+        let tile_canvas = board_for(&mode, &self.options.config);
+        let display = &self.options.config.display;
+        if display.stats {
+            let (width, height) = window_size(&tile_canvas, 1, display.tile_size);
+            let (stats_width, stats_height) = stats_size(display.tile_size, stats_sidebar(&Stats::new()).len());
+            self.renderer.set_window_size((width + stats_width, height.max(stats_height)))?;
+        } else {
+            self.resize(&tile_canvas, 1, 0)?;
+        }
+        let seed = rand::random();
+        let mut game = Game::new(Tetris::new(tile_canvas, seed), MARATHON_GRAVITY, Timings::default());
+        self.options.config.handling.apply(&mut game);
+        let replay = match &mode {
+            Mode::Marathon => Some(Replay::new(&game, seed)),
+            Mode::Dig(dig) => Some(Replay::with_mode(&game, seed, dig.replay_mode())),
+            Mode::Master(_) => Some(Replay::with_mode(&game, seed, ReplayMode::Master)),
+            _ => None
+        };
+        match &mut mode {
+            Mode::Dig(dig) => dig.setup(&mut game.tetris),
+            Mode::Master(master) => master.setup(&mut game),
+            Mode::Zen(zen, _) => zen.setup(&mut game),
+            Mode::Puzzle(puzzles) => puzzles.setup(&mut game),
+            Mode::Opener(opener) => opener.setup(&mut game),
+            Mode::Marathon | Mode::Versus(_) | Mode::Online(_) | Mode::Royale(_) | Mode::Spectate(_) | Mode::Options => {}
+        }
+        let training = &mut self.options.training;
+        if training.finesse.is_some() {
+            training.finesse = Some(Finesse::new());
+        }
+        if let Some(planner) = &training.planner {
+            training.planner = Some(Planner::new(planner.lines));
+        }
+        let countdown = if is_timed(&mode) { self.options.config.handling.countdown * 60 } else { 0 };
+        let report = Report::new(&args.join(" "), seed, records::now());
+        Ok(Session { args, mode, game, seed, replay, countdown, counted_down: countdown > 0, reveal_frames: 0, stats: Stats::new(), report,
+                     report_path: None, rank: None })
+    }
+
+    // A fresh game of the same mode. Puzzles and openers start the current one
+    // over on the same pieces instead.
+    fn restart(&mut self, mut session: Session) -> Result<Screen, String> {
+        let Session { mode, game, .. } = &mut session;
+        match mode {
+            Mode::Puzzle(puzzles) => puzzles.retry(game),
+            Mode::Opener(opener) => opener.retry(game),
+            _ => {
+                let mode = parse_mode(&session.args, &self.options.config)?;
+                return self.start(mode, session.args);
+            }
+        }
+        session.reveal_frames = 0;
+        Ok(Screen::Playing(Box::new(session)))
+    }
+
+    // Wraps up a game that is over or being left. Only games that ended go in
+    // the records, not ones restarted or quit part way.
+    fn finish(&mut self, session: &mut Session) -> Result<(), String> {
+        session.report.finish(&session.game);
+        match &session.mode {
+            Mode::Dig(dig) if dig.is_cleared() => println!("Cleared all garbage in {}", master::format_time(session.game.frames())),
+            Mode::Master(master) if master.is_finished() => {
+                println!("Grade {} in {}", master.grade(), master::format_time(session.game.frames()));
+                for (section, frames) in master.section_times().iter().enumerate() {
+                    println!("{:3}-{:3}: {}", section * 100, section * 100 + 99, master::format_time(*frames));
+                }
+            }
+            _ => {}
+        }
+        if let Some(finesse) = &self.options.training.finesse {
+            print_finesse(finesse);
+        }
+        if let (Some(replay), Some(path)) = (&session.replay, &self.options.record) {
+            replay.save(path)?;
+            println!("Saved the replay to {}", path.display());
+        }
+        let ended = session.game.is_over() || is_finished(&session.mode);
+        if is_timed(&session.mode) && ended && session.stats.pieces > 0 {
+            self.record(session);
+        }
+        Ok(())
+    }
+
+    // Adds the game to the records, saving its replay if it made them. Neither
+    // failing to save is worth ending the game over.
+    fn record(&mut self, session: &mut Session) {
+        let game = &session.game;
+        let (score, cleared, grade) = match &session.mode {
+            Mode::Dig(dig) => (game.tetris.score(), dig.is_cleared(), None),
+            Mode::Master(master) => (master.score(), master.is_finished(), Some(master.grade().to_string())),
+            _ => (game.tetris.score(), false, None)
+        };
+        let mut record = Record { mode: session.args.join(" "), score, lines: session.stats.lines, pieces: session.stats.pieces,
+                                  frames: game.frames(), pps: session.stats.pps(), cleared, grade, date: records::now(), seed: session.seed, replay: None };
+        let Options { records, records_path, .. } = &mut self.options;
+        if let (Some(replay), Some(path), Some(_)) = (&session.replay, &records_path, records.rank(&record)) {
+            let name = format!("{}-{}-{}.json", record.mode.replace(' ', "_"), record.date, record.seed);
+            let path = path.with_file_name("replays").join(name);
+            let saved = std::fs::create_dir_all(path.parent().unwrap()).map_err(|e| e.to_string()).and_then(|()| replay.save(&path));
+            match saved {
+                Ok(()) => record.replay = Some(path),
+                Err(e) => println!("Couldn't save the replay: {}", e)
+            }
+        }
+        session.rank = records.add(record);
+        if let Some(path) = &records_path {
+            if let Err(e) = records.save(path) {
+                println!("Couldn't save the records: {}", e);
+            }
+        }
+    }
+
+    // Saves the report of the game as JSON and HTML, next to the records if
+    // they are kept and in the working directory if not.
+    fn save_report(&mut self, session: &mut Session) {
+        let report = &session.report;
+        let name = format!("{}-{}-{}.json", report.mode.replace(' ', "_"), report.date, report.seed);
+        let path = match &self.options.records_path {
+            Some(path) => path.with_file_name("reports").join(name),
+            None => PathBuf::from(name)
+        };
+        match report.save(&path) {
+            Ok(()) => {
+                println!("Saved the report to {}", path.display());
+                session.report_path = Some(path.with_extension("html"));
+            }
+            Err(e) => println!("Couldn't save the report: {}", e)
+        }
+    }
+
+    fn title(&mut self, mut menu: Menu<TitleItem>) -> Result<Screen, String> {
+        for event in self.poll() {
+            if let Event::Quit {..} = event {
+                return Ok(Screen::Quit);
+            }
+            let chosen = match self.options.controls.nav(&event) {
+                Some(nav) => menu.navigate(nav),
+                None => None
+            };
+            match chosen {
+                Some(TitleItem::Play(name)) => {
+                    let args = vec![name.to_string()];
+                    let mode = parse_mode(&args, &self.options.config)?;
+                    return self.start(mode, args);
+                }
+                Some(TitleItem::Options) => return Ok(Screen::Options),
+                Some(TitleItem::Records) => return Ok(Screen::Records(0)),
+                Some(TitleItem::Quit) => return Ok(Screen::Quit),
+                None => {}
+            }
+        }
+        self.renderer.draw_menu(&menu.title, &menu.lines(), &[String::from("Arrows: move  Enter: choose")])?;
+        Ok(Screen::Title(menu))
+    }
+
+    // The table of one mode at a time; left and right go through the modes.
+    fn records(&mut self, mut index: usize) -> Result<Screen, String> {
+        let mut modes: Vec<String> = RECORD_MODES.iter().map(|mode| mode.to_string()).collect();
+        for mode in self.options.records.modes() {
+            if !modes.iter().any(|m| m == mode) {
+                modes.push(mode.to_string());
+            }
+        }
+        for event in self.poll() {
+            match (&event, self.options.controls.nav(&event)) {
+                (Event::Quit {..}, _) => return Ok(Screen::Quit),
+                (_, Some(Nav::Left)) => index = (index + modes.len() - 1) % modes.len(),
+                (_, Some(Nav::Right)) => index = (index + 1) % modes.len(),
+                (_, Some(Nav::Select)) | (_, Some(Nav::Back)) => return Ok(Screen::title()),
                 _ => {}
             }
         }
-        ticks += 1;
-        if ticks % 16 == 0 {
-            if tetris.check_loss() == true { 
-                break 'running 
+        let mode = &modes[index % modes.len()];
+        let mut lines: Vec<String> = self.options.records.table(mode).iter().enumerate().map(|(i, record)| {
+            let replay = if record.replay.is_some() { "" } else { "  no replay" };
+            format!("{:2}. {}  {}  {:.2} PPS  {}{}", i + 1, record.headline(), master::format_time(record.frames), record.pps,
+                    records::format_date(record.date), replay)
+        }).collect();
+        if lines.is_empty() {
+            lines.push(String::from("No games yet"));
+        }
+        self.renderer.draw_menu(&format!("Records < {} >", mode), &lines, &[String::from("Left/Right: mode  Escape: back")])?;
+        Ok(Screen::Records(index))
+    }
+
+    fn play(&mut self, mut session: Box<Session>) -> Result<Screen, String> {
+        let Session { mode, game, replay, countdown, counted_down, reveal_frames, stats, report, .. } = &mut *session;
+        for event in self.poll() {
+            match event {
+                Event::Quit {..} => {
+                    self.finish(&mut session)?;
+                    return Ok(Screen::Quit);
+                }
+                _ if self.options.controls.is_quit(&event) => return Ok(Screen::paused(session)),
+                _ if self.options.controls.is_restart(&event) => {
+                    if !retries_in_place(mode) {
+                        self.finish(&mut session)?;
+                    }
+                    return self.restart(*session);
+                }
+                _ => {
+                    let controls = &self.options.controls;
+                    match &mut *mode {
+                        Mode::Zen(zen, _) if controls.is_pressed(&event, Action::Undo) => {
+                            zen.undo(game);
+                        }
+                        Mode::Zen(zen, _) if controls.is_pressed(&event, Action::Export) => export_zen(zen, game),
+                        Mode::Zen(zen, _) => {
+                            if let Some(i) = controls.pick(&event) {
+                                zen.pick_next(game, PICK_PIECES[i]);
+                            }
+                        }
+                        Mode::Puzzle(puzzles) if controls.is_pressed(&event, Action::Next) => puzzles.next(game),
+                        Mode::Opener(opener) if controls.is_pressed(&event, Action::Next) => opener.next(game),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        let Options { visibility, broadcaster, bots, training, controls, .. } = &mut self.options;
+        let visibility = *visibility;
+        let hint = match mode {
+            Mode::Opener(opener) => opener.hint(game).cloned(),
+            _ => training.planner.as_mut().and_then(|p| p.update(&game.tetris, &game.tetris.queue.peek().copied().collect::<Vec<_>>()).cloned())
+        };
+        self.renderer.set_hint(hint.as_ref().map(|m| m.placement.piece));
+        let stuck = match mode {
+            Mode::Puzzle(puzzles) => puzzles.solved().is_some(),
+            Mode::Opener(opener) => opener.failed(),
+            _ => false
+        };
+        if stuck {
+            self.renderer.draw_game(&game.tetris, visibility, game.frames(), &status(game, mode, training, controls))?;
+            self.renderer.draw_stats(&game.tetris.tile_canvas, &stats_sidebar(stats), &stats.pieces_by_type)?;
+            self.renderer.present();
+            return Ok(Screen::Playing(session));
+        }
+        if *countdown > 0 {
+            let input = local_input(solo_bot(bots), game, controls, &self.event_pump.keyboard_state())?;
+            game.charge(input);
+            if let Some(replay) = replay {
+                replay.record_countdown(input);
+            }
+            self.renderer.draw_game(&game.tetris, visibility, game.frames(), &status(game, mode, training, controls))?;
+            self.renderer.draw_stats(&game.tetris.tile_canvas, &stats_sidebar(stats), &stats.pieces_by_type)?;
+            self.renderer.draw_banner(&game.tetris.tile_canvas, &countdown.div_ceil(60).to_string())?;
+            self.renderer.present();
+            *countdown -= 1;
+            return Ok(Screen::Playing(session));
+        }
+        if game.is_over() || is_finished(mode) {
+            // Show the whole stack for a moment before the results.
+            self.renderer.draw_game(&game.tetris, StackVisibility::Visible, game.frames(), &status(game, mode, training, controls))?;
+            self.renderer.draw_stats(&game.tetris.tile_canvas, &stats_sidebar(stats), &stats.pieces_by_type)?;
+            self.renderer.present();
+            *reveal_frames += 1;
+            if visibility.revealed(*reveal_frames) {
+                self.finish(&mut session)?;
+                return Ok(Screen::game_over(session));
+            }
+            return Ok(Screen::Playing(session));
+        }
+
+        let input = local_input(solo_bot(bots), game, controls, &self.event_pump.keyboard_state())?;
+        let step = game.step(input);
+        if let Some(replay) = replay {
+            replay.record(input);
+        }
+        if let Some(finesse) = &mut training.finesse {
+            finesse.update(game, input);
+        }
+        stats.update(game);
+        match mode {
+            Mode::Dig(dig) if step.locked.is_some() => dig.update(&mut game.tetris),
+            Mode::Master(master) => master.update(game, &step),
+            Mode::Zen(zen, _) => zen.update(game, &step),
+            Mode::Puzzle(puzzles) => puzzles.update(game, &step),
+            Mode::Opener(opener) => opener.update(game, &step),
+            _ => {}
+        }
+        report.update(game, stats);
+
+        self.renderer.draw_game(&game.tetris, visibility, game.frames(), &status(game, mode, training, controls))?;
+        self.renderer.draw_stats(&game.tetris.tile_canvas, &stats_sidebar(stats), &stats.pieces_by_type)?;
+        if *counted_down && game.frames() <= GO_FRAMES {
+            self.renderer.draw_banner(&game.tetris.tile_canvas, "GO")?;
+        }
+        self.renderer.present();
+        if let Some(broadcaster) = broadcaster {
+            broadcaster.update(game)?;
+        }
+        Ok(Screen::Playing(session))
+    }
+
+    // The pause menu. Nothing steps while it is up, so the game's timers stand
+    // still; the board is darkened so it can't be studied in the meantime.
+    fn pause(&mut self, mut session: Box<Session>, mut menu: Menu<GameItem>) -> Result<Screen, String> {
+        for event in self.poll() {
+            let chosen = match (&event, self.options.controls.nav(&event)) {
+                (Event::Quit {..}, _) => Some(GameItem::Quit),
+                _ if self.options.controls.is_restart(&event) => Some(GameItem::Restart),
+                (_, Some(Nav::Back)) => Some(GameItem::Resume),
+                (_, Some(nav)) => menu.navigate(nav),
+                _ => None
+            };
+            match chosen {
+                Some(GameItem::Resume) => return Ok(Screen::Playing(session)),
+                Some(GameItem::Restart) if retries_in_place(&session.mode) => return self.restart(*session),
+                Some(item) => {
+                    self.finish(&mut session)?;
+                    return self.leave(*session, item);
+                }
+                None => {}
             }
+        }
+        let game = &session.game;
+        let status = status(game, &session.mode, &self.options.training, &self.options.controls);
+        self.renderer.draw_game(&game.tetris, self.options.visibility, game.frames(), &status)?;
+        self.renderer.draw_stats(&game.tetris.tile_canvas, &stats_sidebar(&session.stats), &session.stats.pieces_by_type)?;
+        self.renderer.draw_overlay(&menu.title, &menu.lines(), &[])?;
+        Ok(Screen::Paused(session, menu))
+    }
 
-            if tetris.update_screen()? == true {
-                tetris_collision(&mut tetris, rng.gen_range(2..TILE_CANVAS_WIDTH - 5))?;
+    fn game_over(&mut self, session: Box<Session>, mut menu: Menu<GameItem>) -> Result<Screen, String> {
+        for event in self.poll() {
+            let chosen = match (&event, self.options.controls.nav(&event)) {
+                (Event::Quit {..}, _) => Some(GameItem::Quit),
+                _ if self.options.controls.is_restart(&event) => Some(GameItem::Restart),
+                (_, Some(Nav::Back)) => Some(GameItem::MainMenu),
+                (_, Some(nav)) => menu.navigate(nav),
+                _ => None
             };
+            if let Some(item) = chosen {
+                return self.leave(*session, item);
+            }
         }
+        let game = &session.game;
+        self.renderer.draw_game(&game.tetris, StackVisibility::Visible, game.frames(), &[])?;
+        self.renderer.draw_stats(&game.tetris.tile_canvas, &stats_sidebar(&session.stats), &session.stats.pieces_by_type)?;
+        self.renderer.draw_overlay(&menu.title, &results(&session), &menu.lines())?;
+        Ok(Screen::GameOver(session, menu))
+    }
+
+    // Where to go from a finished session.
+    fn leave(&mut self, session: Session, item: GameItem) -> Result<Screen, String> {
+        match item {
+            GameItem::Resume => Ok(Screen::Playing(Box::new(session))),
+            GameItem::Restart => self.restart(session),
+            GameItem::SaveReport => {
+                let mut session = Box::new(session);
+                self.save_report(&mut session);
+                Ok(Screen::game_over(session))
+            }
+            GameItem::MainMenu => {
+                self.resize_for_menus()?;
+                Ok(Screen::title())
+            }
+            GameItem::Quit => Ok(Screen::Quit)
+        }
+    }
+}
 
-        std::thread::sleep(std::time::Duration::from_millis(20));
+// The solo modes use whichever bot is set, the second player's first.
+fn solo_bot(bots: &mut [Option<Box<dyn Controller>>; 2]) -> &mut Option<Box<dyn Controller>> {
+    let [first, second] = bots;
+    if second.is_some() { second } else { first }
+}
+
+fn main() -> Result<(), String> {
+    let (mode, args, mut options) = parse_args()?;
+    let config = &options.config;
+
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+    options.controls.open_gamepads(sdl_context.game_controller()?, &config.gamepad)?;
+    let ttf_context = sdl2::ttf::init().map_err(|e| e.to_string())?;
+
+    let font_path = config.theme.assets.join("font.ttf");
+    let mut font = ttf_context.load_font(&font_path, config.display.font_size).map_err(|e| format!("{}: {}", font_path.display(), e))?;
+    font.set_style(sdl2::ttf::FontStyle::NORMAL);
 
+    // Sized for the menus; each mode resizes it for its boards.
+    let (window_width, window_height) = window_size(&TileCanvas::new(config.board.width, config.board.height), 1, config.display.tile_size);
+    let game_window = video_subsystem.window("tetrust", window_width, window_height)
+        .position_centered()
+        .build().map_err(|e| e.to_string())?;
+
+    let game_canvas = game_window.into_canvas().software().build().map_err(|e| e.to_string())?;
+    let texture_creator = game_canvas.texture_creator();
+    let textures = TileTexture::new(&texture_creator, &config.theme.assets)?;
+    let renderer = Renderer::new(game_canvas, &texture_creator, textures, font, &config.display, &config.theme);
+    let event_pump = sdl_context.event_pump()?;
+    let mut app = App { renderer, event_pump, options };
+
+    let mut screen = match mode {
+        Some(mode) => app.start(mode, args)?,
+        None => Screen::title()
+    };
+    loop {
+        let frame_start = Instant::now();
+        screen = match screen {
+            Screen::Title(menu) => app.title(menu)?,
+            Screen::Options => app.start(Mode::Options, Vec::new())?,
+            Screen::Records(index) => app.records(index)?,
+            Screen::Playing(session) => app.play(session)?,
+            Screen::Paused(session, menu) => app.pause(session, menu)?,
+            Screen::GameOver(session, menu) => app.game_over(session, menu)?,
+            Screen::Quit => break
+        };
+        if let Some(remaining) = FRAME.checked_sub(frame_start.elapsed()) {
+            std::thread::sleep(remaining);
+        }
     }
+    close_broadcast(app.options.broadcaster);
     Ok(())
 }
+
+fn print_finesse(finesse: &Finesse) {
+    println!("Finesse: {} faults in {} pieces ({:.1}% clean), {} extra presses",
+             finesse.faults(), finesse.pieces(), finesse.accuracy(), finesse.extra_presses());
+    for (i, log) in finesse.log().iter().enumerate() {
+        let fault = if log.is_fault() { format!("  fault, best: {}", finesse::format_inputs(&log.optimal)) } else { String::new() };
+        println!("{:4} {:?}: {}{}", i + 1, log.piece, finesse::format_inputs(&log.inputs), fault);
+    }
+}
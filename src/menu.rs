@@ -0,0 +1,105 @@
+// A list of choices for the frontend's screens, moved through with up and
+// down. Each item has an id the screen acts on when it is chosen and an
+// optional value shown next to its label, like the keys bound to an action.
+
+// What a player can do on a menu, whatever they do it with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Nav {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Back
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Item<T> {
+    pub id: T,
+    pub label: String,
+    pub value: String
+}
+
+impl<T> Item<T> {
+    pub fn new(id: T, label: &str) -> Item<T> {
+        Item { id, label: label.to_string(), value: String::new() }
+    }
+    pub fn with_value(id: T, label: &str, value: String) -> Item<T> {
+        Item { id, label: label.to_string(), value }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Menu<T> {
+    pub title: String,
+    items: Vec<Item<T>>,
+    selected: usize
+}
+
+impl<T: Copy + PartialEq> Menu<T> {
+    pub fn new(title: &str, items: Vec<Item<T>>) -> Menu<T> {
+        Menu { title: title.to_string(), items, selected: 0 }
+    }
+
+    pub fn items(&self) -> &[Item<T>] {
+        &self.items
+    }
+    // Replaces the items, keeping the same one selected if it is still there.
+    pub fn set_items(&mut self, items: Vec<Item<T>>) {
+        let selected = self.selected();
+        self.items = items;
+        self.selected = selected.and_then(|id| self.items.iter().position(|item| item.id == id)).unwrap_or(0);
+    }
+
+    pub fn selected(&self) -> Option<T> {
+        self.items.get(self.selected).map(|item| item.id)
+    }
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+    pub fn select(&mut self, id: T) {
+        if let Some(i) = self.items.iter().position(|item| item.id == id) {
+            self.selected = i;
+        }
+    }
+
+    // Wraps around at either end.
+    pub fn up(&mut self) {
+        if !self.items.is_empty() {
+            self.selected = (self.selected + self.items.len() - 1) % self.items.len();
+        }
+    }
+    pub fn down(&mut self) {
+        if !self.items.is_empty() {
+            self.selected = (self.selected + 1) % self.items.len();
+        }
+    }
+
+    // Moves the selection, returning the item chosen if `nav` is Select.
+    pub fn navigate(&mut self, nav: Nav) -> Option<T> {
+        match nav {
+            Nav::Up => self.up(),
+            Nav::Down => self.down(),
+            Nav::Select => return self.selected(),
+            Nav::Left | Nav::Right | Nav::Back => {}
+        }
+        None
+    }
+
+    // One line per item, the selected one marked.
+    pub fn lines(&self) -> Vec<String> {
+        self.items.iter().enumerate().map(|(i, item)| {
+            let marker = if i == self.selected { "> " } else { "  " };
+            if item.value.is_empty() { format!("{}{}", marker, item.label) } else { format!("{}{}: {}", marker, item.label, item.value) }
+        }).collect()
+    }
+    // At most `rows` of the lines, scrolled to keep the selected one in view.
+    pub fn visible_lines(&self, rows: usize) -> Vec<String> {
+        let lines = self.lines();
+        if lines.len() <= rows {
+            return lines;
+        }
+        let first = self.selected.saturating_sub(rows / 2).min(lines.len() - rows);
+        lines[first..first + rows].to_vec()
+    }
+}
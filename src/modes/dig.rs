@@ -0,0 +1,51 @@
+use crate::garbage::GarbageGenerator;
+use crate::replay::ReplayMode;
+use crate::tetrust::{TileColor, Tetris};
+
+// Cheese race: `lines` garbage rows in total, of which at most `visible` sit on
+// the board at once. Cleared garbage is refilled from the bottom until the total
+// has been sent, and the race ends once every garbage row is cleared.
+pub struct Dig {
+    generator: GarbageGenerator,
+    lines: u16,
+    visible: u16,
+    messiness: f64,
+    seed: u64,
+    sent: u16,
+    remaining: u16
+}
+
+impl Dig {
+    pub fn new(width: u16, lines: u16, visible: u16, messiness: f64, seed: u64) -> Dig {
+        Dig { generator: GarbageGenerator::new(width, messiness, seed), lines, visible: visible.max(1), messiness, seed, sent: 0,
+              remaining: lines }
+    }
+    // What a replay needs to dig through the same garbage.
+    pub fn replay_mode(&self) -> ReplayMode {
+        ReplayMode::Dig { lines: self.lines, visible: self.visible, messiness: self.messiness, seed: self.seed }
+    }
+    pub fn setup(&mut self, tetris: &mut Tetris) {
+        self.refill(tetris);
+    }
+    // Called after each piece locks.
+    pub fn update(&mut self, tetris: &mut Tetris) {
+        self.refill(tetris);
+    }
+    pub fn remaining(&self) -> u16 {
+        self.remaining
+    }
+    pub fn is_cleared(&self) -> bool {
+        self.remaining == 0
+    }
+
+    fn refill(&mut self, tetris: &mut Tetris) {
+        let on_board = tetris.tile_canvas.count_rows(TileColor::Gray);
+        let wanted = std::cmp::min(self.visible, on_board + self.lines - self.sent);
+        if wanted > on_board {
+            let count = wanted - on_board;
+            tetris.add_garbage(self.generator.rows(count));
+            self.sent += count;
+        }
+        self.remaining = on_board.max(wanted) + self.lines - self.sent;
+    }
+}
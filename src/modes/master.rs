@@ -0,0 +1,140 @@
+use crate::game::*;
+
+pub const MASTER_WIDTH: u16 = 10;
+pub const MASTER_HEIGHT: u16 = 20;
+const MAX_LEVEL: u16 = 999;
+
+// (level, internal gravity) pairs; the gravity applies from that level on.
+const GRAVITY_TABLE: [(u16, u32); 30] = [
+    (0, 4), (30, 6), (35, 8), (40, 10), (50, 12), (60, 16), (70, 32), (80, 48), (90, 64), (100, 80),
+    (120, 96), (140, 112), (160, 128), (170, 144), (200, 4), (220, 32), (230, 64), (233, 96), (236, 128), (239, 160),
+    (243, 192), (247, 224), (251, 256), (300, 512), (330, 768), (360, 1024), (400, 1280), (420, 1024), (450, 768), (500, GRAVITY_20G),
+];
+
+// (level, ARE, line ARE, DAS, lock delay, line clear delay), all in frames.
+const TIMING_TABLE: [(u16, u32, u32, u32, u32, u32); 6] = [
+    (0, 25, 25, 14, 30, 40),
+    (500, 25, 25, 8, 30, 25),
+    (600, 25, 16, 8, 30, 16),
+    (700, 16, 12, 8, 30, 12),
+    (800, 12, 6, 8, 30, 6),
+    (900, 12, 6, 6, 17, 6),
+];
+
+const GRADES: [(u32, &str); 18] = [
+    (0, "9"), (400, "8"), (800, "7"), (1400, "6"), (2000, "5"), (3500, "4"), (5500, "3"), (8000, "2"), (12000, "1"),
+    (16000, "S1"), (22000, "S2"), (30000, "S3"), (40000, "S4"), (52000, "S5"), (66000, "S6"), (82000, "S7"), (100000, "S8"), (120000, "S9"),
+];
+
+// Grand Master checkpoints: (level, minimum grade index, time limit in frames).
+const GM_CHECKPOINTS: [(u16, usize, u64); 3] = [
+    (300, 8, (4 * 60 + 15) * 60),
+    (500, 12, (7 * 60 + 30) * 60),
+    (999, 17, (13 * 60 + 30) * 60),
+];
+
+pub fn gravity_for(level: u16) -> u32 {
+    GRAVITY_TABLE.iter().rev().find(|&&(l, _)| level >= l).map(|&(_, g)| g).unwrap_or(GRAVITY_TABLE[0].1)
+}
+
+pub fn timings_for(level: u16) -> Timings {
+    let &(_, are, line_are, das, lock_delay, line_clear) = TIMING_TABLE.iter().rev().find(|t| level >= t.0).unwrap_or(&TIMING_TABLE[0]);
+    Timings { are, line_are, das, arr: 1, lock_delay, line_clear }
+}
+
+pub fn format_time(frames: u64) -> String {
+    let centis = frames * 100 / 60;
+    format!("{:02}:{:02}.{:02}", centis / 6000, centis / 100 % 60, centis % 100)
+}
+
+pub struct Master {
+    level: u16,
+    score: u32,
+    combo: u32,
+    section_times: Vec<u64>,
+    gm_eligible: bool,
+    finished: bool
+}
+
+impl Default for Master {
+    fn default() -> Master {
+        Master::new()
+    }
+}
+
+impl Master {
+    pub fn new() -> Master {
+        Master { level: 0, score: 0, combo: 1, section_times: Vec::new(), gm_eligible: true, finished: false }
+    }
+    pub fn setup(&mut self, game: &mut Game) {
+        self.apply_level(game);
+    }
+    pub fn level(&self) -> u16 {
+        self.level
+    }
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+    // Frames spent in each completed 100 level section.
+    pub fn section_times(&self) -> &[u64] {
+        &self.section_times
+    }
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+    pub fn grade(&self) -> &'static str {
+        if self.finished && self.gm_eligible {
+            return "GM";
+        }
+        GRADES[self.grade_index()].1
+    }
+    // Level stops at the end of every section until a line is cleared.
+    pub fn level_stop(&self) -> u16 {
+        if self.level >= 900 { MAX_LEVEL } else { (self.level / 100 + 1) * 100 }
+    }
+
+    pub fn update(&mut self, game: &mut Game, step: &Step) {
+        if self.finished {
+            return;
+        }
+        if let Some(lock) = step.locked {
+            let lines = lock.clear.lines;
+            if lines > 0 {
+                self.combo += 2 * lines as u32 - 2;
+                let bravo = if lock.clear.perfect { 4 } else { 1 };
+                self.score += ((self.level as u32 + lines as u32).div_ceil(4) + lock.soft_drop) * lines as u32 * self.combo * bravo;
+                self.advance(game, lines);
+            } else {
+                self.combo = 1;
+            }
+        }
+        if step.spawned.is_some() && self.level + 1 < self.level_stop() {
+            self.advance(game, 1);
+        }
+    }
+
+    fn grade_index(&self) -> usize {
+        GRADES.iter().rposition(|&(s, _)| self.score >= s).unwrap_or(0)
+    }
+
+    fn advance(&mut self, game: &mut Game, levels: u16) {
+        let before = self.level;
+        self.level = std::cmp::min(self.level + levels, MAX_LEVEL);
+        if self.level / 100 > before / 100 || self.level == MAX_LEVEL {
+            let elapsed = game.frames() - self.section_times.iter().sum::<u64>();
+            self.section_times.push(elapsed);
+        }
+        for &(level, grade, limit) in GM_CHECKPOINTS.iter() {
+            if before < level && self.level >= level && (self.grade_index() < grade || game.frames() > limit) {
+                self.gm_eligible = false;
+            }
+        }
+        self.finished = self.level == MAX_LEVEL;
+        self.apply_level(game);
+    }
+
+    fn apply_level(&self, game: &mut Game) {
+        game.gravity = gravity_for(self.level);
+        game.timings = timings_for(self.level);
+    }
+}
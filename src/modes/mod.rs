@@ -0,0 +1,6 @@
+pub mod dig;
+pub mod master;
+pub mod opener;
+pub mod puzzle;
+pub mod versus;
+pub mod zen;
@@ -0,0 +1,231 @@
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use crate::game::*;
+use crate::movegen::{self, Placement};
+use crate::notation::parse_board;
+use crate::perfect::{self, Move, Planner, Search};
+use crate::tetrust::*;
+
+// Perfect clear opener practice. Every round starts on an empty board with two
+// shuffled bags of pieces. The first bag builds one of the setups below, shown
+// a piece at a time, and the solver then shows how to clear the bottom four
+// lines from the second. The round is lost on leaving the setup or once there
+// is no perfect clear left.
+
+pub const OPENER_BAGS: usize = 2;
+// Two bags fill four lines only on a board 10 wide.
+pub const OPENER_WIDTH: u16 = 10;
+pub const OPENER_HEIGHT: u16 = 20;
+
+// A named stack built from six pieces of the first bag, the seventh going to
+// hold.
+pub struct Setup {
+    pub name: &'static str,
+    pub board: &'static str
+}
+
+pub const SETUPS: [Setup; 2] = [
+    Setup { name: "PCO", board: "...S....ZI\n...SSOOZZI\n...JSOOZLI\n...JJJLLLI" },
+    Setup { name: "PCO-mirror", board: "IS....Z...\nISSOOZZ...\nIJSOOZL...\nIJJJLLL..." }
+];
+
+pub fn find_setup(name: &str) -> Result<usize, String> {
+    SETUPS.iter().position(|s| s.name.eq_ignore_ascii_case(name)).ok_or_else(|| {
+        let names: Vec<&str> = SETUPS.iter().map(|s| s.name).collect();
+        format!("unknown opener '{}', expected one of {}", name, names.join(", "))
+    })
+}
+
+// The active piece, hold and the number of pieces left.
+type BuildKey = (PieceEnum, Option<PieceEnum>, usize);
+
+pub struct Opener {
+    rng: StdRng,
+    width: u16,
+    height: u16,
+    setup: usize,
+    target: TileCanvas,
+    pieces: Vec<PieceEnum>,
+    // The next move towards the setup and what it was worked out for.
+    building: Option<(BuildKey, Option<Move>)>,
+    built: bool,
+    planner: Planner,
+    rounds: u32,
+    perfects: u32,
+    failed: bool
+}
+
+impl Opener {
+    pub fn new(width: u16, height: u16, seed: u64, setup: usize) -> Opener {
+        Opener { rng: StdRng::seed_from_u64(seed), width, height, setup, target: setup_board(setup, width, height), pieces: Vec::new(),
+                 building: None, built: false, planner: Planner::new(perfect::DEFAULT_PC_LINES), rounds: 0, perfects: 0, failed: false }
+    }
+    pub fn setup(&mut self, game: &mut Game) {
+        game.gravity = 0;
+        game.timings.lock_delay = u32::MAX;
+        self.deal(game);
+    }
+    // Plays the same pieces again.
+    pub fn retry(&mut self, game: &mut Game) {
+        self.failed = false;
+        self.rounds += 1;
+        self.building = None;
+        self.built = false;
+        game.set_tetris(self.start());
+    }
+    // Moves on to the next setup.
+    pub fn next(&mut self, game: &mut Game) {
+        self.setup = (self.setup + 1) % SETUPS.len();
+        self.target = setup_board(self.setup, self.width, self.height);
+        self.deal(game);
+    }
+    // Deals new bags, in an order the setup can be built from and cleared
+    // after.
+    fn deal(&mut self, game: &mut Game) {
+        loop {
+            self.pieces.clear();
+            for _ in 0..OPENER_BAGS {
+                let mut bag = vec![PieceEnum::O, PieceEnum::S, PieceEnum::Z, PieceEnum::T, PieceEnum::L, PieceEnum::J, PieceEnum::I];
+                bag.shuffle(&mut self.rng);
+                self.pieces.extend(bag);
+            }
+            let mut tetris = self.start();
+            if let Some(moves) = build(&tetris, &self.target) {
+                play(&mut tetris, &moves);
+                let queue = self.queue(&tetris);
+                if perfect::solve(&tetris, &queue, self.planner.lines).solution().is_some() {
+                    break;
+                }
+            }
+        }
+        self.retry(game);
+    }
+    fn start(&self) -> Tetris {
+        Tetris::with_queue(TileCanvas::new(self.width, self.height), PieceQueue::fixed(self.pieces.clone()))
+    }
+    pub fn update(&mut self, game: &mut Game, step: &Step) {
+        if self.failed {
+            return;
+        }
+        if step.locked.is_some_and(|lock| lock.clear.perfect) {
+            self.perfects += 1;
+            self.deal(game);
+            return;
+        }
+        self.built |= same_tiles(&game.tetris.tile_canvas, &self.target);
+        self.failed = game.is_over() || !self.built && !on_target(&game.tetris.tile_canvas, &self.target);
+        // Between pieces the last one is still the active piece.
+        if self.failed || !game.is_falling() {
+            return;
+        }
+        if !self.built {
+            self.failed = self.build_hint(&game.tetris).is_none();
+            return;
+        }
+        let queue = self.queue(&game.tetris);
+        self.planner.update(&game.tetris, &queue);
+        self.failed = self.planner.status() == Some(&Search::Impossible);
+    }
+
+    // The pieces still to come, beyond the preview too.
+    fn queue(&self, tetris: &Tetris) -> Vec<PieceEnum> {
+        self.pieces[self.pieces.len() - tetris.queue.len()..].to_vec()
+    }
+    fn build_hint(&mut self, tetris: &Tetris) -> Option<&Move> {
+        let key = (tetris.active_piece().piece, tetris.hold(), tetris.queue.len());
+        if self.building.as_ref().is_none_or(|(k, _)| *k != key) {
+            self.building = Some((key, build(tetris, &self.target).and_then(|moves| moves.into_iter().next())));
+        }
+        self.building.as_ref().and_then(|(_, next)| next.as_ref())
+    }
+    // Where the active piece goes: its place in the setup while building, then
+    // wherever the solver would put it.
+    pub fn hint(&mut self, game: &Game) -> Option<&Move> {
+        if !game.is_falling() {
+            return None;
+        }
+        if !self.built {
+            return self.build_hint(&game.tetris);
+        }
+        let queue = self.queue(&game.tetris);
+        self.planner.update(&game.tetris, &queue)
+    }
+    pub fn name(&self) -> &'static str {
+        SETUPS[self.setup].name
+    }
+    pub fn is_searching(&self) -> bool {
+        self.planner.is_searching()
+    }
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+    pub fn rounds(&self) -> u32 {
+        self.rounds
+    }
+    pub fn perfects(&self) -> u32 {
+        self.perfects
+    }
+}
+
+fn setup_board(setup: usize, width: u16, height: u16) -> TileCanvas {
+    parse_board(SETUPS[setup].board, width, height).expect("invalid opener setup")
+}
+
+fn same_tiles(canvas: &TileCanvas, target: &TileCanvas) -> bool {
+    (0..canvas.height()).all(|y| (0..canvas.width()).all(|x| canvas.get_tile(x, y) == target.get_tile(x, y)))
+}
+
+// Whether every tile on `canvas` is where the setup has it.
+fn on_target(canvas: &TileCanvas, target: &TileCanvas) -> bool {
+    (0..canvas.height()).all(|y| (0..canvas.width()).all(|x| {
+        let tile = canvas.get_tile(x, y);
+        tile == TileColor::Empty || tile == target.get_tile(x, y)
+    }))
+}
+
+// Whether `placement` covers the empty cells the setup has for its piece.
+fn fits(placement: &Placement, canvas: &TileCanvas, target: &TileCanvas) -> bool {
+    placement.piece.cells().iter().all(|&(x, y)| {
+        x >= 0 && y >= 0 && canvas.get_tile(x as u16, y as u16) == TileColor::Empty
+            && target.get_tile(x as u16, y as u16) == placement.piece.piece.color()
+    })
+}
+
+pub fn play(tetris: &mut Tetris, moves: &[Move]) {
+    for m in moves {
+        if m.hold {
+            tetris.hold_piece();
+        }
+        tetris.place_piece(m.placement.piece, m.placement.kick);
+        tetris.disable_piece();
+        tetris.spawn_next();
+    }
+}
+
+// The moves that finish building `target` from `tetris`, if the pieces come in
+// an order that can.
+pub fn build(tetris: &Tetris, target: &TileCanvas) -> Option<Vec<Move>> {
+    if same_tiles(&tetris.tile_canvas, target) {
+        return Some(Vec::new());
+    }
+    for hold in [false, true] {
+        let mut from = tetris.clone();
+        if hold && (from.hold() == Some(from.active_piece().piece) || !from.hold_piece() || from.check_loss()) {
+            continue;
+        }
+        let Some(placement) = movegen::placements(&from).into_iter().find(|p| fits(p, &from.tile_canvas, target)) else {
+            continue;
+        };
+        let mut next = from;
+        next.place_piece(placement.piece, placement.kick);
+        next.disable_piece();
+        if next.check_loss() || !next.spawn_next() {
+            continue;
+        }
+        if let Some(mut moves) = build(&next, target) {
+            moves.insert(0, Move { hold, placement });
+            return Some(moves);
+        }
+    }
+    None
+}
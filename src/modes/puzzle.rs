@@ -0,0 +1,259 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::game::*;
+use crate::movegen;
+use crate::notation::*;
+use crate::tetrust::*;
+
+pub const PUZZLE_DIR: &str = "assets/puzzles";
+const PUZZLE_WIDTH: u16 = 10;
+const PUZZLE_HEIGHT: u16 = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Goal {
+    // Clear at least this many lines in total.
+    Lines(u16),
+    // Make `count` T-spins that clear `lines` lines each.
+    TSpin { lines: u16, count: u16 },
+    // Clear four lines at once this many times.
+    Tetris(u16),
+    PerfectClear
+}
+
+impl Goal {
+    pub fn parse(text: &str) -> Result<Goal, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let number = |i: usize, default: u16| -> Result<u16, String> {
+            match words.get(i) {
+                Some(s) => s.parse::<u16>().map_err(|e| format!("invalid number '{}' in goal: {}", s, e)),
+                None => Ok(default)
+            }
+        };
+        match words.first().copied() {
+            Some("lines") => Ok(Goal::Lines(number(1, 1)?)),
+            Some("tss") => Ok(Goal::TSpin { lines: 1, count: number(1, 1)? }),
+            Some("tsd") => Ok(Goal::TSpin { lines: 2, count: number(1, 1)? }),
+            Some("tst") => Ok(Goal::TSpin { lines: 3, count: number(1, 1)? }),
+            Some("tetris") => Ok(Goal::Tetris(number(1, 1)?)),
+            Some("pc") | Some("perfect-clear") => Ok(Goal::PerfectClear),
+            _ => Err(format!("unknown goal '{}'", text))
+        }
+    }
+    pub fn describe(&self) -> String {
+        match *self {
+            Goal::Lines(lines) => format!("Clear {} lines", lines),
+            Goal::TSpin { lines, count } => {
+                let name = match lines { 1 => "single", 2 => "double", _ => "triple" };
+                format!("{} T-spin {}", count, name)
+            }
+            Goal::Tetris(count) => format!("{} Tetris", count),
+            Goal::PerfectClear => String::from("Perfect clear")
+        }
+    }
+}
+
+// What the player achieved so far in the current attempt.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Progress {
+    pub lines: u16,
+    pub tspins: [u16; 4],
+    pub tetrises: u16,
+    pub perfect: bool
+}
+
+impl Progress {
+    pub fn record(&mut self, clear: &Clear) {
+        self.lines += clear.lines;
+        if clear.tspin == TSpin::Full && clear.lines > 0 {
+            self.tspins[clear.lines as usize] += 1;
+        }
+        if clear.lines == 4 {
+            self.tetrises += 1;
+        }
+        self.perfect |= clear.perfect;
+    }
+    pub fn satisfies(&self, goals: &[Goal]) -> bool {
+        goals.iter().all(|goal| match *goal {
+            Goal::Lines(lines) => self.lines >= lines,
+            Goal::TSpin { lines, count } => self.tspins[lines as usize] >= count,
+            Goal::Tetris(count) => self.tetrises >= count,
+            Goal::PerfectClear => self.perfect
+        })
+    }
+}
+
+// Puzzle files are made of `key: value` lines followed by a `board:` section
+// listing the bottom rows of the board, e.g.
+//
+//     name: T-spin double
+//     pieces: T
+//     goal: tsd
+//     board:
+//     XX........
+//     X...XXXXXX
+//     XX.XXXXXXX
+//
+// Board rows use `.` for empty cells, `X` for garbage and piece letters for
+// colored tiles. Several `goal:` lines must all be met.
+#[derive(Clone, Debug)]
+pub struct Puzzle {
+    pub name: String,
+    pub board: TileCanvas,
+    pub pieces: Vec<PieceEnum>,
+    pub goals: Vec<Goal>
+}
+
+impl Puzzle {
+    pub fn parse(text: &str) -> Result<Puzzle, String> {
+        let mut name = String::new();
+        let mut pieces = Vec::new();
+        let mut goals = Vec::new();
+        let mut lines = text.lines().map(|l| l.trim()).filter(|l| !l.is_empty() && !l.starts_with('#'));
+        for line in &mut lines {
+            let (key, value) = line.split_once(':').ok_or(format!("expected 'key: value', got '{}'", line))?;
+            let value = value.trim();
+            match key.trim() {
+                "name" => name = value.to_string(),
+                "pieces" => {
+                    for c in value.chars().filter(|c| !c.is_whitespace()) {
+                        pieces.push(parse_piece(c).ok_or(format!("invalid piece '{}'", c))?);
+                    }
+                }
+                "goal" => goals.push(Goal::parse(value)?),
+                "board" => break,
+                key => return Err(format!("unknown key '{}'", key))
+            }
+        }
+        let board = parse_board(&lines.collect::<Vec<_>>().join("\n"), PUZZLE_WIDTH, PUZZLE_HEIGHT)?;
+        if pieces.is_empty() {
+            return Err(String::from("puzzle has no pieces"));
+        }
+        if goals.is_empty() {
+            return Err(String::from("puzzle has no goal"));
+        }
+        Ok(Puzzle { name, board, pieces, goals })
+    }
+    pub fn load(path: &Path) -> Result<Puzzle, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Puzzle::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+    // Loads every `.txt` puzzle in `dir`, sorted by file name.
+    pub fn load_pack(dir: &Path) -> Result<Vec<Puzzle>, String> {
+        let mut paths: Vec<_> = std::fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().map(|e| e == "txt").unwrap_or(false))
+            .collect();
+        paths.sort();
+        paths.iter().map(|path| Puzzle::load(path)).collect()
+    }
+    pub fn tetris(&self) -> Tetris {
+        let mut tetris = Tetris::with_queue(self.board.clone(), PieceQueue::fixed(self.pieces.clone()));
+        tetris.hold_mode = HoldMode::Disabled;
+        tetris
+    }
+    pub fn describe(&self) -> String {
+        self.goals.iter().map(|g| g.describe()).collect::<Vec<_>>().join(", ")
+    }
+
+    // Brute force search over every reachable placement of every piece. Returns
+    // the placements of a solution if there is one.
+    pub fn solve(&self) -> Option<Vec<TetrisPiece>> {
+        let mut seen = HashSet::new();
+        solve_from(&self.tetris(), &Progress::default(), &self.goals, &mut seen)
+    }
+}
+
+fn solve_from(tetris: &Tetris, progress: &Progress, goals: &[Goal], seen: &mut HashSet<(Vec<bool>, Progress, usize)>) -> Option<Vec<TetrisPiece>> {
+    if tetris.check_loss() {
+        return None;
+    }
+    for placement in movegen::placements(tetris) {
+        let piece = placement.piece;
+        let mut next = tetris.clone();
+        next.place_piece(piece, placement.kick);
+        let clear = next.disable_piece();
+        let mut progress = progress.clone();
+        progress.record(&clear);
+        if progress.satisfies(goals) {
+            return Some(vec![piece]);
+        }
+        let key = (canvas_key(&next.tile_canvas), progress.clone(), next.queue.peek().count());
+        if !seen.insert(key) || !next.spawn_next() {
+            continue;
+        }
+        if let Some(mut solution) = solve_from(&next, &progress, goals, seen) {
+            solution.insert(0, piece);
+            return Some(solution);
+        }
+    }
+    None
+}
+
+fn canvas_key(canvas: &TileCanvas) -> Vec<bool> {
+    let mut key = Vec::new();
+    for y in 0..canvas.height() {
+        for x in 0..canvas.width() {
+            key.push(canvas.get_tile(x, y) != TileColor::Empty);
+        }
+    }
+    key
+}
+
+pub struct PuzzleMode {
+    puzzles: Vec<Puzzle>,
+    index: usize,
+    progress: Progress,
+    solved: Option<bool>
+}
+
+impl PuzzleMode {
+    pub fn new(puzzles: Vec<Puzzle>) -> PuzzleMode {
+        PuzzleMode { puzzles, index: 0, progress: Progress::default(), solved: None }
+    }
+    pub fn current(&self) -> &Puzzle {
+        &self.puzzles[self.index]
+    }
+    pub fn index(&self) -> usize {
+        self.index
+    }
+    pub fn len(&self) -> usize {
+        self.puzzles.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.puzzles.is_empty()
+    }
+    // Some(true) once the goal is met, Some(false) once it no longer can be.
+    pub fn solved(&self) -> Option<bool> {
+        self.solved
+    }
+    pub fn setup(&mut self, game: &mut Game) {
+        game.gravity = 0;
+        game.timings.lock_delay = u32::MAX;
+        self.retry(game);
+    }
+    pub fn retry(&mut self, game: &mut Game) {
+        self.progress = Progress::default();
+        self.solved = None;
+        game.set_tetris(self.current().tetris());
+    }
+    pub fn next(&mut self, game: &mut Game) {
+        self.index = (self.index + 1) % self.puzzles.len();
+        self.retry(game);
+    }
+    pub fn update(&mut self, game: &mut Game, step: &Step) {
+        if self.solved.is_some() {
+            return;
+        }
+        if let Some(lock) = step.locked {
+            self.progress.record(&lock.clear);
+            if self.progress.satisfies(&self.current().goals) {
+                self.solved = Some(true);
+                return;
+            }
+        }
+        if game.is_over() {
+            self.solved = Some(false);
+        }
+    }
+}
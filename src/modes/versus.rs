@@ -0,0 +1,188 @@
+use std::collections::VecDeque;
+
+use crate::game::*;
+use crate::garbage::GarbageGenerator;
+use crate::tetrust::*;
+
+pub const VERSUS_WIDTH: u16 = 10;
+pub const VERSUS_HEIGHT: u16 = 20;
+pub const VERSUS_MESSINESS: f64 = 0.5;
+
+// Lines of garbage sent for each kind of clear.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttackTable {
+    // Indexed by lines cleared.
+    pub lines: [u16; 5],
+    pub tspin: [u16; 4],
+    pub tspin_mini: [u16; 3],
+    // Extra lines by combo count, the last entry repeating for longer combos.
+    pub combo: Vec<u16>,
+    pub back_to_back: u16,
+    pub perfect_clear: u16
+}
+
+impl Default for AttackTable {
+    fn default() -> AttackTable {
+        AttackTable {
+            lines: [0, 0, 1, 2, 4],
+            tspin: [0, 2, 4, 6],
+            tspin_mini: [0, 0, 1],
+            combo: vec![0, 0, 1, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5],
+            back_to_back: 1,
+            perfect_clear: 10
+        }
+    }
+}
+
+impl AttackTable {
+    // `combo` counts the clears in a row before this one.
+    pub fn attack(&self, clear: &Clear, combo: u32, back_to_back: bool) -> u16 {
+        if clear.lines == 0 {
+            return 0;
+        }
+        let lines = clear.lines as usize;
+        let mut attack = match clear.tspin {
+            TSpin::Full => self.tspin.get(lines).copied().unwrap_or(0),
+            TSpin::Mini => self.tspin_mini.get(lines).copied().unwrap_or(0),
+            TSpin::None => self.lines.get(lines).copied().unwrap_or(0),
+        };
+        attack += self.combo.get(combo as usize).or_else(|| self.combo.last()).copied().unwrap_or(0);
+        if back_to_back {
+            attack += self.back_to_back;
+        }
+        if clear.perfect {
+            attack += self.perfect_clear;
+        }
+        attack
+    }
+}
+
+// Tetrises and T-spins keep back-to-back alive, other clears end it.
+pub fn is_difficult(clear: &Clear) -> bool {
+    clear.lines == 4 || (clear.lines > 0 && clear.tspin != TSpin::None)
+}
+
+pub struct Player {
+    pub game: Game,
+    generator: GarbageGenerator,
+    // Attacks waiting to be pushed in, oldest first.
+    incoming: VecDeque<u16>,
+    combo: Option<u32>,
+    back_to_back: bool,
+    sent: u32
+}
+
+impl Player {
+    pub fn new(game: Game, messiness: f64, seed: u64) -> Player {
+        let width = game.tetris.tile_canvas.width();
+        Player { game, generator: GarbageGenerator::new(width, messiness, seed), incoming: VecDeque::new(), combo: None, back_to_back: false, sent: 0 }
+    }
+    // The garbage meter: lines queued up but not yet on the board.
+    pub fn incoming(&self) -> u16 {
+        self.incoming.iter().sum()
+    }
+    pub fn sent(&self) -> u32 {
+        self.sent
+    }
+    pub fn combo(&self) -> Option<u32> {
+        self.combo
+    }
+    pub fn back_to_back(&self) -> bool {
+        self.back_to_back
+    }
+    pub fn receive(&mut self, lines: u16) {
+        if lines > 0 {
+            self.incoming.push_back(lines);
+        }
+    }
+
+    // Steps the game and returns the garbage to send after cancelling incoming lines.
+    pub fn step(&mut self, input: Input, table: &AttackTable) -> (Step, u16) {
+        let step = self.game.step(input);
+        let mut attack = 0;
+        if let Some(lock) = step.locked {
+            if lock.clear.lines > 0 {
+                let combo = self.combo.map(|c| c + 1).unwrap_or(0);
+                let difficult = is_difficult(&lock.clear);
+                attack = table.attack(&lock.clear, combo, difficult && self.back_to_back);
+                self.combo = Some(combo);
+                self.back_to_back = difficult;
+                attack = self.cancel(attack);
+                self.sent += attack as u32;
+            } else {
+                self.combo = None;
+                while let Some(lines) = self.incoming.pop_front() {
+                    let rows = self.generator.chunk(lines);
+                    self.game.add_garbage(rows);
+                }
+            }
+        }
+        (step, attack)
+    }
+
+    fn cancel(&mut self, mut attack: u16) -> u16 {
+        while attack > 0 {
+            match self.incoming.front_mut() {
+                Some(lines) if *lines > attack => {
+                    *lines -= attack;
+                    attack = 0;
+                }
+                Some(lines) => {
+                    attack -= *lines;
+                    self.incoming.pop_front();
+                }
+                None => break
+            }
+        }
+        attack
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Winner(usize),
+    Draw
+}
+
+// Two players sending garbage to each other until one of them tops out.
+pub struct Versus {
+    pub players: Vec<Player>,
+    pub table: AttackTable,
+    outcome: Option<Outcome>
+}
+
+impl Versus {
+    // Both players get the same piece sequence, but different garbage holes.
+    pub fn new(game: Game, messiness: f64, seed: u64) -> Versus {
+        let players = (0..2).map(|i| Player::new(game.clone(), messiness, seed.wrapping_add(i + 1))).collect();
+        Versus { players, table: AttackTable::default(), outcome: None }
+    }
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.outcome
+    }
+
+    pub fn step(&mut self, inputs: &[Input]) -> Vec<Step> {
+        if self.outcome.is_some() {
+            return vec![Step::default(); self.players.len()];
+        }
+        let mut steps = Vec::new();
+        let mut attacks = Vec::new();
+        for (player, &input) in self.players.iter_mut().zip(inputs.iter()) {
+            let (step, attack) = player.step(input, &self.table);
+            steps.push(step);
+            attacks.push(attack);
+        }
+        for (i, &attack) in attacks.iter().enumerate() {
+            let target = (i + 1) % self.players.len();
+            self.players[target].receive(attack);
+        }
+
+        let alive: Vec<usize> = (0..self.players.len()).filter(|&i| !self.players[i].game.is_over()).collect();
+        self.outcome = match alive.len() {
+            0 => Some(Outcome::Draw),
+            1 => Some(Outcome::Winner(alive[0])),
+            _ => None
+        };
+        steps
+    }
+}
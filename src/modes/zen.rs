@@ -0,0 +1,61 @@
+use crate::game::*;
+use crate::tetrust::*;
+
+pub const MAX_UNDO: usize = 100;
+
+// Practice mode without gravity or top out. A snapshot of the engine is taken
+// every time a piece spawns, so placements can be undone one at a time.
+pub struct Zen {
+    snapshots: Vec<Tetris>
+}
+
+impl Default for Zen {
+    fn default() -> Zen {
+        Zen::new()
+    }
+}
+
+impl Zen {
+    pub fn new() -> Zen {
+        Zen { snapshots: Vec::new() }
+    }
+    pub fn setup(&mut self, game: &mut Game) {
+        game.gravity = 0;
+        game.timings.lock_delay = u32::MAX;
+        game.tetris.hold_mode = HoldMode::Infinite;
+        self.snapshots.push(game.tetris.clone());
+    }
+    pub fn update(&mut self, game: &mut Game, step: &Step) {
+        if game.is_over() {
+            let mut tetris = game.tetris.clone();
+            tetris.clear_canvas();
+            game.set_tetris(tetris);
+        }
+        if step.spawned.is_some() {
+            if self.snapshots.len() > MAX_UNDO {
+                self.snapshots.remove(0);
+            }
+            self.snapshots.push(game.tetris.clone());
+        }
+    }
+    // The engine at every spawn still in the undo history, oldest first.
+    pub fn snapshots(&self) -> &[Tetris] {
+        &self.snapshots
+    }
+    pub fn undo_count(&self) -> usize {
+        self.snapshots.len() - 1
+    }
+    // Restores the board and queue from before the last placement.
+    pub fn undo(&mut self, game: &mut Game) -> bool {
+        if self.snapshots.len() < 2 {
+            return false;
+        }
+        self.snapshots.pop();
+        game.set_tetris(self.snapshots[self.snapshots.len() - 1].clone());
+        true
+    }
+    // Chooses the piece that spawns after the active one.
+    pub fn pick_next(&mut self, game: &mut Game, piece: PieceEnum) {
+        game.tetris.queue.set_next(piece);
+    }
+}
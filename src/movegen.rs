@@ -0,0 +1,356 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::game::Input;
+use crate::tetrust::*;
+
+// Move generation: every place a piece can lock from where it is, and the
+// fewest key presses that put it there. Pieces move exactly as `Tetris` moves
+// them, through `fits` and `try_rotate`, so tucks and spins come for free.
+
+// A key press. Shifting into a wall and dropping to the floor are one press
+// held down, so they count once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Left, Right, DasLeft, DasRight, RotateCw, RotateCcw, SoftDrop, SonicDrop, HardDrop, Hold
+}
+
+impl Action {
+    // Moves are tried in this order, so on ties placements prefer taps over
+    // held keys and rotations over drops.
+    pub const MOVES: [Action; 8] = [
+        Action::Left, Action::Right, Action::RotateCw, Action::RotateCcw, Action::DasLeft, Action::DasRight, Action::SoftDrop, Action::SonicDrop
+    ];
+
+    // The buttons that are down while the action is performed.
+    pub fn input(&self) -> Input {
+        let mut input = Input::default();
+        match self {
+            Action::Left | Action::DasLeft => input.left = true,
+            Action::Right | Action::DasRight => input.right = true,
+            Action::RotateCw => input.rotate_cw = true,
+            Action::RotateCcw => input.rotate_ccw = true,
+            Action::SoftDrop | Action::SonicDrop => input.soft_drop = true,
+            Action::HardDrop => input.hard_drop = true,
+            Action::Hold => input.hold = true
+        }
+        input
+    }
+}
+
+// Where `action` takes `piece`, along with the kick if it rotated. None if the
+// piece can't move that way.
+pub fn apply(tetris: &Tetris, piece: &TetrisPiece, action: Action) -> Option<(TetrisPiece, Option<u8>)> {
+    apply_with(piece, action, |p| tetris.fits(p))
+}
+
+// `apply` with the collision check of the caller's choosing.
+fn apply_with(piece: &TetrisPiece, action: Action, fits: impl Fn(&TetrisPiece) -> bool) -> Option<(TetrisPiece, Option<u8>)> {
+    let shift = |dx: i16, dy: i16| Some(TetrisPiece { x: piece.x + dx, y: piece.y + dy, ..*piece }).filter(|p| fits(p));
+    let slide = |dx: i16, dy: i16| {
+        let mut moved = shift(dx, dy)?;
+        while let Some(next) = Some(TetrisPiece { x: moved.x + dx, y: moved.y + dy, ..moved }).filter(|p| fits(p)) {
+            moved = next;
+        }
+        Some(moved)
+    };
+    let moved = match action {
+        Action::Left => shift(-1, 0),
+        Action::Right => shift(1, 0),
+        Action::DasLeft => slide(-1, 0),
+        Action::DasRight => slide(1, 0),
+        Action::SoftDrop => shift(0, 1),
+        Action::SonicDrop => slide(0, 1),
+        Action::RotateCw | Action::RotateCcw => {
+            return piece.rotated(action == Action::RotateCw, fits).map(|(rotated, kick)| (rotated, Some(kick)));
+        }
+        Action::HardDrop => slide(0, 1).or(Some(*piece)),
+        Action::Hold => None
+    };
+    moved.map(|p| (p, None))
+}
+
+// A piece's position and the kick of its last move if that was a rotation.
+type Node = (TetrisPiece, Option<u8>);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Placement {
+    // Where the piece locks, and the kick of the rotation that got it there if
+    // that was its last move.
+    pub piece: TetrisPiece,
+    pub kick: Option<u8>,
+    pub tspin: TSpin,
+    // Shortest key presses from the start, ending with the hard drop.
+    pub inputs: Vec<Action>
+}
+
+pub fn placements(tetris: &Tetris) -> Vec<Placement> {
+    MoveFinder::new().placements(tetris)
+}
+
+pub fn placements_from(tetris: &Tetris, start: TetrisPiece) -> Vec<Placement> {
+    MoveFinder::new().placements_from(tetris, start)
+}
+
+// A flat map from node indices that empties in constant time, so one search can
+// reuse the memory of the last.
+struct Marks<T> {
+    generation: u32,
+    entries: Vec<(u32, Option<T>)>
+}
+
+impl<T> Default for Marks<T> {
+    fn default() -> Marks<T> {
+        Marks { generation: 0, entries: Vec::new() }
+    }
+}
+
+impl<T: Copy> Marks<T> {
+    fn reset(&mut self, len: usize) {
+        if self.entries.len() < len || self.generation == u32::MAX {
+            self.entries = vec![(0, None); len];
+            self.generation = 0;
+        }
+        self.generation += 1;
+    }
+    fn get(&self, i: usize) -> Option<T> {
+        match self.entries[i] {
+            (generation, value) if generation == self.generation => value,
+            _ => None
+        }
+    }
+    fn set(&mut self, i: usize, value: T) {
+        self.entries[i] = (self.generation, Some(value));
+    }
+}
+
+// Finds placements, keeping its buffers from one search to the next. Worth
+// holding on to when searching every piece.
+#[derive(Default)]
+pub struct MoveFinder {
+    // Indexed by node: the node it was reached from and how.
+    parents: Marks<(Node, Action)>,
+    // Whether a piece has been hard dropped from each node yet.
+    dropped_from: Marks<()>,
+    // Moves from a position don't depend on the kick that got the piece there,
+    // so each position is only expanded once.
+    expanded: Marks<()>,
+    // Where a piece slides to from each position, down, left and right.
+    slides: [Marks<(i16, i16)>; 3],
+    // Blocked cells of the canvas, see `Grid`.
+    grid: Vec<bool>,
+    queue: VecDeque<Node>,
+    seen: HashSet<([(i16, i16); 4], TSpin)>
+}
+
+impl MoveFinder {
+    pub fn new() -> MoveFinder {
+        MoveFinder::default()
+    }
+
+    pub fn placements(&mut self, tetris: &Tetris) -> Vec<Placement> {
+        self.placements_from(tetris, tetris.active_piece())
+    }
+
+    // Every distinct way `start` can lock: placements covering the same cells
+    // only count once unless they differ in T-spin. Ordered by input count.
+    pub fn placements_from(&mut self, tetris: &Tetris, start: TetrisPiece) -> Vec<Placement> {
+        let nodes = Nodes::new(&tetris.tile_canvas);
+        let MoveFinder { parents, dropped_from, expanded, slides, grid, queue, seen } = self;
+        parents.reset(nodes.len());
+        dropped_from.reset(nodes.len());
+        expanded.reset(nodes.len() / KICKS);
+        for slide in slides.iter_mut() {
+            slide.reset(nodes.len() / KICKS);
+        }
+        queue.clear();
+        seen.clear();
+        let grid = Grid::new(tetris, start.piece, grid);
+        let fits = |piece: &TetrisPiece| grid.fits(piece);
+        let mut found = Vec::new();
+        let start = nodes.node(start, None);
+        match nodes.index(start) {
+            Some(i) => parents.set(i, (start, Action::HardDrop)),
+            None => return found
+        }
+        queue.push_back(start);
+        while let Some(state) = queue.pop_front() {
+            let (piece, kick) = state;
+            let dropped = nodes.slide(&fits, piece, (0, 1), &mut slides[0]);
+            let kick = if dropped == piece { kick } else { None };
+            let landed = nodes.node(dropped, kick);
+            if let Some(i) = nodes.index(landed).filter(|&i| dropped_from.get(i).is_none()) {
+                dropped_from.set(i, ());
+                let tspin = tetris.tspin_for(&dropped, kick);
+                let mut cells = dropped.cells();
+                cells.sort_unstable();
+                if seen.insert((cells, tspin)) {
+                    let mut inputs = vec![Action::HardDrop];
+                    let mut at = state;
+                    while at != start {
+                        let (parent, action) = nodes.index(at).and_then(|i| parents.get(i)).expect("visited node has a parent");
+                        inputs.push(action);
+                        at = parent;
+                    }
+                    inputs.reverse();
+                    found.push(Placement { piece: dropped, kick, tspin, inputs });
+                }
+            }
+            match nodes.index(state).map(|i| i / KICKS) {
+                Some(i) if expanded.get(i).is_none() => expanded.set(i, ()),
+                _ => continue
+            }
+            for &action in Action::MOVES.iter() {
+                // Dropping to the floor lands where the hard drop above did.
+                let moved = match action {
+                    Action::SonicDrop => Some((dropped, None)).filter(|_| dropped != piece),
+                    // An O looks the same turned, so turning it never reaches anywhere new.
+                    Action::RotateCw | Action::RotateCcw if piece.piece == PieceEnum::O => None,
+                    Action::DasLeft | Action::DasRight => {
+                        let (dx, cache) = if action == Action::DasLeft { (-1, &mut slides[1]) } else { (1, &mut slides[2]) };
+                        Some(nodes.slide(&fits, piece, (dx, 0), cache)).filter(|&p| p != piece).map(|p| (p, None))
+                    }
+                    action => apply_with(&piece, action, fits)
+                };
+                if let Some((next, kick)) = moved {
+                    let next = nodes.node(next, kick);
+                    if let Some(i) = nodes.index(next).filter(|&i| parents.get(i).is_none()) {
+                        parents.set(i, (state, action));
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        found
+    }
+}
+
+// The canvas as a flat array of blocked cells with a border around it, for
+// checking one piece quickly: the walls and floor are blocked and the space
+// above the canvas is free, as in `TileCanvas::is_free`.
+struct Grid<'a> {
+    tetris: &'a Tetris,
+    piece: PieceEnum,
+    shapes: [[(i16, i16); 4]; 4],
+    width: i16,
+    height: i16,
+    blocked: &'a [bool]
+}
+
+// Wide enough for any piece whose position `Nodes` can number, after a kick.
+const BORDER: i16 = ABOVE + 4;
+
+impl<'a> Grid<'a> {
+    fn new(tetris: &'a Tetris, piece: PieceEnum, blocked: &'a mut Vec<bool>) -> Grid<'a> {
+        let canvas = &tetris.tile_canvas;
+        let width = canvas.width() as i16 + BORDER * 2;
+        let height = canvas.height() as i16 + BORDER * 2;
+        blocked.clear();
+        for y in 0..height {
+            blocked.extend((0..width).map(|x| !canvas.is_free(x - BORDER, y - BORDER)));
+        }
+        Grid { tetris, piece, shapes: [0, 1, 2, 3].map(|state| piece.cells(state)), width, height, blocked }
+    }
+    fn fits(&self, piece: &TetrisPiece) -> bool {
+        if piece.piece != self.piece {
+            return self.tetris.fits(piece);
+        }
+        self.shapes[piece.state as usize % 4].iter().all(|&(cx, cy)| {
+            let (x, y) = (piece.x + cx + BORDER, piece.y + cy + BORDER);
+            if x < 0 || y < 0 || x >= self.width || y >= self.height {
+                return self.tetris.tile_canvas.is_free(x - BORDER, y - BORDER);
+            }
+            !self.blocked[(y * self.width + x) as usize]
+        })
+    }
+}
+
+// Numbers the positions a piece can be in on a board so searches can use flat
+// arrays instead of hashing.
+struct Nodes {
+    width: usize,
+    height: usize
+}
+
+// How far outside the canvas a piece's origin can be: cells sit up to three to
+// the right and below it, and kicks can lift it above the top.
+const MARGIN: i16 = 4;
+const ABOVE: i16 = 8;
+// No kick, or the index of the kick a T piece rotated with.
+const KICKS: usize = 6;
+
+impl Nodes {
+    fn new(canvas: &TileCanvas) -> Nodes {
+        Nodes { width: (canvas.width() as i16 + MARGIN * 2) as usize, height: (canvas.height() as i16 + ABOVE + MARGIN) as usize }
+    }
+    fn len(&self) -> usize {
+        self.width * self.height * 4 * KICKS
+    }
+    // Only T pieces care how they got somewhere.
+    fn node(&self, piece: TetrisPiece, kick: Option<u8>) -> Node {
+        (piece, kick.filter(|_| piece.piece == PieceEnum::T))
+    }
+    fn index(&self, (piece, kick): Node) -> Option<usize> {
+        let x = piece.x + MARGIN;
+        let y = piece.y + ABOVE;
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+        let kick = kick.map(|k| k as usize + 1).unwrap_or(0).min(KICKS - 1);
+        Some((((y as usize * self.width) + x as usize) * 4 + piece.state as usize % 4) * KICKS + kick)
+    }
+    // Where `piece` ends up moving by `(dx, dy)` until it can't. Every position
+    // passed on the way ends up in the same place, so they are all remembered in
+    // `cache`.
+    fn slide(&self, fits: &impl Fn(&TetrisPiece) -> bool, piece: TetrisPiece, (dx, dy): (i16, i16), cache: &mut Marks<(i16, i16)>) -> TetrisPiece {
+        let mut at = piece;
+        let (x, y) = loop {
+            let i = self.index((at, None)).map(|i| i / KICKS);
+            if let Some(end) = i.and_then(|i| cache.get(i)) {
+                break end;
+            }
+            let next = TetrisPiece { x: at.x + dx, y: at.y + dy, ..at };
+            if !fits(&next) {
+                break (at.x, at.y);
+            }
+            at = next;
+        };
+        // Walk the path again rather than collecting it, which is cheaper than
+        // allocating on every call.
+        let mut at = piece;
+        while let Some(i) = self.index((at, None)).map(|i| i / KICKS).filter(|&i| cache.get(i).is_none()) {
+            cache.set(i, (x, y));
+            if (at.x, at.y) == (x, y) {
+                break;
+            }
+            at = TetrisPiece { x: at.x + dx, y: at.y + dy, ..at };
+        }
+        TetrisPiece { x, y, ..piece }
+    }
+}
+
+// Replays `inputs` from `start` one cell at a time: held shifts and drops
+// become a tap per cell. Each step comes with where the piece is after it.
+pub fn steps(tetris: &Tetris, start: TetrisPiece, inputs: &[Action]) -> Vec<(Action, TetrisPiece)> {
+    let mut steps = Vec::new();
+    let mut piece = start;
+    for &action in inputs {
+        let tap = match action {
+            Action::DasLeft => Action::Left,
+            Action::DasRight => Action::Right,
+            Action::SonicDrop => Action::SoftDrop,
+            action => action
+        };
+        if tap == action {
+            if let Some((next, _)) = apply(tetris, &piece, action) {
+                piece = next;
+            }
+            steps.push((action, piece));
+            continue;
+        }
+        while let Some((next, _)) = apply(tetris, &piece, tap) {
+            piece = next;
+            steps.push((tap, piece));
+        }
+    }
+    steps
+}
@@ -0,0 +1,323 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::game::*;
+use crate::modes::versus::{self, Versus};
+use crate::tetrust::*;
+
+// Lockstep netplay: every client simulates the whole match from the same seed
+// and only inputs travel over the wire. The server pairs clients up, relays
+// their inputs and compares the state hashes they report to catch desyncs.
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const DEFAULT_PORT: u16 = 7878;
+pub const DEFAULT_INPUT_DELAY: u32 = 3;
+// How long a new connection has to say hello before it is dropped.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+// Frames between state hashes.
+pub const HASH_INTERVAL: u64 = 60;
+
+// Messages are sent one per line as space separated words.
+pub trait Wire: Sized {
+    fn encode(&self) -> String;
+    fn parse(line: &str) -> Result<Self, String>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Hello { version: u32 },
+    Welcome { version: u32, player: usize, seed: u64, delay: u32 },
+    Error(String),
+    // `input` is what `player` pressed for `frame`.
+    Input { player: usize, frame: u64, input: Input },
+    // Hash of the match state after `frame` frames, covering the garbage both
+    // players have sent and received.
+    Hash { frame: u64, hash: u64 },
+    Desync { frame: u64 },
+    Bye
+}
+
+impl Wire for Message {
+    fn encode(&self) -> String {
+        match self {
+            Message::Hello { version } => format!("hello {}", version),
+            Message::Welcome { version, player, seed, delay } => format!("welcome {} {} {} {}", version, player, seed, delay),
+            Message::Error(text) => format!("error {}", text),
+            Message::Input { player, frame, input } => format!("input {} {} {}", player, frame, input.bits()),
+            Message::Hash { frame, hash } => format!("hash {} {}", frame, hash),
+            Message::Desync { frame } => format!("desync {}", frame),
+            Message::Bye => String::from("bye")
+        }
+    }
+
+    fn parse(line: &str) -> Result<Message, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let field = |i: usize| -> Result<u64, String> {
+            let word = words.get(i).ok_or(format!("missing field in message '{}'", line))?;
+            word.parse::<u64>().map_err(|e| format!("invalid field '{}' in message '{}': {}", word, line, e))
+        };
+        match words.first().copied() {
+            Some("hello") => Ok(Message::Hello { version: field(1)? as u32 }),
+            Some("welcome") => Ok(Message::Welcome { version: field(1)? as u32, player: field(2)? as usize, seed: field(3)?, delay: field(4)? as u32 }),
+            Some("error") => Ok(Message::Error(line.trim()["error".len()..].trim().to_string())),
+            Some("input") => Ok(Message::Input { player: field(1)? as usize, frame: field(2)?, input: Input::from_bits(field(3)? as u8) }),
+            Some("hash") => Ok(Message::Hash { frame: field(1)?, hash: field(2)? }),
+            Some("desync") => Ok(Message::Desync { frame: field(1)? }),
+            Some("bye") => Ok(Message::Bye),
+            _ => Err(format!("unknown message '{}'", line))
+        }
+    }
+}
+
+pub fn write_message<M: Wire>(stream: &mut TcpStream, message: &M) -> Result<(), String> {
+    writeln!(stream, "{}", message.encode()).and_then(|_| stream.flush()).map_err(|e| e.to_string())
+}
+
+pub struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Result<Connection, String> {
+        stream.set_nodelay(true).map_err(|e| e.to_string())?;
+        let writer = stream.try_clone().map_err(|e| e.to_string())?;
+        Ok(Connection { reader: BufReader::new(stream), writer })
+    }
+    pub fn send<M: Wire>(&mut self, message: &M) -> Result<(), String> {
+        write_message(&mut self.writer, message)
+    }
+    // Makes `recv` give up after `timeout`, or wait forever with None.
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<(), String> {
+        self.writer.set_read_timeout(timeout).map_err(|e| e.to_string())
+    }
+    // Blocks until a message arrives.
+    pub fn recv<M: Wire>(&mut self) -> Result<M, String> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => Err(String::from("connection closed")),
+            Ok(_) => M::parse(&line),
+            Err(e) => Err(e.to_string())
+        }
+    }
+    // Reads messages on a background thread, sending them to `sender` tagged
+    // with `id` until the connection closes, and returns the writing half.
+    pub fn forward<M: Wire + Send + 'static>(mut self, id: usize, sender: Sender<(usize, Result<M, String>)>) -> Result<TcpStream, String> {
+        let writer = self.writer.try_clone().map_err(|e| e.to_string())?;
+        std::thread::spawn(move || loop {
+            let message = self.recv();
+            let closed = message.is_err();
+            if sender.send((id, message)).is_err() || closed {
+                return;
+            }
+        });
+        Ok(writer)
+    }
+}
+
+// 64-bit FNV-1a. Std's hashers may change between Rust releases, and clients
+// built with different ones must still agree.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+// Hashes each player's board, one byte per tile row by row, then the active
+// piece's x and y, kind and rotation, the incoming garbage and the lines
+// sent. Numbers are little endian.
+pub fn state_hash(versus: &Versus) -> u64 {
+    let mut hasher = Fnv::new();
+    for player in versus.players.iter() {
+        let canvas = &player.game.tetris.tile_canvas;
+        for y in 0..canvas.height() {
+            for x in 0..canvas.width() {
+                hasher.write(&[canvas.get_tile(x, y) as u8]);
+            }
+        }
+        let piece = player.game.tetris.active_piece();
+        hasher.write(&piece.x.to_le_bytes());
+        hasher.write(&piece.y.to_le_bytes());
+        hasher.write(&[piece.piece as u8, piece.state]);
+        hasher.write(&player.incoming().to_le_bytes());
+        hasher.write(&player.sent().to_le_bytes());
+    }
+    hasher.0
+}
+
+// Waits for a connection and reads its first message. Connections that fail
+// or stay silent for `HANDSHAKE_TIMEOUT` are dropped, so they can't keep the
+// server from accepting the next one.
+pub fn accept<M: Wire>(listener: &TcpListener) -> (Connection, Result<M, String>) {
+    loop {
+        let Ok((stream, _)) = listener.accept() else { continue };
+        let Ok(mut connection) = Connection::new(stream) else { continue };
+        if connection.set_timeout(Some(HANDSHAKE_TIMEOUT)).is_err() {
+            continue;
+        }
+        let message = connection.recv();
+        if connection.set_timeout(None).is_ok() {
+            return (connection, message);
+        }
+    }
+}
+
+// Waits for a client speaking our protocol version, turning away the others.
+fn accept_client(listener: &TcpListener) -> Connection {
+    loop {
+        let (mut connection, hello) = accept(listener);
+        match hello {
+            Ok(Message::Hello { version }) if version == PROTOCOL_VERSION => return connection,
+            Ok(Message::Hello { version }) => {
+                let error = format!("protocol version {} is not supported, the server speaks {}", version, PROTOCOL_VERSION);
+                let _ = connection.send(&Message::Error(error));
+            }
+            _ => {
+                let _ = connection.send(&Message::Error(String::from("expected hello")));
+            }
+        }
+    }
+}
+
+// Pairs up clients as they connect and runs each match on its own thread.
+pub fn serve(listener: TcpListener, delay: u32) -> Result<(), String> {
+    loop {
+        let first = accept_client(&listener);
+        let second = accept_client(&listener);
+        std::thread::spawn(move || run_match([first, second], delay, rand::random()));
+    }
+}
+
+pub fn run_match(mut clients: [Connection; 2], delay: u32, seed: u64) -> Result<(), String> {
+    for (player, client) in clients.iter_mut().enumerate() {
+        client.send(&Message::Welcome { version: PROTOCOL_VERSION, player, seed, delay })?;
+    }
+    let writers: Vec<Arc<Mutex<TcpStream>>> = clients.iter()
+        .map(|c| c.writer.try_clone().map(|w| Arc::new(Mutex::new(w))).map_err(|e| e.to_string()))
+        .collect::<Result<_, String>>()?;
+    let hashes = Arc::new(Mutex::new(HashMap::new()));
+
+    let mut threads = Vec::new();
+    for (player, mut client) in Vec::from(clients).into_iter().enumerate() {
+        let writers = writers.clone();
+        let hashes = hashes.clone();
+        threads.push(std::thread::spawn(move || {
+            let send = |to: usize, message: &Message| {
+                let _ = write_message(&mut writers[to].lock().unwrap(), message);
+            };
+            loop {
+                match client.recv() {
+                    Ok(message @ Message::Input { .. }) => send(1 - player, &message),
+                    Ok(Message::Hash { frame, hash }) => {
+                        let mut hashes = hashes.lock().unwrap();
+                        match hashes.remove(&frame) {
+                            Some(other) if other != hash => {
+                                send(0, &Message::Desync { frame });
+                                send(1, &Message::Desync { frame });
+                            }
+                            Some(_) => {}
+                            None => {
+                                hashes.insert(frame, hash);
+                            }
+                        }
+                    }
+                    Ok(Message::Bye) | Err(_) => {
+                        send(1 - player, &Message::Bye);
+                        return;
+                    }
+                    Ok(_) => {}
+                }
+            }
+        }));
+    }
+    for thread in threads {
+        let _ = thread.join();
+    }
+    Ok(())
+}
+
+pub struct Client {
+    connection: Connection,
+    player: usize,
+    delay: u32,
+    pub versus: Versus,
+    frame: u64,
+    // Inputs not yet played, by player, starting at the current frame.
+    inputs: [VecDeque<Input>; 2]
+}
+
+impl Client {
+    // Connects and waits for an opponent. `game` supplies the board size, gravity
+    // and timings; the pieces come from the seed the server picks.
+    pub fn connect(addr: &str, game: Game) -> Result<Client, String> {
+        let stream = TcpStream::connect(addr).map_err(|e| format!("{}: {}", addr, e))?;
+        let mut connection = Connection::new(stream)?;
+        connection.send(&Message::Hello { version: PROTOCOL_VERSION })?;
+        let (player, seed, delay) = match connection.recv()? {
+            Message::Welcome { version, player, seed, delay } if version == PROTOCOL_VERSION && player < 2 => (player, seed, delay),
+            Message::Error(error) => return Err(error),
+            message => return Err(format!("unexpected message '{}'", message.encode()))
+        };
+        let mut game = game;
+        let canvas = &game.tetris.tile_canvas;
+        game.set_tetris(Tetris::new(TileCanvas::new(canvas.width(), canvas.height()), seed));
+        let versus = Versus::new(game, versus::VERSUS_MESSINESS, seed);
+        // Nobody has pressed anything during the first `delay` frames.
+        let pending: VecDeque<Input> = vec![Input::default(); delay as usize].into();
+        Ok(Client { connection, player, delay, versus, frame: 0, inputs: [pending.clone(), pending] })
+    }
+    pub fn player(&self) -> usize {
+        self.player
+    }
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // Queues `input` to be played `delay` frames from now and plays the current
+    // frame once the opponent's input for it has arrived.
+    pub fn step(&mut self, input: Input) -> Result<Vec<Step>, String> {
+        let opponent = 1 - self.player;
+        self.inputs[self.player].push_back(input);
+        self.connection.send(&Message::Input { player: self.player, frame: self.frame + self.delay as u64, input })?;
+        while self.inputs[opponent].is_empty() {
+            match self.connection.recv()? {
+                Message::Input { player, frame, input } if player == opponent => {
+                    let expected = self.frame + self.inputs[opponent].len() as u64;
+                    if frame != expected {
+                        return Err(format!("expected input for frame {}, got {}", expected, frame));
+                    }
+                    self.inputs[opponent].push_back(input);
+                }
+                Message::Desync { frame } => return Err(format!("desync detected at frame {}", frame)),
+                Message::Error(error) => return Err(error),
+                Message::Bye => return Err(String::from("opponent left")),
+                _ => {}
+            }
+        }
+        let mut inputs = [Input::default(); 2];
+        for (player, input) in inputs.iter_mut().enumerate() {
+            *input = self.inputs[player].pop_front().unwrap_or_default();
+        }
+        let steps = self.versus.step(&inputs);
+        self.frame += 1;
+        if self.frame.is_multiple_of(HASH_INTERVAL) {
+            self.connection.send(&Message::Hash { frame: self.frame, hash: state_hash(&self.versus) })?;
+        }
+        Ok(steps)
+    }
+
+    pub fn close(mut self) {
+        let _ = self.connection.send(&Message::Bye);
+    }
+}
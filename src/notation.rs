@@ -0,0 +1,84 @@
+use crate::tetrust::*;
+
+// Plain text boards, one line per row from top to bottom. `.` is an empty
+// cell, `X` (or `G`) garbage and a piece letter a tile of that piece's color.
+
+pub fn piece_letter(piece: PieceEnum) -> char {
+    match piece {
+        PieceEnum::O => 'O',
+        PieceEnum::S => 'S',
+        PieceEnum::Z => 'Z',
+        PieceEnum::T => 'T',
+        PieceEnum::L => 'L',
+        PieceEnum::J => 'J',
+        PieceEnum::I => 'I',
+    }
+}
+
+pub fn parse_piece(c: char) -> Option<PieceEnum> {
+    match c.to_ascii_uppercase() {
+        'O' => Some(PieceEnum::O),
+        'S' => Some(PieceEnum::S),
+        'Z' => Some(PieceEnum::Z),
+        'T' => Some(PieceEnum::T),
+        'L' => Some(PieceEnum::L),
+        'J' => Some(PieceEnum::J),
+        'I' => Some(PieceEnum::I),
+        _ => None
+    }
+}
+
+pub fn tile_letter(color: TileColor) -> char {
+    match color {
+        TileColor::Empty => '.',
+        TileColor::Gray => 'X',
+        TileColor::Yellow => 'O',
+        TileColor::Green => 'S',
+        TileColor::Red => 'Z',
+        TileColor::Purple => 'T',
+        TileColor::Orange => 'L',
+        TileColor::Blue => 'J',
+        TileColor::Cyan => 'I',
+    }
+}
+
+pub fn parse_tile(c: char) -> Option<TileColor> {
+    match c {
+        '.' => Some(TileColor::Empty),
+        'X' | 'G' => Some(TileColor::Gray),
+        _ => parse_piece(c).map(|p| p.color())
+    }
+}
+
+// Rows are bottom aligned, so only the non-empty part of the stack has to be given.
+pub fn parse_board(text: &str, width: u16, height: u16) -> Result<TileCanvas, String> {
+    let rows: Vec<&str> = text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()).collect();
+    if rows.len() > height as usize {
+        return Err(format!("board has {} rows, at most {} are allowed", rows.len(), height));
+    }
+    let mut canvas = TileCanvas::new(width, height);
+    let top = height as usize - rows.len();
+    for (i, row) in rows.iter().enumerate() {
+        if row.chars().count() != width as usize {
+            return Err(format!("board row '{}' is not {} cells wide", row, width));
+        }
+        for (x, c) in row.chars().enumerate() {
+            let color = parse_tile(c).ok_or(format!("invalid board cell '{}'", c))?;
+            canvas.set_tile(x as u16, (top + i) as u16, color);
+        }
+    }
+    Ok(canvas)
+}
+
+// The rows from the highest tile down to the floor; an empty board is an empty string.
+pub fn format_board(canvas: &TileCanvas) -> String {
+    let top = (0..canvas.height())
+        .find(|&y| (0..canvas.width()).any(|x| canvas.get_tile(x, y) != TileColor::Empty))
+        .unwrap_or(canvas.height());
+    let mut text = String::new();
+    for y in top..canvas.height() {
+        text.extend((0..canvas.width()).map(|x| tile_letter(canvas.get_tile(x, y))));
+        text.push('\n');
+    }
+    text
+}
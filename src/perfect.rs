@@ -0,0 +1,373 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+
+use crate::movegen::{self, Placement};
+use crate::tetrust::*;
+
+// Perfect clear search: a sequence of placements, using hold, that empties the
+// board without ever stacking above the bottom `lines` rows.
+
+pub const DEFAULT_PC_LINES: u16 = 4;
+// How many boards the search looks at before giving up.
+pub const MAX_NODES: usize = 50_000;
+// Empty rows kept above the ones being cleared, enough to spawn and rotate in.
+const ROOM: u16 = 4;
+
+// One piece of a solution. With `hold` the piece is swapped into hold first and
+// the placement is of the piece that comes out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Move {
+    pub hold: bool,
+    pub placement: Placement
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Search {
+    Found(Vec<Move>),
+    // Every sequence was tried.
+    Impossible,
+    // The node limit ran out first.
+    GaveUp
+}
+
+impl Search {
+    pub fn solution(&self) -> Option<&[Move]> {
+        match self {
+            Search::Found(moves) => Some(moves),
+            _ => None
+        }
+    }
+}
+
+// Searches from `tetris` as if its active piece had just spawned, followed by
+// `queue`. Only the pieces given are used, so a short preview only finds short
+// clears.
+pub fn solve(tetris: &Tetris, queue: &[PieceEnum], lines: u16) -> Search {
+    solve_limited(tetris, queue, lines, MAX_NODES)
+}
+
+pub fn solve_limited(tetris: &Tetris, queue: &[PieceEnum], lines: u16, max_nodes: usize) -> Search {
+    search(tetris, queue, lines, max_nodes, None)
+}
+
+fn search(tetris: &Tetris, queue: &[PieceEnum], lines: u16, max_nodes: usize, cancel: Option<Arc<AtomicBool>>) -> Search {
+    let canvas = &tetris.tile_canvas;
+    if canvas.width() > 64 || lines == 0 || lines > canvas.height() {
+        return Search::Impossible;
+    }
+    // Everything above the bottom rows has to be empty air, so the search runs
+    // on a board just tall enough to spawn into and shifts the answer back down.
+    let height = canvas.height().min(lines + ROOM);
+    let offset = canvas.height() - height;
+    if rows(canvas)[..offset as usize].iter().any(|&r| r != 0) {
+        return Search::Impossible;
+    }
+    let mut cropped = TileCanvas::new(canvas.width(), height);
+    for y in 0..height {
+        for x in 0..canvas.width() {
+            cropped.set_tile(x, y, canvas.get_tile(x, y + offset));
+        }
+    }
+    let mut pieces = vec![tetris.active_piece().piece];
+    pieces.extend_from_slice(queue);
+    let mut start = Tetris::with_queue(cropped, PieceQueue::fixed(pieces));
+    start.hold_mode = tetris.hold_mode;
+    start.set_hold(tetris.hold());
+    let mut searcher = Searcher { failed: HashSet::new(), nodes: 0, max_nodes, cancel };
+    // Clears lower than `lines` count too, and the lowest ones need the fewest
+    // pieces, so each height is tried from the top of the stack up.
+    let stack = rows(&start.tile_canvas).iter().position(|&r| r != 0).map(|y| height - y as u16).unwrap_or(0);
+    for lines in stack.max(1)..=lines {
+        if let Some(mut moves) = searcher.solve(&start, lines) {
+            moves.reverse();
+            for m in moves.iter_mut() {
+                m.placement.piece.y += offset as i16;
+            }
+            return Search::Found(moves);
+        }
+        if searcher.nodes > searcher.max_nodes {
+            return Search::GaveUp;
+        }
+    }
+    Search::Impossible
+}
+
+// A board, the pieces left and the lines left to clear, enough to tell two
+// searches would go the same way.
+type Key = (Vec<u64>, PieceEnum, Option<PieceEnum>, usize, u16);
+
+struct Searcher {
+    failed: HashSet<Key>,
+    nodes: usize,
+    max_nodes: usize,
+    cancel: Option<Arc<AtomicBool>>
+}
+
+impl Searcher {
+    // Returns the moves last first.
+    fn solve(&mut self, tetris: &Tetris, lines: u16) -> Option<Vec<Move>> {
+        let canvas = &tetris.tile_canvas;
+        let top = canvas.height() - lines;
+        let rows = rows(canvas);
+        if rows[..top as usize].iter().any(|&r| r != 0) {
+            return None;
+        }
+        let filled: u32 = rows.iter().map(|r| r.count_ones()).sum();
+        let empty = lines as u32 * canvas.width() as u32 - filled;
+        let pieces = 1 + tetris.queue.len() + tetris.hold().is_some() as usize;
+        if !empty.is_multiple_of(4) || empty as usize / 4 > pieces || !walls_split_evenly(&rows[top as usize..], canvas.width()) {
+            return None;
+        }
+        // A fresh piece and the held one can be played in either order.
+        let mut pair = (tetris.active_piece().piece, tetris.hold());
+        if tetris.hold_mode != HoldMode::Disabled {
+            if let (active, Some(held)) = pair {
+                if (held as u8) < (active as u8) {
+                    pair = (held, Some(active));
+                }
+            }
+        }
+        let key = (rows, pair.0, pair.1, tetris.queue.len(), lines);
+        if self.failed.contains(&key) {
+            return None;
+        }
+        self.nodes += 1;
+        if self.cancel.as_ref().is_some_and(|c| c.load(Ordering::Relaxed)) {
+            self.nodes = self.max_nodes + 1;
+        }
+        if self.nodes > self.max_nodes {
+            return None;
+        }
+        for hold in [false, true] {
+            let mut from = tetris.clone();
+            if hold && (from.hold() == Some(from.active_piece().piece) || !from.hold_piece() || from.check_loss()) {
+                continue;
+            }
+            for placement in candidates(&from, top) {
+                let mut next = from.clone();
+                next.place_piece(placement.piece, placement.kick);
+                let clear = next.disable_piece();
+                if clear.perfect {
+                    return Some(vec![Move { hold, placement }]);
+                }
+                if next.check_loss() || !next.spawn_next() {
+                    continue;
+                }
+                if let Some(mut moves) = self.solve(&next, lines - clear.lines) {
+                    moves.push(Move { hold, placement });
+                    return Some(moves);
+                }
+            }
+        }
+        self.failed.insert(key);
+        None
+    }
+}
+
+// Placements that stay below `top`, one per set of cells, lowest first.
+fn candidates(tetris: &Tetris, top: u16) -> Vec<Placement> {
+    let mut seen = HashSet::new();
+    let mut found: Vec<Placement> = movegen::placements(tetris).into_iter()
+        .filter(|p| p.piece.cells().iter().all(|&(_, y)| y >= top as i16))
+        .filter(|p| {
+            let mut cells = p.piece.cells();
+            cells.sort_unstable();
+            seen.insert(cells)
+        })
+        .collect();
+    let rows = rows(&tetris.tile_canvas);
+    found.sort_by_cached_key(|p| {
+        let mut after = rows.clone();
+        for &(x, y) in p.piece.cells().iter() {
+            after[y as usize] |= 1 << x;
+        }
+        (covered(&after), -p.piece.cells().iter().map(|&(_, y)| y).sum::<i16>())
+    });
+    found
+}
+
+// Empty cells with something above them.
+fn covered(rows: &[u64]) -> u32 {
+    let mut above = 0;
+    let mut count = 0;
+    for &row in rows {
+        count += (above & !row).count_ones();
+        above |= row;
+    }
+    count
+}
+
+// Pieces can only cross between two columns through a row with both cells
+// empty. Rows move as a whole when lines clear, so if there is none there never
+// will be, and the empty cells on each side have to take whole pieces.
+fn walls_split_evenly(rows: &[u64], width: u16) -> bool {
+    let mut empty = 0;
+    for x in 0..width {
+        empty += rows.iter().filter(|&&r| r & 1 << x == 0).count();
+        let wall = x + 1 == width || !rows.iter().any(|&r| r & 3 << x == 0);
+        if wall && !empty.is_multiple_of(4) {
+            return false;
+        }
+        if wall {
+            empty = 0;
+        }
+    }
+    true
+}
+
+// One bit per filled cell, a row at a time.
+fn rows(canvas: &TileCanvas) -> Vec<u64> {
+    (0..canvas.height())
+        .map(|y| (0..canvas.width()).filter(|&x| canvas.get_tile(x, y) != TileColor::Empty).fold(0, |row, x| row | 1 << x))
+        .collect()
+}
+
+// What the player is facing when a piece spawns: the board, the piece, hold and
+// the pieces known to come next. The canvas is kept to play a solution out on;
+// two situations compare by their rows.
+#[derive(Clone, Debug)]
+struct Situation {
+    canvas: TileCanvas,
+    rows: Vec<u64>,
+    active: PieceEnum,
+    hold: Option<PieceEnum>,
+    queue: Vec<PieceEnum>
+}
+
+impl Situation {
+    fn new(tetris: &Tetris, queue: &[PieceEnum]) -> Situation {
+        Situation { canvas: tetris.tile_canvas.clone(), rows: rows(&tetris.tile_canvas), active: tetris.active_piece().piece, hold: tetris.hold(), queue: queue.to_vec() }
+    }
+    // Whether a plan made for `self` still holds in `other`, which may know
+    // more of the queue.
+    fn covers(&self, other: &Situation) -> bool {
+        self.rows == other.rows && self.active == other.active && self.hold == other.hold && other.queue.starts_with(&self.queue)
+    }
+}
+
+impl PartialEq for Situation {
+    fn eq(&self, other: &Situation) -> bool {
+        self.rows == other.rows && self.active == other.active && self.hold == other.hold && self.queue == other.queue
+    }
+}
+
+// A step of a solution, with the situations it can be played from: before
+// holding and, for moves that hold, after.
+#[derive(Clone, Debug)]
+struct Step {
+    before: Situation,
+    held: Option<Situation>,
+    placement: Move
+}
+
+// Keeps a perfect clear solution for a game in progress, following it while the
+// player sticks to it and searching again on a background thread when they
+// don't.
+pub struct Planner {
+    pub lines: u16,
+    steps: VecDeque<Step>,
+    // The situation being searched, or the last one searched for nothing.
+    pending: Option<(Situation, Receiver<Search>, Arc<AtomicBool>)>,
+    result: Option<(Situation, Search)>
+}
+
+impl Planner {
+    pub fn new(lines: u16) -> Planner {
+        Planner { lines, steps: VecDeque::new(), pending: None, result: None }
+    }
+
+    // Call every frame with the pieces known to come after the active one.
+    // Returns the move to make with the active piece, if any.
+    pub fn update(&mut self, tetris: &Tetris, queue: &[PieceEnum]) -> Option<&Move> {
+        let now = Situation::new(tetris, queue);
+        if let Some(i) = self.steps.iter().position(|s| s.before.covers(&now) || s.held.as_ref().is_some_and(|h| h.covers(&now))) {
+            self.steps.drain(..i);
+            self.cancel();
+            return self.steps.front().map(|s| &s.placement);
+        }
+        self.steps.clear();
+        if let Some((situation, receiver, _)) = &self.pending {
+            if let Ok(search) = receiver.try_recv() {
+                let situation = situation.clone();
+                self.pending = None;
+                if let Search::Found(moves) = &search {
+                    self.steps = steps(tetris, &situation, moves);
+                }
+                self.result = Some((situation, search));
+                return self.update(tetris, queue);
+            }
+        }
+        let searching = self.pending.as_ref().is_some_and(|(s, _, _)| *s == now);
+        let searched = self.result.as_ref().is_some_and(|(s, _)| *s == now);
+        if !searching && !searched {
+            self.start(tetris, now);
+        }
+        None
+    }
+
+    // The outcome of the last search if the player is still where it started.
+    pub fn status(&self) -> Option<&Search> {
+        match (&self.pending, &self.result) {
+            (Some(_), _) => None,
+            (None, Some((_, search))) => Some(search),
+            (None, None) => None
+        }
+    }
+    pub fn is_searching(&self) -> bool {
+        self.pending.is_some()
+    }
+    // Moves left in the solution being followed.
+    pub fn remaining(&self) -> usize {
+        self.steps.len()
+    }
+
+    fn start(&mut self, tetris: &Tetris, situation: Situation) {
+        self.cancel();
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tetris, queue, lines, flag) = (tetris.clone(), situation.queue.clone(), self.lines, cancel.clone());
+        std::thread::spawn(move || {
+            let _ = sender.send(search(&tetris, &queue, lines, MAX_NODES, Some(flag)));
+        });
+        self.pending = Some((situation, receiver, cancel));
+    }
+
+    fn cancel(&mut self) {
+        if let Some((_, _, cancel)) = self.pending.take() {
+            cancel.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for Planner {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+// Plays `moves` out from `situation` to tag each with where it starts.
+fn steps(tetris: &Tetris, situation: &Situation, moves: &[Move]) -> VecDeque<Step> {
+    let mut pieces = vec![situation.active];
+    pieces.extend_from_slice(&situation.queue);
+    let mut board = Tetris::with_queue(situation.canvas.clone(), PieceQueue::fixed(pieces));
+    board.hold_mode = tetris.hold_mode;
+    board.set_hold(situation.hold);
+    let mut steps = VecDeque::new();
+    for m in moves {
+        let queue: Vec<PieceEnum> = board.queue.peek().copied().collect();
+        let before = Situation::new(&board, &queue);
+        let mut held = None;
+        if m.hold {
+            board.hold_piece();
+            let queue: Vec<PieceEnum> = board.queue.peek().copied().collect();
+            held = Some(Situation::new(&board, &queue));
+        }
+        steps.push_back(Step { before, held, placement: m.clone() });
+        board.place_piece(m.placement.piece, m.placement.kick);
+        board.disable_piece();
+        board.spawn_next();
+    }
+    steps
+}
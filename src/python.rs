@@ -0,0 +1,305 @@
+// The pyo3 macros convert every PyResult they wrap into itself.
+#![allow(clippy::useless_conversion)]
+
+use std::path::Path;
+
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::finesse;
+use crate::game::*;
+use crate::gym::{self, Action, Env, Info, Observation};
+use crate::notation;
+use crate::perfect::Move;
+use crate::replay::Replay;
+use crate::tetrust::*;
+
+// Python bindings, built with
+//
+//     cargo rustc --release --lib --features python --crate-type cdylib
+//
+// and then importable as `tetrust` once target/release/libtetrust.so is copied
+// to tetrust.so (tetrust.pyd on Windows) somewhere on the Python path. Pieces
+// and tiles are the letters used by `notation`, buttons are the names of the
+// `Input` fields. python/smoke.py checks the bindings once built.
+
+fn letter(piece: PieceEnum) -> String {
+    notation::piece_letter(piece).to_string()
+}
+
+fn tspin_name(tspin: TSpin) -> &'static str {
+    match tspin {
+        TSpin::None => "none",
+        TSpin::Mini => "mini",
+        TSpin::Full => "full"
+    }
+}
+
+fn error(e: String) -> PyErr {
+    PyValueError::new_err(e)
+}
+
+fn input(buttons: &[String]) -> PyResult<Input> {
+    let mut input = Input::default();
+    for button in buttons {
+        let down = match button.as_str() {
+            "left" => &mut input.left,
+            "right" => &mut input.right,
+            "soft_drop" => &mut input.soft_drop,
+            "hard_drop" => &mut input.hard_drop,
+            "hold" => &mut input.hold,
+            "rotate_cw" => &mut input.rotate_cw,
+            "rotate_ccw" => &mut input.rotate_ccw,
+            _ => return Err(error(format!("unknown button '{}'", button)))
+        };
+        *down = true;
+    }
+    Ok(input)
+}
+
+// The lines, T-spin and perfect clear flag of a clear.
+fn clear(clear: Clear) -> (u16, &'static str, bool) {
+    (clear.lines, tspin_name(clear.tspin), clear.perfect)
+}
+
+#[pyclass(name = "Placement")]
+#[derive(Clone)]
+struct PyPlacement {
+    m: Move
+}
+
+#[pymethods]
+impl PyPlacement {
+    #[getter]
+    fn hold(&self) -> bool {
+        self.m.hold
+    }
+    #[getter]
+    fn piece(&self) -> String {
+        letter(self.m.placement.piece.piece)
+    }
+    #[getter]
+    fn x(&self) -> i16 {
+        self.m.placement.piece.x
+    }
+    #[getter]
+    fn y(&self) -> i16 {
+        self.m.placement.piece.y
+    }
+    #[getter]
+    fn rotation(&self) -> u8 {
+        self.m.placement.piece.state
+    }
+    #[getter]
+    fn cells(&self) -> Vec<(i16, i16)> {
+        self.m.placement.piece.cells().to_vec()
+    }
+    #[getter]
+    fn tspin(&self) -> &'static str {
+        tspin_name(self.m.placement.tspin)
+    }
+    // The shortest key presses from the spawn, e.g. "DL CW HD".
+    #[getter]
+    fn inputs(&self) -> String {
+        finesse::format_inputs(&self.m.placement.inputs)
+    }
+    fn __repr__(&self) -> String {
+        format!("Placement({}{} x={} y={} r={} {})", if self.m.hold { "hold " } else { "" }, self.piece(), self.x(), self.y(), self.rotation(),
+                self.inputs())
+    }
+}
+
+#[pyclass(name = "Game")]
+struct PyGame {
+    game: Game
+}
+
+#[pymethods]
+impl PyGame {
+    #[new]
+    #[pyo3(signature = (width = 10, height = 20, seed = 0, gravity = 0))]
+    fn new(width: u16, height: u16, seed: u64, gravity: u32) -> PyResult<PyGame> {
+        check_board_size(width, height).map_err(error)?;
+        Ok(PyGame { game: Game::new(Tetris::new(TileCanvas::new(width, height), seed), gravity, Timings::default()) })
+    }
+
+    // Advances one frame with `buttons` held down. Returns the clear if a piece locked.
+    #[pyo3(signature = (buttons = Vec::new()))]
+    fn step(&mut self, buttons: Vec<String>) -> PyResult<Option<(u16, &'static str, bool)>> {
+        Ok(self.game.step(input(&buttons)?).locked.map(|lock| clear(lock.clear)))
+    }
+    // Like `step`, with the buttons packed as in replays.
+    fn step_bits(&mut self, bits: u8) -> Option<(u16, &'static str, bool)> {
+        self.game.step(Input::from_bits(bits)).locked.map(|lock| clear(lock.clear))
+    }
+    fn placements(&self) -> Vec<PyPlacement> {
+        gym::moves(&self.game).into_iter().map(|m| PyPlacement { m }).collect()
+    }
+    // Plays a placement from `placements` straight away.
+    fn place(&mut self, placement: &PyPlacement) -> PyResult<(u16, &'static str, bool)> {
+        gym::place(&mut self.game, &placement.m).map(|lock| clear(lock.clear)).ok_or_else(|| error(String::from("placement is not for this piece")))
+    }
+
+    // Rows from the top in text board notation.
+    #[getter]
+    fn board(&self) -> Vec<String> {
+        notation::format_board(&self.game.tetris.tile_canvas).lines().map(String::from).collect()
+    }
+    // The active piece, its position and rotation.
+    #[getter]
+    fn active(&self) -> (String, i16, i16, u8) {
+        let piece = self.game.tetris.active_piece();
+        (letter(piece.piece), piece.x, piece.y, piece.state)
+    }
+    #[getter]
+    fn hold(&self) -> Option<String> {
+        self.game.tetris.hold().map(letter)
+    }
+    #[getter]
+    fn queue(&self) -> Vec<String> {
+        self.game.tetris.queue.peek().map(|&p| letter(p)).collect()
+    }
+    #[getter]
+    fn frames(&self) -> u64 {
+        self.game.frames()
+    }
+    #[getter]
+    fn score(&self) -> u32 {
+        self.game.tetris.score()
+    }
+    #[getter]
+    fn falling(&self) -> bool {
+        self.game.is_falling()
+    }
+    #[getter]
+    fn over(&self) -> bool {
+        self.game.is_over()
+    }
+    fn __repr__(&self) -> String {
+        notation::format_board(&self.game.tetris.tile_canvas)
+    }
+}
+
+fn observation(py: Python<'_>, obs: &Observation) -> PyResult<PyObject> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("board", &obs.board)?;
+    dict.set_item("piece", obs.piece)?;
+    dict.set_item("x", obs.x)?;
+    dict.set_item("y", obs.y)?;
+    dict.set_item("rotation", obs.rotation)?;
+    dict.set_item("hold", obs.hold)?;
+    dict.set_item("queue", obs.queue.to_vec())?;
+    Ok(dict.into_any().unbind())
+}
+
+fn info(py: Python<'_>, info: &Info) -> PyResult<PyObject> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("frames", info.frames)?;
+    dict.set_item("pieces", info.pieces)?;
+    dict.set_item("lines", info.lines)?;
+    dict.set_item("clear", info.clear.map(clear))?;
+    dict.set_item("invalid", info.invalid)?;
+    Ok(dict.into_any().unbind())
+}
+
+// The training environment from `gym`, with observations as dicts.
+#[pyclass(name = "Env")]
+struct PyEnv {
+    env: Env
+}
+
+type Transition = (PyObject, f64, bool, PyObject);
+
+impl PyEnv {
+    fn transition(&mut self, py: Python<'_>, action: Action) -> PyResult<Transition> {
+        let (obs, reward, done, i) = self.env.step(action);
+        Ok((observation(py, &obs)?, reward, done, info(py, &i)?))
+    }
+}
+
+#[pymethods]
+impl PyEnv {
+    #[new]
+    #[pyo3(signature = (width = 10, height = 20, max_pieces = None))]
+    fn new(width: u16, height: u16, max_pieces: Option<u32>) -> PyResult<PyEnv> {
+        let mut env = Env::new(width, height).map_err(error)?;
+        env.max_pieces = max_pieces;
+        Ok(PyEnv { env })
+    }
+    fn reset(&mut self, py: Python<'_>, seed: u64) -> PyResult<PyObject> {
+        let obs = self.env.reset(seed);
+        observation(py, &obs)
+    }
+    // One frame with the buttons packed as in replays.
+    fn step_input(&mut self, py: Python<'_>, bits: u8) -> PyResult<Transition> {
+        self.transition(py, Action::from_bits(bits))
+    }
+    fn step_placement(&mut self, py: Python<'_>, index: usize) -> PyResult<Transition> {
+        if index >= self.env.placements().len() {
+            return Err(PyIndexError::new_err(format!("no placement {}", index)));
+        }
+        self.transition(py, Action::Placement(index))
+    }
+    fn placements(&mut self) -> Vec<PyPlacement> {
+        self.env.placements().iter().map(|m| PyPlacement { m: m.clone() }).collect()
+    }
+    #[getter]
+    fn done(&self) -> bool {
+        self.env.is_done()
+    }
+}
+
+#[pyclass(name = "Replay")]
+struct PyReplay {
+    replay: Replay
+}
+
+#[pymethods]
+impl PyReplay {
+    #[getter]
+    fn seed(&self) -> u64 {
+        self.replay.seed
+    }
+    #[getter]
+    fn width(&self) -> u16 {
+        self.replay.width
+    }
+    #[getter]
+    fn height(&self) -> u16 {
+        self.replay.height
+    }
+    // The buttons held on each frame, packed.
+    #[getter]
+    fn inputs(&self) -> Vec<u8> {
+        self.replay.inputs.clone()
+    }
+    fn __len__(&self) -> usize {
+        self.replay.frames()
+    }
+    // The game after `frames` frames, from the start by default.
+    #[pyo3(signature = (frames = 0))]
+    fn game(&self, frames: usize) -> PyGame {
+        PyGame { game: self.replay.seek(frames) }
+    }
+    // The game as it ended.
+    fn play(&self) -> PyGame {
+        PyGame { game: self.replay.play() }
+    }
+}
+
+#[pyfunction]
+fn load_replay(path: &str) -> PyResult<PyReplay> {
+    Replay::load(Path::new(path)).map(|replay| PyReplay { replay }).map_err(error)
+}
+
+#[pymodule]
+#[pyo3(name = "tetrust")]
+fn python_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyGame>()?;
+    m.add_class::<PyPlacement>()?;
+    m.add_class::<PyEnv>()?;
+    m.add_class::<PyReplay>()?;
+    m.add_function(wrap_pyfunction!(load_replay, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+// Results of finished solo games, kept in a JSON file so the best ones of each
+// mode can be shown as a table. Every game is kept; the tables are worked out
+// when they are shown.
+
+pub const RECORDS_FILE: &str = "records.json";
+pub const RECORDS_VERSION: u32 = 1;
+// Games shown per mode.
+pub const TABLE_SIZE: usize = 10;
+// Modes where reaching the goal sooner beats a bigger score.
+const RACE_MODES: [&str; 1] = ["dig"];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    // The mode and its arguments as given on the command line, so that games
+    // with different settings are ranked apart.
+    pub mode: String,
+    // Lines in marathon and dig, grade points in master.
+    pub score: u32,
+    pub lines: u32,
+    pub pieces: u32,
+    pub frames: u64,
+    pub pps: f64,
+    // Whether the mode's goal was reached, like clearing all of dig's garbage.
+    pub cleared: bool,
+    // Shown in place of the score, like a master grade.
+    pub grade: Option<String>,
+    // Seconds since the Unix epoch.
+    pub date: u64,
+    pub seed: u64,
+    // Saved for games that make the table. Games from before dig and master
+    // could be replayed, and any whose replay couldn't be saved, have none.
+    pub replay: Option<PathBuf>
+}
+
+impl Record {
+    // What the game is ranked by, as shown in the tables.
+    pub fn headline(&self) -> String {
+        match &self.grade {
+            Some(grade) => grade.clone(),
+            None if self.cleared => String::from("Cleared"),
+            None => self.score.to_string()
+        }
+    }
+}
+
+// Better games first. A cleared game beats one that isn't; race modes then
+// go by time, and everything else by score, with the faster game first on
+// a tie.
+pub fn compare(a: &Record, b: &Record) -> Ordering {
+    let race = a.cleared && b.cleared && RACE_MODES.contains(&a.mode.split_whitespace().next().unwrap_or(""));
+    b.cleared.cmp(&a.cleared)
+        .then(if race { a.frames.cmp(&b.frames) } else { Ordering::Equal })
+        .then(b.score.cmp(&a.score))
+        .then(a.frames.cmp(&b.frames))
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Records {
+    pub version: u32,
+    pub games: Vec<Record>
+}
+
+impl Default for Records {
+    fn default() -> Records {
+        Records { version: RECORDS_VERSION, games: Vec::new() }
+    }
+}
+
+impl Records {
+    // The best games of `mode`, best first.
+    pub fn table(&self, mode: &str) -> Vec<&Record> {
+        let mut games: Vec<&Record> = self.games.iter().filter(|r| r.mode == mode).collect();
+        games.sort_by(|a, b| compare(a, b));
+        games.truncate(TABLE_SIZE);
+        games
+    }
+    // Modes with games, in the order they were first played.
+    pub fn modes(&self) -> Vec<&str> {
+        let mut modes: Vec<&str> = Vec::new();
+        for record in &self.games {
+            if !modes.contains(&record.mode.as_str()) {
+                modes.push(&record.mode);
+            }
+        }
+        modes
+    }
+
+    // The place `record` would take in its mode's table, counting from 1, if
+    // it makes it. Earlier games just as good stay ahead of it.
+    pub fn rank(&self, record: &Record) -> Option<usize> {
+        let better = self.games.iter().filter(|r| r.mode == record.mode && compare(r, record) != Ordering::Greater).count();
+        if better < TABLE_SIZE { Some(better + 1) } else { None }
+    }
+    // Adds a game, returning its place in the table like `rank`.
+    pub fn add(&mut self, record: Record) -> Option<usize> {
+        let rank = self.rank(&record);
+        self.games.push(record);
+        rank
+    }
+
+    // A missing file has no records yet.
+    pub fn load(path: &Path) -> Result<Records, String> {
+        if !path.exists() {
+            return Ok(Records::default());
+        }
+        let json = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let records: Records = serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if records.version > RECORDS_VERSION {
+            return Err(format!("{}: records version {} is newer than this build", path.display(), records.version));
+        }
+        Ok(records)
+    }
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+// $XDG_DATA_HOME/tetrust/records.json, or under ~/.local/share without it.
+pub fn default_path() -> Option<PathBuf> {
+    let non_empty = |var| std::env::var_os(var).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let dir = non_empty("XDG_DATA_HOME").or_else(|| non_empty("HOME").map(|home| home.join(".local").join("share")))?;
+    Some(dir.join("tetrust").join(RECORDS_FILE))
+}
+
+pub fn now() -> u64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// YYYY-MM-DD in UTC.
+pub fn format_date(seconds: u64) -> String {
+    // Days to a civil date, counting in 400 year eras that start on March 1st.
+    let days = (seconds / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::ops::{Index, IndexMut};
+use std::path::{Path, PathBuf};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
+use sdl2::surface::Surface;
+use sdl2::ttf::Font;
+use sdl2::video::{Window, WindowContext};
+
+use tetrust::config::{DisplayConfig, Theme};
+use tetrust::tetrust::*;
+use tetrust::visibility::StackVisibility;
+
+// The board is drawn with a one tile margin and a one tile wall on each side.
+const BOARD_X: i32 = 2;
+// Hold and next pieces are drawn in a column to the right of the board.
+const SIDEBAR_WIDTH: i32 = 6;
+const STATUS_ROWS: u32 = 4;
+// Opponents' boards are drawn small, in a grid to the right of the first panel.
+const MINI_TILE_SIZE: u32 = 6;
+const MINI_COLUMNS: u32 = 4;
+const HINT_ALPHA: u8 = 96;
+const GHOST_ALPHA: u8 = 64;
+// Height in tiles of the countdown.
+const BANNER_TILES: u32 = 3;
+// How much of the game shows through a menu drawn over it.
+const OVERLAY_ALPHA: u8 = 200;
+// Live stats are drawn in a column to the right of the first panel, with a bar
+// per piece type under them.
+const STATS_WIDTH: u32 = 9;
+const HISTOGRAM_ROWS_PER_TILE: u32 = 2;
+
+// Width in tiles of one board with its walls and sidebar.
+fn panel_width(tile_canvas: &TileCanvas) -> i32 {
+    BOARD_X + tile_canvas.width() as i32 + 1 + SIDEBAR_WIDTH
+}
+
+// Size of a window showing `boards` boards side by side.
+pub fn window_size(tile_canvas: &TileCanvas, boards: u32, tile_size: u32) -> (u32, u32) {
+    (boards * panel_width(tile_canvas) as u32 * tile_size, (tile_canvas.height() as u32 + 1 + STATUS_ROWS) * tile_size)
+}
+
+// Extra window width needed for `count` mini boards.
+pub fn mini_boards_width(tile_canvas: &TileCanvas, count: usize) -> u32 {
+    std::cmp::min(count as u32, MINI_COLUMNS) * (tile_canvas.width() as u32 + 1) * MINI_TILE_SIZE
+}
+
+// Width and height of the stats column with `lines` lines of text.
+pub fn stats_size(tile_size: u32, lines: usize) -> (u32, u32) {
+    let histogram = PieceEnum::ALL.len() as u32 * (tile_size / HISTOGRAM_ROWS_PER_TILE);
+    (STATS_WIDTH * tile_size, (lines as u32 + 1) * tile_size + histogram)
+}
+
+fn tile_rgb(color: TileColor) -> Color {
+    match color {
+        TileColor::Empty => Color::RGB(40, 40, 70),
+        TileColor::Red => Color::RGB(220, 50, 50),
+        TileColor::Green => Color::RGB(70, 200, 70),
+        TileColor::Blue => Color::RGB(60, 80, 220),
+        TileColor::Purple => Color::RGB(170, 60, 200),
+        TileColor::Cyan => Color::RGB(60, 200, 220),
+        TileColor::Yellow => Color::RGB(230, 210, 60),
+        TileColor::Orange => Color::RGB(240, 150, 40),
+        TileColor::Gray => Color::RGB(130, 130, 130)
+    }
+}
+
+fn get_asset_path(assets: &Path, color: &TileColor) -> PathBuf {
+    assets.join(match color {
+        TileColor::Empty => "empty.bmp", TileColor::Red => "red.bmp",
+        TileColor::Green => "green.bmp", TileColor::Blue => "blue.bmp",
+        TileColor::Cyan => "cyan.bmp", TileColor::Purple => "purple.bmp",
+        TileColor::Yellow => "yellow.bmp", TileColor::Orange => "orange.bmp",
+        TileColor::Gray => "gray.bmp"
+    })
+}
+
+pub struct TileTexture<'t> {
+    empty: Texture<'t>, red: Texture<'t>,
+    green: Texture<'t>, blue: Texture<'t>,
+    purple: Texture<'t>, cyan: Texture<'t>,
+    yellow: Texture<'t>, orange: Texture<'t>,
+    gray: Texture<'t>
+}
+
+impl<'t> TileTexture<'t> {
+    pub fn load_from_path(texture_creator: &'t TextureCreator<WindowContext>, assets: &Path, color: TileColor) -> Result<Texture<'t>, String> {
+        let path = get_asset_path(assets, &color);
+        let surface = Surface::load_bmp(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut texture = texture_creator.create_texture_from_surface(surface).map_err(|err| {err.to_string()})?;
+        texture.set_blend_mode(BlendMode::Blend);
+        Ok(texture)
+    }
+
+    pub fn new(texture_creator: &'t TextureCreator<WindowContext>, assets: &Path) -> Result<TileTexture<'t>, String> {
+        Ok(TileTexture { empty: TileTexture::load_from_path(texture_creator, assets, TileColor::Empty)?,
+                          red: TileTexture::load_from_path(texture_creator, assets, TileColor::Red)?,
+                          green: TileTexture::load_from_path(texture_creator, assets, TileColor::Green)?,
+                          blue: TileTexture::load_from_path(texture_creator, assets, TileColor::Blue)?,
+                          purple: TileTexture::load_from_path(texture_creator, assets, TileColor::Purple)?,
+                          cyan: TileTexture::load_from_path(texture_creator, assets, TileColor::Cyan)?,
+                          yellow: TileTexture::load_from_path(texture_creator, assets, TileColor::Yellow)?,
+                          orange: TileTexture::load_from_path(texture_creator, assets, TileColor::Orange)?,
+                          gray: TileTexture::load_from_path(texture_creator, assets, TileColor::Gray)?,
+                        })
+    }
+}
+
+impl<'t> Index<TileColor> for TileTexture<'t> {
+    type Output = Texture<'t>;
+
+    fn index(&self, color: TileColor) -> &Self::Output {
+        match color {
+            TileColor::Empty => &self.empty,
+            TileColor::Red => &self.red,
+            TileColor::Green => &self.green,
+            TileColor::Blue => &self.blue,
+            TileColor::Purple => &self.purple,
+            TileColor::Cyan => &self.cyan,
+            TileColor::Yellow => &self.yellow,
+            TileColor::Orange => &self.orange,
+            TileColor::Gray => &self.gray,
+        }
+    }
+}
+
+impl<'t> IndexMut<TileColor> for TileTexture<'t> {
+    fn index_mut(&mut self, color: TileColor) -> &mut Self::Output {
+        match color {
+            TileColor::Empty => &mut self.empty,
+            TileColor::Red => &mut self.red,
+            TileColor::Green => &mut self.green,
+            TileColor::Blue => &mut self.blue,
+            TileColor::Purple => &mut self.purple,
+            TileColor::Cyan => &mut self.cyan,
+            TileColor::Yellow => &mut self.yellow,
+            TileColor::Orange => &mut self.orange,
+            TileColor::Gray => &mut self.gray,
+        }
+    }
+}
+
+pub struct Renderer<'t> {
+    canvas: Canvas<Window>,
+    texture_creator: &'t TextureCreator<WindowContext>,
+    textures: TileTexture<'t>,
+    font: Font<'t, 't>,
+    // Rendered text by panel, top and line.
+    text_cache: HashMap<(i32, i32, usize), (String, Texture<'t>)>,
+    // A suggested placement drawn see-through on the first board.
+    hint: Option<TetrisPiece>,
+    tile_size: u32,
+    ghost: bool,
+    stats: bool,
+    background: Color
+}
+
+impl<'t> Renderer<'t> {
+    pub fn new(canvas: Canvas<Window>, texture_creator: &'t TextureCreator<WindowContext>, textures: TileTexture<'t>, font: Font<'t, 't>,
+               display: &DisplayConfig, theme: &Theme) -> Renderer<'t> {
+        let [r, g, b] = theme.background;
+        Renderer { canvas, texture_creator, textures, font, text_cache: HashMap::new(), hint: None, tile_size: display.tile_size,
+                   ghost: display.ghost, stats: display.stats, background: Color::RGB(r, g, b) }
+    }
+
+    fn draw_tile(&mut self, x: i32, y: i32, color: TileColor) -> Result<(), String> {
+        let size = self.tile_size as i32;
+        self.canvas.copy(&self.textures[color], None, Rect::new(x * size, y * size, self.tile_size, self.tile_size))
+    }
+
+    fn draw_faded_tile(&mut self, x: i32, y: i32, color: TileColor, alpha: u8) -> Result<(), String> {
+        self.draw_tile(x, y, TileColor::Empty)?;
+        if color == TileColor::Empty || alpha == 0 {
+            return Ok(());
+        }
+        self.textures[color].set_alpha_mod(alpha);
+        let result = self.draw_tile(x, y, color);
+        self.textures[color].set_alpha_mod(255);
+        result
+    }
+
+    // Text lines are drawn below the board, one per row of the status area.
+    fn draw_text(&mut self, left: i32, top: i32, line: usize, text: &str) -> Result<(), String> {
+        let key = (left, top, line);
+        self.render_text(key, text)?;
+        let texture = &self.text_cache[&key].1;
+        let query = texture.query();
+        let height = self.tile_size;
+        let width = query.width * height / query.height.max(1);
+        self.canvas.copy(texture, None, Rect::new((left + BOARD_X) * height as i32, top + line as i32 * height as i32, width, height))
+    }
+
+    fn render_text(&mut self, key: (i32, i32, usize), text: &str) -> Result<(), String> {
+        if self.text_cache.get(&key).map(|(cached, _)| cached != text).unwrap_or(true) {
+            let surface = self.font.render(text).blended(Color::RGB(255, 255, 255)).map_err(|e| e.to_string())?;
+            let texture = self.texture_creator.create_texture_from_surface(surface).map_err(|e| e.to_string())?;
+            self.text_cache.insert(key, (text.to_string(), texture));
+        }
+        Ok(())
+    }
+
+    fn draw_preview(&mut self, x: i32, y: i32, piece: PieceEnum) -> Result<(), String> {
+        for &(cx, cy) in piece.cells(0).iter() {
+            self.draw_tile(x + cx as i32, y + cy as i32, piece.color())?;
+        }
+        Ok(())
+    }
+
+    // `now` is the current frame, used with `visibility` to hide locked tiles.
+    pub fn draw(&mut self, tetris: &Tetris, visibility: StackVisibility, now: u64, status: &[String]) -> Result<(), String> {
+        self.draw_game(tetris, visibility, now, status)?;
+        self.present();
+        Ok(())
+    }
+
+    // Like `draw`, but leaves the frame open for something to go over it.
+    pub fn draw_game(&mut self, tetris: &Tetris, visibility: StackVisibility, now: u64, status: &[String]) -> Result<(), String> {
+        self.clear();
+        self.draw_board(0, tetris, visibility, now, 0, status)?;
+        if let Some(hint) = self.hint {
+            let active = tetris.active_piece().cells();
+            for &(x, y) in hint.cells().iter() {
+                if y >= 0 && !active.contains(&(x, y)) {
+                    self.draw_faded_tile(BOARD_X + x as i32, y as i32, hint.piece.color(), HINT_ALPHA)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Large text in the middle of the first board, like the countdown. It is
+    // cached as if it were a line above the window.
+    pub fn draw_banner(&mut self, tile_canvas: &TileCanvas, text: &str) -> Result<(), String> {
+        let key = (0, -1, 0);
+        self.render_text(key, text)?;
+        let texture = &self.text_cache[&key].1;
+        let query = texture.query();
+        let height = BANNER_TILES * self.tile_size;
+        let width = query.width * height / query.height.max(1);
+        let size = self.tile_size as i32;
+        let x = BOARD_X * size + (tile_canvas.width() as i32 * size - width as i32) / 2;
+        let y = (tile_canvas.height() as i32 * size - height as i32) / 2;
+        self.canvas.copy(texture, None, Rect::new(x, y, width, height))
+    }
+
+    // A screen of text: the title, then the menu and some help under it.
+    pub fn draw_menu(&mut self, title: &str, lines: &[String], footer: &[String]) -> Result<(), String> {
+        self.clear();
+        self.draw_menu_text(title, lines, footer)
+    }
+
+    // A menu over whatever was drawn since the last frame, which is darkened.
+    pub fn draw_overlay(&mut self, title: &str, lines: &[String], footer: &[String]) -> Result<(), String> {
+        let (width, height) = self.canvas.output_size()?;
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(0, 0, 0, OVERLAY_ALPHA));
+        self.canvas.fill_rect(Rect::new(0, 0, width, height))?;
+        self.canvas.set_blend_mode(BlendMode::None);
+        self.draw_menu_text(title, lines, footer)
+    }
+
+    fn draw_menu_text(&mut self, title: &str, lines: &[String], footer: &[String]) -> Result<(), String> {
+        let mut text = vec![title.to_string(), String::new()];
+        text.extend(lines.iter().cloned());
+        text.push(String::new());
+        text.extend(footer.iter().cloned());
+        let top = self.tile_size as i32;
+        for (line, text) in text.iter().enumerate().filter(|(_, text)| !text.is_empty()) {
+            self.draw_text(0, top, line, text)?;
+        }
+        self.present();
+        Ok(())
+    }
+
+    // How many lines of menu text fit in the window.
+    pub fn text_rows(&self) -> Result<usize, String> {
+        let (_, height) = self.canvas.output_size()?;
+        Ok((height / self.tile_size).saturating_sub(1) as usize)
+    }
+
+    pub fn set_window_size(&mut self, (width, height): (u32, u32)) -> Result<(), String> {
+        self.canvas.window_mut().set_size(width, height).map_err(|e| e.to_string())
+    }
+
+    pub fn set_hint(&mut self, hint: Option<TetrisPiece>) {
+        self.hint = hint;
+    }
+
+    pub fn clear(&mut self) {
+        self.canvas.set_draw_color(self.background);
+        self.canvas.clear();
+    }
+
+    pub fn present(&mut self) {
+        self.canvas.present();
+    }
+
+    // Draws opponents' boards next to the board in the first panel, knocked out
+    // ones darkened. Boards nothing was heard from yet are drawn empty.
+    pub fn draw_mini_boards(&mut self, tile_canvas: &TileCanvas, boards: &[(Option<&TileCanvas>, bool)]) -> Result<(), String> {
+        let left = panel_width(tile_canvas) * self.tile_size as i32;
+        let width = tile_canvas.width() as i32;
+        let height = tile_canvas.height() as i32;
+        let size = MINI_TILE_SIZE as i32;
+        for (i, &(board, alive)) in boards.iter().enumerate() {
+            let x0 = left + (i as i32 % MINI_COLUMNS as i32) * (width + 1) * size;
+            let y0 = size + (i as i32 / MINI_COLUMNS as i32) * (height + 1) * size;
+            self.canvas.set_draw_color(tile_rgb(TileColor::Empty));
+            self.canvas.fill_rect(Rect::new(x0, y0, (width * size) as u32, (height * size) as u32))?;
+            if let Some(board) = board {
+                for y in 0..board.height().min(height as u16) {
+                    for x in 0..board.width().min(width as u16) {
+                        let color = board.get_tile(x, y);
+                        if color != TileColor::Empty {
+                            self.canvas.set_draw_color(tile_rgb(color));
+                            self.canvas.fill_rect(Rect::new(x0 + x as i32 * size, y0 + y as i32 * size, MINI_TILE_SIZE, MINI_TILE_SIZE))?;
+                        }
+                    }
+                }
+            }
+            if !alive {
+                self.canvas.set_blend_mode(BlendMode::Blend);
+                self.canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+                self.canvas.fill_rect(Rect::new(x0, y0, (width * size) as u32, (height * size) as u32))?;
+                self.canvas.set_blend_mode(BlendMode::None);
+            }
+        }
+        Ok(())
+    }
+
+    // Draws `lines` of stats next to the first panel, and under them a bar for
+    // each piece type as long as its share of `pieces`, in `PieceEnum::ALL` order.
+    // Nothing is drawn with the stats turned off.
+    pub fn draw_stats(&mut self, tile_canvas: &TileCanvas, lines: &[String], pieces: &[u32; 7]) -> Result<(), String> {
+        if !self.stats {
+            return Ok(());
+        }
+        let left = panel_width(tile_canvas);
+        for (line, text) in lines.iter().enumerate() {
+            self.draw_text(left - BOARD_X, 0, line, text)?;
+        }
+        let size = self.tile_size as i32;
+        let row = self.tile_size / HISTOGRAM_ROWS_PER_TILE;
+        let top = (lines.len() as i32 + 1) * size;
+        let longest = (STATS_WIDTH - 1) * self.tile_size;
+        let most = pieces.iter().copied().max().unwrap_or(0).max(1);
+        for (i, (&piece, &count)) in PieceEnum::ALL.iter().zip(pieces.iter()).enumerate() {
+            let y = top + i as i32 * row as i32;
+            self.canvas.set_draw_color(tile_rgb(TileColor::Empty));
+            self.canvas.fill_rect(Rect::new(left * size, y, longest, row - 1))?;
+            let width = (longest as u64 * count as u64 / most as u64) as u32;
+            if width > 0 {
+                self.canvas.set_draw_color(tile_rgb(piece.color()));
+                self.canvas.fill_rect(Rect::new(left * size, y, width, row - 1))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Draws the board in the `panel`th column of boards, with `incoming` lines of
+    // garbage shown as a red meter in the left wall.
+    pub fn draw_board(&mut self, panel: i32, tetris: &Tetris, visibility: StackVisibility, now: u64, incoming: u16, status: &[String]) -> Result<(), String> {
+        let left = panel * panel_width(&tetris.tile_canvas);
+        let board_x = left + BOARD_X;
+        let width = tetris.tile_canvas.width() as i32;
+        let height = tetris.tile_canvas.height() as i32;
+        for y in 0..=height {
+            let meter = y < height && height - y <= incoming as i32;
+            self.draw_tile(board_x - 1, y, if meter { TileColor::Red } else { TileColor::Gray })?;
+            self.draw_tile(board_x + width, y, TileColor::Gray)?;
+        }
+        for x in 0..width {
+            self.draw_tile(board_x + x, height, TileColor::Gray)?;
+            for y in 0..height {
+                let Tile(color, locked_at) = tetris.tile_canvas.get_tile_override(x as u16, y as u16);
+                self.draw_faded_tile(board_x + x, y, color, visibility.alpha(locked_at, now))?;
+            }
+        }
+        let piece = tetris.active_piece();
+        if self.ghost {
+            let ghost = tetris.ghost_piece();
+            for &(x, y) in ghost.cells().iter() {
+                if y >= 0 && ghost.y != piece.y {
+                    self.draw_faded_tile(board_x + x as i32, y as i32, piece.piece.color(), GHOST_ALPHA)?;
+                }
+            }
+        }
+        for &(x, y) in piece.cells().iter() {
+            if y >= 0 {
+                self.draw_tile(board_x + x as i32, y as i32, piece.piece.color())?;
+            }
+        }
+        let sidebar = board_x + width + 2;
+        if let Some(hold) = tetris.hold() {
+            self.draw_preview(sidebar, 0, hold)?;
+        }
+        let queue: Vec<PieceEnum> = tetris.queue.peek().cloned().collect();
+        for (i, &piece) in queue.iter().enumerate() {
+            self.draw_preview(sidebar, 4 + 3 * i as i32, piece)?;
+        }
+
+        let top = (height + 1) * self.tile_size as i32;
+        for (line, text) in status.iter().enumerate() {
+            self.draw_text(left, top, line, text)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::*;
+use crate::modes::dig::Dig;
+use crate::modes::master::Master;
+use crate::tetrust::*;
+
+// A recorded game: the settings it started with and the buttons held on every
+// frame. Games are deterministic given the seed of their piece queue, so
+// stepping a fresh game through the inputs plays it out exactly again. Dig and
+// master are set up and run alongside the game the same way when it is played
+// back. Zen, puzzles and openers change the board in ways the inputs don't
+// capture, like undo and retries, so they can't be recorded.
+
+pub const REPLAY_VERSION: u32 = 2;
+
+// The mode a game was played in, with whatever it needs to do the same again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayMode {
+    // Plain rules, as in marathon.
+    #[default]
+    Plain,
+    Dig { lines: u16, visible: u16, messiness: f64, seed: u64 },
+    Master
+}
+
+// A mode being played back.
+enum Running {
+    Plain,
+    Dig(Box<Dig>),
+    Master(Box<Master>)
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub width: u16,
+    pub height: u16,
+    pub seed: u64,
+    pub gravity: u32,
+    // Missing from replays made before soft drop speed could be set.
+    #[serde(default = "default_soft_drop_gravity")]
+    pub soft_drop_gravity: u32,
+    pub timings: Timings,
+    // Missing from replays made before other modes than marathon could be
+    // recorded.
+    #[serde(default)]
+    pub mode: ReplayMode,
+    // Buttons held during the countdown, one entry per frame like `inputs`.
+    #[serde(default)]
+    pub countdown: Vec<u8>,
+    // One entry per frame, see `Input::bits`.
+    pub inputs: Vec<u8>
+}
+
+fn default_soft_drop_gravity() -> u32 {
+    GRAVITY_UNIT
+}
+
+impl Replay {
+    // Starts recording `game`, which must not have been stepped yet.
+    pub fn new(game: &Game, seed: u64) -> Replay {
+        Replay::with_mode(game, seed, ReplayMode::Plain)
+    }
+    // Like `new`, for a game of `mode` that it hasn't been set up for yet.
+    pub fn with_mode(game: &Game, seed: u64, mode: ReplayMode) -> Replay {
+        let canvas = &game.tetris.tile_canvas;
+        Replay { version: REPLAY_VERSION, width: canvas.width(), height: canvas.height(), seed, gravity: game.gravity,
+                 soft_drop_gravity: game.soft_drop_gravity, timings: game.timings, mode, countdown: Vec::new(), inputs: Vec::new() }
+    }
+    // Records a frame of the countdown, see `Game::charge`.
+    pub fn record_countdown(&mut self, input: Input) {
+        self.countdown.push(input.bits());
+    }
+    pub fn record(&mut self, input: Input) {
+        self.inputs.push(input.bits());
+    }
+    pub fn frames(&self) -> usize {
+        self.inputs.len()
+    }
+
+    // The game as it was before the first frame.
+    pub fn start(&self) -> Game {
+        self.begin().0
+    }
+    fn begin(&self) -> (Game, Running) {
+        let mut game = Game::new(Tetris::new(TileCanvas::new(self.width, self.height), self.seed), self.gravity, self.timings);
+        game.soft_drop_gravity = self.soft_drop_gravity;
+        let running = match self.mode {
+            ReplayMode::Plain => Running::Plain,
+            ReplayMode::Dig { lines, visible, messiness, seed } => {
+                let mut dig = Dig::new(self.width, lines, visible, messiness, seed);
+                dig.setup(&mut game.tetris);
+                Running::Dig(Box::new(dig))
+            }
+            ReplayMode::Master => {
+                let mut master = Master::new();
+                master.setup(&mut game);
+                Running::Master(Box::new(master))
+            }
+        };
+        for &bits in &self.countdown {
+            game.charge(Input::from_bits(bits));
+        }
+        (game, running)
+    }
+    // The game after the first `frames` frames.
+    pub fn seek(&self, frames: usize) -> Game {
+        let (mut game, mut running) = self.begin();
+        for &bits in self.inputs.iter().take(frames) {
+            let step = game.step(Input::from_bits(bits));
+            match &mut running {
+                Running::Dig(dig) if step.locked.is_some() => dig.update(&mut game.tetris),
+                Running::Master(master) => master.update(&mut game, &step),
+                _ => {}
+            }
+        }
+        game
+    }
+    pub fn play(&self) -> Game {
+        self.seek(self.inputs.len())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let json = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let replay: Replay = serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if replay.version > REPLAY_VERSION {
+            return Err(format!("{}: replay version {} is newer than this build", path.display(), replay.version));
+        }
+        check_board_size(replay.width, replay.height).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(replay)
+    }
+}
@@ -0,0 +1,238 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::*;
+use crate::modes::master::format_time;
+use crate::records::format_date;
+use crate::stats::Stats;
+use crate::tetrust::*;
+
+// What happened over a game, for going through it afterwards: the stats once a
+// second, and every placement with the shape of the stack it left. It is saved
+// as JSON and as an HTML page with the charts drawn in SVG, so the page needs
+// nothing but a browser.
+
+pub const REPORT_VERSION: u32 = 1;
+// Frames between samples of the timeline.
+const SAMPLE_FRAMES: u64 = 60;
+const CHART_WIDTH: f64 = 640.0;
+const CHART_HEIGHT: f64 = 200.0;
+// Room around the plot for the axis labels.
+const CHART_MARGIN: f64 = 40.0;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub frame: u64,
+    pub pieces: u32,
+    pub lines: u32,
+    pub attack: u32,
+    pub pps: f64,
+    pub apm: f64,
+    // Rows from the floor to the highest tile.
+    pub height: u16,
+    pub holes: u32
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Placement {
+    // The frame the piece locked on.
+    pub frame: u64,
+    pub piece: PieceEnum,
+    pub x: i16,
+    pub y: i16,
+    pub rotation: u8,
+    pub lines: u16,
+    pub tspin: TSpin,
+    pub perfect: bool,
+    // The stack after the lines cleared.
+    pub height: u16,
+    pub holes: u32
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub version: u32,
+    // The mode and its arguments, as in the records.
+    pub mode: String,
+    pub seed: u64,
+    // Seconds since the Unix epoch.
+    pub date: u64,
+    pub frames: u64,
+    pub stats: Stats,
+    pub timeline: Vec<Sample>,
+    pub placements: Vec<Placement>
+}
+
+fn height(tile_canvas: &TileCanvas) -> u16 {
+    tile_canvas.column_heights().into_iter().max().unwrap_or(0)
+}
+
+impl Report {
+    pub fn new(mode: &str, seed: u64, date: u64) -> Report {
+        Report { version: REPORT_VERSION, mode: mode.to_string(), seed, date, frames: 0, stats: Stats::new(), timeline: Vec::new(),
+                 placements: Vec::new() }
+    }
+
+    // Call after every frame of the game, with `stats` already updated.
+    pub fn update(&mut self, game: &Game, stats: &Stats) {
+        let tile_canvas = &game.tetris.tile_canvas;
+        for event in game.events() {
+            if let Event::Locked(piece, clear) = *event {
+                self.placements.push(Placement { frame: game.frames(), piece: piece.piece, x: piece.x, y: piece.y, rotation: piece.state,
+                                                 lines: clear.lines, tspin: clear.tspin, perfect: clear.perfect,
+                                                 height: height(tile_canvas), holes: tile_canvas.holes() as u32 });
+            }
+        }
+        self.frames = game.frames();
+        self.stats = stats.clone();
+        if self.frames.is_multiple_of(SAMPLE_FRAMES) {
+            self.sample(tile_canvas);
+        }
+    }
+    // Samples the end of the game, so the timeline reaches it.
+    pub fn finish(&mut self, game: &Game) {
+        if self.timeline.last().map(|s| s.frame) != Some(self.frames) {
+            self.sample(&game.tetris.tile_canvas);
+        }
+    }
+    fn sample(&mut self, tile_canvas: &TileCanvas) {
+        let stats = &self.stats;
+        self.timeline.push(Sample { frame: self.frames, pieces: stats.pieces, lines: stats.lines, attack: stats.attack, pps: stats.pps(),
+                                    apm: stats.apm(), height: height(tile_canvas), holes: tile_canvas.holes() as u32 });
+    }
+
+    // Saves the JSON to `path` and the HTML page next to it.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let html = path.with_extension("html");
+        std::fs::write(&html, self.to_html()).map_err(|e| format!("{}: {}", html.display(), e))
+    }
+
+    pub fn to_html(&self) -> String {
+        let stats = &self.stats;
+        let title = format!("tetrust report: {} on {}", escape(&self.mode), format_date(self.date));
+        let mut html = String::new();
+        let _ = write!(html, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n",
+                       title, STYLE);
+        let _ = writeln!(html, "<h1>{}</h1>", title);
+
+        let tspin_minis: u32 = stats.tspin_minis.iter().sum();
+        let summary = [
+            ("Time", format_time(self.frames)),
+            ("Pieces", stats.pieces.to_string()),
+            ("Lines", stats.lines.to_string()),
+            ("Attack", stats.attack.to_string()),
+            ("PPS", format!("{:.2}", stats.pps())),
+            ("APM", format!("{:.1}", stats.apm())),
+            ("KPP", format!("{:.2}", stats.kpp())),
+            ("Singles / doubles / triples / tetrises", format!("{} / {} / {} / {}", stats.clears[1], stats.clears[2], stats.clears[3], stats.clears[4])),
+            ("T-spin zeros / singles / doubles / triples", format!("{} / {} / {} / {}", stats.tspins[0], stats.tspins[1], stats.tspins[2], stats.tspins[3])),
+            ("T-spin minis", tspin_minis.to_string()),
+            ("Max combo", stats.max_combo.to_string()),
+            ("Perfect clears", stats.perfect_clears.to_string()),
+            ("Seed", self.seed.to_string()),
+        ];
+        html.push_str("<table>\n");
+        for (name, value) in summary.iter() {
+            let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", name, value);
+        }
+        html.push_str("</table>\n");
+
+        let seconds = |frame: u64| frame as f64 / 60.0;
+        let timeline = |value: fn(&Sample) -> f64| -> Vec<(f64, f64)> { self.timeline.iter().map(|s| (seconds(s.frame), value(s))).collect() };
+        let placements = |value: fn(&Placement) -> f64| -> Vec<(f64, f64)> { self.placements.iter().map(|p| (seconds(p.frame), value(p))).collect() };
+        html.push_str("<h2>Over time</h2>\n");
+        html.push_str(&line_chart("Pieces per second", &timeline(|s| s.pps), "#3cc8dc"));
+        html.push_str(&line_chart("Attack per minute", &timeline(|s| s.apm), "#dc3232"));
+        html.push_str(&line_chart("Stack height after each piece", &placements(|p| p.height as f64), "#aa3cc8"));
+        html.push_str(&line_chart("Holes after each piece", &placements(|p| p.holes as f64), "#f09628"));
+        html.push_str("<h2>Pieces</h2>\n");
+        html.push_str(&bar_chart(&stats.pieces_by_type));
+
+        html.push_str("<h2>Placements</h2>\n<table>\n<tr><th>#</th><th>Time</th><th>Piece</th><th>x</th><th>y</th><th>Rotation</th>");
+        html.push_str("<th>Clear</th><th>Height</th><th>Holes</th></tr>\n");
+        for (i, p) in self.placements.iter().enumerate() {
+            let clear = Clear { lines: p.lines, tspin: p.tspin, perfect: p.perfect }.name();
+            let _ = writeln!(html, "<tr><td>{}</td><td>{}</td><td>{:?}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                             i + 1, format_time(p.frame), p.piece, p.x, p.y, p.rotation, clear, p.height, p.holes);
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+}
+
+const STYLE: &str = "body { font-family: sans-serif; background: #1c1c30; color: #eee; margin: 2em; } \
+table { border-collapse: collapse; margin-bottom: 2em; } th, td { padding: 2px 12px; text-align: left; } \
+tr:nth-child(even) { background: #28284a; } svg { display: block; margin-bottom: 1em; } svg text { fill: #ccc; font-size: 12px; }";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn color_hex(color: TileColor) -> &'static str {
+    match color {
+        TileColor::Empty => "#282846",
+        TileColor::Red => "#dc3232",
+        TileColor::Green => "#46c846",
+        TileColor::Blue => "#3c50dc",
+        TileColor::Purple => "#aa3cc8",
+        TileColor::Cyan => "#3cc8dc",
+        TileColor::Yellow => "#e6d23c",
+        TileColor::Orange => "#f09628",
+        TileColor::Gray => "#828282"
+    }
+}
+
+// `points` as a line from the origin up to the largest x and y, each labelled
+// on its axis.
+fn line_chart(title: &str, points: &[(f64, f64)], color: &str) -> String {
+    let max_x = points.iter().map(|p| p.0).fold(0.0, f64::max).max(1.0);
+    let max_y = points.iter().map(|p| p.1).fold(0.0, f64::max).max(1.0);
+    let (width, height) = (CHART_WIDTH - 2.0 * CHART_MARGIN, CHART_HEIGHT - 2.0 * CHART_MARGIN);
+    let line: Vec<String> = points.iter()
+        .map(|&(x, y)| format!("{:.1},{:.1}", CHART_MARGIN + x / max_x * width, CHART_MARGIN + height - y / max_y * height))
+        .collect();
+    let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+                          w = CHART_WIDTH, h = CHART_HEIGHT);
+    let _ = writeln!(svg, "<text x=\"{}\" y=\"20\">{}</text>", CHART_MARGIN, escape(title));
+    let _ = writeln!(svg, "<path d=\"M{l},{t} V{b} H{r}\" fill=\"none\" stroke=\"#888\"/>",
+                     l = CHART_MARGIN, t = CHART_MARGIN, b = CHART_MARGIN + height, r = CHART_MARGIN + width);
+    let _ = writeln!(svg, "<text x=\"4\" y=\"{}\">{}</text>", CHART_MARGIN + 4.0, format_value(max_y));
+    let _ = writeln!(svg, "<text x=\"4\" y=\"{}\">0</text>", CHART_MARGIN + height);
+    let _ = writeln!(svg, "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>", CHART_MARGIN + width, CHART_HEIGHT - CHART_MARGIN / 2.0,
+                     format_time((max_x * 60.0) as u64));
+    if !line.is_empty() {
+        let _ = writeln!(svg, "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>", line.join(" "), color);
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// A bar for each piece type in its color, `counts` in `PieceEnum::ALL` order.
+fn bar_chart(counts: &[u32; 7]) -> String {
+    let most = counts.iter().copied().max().unwrap_or(0).max(1) as f64;
+    let height = CHART_HEIGHT - 2.0 * CHART_MARGIN;
+    let slot = (CHART_WIDTH - 2.0 * CHART_MARGIN) / counts.len() as f64;
+    let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+                          w = CHART_WIDTH, h = CHART_HEIGHT);
+    for (i, (&piece, &count)) in PieceEnum::ALL.iter().zip(counts.iter()).enumerate() {
+        let bar = count as f64 / most * height;
+        let x = CHART_MARGIN + i as f64 * slot;
+        let _ = writeln!(svg, "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"/>",
+                         x + slot * 0.1, CHART_MARGIN + height - bar, slot * 0.8, bar, color_hex(piece.color()));
+        let _ = writeln!(svg, "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>", x + slot / 2.0, CHART_MARGIN + height - bar - 4.0, count);
+        let _ = writeln!(svg, "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{:?}</text>", x + slot / 2.0, CHART_HEIGHT - CHART_MARGIN / 2.0, piece);
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn format_value(value: f64) -> String {
+    if value.fract() == 0.0 { format!("{}", value) } else { format!("{:.2}", value) }
+}
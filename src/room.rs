@@ -0,0 +1,439 @@
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::game::*;
+use crate::modes::versus::{self, AttackTable, Player};
+use crate::net::{self, Connection, Wire};
+use crate::notation;
+use crate::tetrust::*;
+
+// Battle royale rooms. Unlike the lockstep matches in `net`, every client runs
+// only its own engine; it tells the server how much garbage it sends and the
+// server picks who receives it. Clients also share small board snapshots so
+// everyone can draw their opponents.
+
+pub const ROOM_PROTOCOL_VERSION: u32 = 1;
+pub const MAX_ROOM_SIZE: usize = 16;
+// Frames between board snapshots.
+pub const BOARD_INTERVAL: u64 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Targeting {
+    Random,
+    // Everyone currently targeting you.
+    Attackers,
+    // The opponent with the highest stack.
+    Kos,
+    // The opponent with the most badges.
+    Badges
+}
+
+impl Targeting {
+    pub const ALL: [Targeting; 4] = [Targeting::Random, Targeting::Attackers, Targeting::Kos, Targeting::Badges];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Targeting::Random => "random",
+            Targeting::Attackers => "attackers",
+            Targeting::Kos => "kos",
+            Targeting::Badges => "badges"
+        }
+    }
+    pub fn parse(name: &str) -> Result<Targeting, String> {
+        Targeting::ALL.iter().copied().find(|t| t.name() == name).ok_or(format!("unknown targeting '{}'", name))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RoomMessage {
+    Join { version: u32 },
+    // Sent to everyone once the room is full and the game starts.
+    Welcome { version: u32, player: usize, players: usize, seed: u64 },
+    Error(String),
+    Attack { lines: u16 },
+    Target(Targeting),
+    // Board rows from top to bottom in text notation, separated by `/`.
+    Board { player: usize, rows: String },
+    Dead,
+    Garbage { from: usize, lines: u16 },
+    Eliminated { player: usize, by: Option<usize>, place: usize },
+    Winner { player: usize },
+    Bye
+}
+
+impl Wire for RoomMessage {
+    fn encode(&self) -> String {
+        match self {
+            RoomMessage::Join { version } => format!("join {}", version),
+            RoomMessage::Welcome { version, player, players, seed } => format!("welcome {} {} {} {}", version, player, players, seed),
+            RoomMessage::Error(text) => format!("error {}", text),
+            RoomMessage::Attack { lines } => format!("attack {}", lines),
+            RoomMessage::Target(targeting) => format!("target {}", targeting.name()),
+            RoomMessage::Board { player, rows } => format!("board {} {}", player, rows),
+            RoomMessage::Dead => String::from("dead"),
+            RoomMessage::Garbage { from, lines } => format!("garbage {} {}", from, lines),
+            RoomMessage::Eliminated { player, by, place } => {
+                format!("eliminated {} {} {}", player, by.map(|b| b.to_string()).unwrap_or(String::from("-")), place)
+            }
+            RoomMessage::Winner { player } => format!("winner {}", player),
+            RoomMessage::Bye => String::from("bye")
+        }
+    }
+
+    fn parse(line: &str) -> Result<RoomMessage, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let word = |i: usize| words.get(i).copied().ok_or(format!("missing field in message '{}'", line));
+        let field = |i: usize| -> Result<u64, String> {
+            let w = word(i)?;
+            w.parse::<u64>().map_err(|e| format!("invalid field '{}' in message '{}': {}", w, line, e))
+        };
+        match words.first().copied() {
+            Some("join") => Ok(RoomMessage::Join { version: field(1)? as u32 }),
+            Some("welcome") => Ok(RoomMessage::Welcome { version: field(1)? as u32, player: field(2)? as usize, players: field(3)? as usize, seed: field(4)? }),
+            Some("error") => Ok(RoomMessage::Error(line.trim()["error".len()..].trim().to_string())),
+            Some("attack") => Ok(RoomMessage::Attack { lines: field(1)? as u16 }),
+            Some("target") => Ok(RoomMessage::Target(Targeting::parse(word(1)?)?)),
+            Some("board") => Ok(RoomMessage::Board { player: field(1)? as usize, rows: word(2)?.to_string() }),
+            Some("dead") => Ok(RoomMessage::Dead),
+            Some("garbage") => Ok(RoomMessage::Garbage { from: field(1)? as usize, lines: field(2)? as u16 }),
+            Some("eliminated") => {
+                let by = if word(2)? == "-" { None } else { Some(field(2)? as usize) };
+                Ok(RoomMessage::Eliminated { player: field(1)? as usize, by, place: field(3)? as usize })
+            }
+            Some("winner") => Ok(RoomMessage::Winner { player: field(1)? as usize }),
+            Some("bye") => Ok(RoomMessage::Bye),
+            _ => Err(format!("unknown message '{}'", line))
+        }
+    }
+}
+
+pub fn encode_board(canvas: &TileCanvas) -> String {
+    (0..canvas.height())
+        .map(|y| (0..canvas.width()).map(|x| notation::tile_letter(canvas.get_tile(x, y))).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub fn decode_board(rows: &str, width: u16, height: u16) -> Result<TileCanvas, String> {
+    notation::parse_board(&rows.replace('/', "\n"), width, height)
+}
+
+// Rows from the highest tile down to the floor.
+fn stack_height(rows: &str) -> u16 {
+    let rows: Vec<&str> = rows.split('/').collect();
+    let top = rows.iter().position(|row| row.chars().any(|c| c != '.')).unwrap_or(rows.len());
+    (rows.len() - top) as u16
+}
+
+#[derive(Clone, Debug)]
+pub struct RoomPlayer {
+    pub alive: bool,
+    pub targeting: Targeting,
+    pub badges: u32,
+    pub height: u16,
+    // Whoever this player attacked last.
+    targets: Vec<usize>,
+    last_attacker: Option<usize>
+}
+
+// The server side bookkeeping for who attacks whom.
+pub struct Room {
+    pub players: Vec<RoomPlayer>,
+    rng: StdRng
+}
+
+impl Room {
+    pub fn new(size: usize, seed: u64) -> Room {
+        let player = RoomPlayer { alive: true, targeting: Targeting::Random, badges: 0, height: 0, targets: Vec::new(), last_attacker: None };
+        Room { players: vec![player; size], rng: StdRng::seed_from_u64(seed) }
+    }
+    pub fn alive(&self) -> usize {
+        self.players.iter().filter(|p| p.alive).count()
+    }
+    pub fn winner(&self) -> Option<usize> {
+        if self.alive() == 1 { self.players.iter().position(|p| p.alive) } else { None }
+    }
+
+    // The opponents `from` sends its next attack to.
+    pub fn targets(&mut self, from: usize) -> Vec<usize> {
+        let opponents: Vec<usize> = (0..self.players.len()).filter(|&i| i != from && self.players[i].alive).collect();
+        if opponents.is_empty() {
+            return Vec::new();
+        }
+        let best = |score: &dyn Fn(&RoomPlayer) -> u32| -> Vec<usize> {
+            let max = opponents.iter().map(|&i| score(&self.players[i])).max().unwrap_or(0);
+            opponents.iter().copied().filter(|&i| score(&self.players[i]) == max).collect()
+        };
+        let candidates = match self.players[from].targeting {
+            Targeting::Random => opponents.clone(),
+            Targeting::Attackers => {
+                let attackers: Vec<usize> = opponents.iter().copied().filter(|&i| self.players[i].targets.contains(&from)).collect();
+                if !attackers.is_empty() {
+                    self.players[from].targets = attackers.clone();
+                    return attackers;
+                }
+                opponents.clone()
+            }
+            Targeting::Kos => best(&|p| p.height as u32),
+            Targeting::Badges => best(&|p| p.badges),
+        };
+        let target = candidates[self.rng.gen_range(0..candidates.len())];
+        self.players[from].targets = vec![target];
+        vec![target]
+    }
+
+    pub fn attack(&mut self, from: usize, lines: u16) -> Vec<(usize, u16)> {
+        if lines == 0 || !self.players[from].alive {
+            return Vec::new();
+        }
+        let targets = self.targets(from);
+        for &target in targets.iter() {
+            self.players[target].last_attacker = Some(from);
+        }
+        targets.into_iter().map(|target| (target, lines)).collect()
+    }
+
+    // Knocks `player` out and credits a badge to the last player that attacked
+    // it. Returns who gets the KO and the place `player` finished in.
+    pub fn eliminate(&mut self, player: usize) -> Option<(Option<usize>, usize)> {
+        if !self.players[player].alive {
+            return None;
+        }
+        self.players[player].alive = false;
+        let by = self.players[player].last_attacker.filter(|&by| self.players[by].alive);
+        if let Some(by) = by {
+            self.players[by].badges += 1;
+        }
+        Some((by, self.alive() + 1))
+    }
+}
+
+fn accept_player(listener: &TcpListener) -> Connection {
+    loop {
+        let (mut connection, join) = net::accept(listener);
+        match join {
+            Ok(RoomMessage::Join { version }) if version == ROOM_PROTOCOL_VERSION => return connection,
+            Ok(RoomMessage::Join { version }) => {
+                let error = format!("room protocol version {} is not supported, the server speaks {}", version, ROOM_PROTOCOL_VERSION);
+                let _ = connection.send(&RoomMessage::Error(error));
+            }
+            _ => {
+                let _ = connection.send(&RoomMessage::Error(String::from("expected join")));
+            }
+        }
+    }
+}
+
+// Hosts rooms of `size` players one after the other.
+pub fn serve_rooms(listener: TcpListener, size: usize) -> Result<(), String> {
+    loop {
+        serve_room(&listener, size, rand::random())?;
+    }
+}
+
+// Waits for `size` players, then runs the room until there is a winner or
+// everyone has left.
+pub fn serve_room(listener: &TcpListener, size: usize, seed: u64) -> Result<(), String> {
+    if !(2..=MAX_ROOM_SIZE).contains(&size) {
+        return Err(format!("rooms hold 2 to {} players", MAX_ROOM_SIZE));
+    }
+    let mut connections = Vec::new();
+    while connections.len() < size {
+        connections.push(accept_player(listener));
+    }
+    let (sender, receiver) = mpsc::channel();
+    let mut writers = Vec::new();
+    for (player, mut connection) in connections.into_iter().enumerate() {
+        // Players that left while the room filled up are knocked out at once.
+        let welcome = RoomMessage::Welcome { version: ROOM_PROTOCOL_VERSION, player, players: size, seed };
+        match connection.send(&welcome).and_then(|_| connection.forward(player, sender.clone())) {
+            Ok(writer) => writers.push(Some(writer)),
+            Err(error) => {
+                writers.push(None);
+                let _ = sender.send((player, Err(error)));
+            }
+        }
+    }
+
+    let mut room = Room::new(size, seed);
+    let send = |writers: &mut [Option<TcpStream>], to: usize, message: &RoomMessage| {
+        if let Some(writer) = writers[to].as_mut() {
+            if net::write_message(writer, message).is_err() {
+                writers[to] = None;
+            }
+        }
+    };
+    while writers.iter().any(|w| w.is_some()) {
+        let (player, message) = receiver.recv().map_err(|e| e.to_string())?;
+        match message {
+            Ok(RoomMessage::Attack { lines }) => {
+                for (target, lines) in room.attack(player, lines) {
+                    send(&mut writers, target, &RoomMessage::Garbage { from: player, lines });
+                }
+            }
+            Ok(RoomMessage::Target(targeting)) => room.players[player].targeting = targeting,
+            Ok(RoomMessage::Board { rows, .. }) => {
+                room.players[player].height = stack_height(&rows);
+                let board = RoomMessage::Board { player, rows };
+                for to in (0..size).filter(|&to| to != player) {
+                    send(&mut writers, to, &board);
+                }
+            }
+            Ok(RoomMessage::Dead) | Ok(RoomMessage::Bye) | Err(_) => {
+                if !matches!(message, Ok(RoomMessage::Dead)) {
+                    writers[player] = None;
+                }
+                if let Some((by, place)) = room.eliminate(player) {
+                    for to in 0..size {
+                        send(&mut writers, to, &RoomMessage::Eliminated { player, by, place });
+                    }
+                    if let Some(winner) = room.winner() {
+                        for to in 0..size {
+                            send(&mut writers, to, &RoomMessage::Winner { player: winner });
+                        }
+                        return Ok(());
+                    }
+                }
+            }
+            Ok(_) => {}
+        }
+    }
+    Ok(())
+}
+
+pub struct RoomClient {
+    writer: TcpStream,
+    receiver: Receiver<(usize, Result<RoomMessage, String>)>,
+    id: usize,
+    pub player: Player,
+    pub table: AttackTable,
+    targeting: Targeting,
+    // The latest snapshot of every board, ours excluded.
+    boards: Vec<Option<TileCanvas>>,
+    alive: Vec<bool>,
+    badges: Vec<u32>,
+    eliminations: Vec<(usize, Option<usize>, usize)>,
+    winner: Option<usize>,
+    reported_dead: bool,
+    frames: u64
+}
+
+impl RoomClient {
+    // Joins a room and waits until it is full. `game` supplies the board size,
+    // gravity and timings; the pieces come from the room's seed.
+    pub fn join(addr: &str, game: Game) -> Result<RoomClient, String> {
+        let stream = TcpStream::connect(addr).map_err(|e| format!("{}: {}", addr, e))?;
+        let mut connection = Connection::new(stream)?;
+        connection.send(&RoomMessage::Join { version: ROOM_PROTOCOL_VERSION })?;
+        let (id, players, seed) = match connection.recv()? {
+            RoomMessage::Welcome { version, player, players, seed } if version == ROOM_PROTOCOL_VERSION && player < players => (player, players, seed),
+            RoomMessage::Error(error) => return Err(error),
+            message => return Err(format!("unexpected message '{}'", message.encode()))
+        };
+        let (sender, receiver) = mpsc::channel();
+        let writer = connection.forward(0, sender)?;
+        let mut game = game;
+        let canvas = &game.tetris.tile_canvas;
+        game.set_tetris(Tetris::new(TileCanvas::new(canvas.width(), canvas.height()), seed));
+        let player = Player::new(game, versus::VERSUS_MESSINESS, seed.wrapping_add(id as u64 + 1));
+        Ok(RoomClient { writer, receiver, id, player, table: AttackTable::default(), targeting: Targeting::Random,
+                        boards: vec![None; players], alive: vec![true; players], badges: vec![0; players],
+                        eliminations: Vec::new(), winner: None, reported_dead: false, frames: 0 })
+    }
+    pub fn id(&self) -> usize {
+        self.id
+    }
+    pub fn players(&self) -> usize {
+        self.alive.len()
+    }
+    pub fn alive(&self, player: usize) -> bool {
+        self.alive[player]
+    }
+    pub fn alive_count(&self) -> usize {
+        self.alive.iter().filter(|&&a| a).count()
+    }
+    pub fn board(&self, player: usize) -> Option<&TileCanvas> {
+        self.boards[player].as_ref()
+    }
+    pub fn badges(&self, player: usize) -> u32 {
+        self.badges[player]
+    }
+    pub fn targeting(&self) -> Targeting {
+        self.targeting
+    }
+    // (player, KO'd by, place) in the order players were knocked out.
+    pub fn eliminations(&self) -> &[(usize, Option<usize>, usize)] {
+        &self.eliminations
+    }
+    pub fn place(&self) -> Option<usize> {
+        if self.winner == Some(self.id) {
+            return Some(1);
+        }
+        self.eliminations.iter().find(|e| e.0 == self.id).map(|e| e.2)
+    }
+    pub fn winner(&self) -> Option<usize> {
+        self.winner
+    }
+
+    pub fn set_targeting(&mut self, targeting: Targeting) -> Result<(), String> {
+        self.targeting = targeting;
+        net::write_message(&mut self.writer, &RoomMessage::Target(targeting))
+    }
+
+    // Handles what the server sent since the last frame, then plays one frame
+    // unless we are out or the room is over.
+    pub fn step(&mut self, input: Input) -> Result<Step, String> {
+        // The server hangs up once the room is over.
+        loop {
+            match self.receiver.try_recv() {
+                Ok((_, Ok(message))) => self.handle(message),
+                Ok((_, Err(_))) | Err(TryRecvError::Disconnected) if self.winner.is_some() => break,
+                Ok((_, Err(e))) => return Err(e),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err(String::from("connection closed"))
+            }
+        }
+        if self.winner.is_some() || self.reported_dead {
+            return Ok(Step::default());
+        }
+        let (step, attack) = self.player.step(input, &self.table);
+        if attack > 0 {
+            net::write_message(&mut self.writer, &RoomMessage::Attack { lines: attack })?;
+        }
+        self.frames += 1;
+        if self.frames.is_multiple_of(BOARD_INTERVAL) || self.player.game.is_over() {
+            let rows = encode_board(&self.player.game.tetris.tile_canvas);
+            net::write_message(&mut self.writer, &RoomMessage::Board { player: self.id, rows })?;
+        }
+        if self.player.game.is_over() {
+            self.reported_dead = true;
+            net::write_message(&mut self.writer, &RoomMessage::Dead)?;
+        }
+        Ok(step)
+    }
+
+    fn handle(&mut self, message: RoomMessage) {
+        match message {
+            RoomMessage::Garbage { lines, .. } => self.player.receive(lines),
+            RoomMessage::Board { player, rows } if player < self.boards.len() => {
+                let canvas = &self.player.game.tetris.tile_canvas;
+                self.boards[player] = decode_board(&rows, canvas.width(), canvas.height()).ok();
+            }
+            RoomMessage::Eliminated { player, by, place } if player < self.alive.len() => {
+                self.alive[player] = false;
+                if let Some(by) = by.filter(|&by| by < self.badges.len()) {
+                    self.badges[by] += 1;
+                }
+                self.eliminations.push((player, by, place));
+            }
+            RoomMessage::Winner { player } => self.winner = Some(player),
+            _ => {}
+        }
+    }
+
+    pub fn close(mut self) {
+        let _ = net::write_message(&mut self.writer, &RoomMessage::Bye);
+    }
+}
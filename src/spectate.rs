@@ -0,0 +1,372 @@
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::game::*;
+use crate::net::{self, Connection, Wire};
+use crate::notation;
+use crate::tetrust::*;
+
+// Spectating: a player opts in to broadcasting their game and anyone can connect
+// to watch. The stream only goes one way; after a hello with the board size it
+// carries the cells that changed each frame, the falling piece, the queue and
+// the game's events.
+
+pub const SPECTATE_PROTOCOL_VERSION: u32 = 2;
+pub const DEFAULT_SPECTATE_PORT: u16 = 7879;
+// Events a spectator keeps around for display.
+pub const EVENT_HISTORY: usize = 8;
+// Frames a spectator can fall behind by before it is dropped.
+pub const SPECTATOR_BACKLOG: usize = 600;
+// How long a write may hold up a spectator's thread.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpectateMessage {
+    // Starts a new empty board, both when a spectator connects and when the
+    // broadcaster's board changes size.
+    Hello { version: u32, width: u16, height: u16 },
+    // Cells as `x,y,tile` with the tile in text notation.
+    Cells(Vec<(u16, u16, TileColor)>),
+    Piece(TetrisPiece),
+    Queue { hold: Option<PieceEnum>, next: Vec<PieceEnum> },
+    Score(u32),
+    Event(Event),
+    Bye
+}
+
+fn tspin_name(tspin: TSpin) -> &'static str {
+    match tspin {
+        TSpin::None => "none",
+        TSpin::Mini => "mini",
+        TSpin::Full => "full"
+    }
+}
+
+fn parse_tspin(name: &str) -> Result<TSpin, String> {
+    match name {
+        "none" => Ok(TSpin::None),
+        "mini" => Ok(TSpin::Mini),
+        "full" => Ok(TSpin::Full),
+        _ => Err(format!("unknown t-spin '{}'", name))
+    }
+}
+
+fn encode_piece(piece: &TetrisPiece) -> String {
+    format!("{} {} {} {}", notation::piece_letter(piece.piece), piece.x, piece.y, piece.state)
+}
+
+fn encode_event(event: &Event) -> String {
+    match event {
+        Event::Pressed(input) => format!("press {}", input.bits()),
+        Event::Spawned(piece) => format!("spawn {}", notation::piece_letter(*piece)),
+        Event::Held(piece) => format!("hold {}", notation::piece_letter(*piece)),
+        Event::Locked(piece, clear) => {
+            format!("lock {} {} {} {}", encode_piece(piece), clear.lines, tspin_name(clear.tspin), clear.perfect as u8)
+        }
+        Event::Garbage(lines) => format!("garbage {}", lines),
+        Event::ToppedOut => String::from("topout")
+    }
+}
+
+impl Wire for SpectateMessage {
+    fn encode(&self) -> String {
+        match self {
+            SpectateMessage::Hello { version, width, height } => format!("hello {} {} {}", version, width, height),
+            SpectateMessage::Cells(cells) => {
+                let cells: Vec<String> = cells.iter().map(|&(x, y, color)| format!("{},{},{}", x, y, notation::tile_letter(color))).collect();
+                format!("cells {}", cells.join(" "))
+            }
+            SpectateMessage::Piece(piece) => format!("piece {}", encode_piece(piece)),
+            SpectateMessage::Queue { hold, next } => {
+                let hold = hold.map(notation::piece_letter).unwrap_or('-');
+                let next: String = next.iter().map(|&p| notation::piece_letter(p)).collect();
+                format!("queue {} {}", hold, if next.is_empty() { String::from("-") } else { next })
+            }
+            SpectateMessage::Score(score) => format!("score {}", score),
+            SpectateMessage::Event(event) => format!("event {}", encode_event(event)),
+            SpectateMessage::Bye => String::from("bye")
+        }
+    }
+
+    fn parse(line: &str) -> Result<SpectateMessage, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let word = |i: usize| words.get(i).copied().ok_or(format!("missing field in message '{}'", line));
+        let field = |i: usize| -> Result<i64, String> {
+            let w = word(i)?;
+            w.parse::<i64>().map_err(|e| format!("invalid field '{}' in message '{}': {}", w, line, e))
+        };
+        let piece = |i: usize| -> Result<PieceEnum, String> {
+            let w = word(i)?;
+            w.chars().next().and_then(notation::parse_piece).ok_or(format!("invalid piece '{}' in message '{}'", w, line))
+        };
+        let tetris_piece = |i: usize| -> Result<TetrisPiece, String> {
+            Ok(TetrisPiece { piece: piece(i)?, x: field(i + 1)? as i16, y: field(i + 2)? as i16, state: field(i + 3)? as u8 % 4 })
+        };
+        match words.first().copied() {
+            Some("hello") => Ok(SpectateMessage::Hello { version: field(1)? as u32, width: field(2)? as u16, height: field(3)? as u16 }),
+            Some("cells") => {
+                let cells = words[1..].iter().map(|cell| {
+                    let parts: Vec<&str> = cell.split(',').collect();
+                    let invalid = || format!("invalid cell '{}' in message '{}'", cell, line);
+                    match parts.as_slice() {
+                        [x, y, tile] => {
+                            let x = x.parse::<u16>().map_err(|_| invalid())?;
+                            let y = y.parse::<u16>().map_err(|_| invalid())?;
+                            let color = tile.chars().next().and_then(notation::parse_tile).ok_or_else(invalid)?;
+                            Ok((x, y, color))
+                        }
+                        _ => Err(invalid())
+                    }
+                }).collect::<Result<Vec<_>, String>>()?;
+                Ok(SpectateMessage::Cells(cells))
+            }
+            Some("piece") => Ok(SpectateMessage::Piece(tetris_piece(1)?)),
+            Some("queue") => {
+                let hold = if word(1)? == "-" { None } else { Some(piece(1)?) };
+                let next = word(2)?;
+                let next = if next == "-" {
+                    Vec::new()
+                } else {
+                    next.chars().map(|c| notation::parse_piece(c).ok_or(format!("invalid piece '{}' in message '{}'", c, line))).collect::<Result<_, _>>()?
+                };
+                Ok(SpectateMessage::Queue { hold, next })
+            }
+            Some("score") => Ok(SpectateMessage::Score(field(1)? as u32)),
+            Some("event") => {
+                let event = match word(1)? {
+                    "press" => Event::Pressed(Input::from_bits(field(2)? as u8)),
+                    "spawn" => Event::Spawned(piece(2)?),
+                    "hold" => Event::Held(piece(2)?),
+                    "lock" => Event::Locked(tetris_piece(2)?, Clear { lines: field(6)? as u16, tspin: parse_tspin(word(7)?)?, perfect: field(8)? != 0 }),
+                    "garbage" => Event::Garbage(field(2)? as u16),
+                    "topout" => Event::ToppedOut,
+                    other => return Err(format!("unknown event '{}'", other))
+                };
+                Ok(SpectateMessage::Event(event))
+            }
+            Some("bye") => Ok(SpectateMessage::Bye),
+            _ => Err(format!("unknown message '{}'", line))
+        }
+    }
+}
+
+// The part of a game spectators see, to tell what changed between frames.
+#[derive(Clone, Debug)]
+struct View {
+    board: TileCanvas,
+    piece: TetrisPiece,
+    hold: Option<PieceEnum>,
+    next: Vec<PieceEnum>,
+    score: u32
+}
+
+impl View {
+    fn of(game: &Game) -> View {
+        let tetris = &game.tetris;
+        View { board: tetris.tile_canvas.clone(), piece: tetris.active_piece(), hold: tetris.hold(), next: tetris.queue.peek().copied().collect(),
+               score: tetris.score() }
+    }
+
+    // Everything needed to bring a spectator showing `before` up to this view.
+    fn messages(&self, before: Option<&View>) -> Vec<SpectateMessage> {
+        let board = &self.board;
+        let mut messages = Vec::new();
+        let before = before.filter(|b| b.board.width() == board.width() && b.board.height() == board.height());
+        let cells = match before {
+            Some(before) => before.board.diff(board),
+            None => {
+                messages.push(SpectateMessage::Hello { version: SPECTATE_PROTOCOL_VERSION, width: board.width(), height: board.height() });
+                TileCanvas::new(board.width(), board.height()).diff(board)
+            }
+        };
+        if !cells.is_empty() {
+            messages.push(SpectateMessage::Cells(cells));
+        }
+        if before.map(|b| b.piece) != Some(self.piece) {
+            messages.push(SpectateMessage::Piece(self.piece));
+        }
+        if before.map(|b| (b.hold, &b.next)) != Some((self.hold, &self.next)) {
+            messages.push(SpectateMessage::Queue { hold: self.hold, next: self.next.clone() });
+        }
+        if before.map(|b| b.score) != Some(self.score) {
+            messages.push(SpectateMessage::Score(self.score));
+        }
+        messages
+    }
+}
+
+// A spectator's connection. Frames are written from a thread of its own, so a
+// slow spectator can't hold up the game.
+struct Feed {
+    sender: SyncSender<Vec<SpectateMessage>>,
+    thread: JoinHandle<()>
+}
+
+impl Feed {
+    fn start(mut stream: TcpStream) -> Result<Feed, String> {
+        stream.set_nonblocking(false).and_then(|_| stream.set_nodelay(true)).and_then(|_| stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+            .map_err(|e| e.to_string())?;
+        let (sender, receiver) = mpsc::sync_channel::<Vec<SpectateMessage>>(SPECTATOR_BACKLOG);
+        let thread = std::thread::spawn(move || {
+            for messages in receiver {
+                if !messages.iter().all(|message| net::write_message(&mut stream, message).is_ok()) {
+                    return;
+                }
+            }
+        });
+        Ok(Feed { sender, thread })
+    }
+    // Queues a frame. Fails once the spectator went away or fell too far
+    // behind.
+    fn send(&self, messages: Vec<SpectateMessage>) -> bool {
+        self.sender.try_send(messages).is_ok()
+    }
+}
+
+// Streams a game to whoever connects. Call `update` once per frame.
+pub struct Broadcaster {
+    listener: TcpListener,
+    spectators: Vec<Feed>,
+    view: Option<View>
+}
+
+impl Broadcaster {
+    pub fn bind(addr: &str) -> Result<Broadcaster, String> {
+        let listener = TcpListener::bind(addr).map_err(|e| format!("{}: {}", addr, e))?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        Ok(Broadcaster { listener, spectators: Vec::new(), view: None })
+    }
+    pub fn local_addr(&self) -> Result<String, String> {
+        self.listener.local_addr().map(|a| a.to_string()).map_err(|e| e.to_string())
+    }
+    pub fn spectators(&self) -> usize {
+        self.spectators.len()
+    }
+
+    // Sends what changed since the last frame and the frame's events, and
+    // catches up spectators that just connected. Spectators that went away
+    // or can't keep up are dropped.
+    pub fn update(&mut self, game: &Game) -> Result<(), String> {
+        let view = View::of(game);
+        let mut messages = view.messages(self.view.as_ref());
+        messages.extend(game.events().iter().map(|&event| SpectateMessage::Event(event)));
+        self.spectators.retain(|feed| feed.send(messages.clone()));
+
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    let feed = Feed::start(stream)?;
+                    if feed.send(view.messages(None)) {
+                        self.spectators.push(feed);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.to_string())
+            }
+        }
+        self.view = Some(view);
+        Ok(())
+    }
+
+    // Says goodbye and waits for what is queued to go out.
+    pub fn close(self) {
+        for feed in self.spectators {
+            feed.send(vec![SpectateMessage::Bye]);
+            drop(feed.sender);
+            let _ = feed.thread.join();
+        }
+    }
+}
+
+// A read-only copy of a broadcast game, rebuilt from the stream.
+pub struct Spectator {
+    receiver: Receiver<(usize, Result<SpectateMessage, String>)>,
+    pub tetris: Tetris,
+    score: u32,
+    events: Vec<Event>,
+    over: bool
+}
+
+impl Spectator {
+    // Connects and waits for the broadcaster's hello.
+    pub fn connect(addr: &str) -> Result<Spectator, String> {
+        let stream = TcpStream::connect(addr).map_err(|e| format!("{}: {}", addr, e))?;
+        let mut connection = Connection::new(stream)?;
+        let (width, height) = match connection.recv()? {
+            SpectateMessage::Hello { version, width, height } if version == SPECTATE_PROTOCOL_VERSION => (width, height),
+            SpectateMessage::Hello { version, .. } => {
+                return Err(format!("protocol version {} is not supported, we speak {}", version, SPECTATE_PROTOCOL_VERSION))
+            }
+            message => return Err(format!("unexpected message '{}'", message.encode()))
+        };
+        let (sender, receiver) = mpsc::channel();
+        connection.forward(0, sender)?;
+        Ok(Spectator { receiver, tetris: Spectator::empty(width, height), score: 0, events: Vec::new(), over: false })
+    }
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+    // The latest events, oldest first.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+    pub fn is_over(&self) -> bool {
+        self.over
+    }
+
+    fn empty(width: u16, height: u16) -> Tetris {
+        Tetris::with_queue(TileCanvas::new(width, height), PieceQueue::fixed(Vec::new()))
+    }
+
+    // Applies everything received since the last call. Fails once the
+    // broadcast ends.
+    pub fn update(&mut self) -> Result<(), String> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok((_, Ok(SpectateMessage::Bye))) => return Err(String::from("broadcast ended")),
+                Ok((_, Ok(message))) => self.handle(message),
+                Ok((_, Err(e))) => return Err(e),
+                Err(TryRecvError::Empty) => return Ok(()),
+                Err(TryRecvError::Disconnected) => return Err(String::from("connection closed"))
+            }
+        }
+    }
+
+    fn handle(&mut self, message: SpectateMessage) {
+        let canvas = &mut self.tetris.tile_canvas;
+        match message {
+            SpectateMessage::Hello { width, height, .. } => {
+                self.tetris = Spectator::empty(width, height);
+                self.over = false;
+            }
+            SpectateMessage::Cells(cells) => {
+                for (x, y, color) in cells {
+                    if x < canvas.width() && y < canvas.height() {
+                        canvas.set_tile(x, y, color);
+                    }
+                }
+            }
+            SpectateMessage::Piece(piece) => self.tetris.place_piece(piece, None),
+            SpectateMessage::Queue { hold, next } => {
+                self.tetris.set_hold(hold);
+                self.tetris.queue = PieceQueue::fixed(next);
+            }
+            SpectateMessage::Score(score) => self.score = score,
+            SpectateMessage::Event(event) => {
+                match event {
+                    Event::Spawned(_) => self.over = false,
+                    Event::ToppedOut => self.over = true,
+                    _ => {}
+                }
+                self.events.push(event);
+                if self.events.len() > EVENT_HISTORY {
+                    self.events.remove(0);
+                }
+            }
+            SpectateMessage::Bye => {}
+        }
+    }
+}
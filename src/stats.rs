@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+
+use crate::game::*;
+use crate::modes::versus::{self, AttackTable};
+use crate::tetrust::*;
+
+// Running statistics of a game, worked out from its events so they come out
+// the same wherever the game is followed from. Attack is what the clears
+// would send in versus, so solo games can be compared with it too.
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub frames: u64,
+    pub pieces: u32,
+    // Button presses; holding a button down counts once.
+    pub keys: u32,
+    pub lines: u32,
+    pub attack: u32,
+    // Clears without a T-spin, indexed by lines.
+    pub clears: [u32; 5],
+    // T-spins, including those that clear nothing, indexed by lines.
+    pub tspins: [u32; 4],
+    pub tspin_minis: [u32; 3],
+    pub perfect_clears: u32,
+    // Clears in a row before the last one, as in versus.
+    pub combo: Option<u32>,
+    pub max_combo: u32,
+    pub back_to_back: bool,
+    // Locked pieces in `PieceEnum::ALL` order.
+    pub pieces_by_type: [u32; 7],
+    #[serde(skip)]
+    table: AttackTable
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats::default()
+    }
+
+    // Call after every frame of the game.
+    pub fn update(&mut self, game: &Game) {
+        self.frames = game.frames();
+        for event in game.events() {
+            match *event {
+                Event::Pressed(input) => self.keys += input.bits().count_ones(),
+                Event::Locked(piece, clear) => self.lock(piece.piece, &clear),
+                _ => {}
+            }
+        }
+    }
+
+    fn lock(&mut self, piece: PieceEnum, clear: &Clear) {
+        self.pieces += 1;
+        if let Some(i) = PieceEnum::ALL.iter().position(|&p| p == piece) {
+            self.pieces_by_type[i] += 1;
+        }
+        let lines = clear.lines as usize;
+        let count = match clear.tspin {
+            TSpin::Full => self.tspins.get_mut(lines),
+            TSpin::Mini => self.tspin_minis.get_mut(lines),
+            TSpin::None if lines > 0 => self.clears.get_mut(lines),
+            TSpin::None => None
+        };
+        if let Some(count) = count {
+            *count += 1;
+        }
+        if lines == 0 {
+            self.combo = None;
+            return;
+        }
+        self.lines += lines as u32;
+        if clear.perfect {
+            self.perfect_clears += 1;
+        }
+        let combo = self.combo.map(|c| c + 1).unwrap_or(0);
+        let difficult = versus::is_difficult(clear);
+        self.attack += self.table.attack(clear, combo, difficult && self.back_to_back) as u32;
+        self.combo = Some(combo);
+        self.max_combo = self.max_combo.max(combo);
+        self.back_to_back = difficult;
+    }
+
+    fn minutes(&self) -> f64 {
+        self.frames as f64 / 3600.0
+    }
+    // Pieces per second.
+    pub fn pps(&self) -> f64 {
+        if self.frames > 0 { self.pieces as f64 * 60.0 / self.frames as f64 } else { 0.0 }
+    }
+    // Attack per minute.
+    pub fn apm(&self) -> f64 {
+        if self.frames > 0 { self.attack as f64 / self.minutes() } else { 0.0 }
+    }
+    // Keys per piece.
+    pub fn kpp(&self) -> f64 {
+        if self.pieces > 0 { self.keys as f64 / self.pieces as f64 } else { 0.0 }
+    }
+}
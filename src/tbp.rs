@@ -0,0 +1,437 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bot::{Controller, Driver, Plan};
+use crate::game::*;
+use crate::movegen::{self, Action, Placement};
+use crate::tetrust::*;
+
+// The Tetris Bot Protocol, which external bots such as Cold Clear speak: one
+// JSON message per line on the bot's stdin and stdout. The frontend sends the
+// rules and the state of the game, asks for a move whenever a piece spawns and
+// tells the bot which move was played and which pieces appear in the preview.
+
+// Rows in a TBP board, counted from the bottom.
+pub const BOARD_HEIGHT: usize = 40;
+// Bots only know the standard board, though they are told about the rows above it.
+pub const BOARD_WIDTH: u16 = 10;
+pub const VISIBLE_HEIGHT: u16 = 20;
+// How long a bot gets to introduce itself and to get ready.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Cell {
+    I, O, T, L, J, S, Z,
+    // Garbage, or anything else that isn't a piece.
+    G
+}
+
+impl Cell {
+    fn from_color(color: TileColor) -> Option<Cell> {
+        match color {
+            TileColor::Empty => None,
+            TileColor::Cyan => Some(Cell::I),
+            TileColor::Yellow => Some(Cell::O),
+            TileColor::Purple => Some(Cell::T),
+            TileColor::Orange => Some(Cell::L),
+            TileColor::Blue => Some(Cell::J),
+            TileColor::Green => Some(Cell::S),
+            TileColor::Red => Some(Cell::Z),
+            TileColor::Gray => Some(Cell::G)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    North, East, South, West
+}
+
+impl Orientation {
+    pub const ALL: [Orientation; 4] = [Orientation::North, Orientation::East, Orientation::South, Orientation::West];
+
+    // The rotation state of the same orientation in the engine.
+    pub fn state(&self) -> u8 {
+        Orientation::ALL.iter().position(|o| o == self).unwrap_or(0) as u8
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Spin {
+    None, Mini, Full
+}
+
+impl Spin {
+    pub fn tspin(&self) -> TSpin {
+        match self {
+            Spin::None => TSpin::None,
+            Spin::Mini => TSpin::Mini,
+            Spin::Full => TSpin::Full
+        }
+    }
+}
+
+// Where a piece ends up: the position of its centre cell with y counting up
+// from the bottom row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    #[serde(rename = "type")]
+    pub piece: PieceEnum,
+    pub orientation: Orientation,
+    pub x: i16,
+    pub y: i16
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Move {
+    pub location: Location,
+    pub spin: Spin
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Start {
+    pub hold: Option<PieceEnum>,
+    // The active piece first, then the preview.
+    pub queue: Vec<PieceEnum>,
+    pub combo: u32,
+    pub back_to_back: bool,
+    // `BOARD_HEIGHT` rows of `BOARD_WIDTH` cells, bottom row first.
+    pub board: Vec<Vec<Option<Cell>>>
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FrontendMessage {
+    Rules,
+    Start(Start),
+    Stop,
+    Suggest,
+    Play {
+        #[serde(rename = "move")]
+        mv: Move
+    },
+    NewPiece { piece: PieceEnum },
+    Quit
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotMessage {
+    Info { name: String, version: String, author: String, features: Vec<String> },
+    Ready,
+    Error { reason: String },
+    Suggestion { moves: Vec<Move> },
+    // Messages from newer versions of the protocol are skipped.
+    #[serde(other)]
+    Unknown
+}
+
+// Cell offsets from the centre of each piece facing north, y up.
+fn offsets(piece: PieceEnum) -> [(i16, i16); 4] {
+    match piece {
+        PieceEnum::I => [(-1, 0), (0, 0), (1, 0), (2, 0)],
+        PieceEnum::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        PieceEnum::T => [(-1, 0), (0, 0), (1, 0), (0, 1)],
+        PieceEnum::L => [(-1, 0), (0, 0), (1, 0), (1, 1)],
+        PieceEnum::J => [(-1, 0), (0, 0), (1, 0), (-1, 1)],
+        PieceEnum::S => [(-1, 0), (0, 0), (0, 1), (1, 1)],
+        PieceEnum::Z => [(-1, 1), (0, 1), (0, 0), (1, 0)]
+    }
+}
+
+// The cells of `location` in engine coordinates on a board `height` rows tall.
+fn location_cells(location: &Location, height: u16) -> [(i16, i16); 4] {
+    offsets(location.piece).map(|(mut x, mut y)| {
+        for _ in 0..location.orientation.state() {
+            let turned = (y, -x);
+            x = turned.0;
+            y = turned.1;
+        }
+        (location.x + x, height as i16 - 1 - (location.y + y))
+    })
+}
+
+fn sorted(mut cells: [(i16, i16); 4]) -> [(i16, i16); 4] {
+    cells.sort_unstable();
+    cells
+}
+
+pub fn location(piece: &TetrisPiece, height: u16) -> Location {
+    let orientation = Orientation::ALL[piece.state as usize % 4];
+    let centred = Location { piece: piece.piece, orientation, x: 0, y: 0 };
+    let (cx, cy) = sorted(location_cells(&centred, height))[0];
+    let (px, py) = sorted(piece.cells())[0];
+    Location { x: px - cx, y: cy - py, ..centred }
+}
+
+// The engine piece covering the same cells as `location`.
+pub fn piece_at(location: &Location, height: u16) -> TetrisPiece {
+    let state = location.orientation.state();
+    let (cx, cy) = sorted(location_cells(location, height))[0];
+    let (px, py) = sorted(location.piece.cells(state))[0];
+    TetrisPiece { x: cx - px, y: cy - py, piece: location.piece, state }
+}
+
+// Whether a bot can play on `canvas`.
+pub fn check_board(canvas: &TileCanvas) -> Result<(), String> {
+    let (width, height) = (canvas.width(), canvas.height());
+    if width != BOARD_WIDTH || !(VISIBLE_HEIGHT..=BOARD_HEIGHT as u16).contains(&height) {
+        return Err(format!("TBP bots need a board {} wide and {} to {} tall, not {}x{}", BOARD_WIDTH, VISIBLE_HEIGHT, BOARD_HEIGHT, width, height));
+    }
+    Ok(())
+}
+
+pub fn board(canvas: &TileCanvas) -> Vec<Vec<Option<Cell>>> {
+    let height = canvas.height();
+    (0..BOARD_HEIGHT as u16).map(|row| {
+        (0..canvas.width()).map(|x| if row < height { Cell::from_color(canvas.get_tile(x, height - 1 - row)) } else { None }).collect()
+    }).collect()
+}
+
+// The state the bot starts thinking from.
+pub fn start(tetris: &Tetris) -> Start {
+    let mut queue = vec![tetris.active_piece().piece];
+    queue.extend(tetris.queue.peek());
+    Start { hold: tetris.hold(), queue, combo: 0, back_to_back: false, board: board(&tetris.tile_canvas) }
+}
+
+pub fn encode<M: Serialize>(message: &M) -> Result<String, String> {
+    serde_json::to_string(message).map_err(|e| e.to_string())
+}
+
+// Reads messages on a background thread until the stream closes.
+pub fn forward<M: for<'de> Deserialize<'de> + Send + 'static>(reader: impl Read + Send + 'static) -> Receiver<Result<M, String>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        loop {
+            let mut line = String::new();
+            let message = match reader.read_line(&mut line) {
+                Ok(0) => Err(String::from("connection closed")),
+                Ok(_) => serde_json::from_str(&line).map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string())
+            };
+            let closed = message.is_err();
+            if sender.send(message).is_err() || closed {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+// An external bot playing through the protocol. It is asked for a move each
+// time a piece spawns and `Driver` presses the buttons to get there.
+pub struct TbpBot {
+    pub name: String,
+    pub author: String,
+    child: Option<Child>,
+    writer: Box<dyn Write + Send>,
+    receiver: Receiver<Result<BotMessage, String>>,
+    driver: Driver,
+    // What the bot believes the game looks like, if it is in step with it.
+    state: Option<Start>,
+    asked: bool,
+    // Set once the bot's move for the current piece has been played.
+    played: bool,
+    error: Option<String>
+}
+
+impl TbpBot {
+    // Runs `command`, split on whitespace, as the bot.
+    pub fn spawn(command: &str) -> Result<TbpBot, String> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or("no bot command")?;
+        let mut child = Command::new(program).args(words).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()
+            .map_err(|e| format!("{}: {}", program, e))?;
+        let (stdin, stdout) = match (child.stdin.take(), child.stdout.take()) {
+            (Some(stdin), Some(stdout)) => (stdin, stdout),
+            _ => return Err(String::from("bot has no stdin or stdout"))
+        };
+        let mut bot = TbpBot::new(stdout, stdin)?;
+        bot.child = Some(child);
+        Ok(bot)
+    }
+
+    // Talks to a bot over any pair of streams, and waits until it is ready.
+    pub fn new(reader: impl Read + Send + 'static, writer: impl Write + Send + 'static) -> Result<TbpBot, String> {
+        let mut bot = TbpBot { name: String::new(), author: String::new(), child: None, writer: Box::new(writer), receiver: forward(reader),
+                               driver: Driver::new(0), state: None, asked: false, played: false, error: None };
+        match bot.wait()? {
+            BotMessage::Info { name, version, author, .. } => {
+                bot.name = format!("{} {}", name, version);
+                bot.author = author;
+            }
+            message => return Err(format!("expected info from the bot, got {:?}", message))
+        }
+        bot.send(&FrontendMessage::Rules)?;
+        match bot.wait()? {
+            BotMessage::Ready => Ok(bot),
+            BotMessage::Error { reason } => Err(format!("bot refused the rules: {}", reason)),
+            message => Err(format!("expected ready from the bot, got {:?}", message))
+        }
+    }
+
+    fn wait(&mut self) -> Result<BotMessage, String> {
+        loop {
+            match self.receiver.recv_timeout(HANDSHAKE_TIMEOUT).map_err(|_| String::from("bot did not answer"))?? {
+                BotMessage::Unknown => continue,
+                message => return Ok(message)
+            }
+        }
+    }
+
+    fn send(&mut self, message: &FrontendMessage) -> Result<(), String> {
+        let line = encode(message)?;
+        writeln!(self.writer, "{}", line).and_then(|_| self.writer.flush()).map_err(|e| e.to_string())
+    }
+
+    // Brings the bot up to date with a new piece. Anything it doesn't expect,
+    // like garbage or a piece the driver couldn't place, restarts it from the
+    // current state.
+    fn sync(&mut self, tetris: &Tetris) -> Result<(), String> {
+        check_board(&tetris.tile_canvas)?;
+        let now = start(tetris);
+        let new_pieces = match &self.state {
+            Some(state) if state.board == now.board && state.hold == now.hold && now.queue.starts_with(&state.queue) => {
+                now.queue[state.queue.len()..].to_vec()
+            }
+            Some(_) => {
+                self.send(&FrontendMessage::Stop)?;
+                self.state = None;
+                Vec::new()
+            }
+            None => Vec::new()
+        };
+        match &self.state {
+            Some(_) => {
+                for piece in new_pieces {
+                    self.send(&FrontendMessage::NewPiece { piece })?;
+                }
+            }
+            None => self.send(&FrontendMessage::Start(now.clone()))?
+        }
+        self.state = Some(now);
+        Ok(())
+    }
+
+    // Called by the driver until there is a plan for the active piece.
+    fn choose(&mut self, tetris: &Tetris) -> Result<Option<Plan>, String> {
+        if self.played {
+            // The piece strayed from the move; drop it and start over with the next one.
+            self.state = None;
+            self.played = false;
+            return Ok(hard_drop(tetris));
+        }
+        if !self.asked {
+            self.sync(tetris)?;
+            self.send(&FrontendMessage::Suggest)?;
+            self.asked = true;
+        }
+        let moves = loop {
+            match self.receiver.try_recv() {
+                Ok(message) => match message? {
+                    BotMessage::Suggestion { moves } => break moves,
+                    BotMessage::Error { reason } => return Err(format!("bot error: {}", reason)),
+                    _ => {}
+                },
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => return Err(String::from("bot closed the connection"))
+            }
+        };
+        self.asked = false;
+        self.played = true;
+        let chosen = moves.iter().find_map(|mv| plan_for(tetris, mv).map(|plan| (*mv, plan)));
+        match chosen {
+            Some((mv, plan)) => {
+                self.send(&FrontendMessage::Play { mv })?;
+                let state = self.state.as_mut().ok_or("bot is not running")?;
+                let mut after = tetris.clone();
+                if plan.hold {
+                    after.hold_piece();
+                }
+                after.place_piece(plan.placement.piece, plan.placement.kick);
+                after.disable_piece();
+                // The piece played, and the one taken out of the queue by the first hold.
+                let used = if plan.hold && state.hold.is_none() { 2 } else { 1 };
+                state.queue.drain(..used.min(state.queue.len()));
+                state.hold = after.hold();
+                state.board = board(&after.tile_canvas);
+                Ok(Some(plan))
+            }
+            None => {
+                self.state = None;
+                Ok(hard_drop(tetris))
+            }
+        }
+    }
+}
+
+// How to reach the bot's move from the current state, if it can be reached.
+fn plan_for(tetris: &Tetris, mv: &Move) -> Option<Plan> {
+    let mut held = tetris.clone();
+    let hold = tetris.active_piece().piece != mv.location.piece;
+    if hold && (!held.hold_piece() || held.check_loss() || held.active_piece().piece != mv.location.piece) {
+        return None;
+    }
+    let target = sorted(piece_at(&mv.location, tetris.tile_canvas.height()).cells());
+    let placements = movegen::placements(&held);
+    let same_cells = |p: &&Placement| sorted(p.piece.cells()) == target;
+    let placement = placements.iter().find(|p| same_cells(p) && p.tspin == mv.spin.tspin())
+        .or_else(|| placements.iter().find(same_cells))?;
+    Some(Plan { hold, start: held.active_piece(), placement: placement.clone(), score: 0.0 })
+}
+
+fn hard_drop(tetris: &Tetris) -> Option<Plan> {
+    let start = tetris.active_piece();
+    movegen::placements(tetris).into_iter().find(|p| p.inputs == [Action::HardDrop]).map(|placement| Plan { hold: false, start, placement, score: 0.0 })
+}
+
+impl Controller for TbpBot {
+    fn input(&mut self, game: &Game) -> Result<Input, String> {
+        if let Some(e) = &self.error {
+            return Err(e.clone());
+        }
+        // A new piece needs a new move.
+        if game.events().iter().any(|e| matches!(e, Event::Locked(..))) {
+            self.played = false;
+        }
+        let mut driver = std::mem::take(&mut self.driver);
+        let mut error = None;
+        let input = driver.input(game, |tetris| self.choose(tetris).unwrap_or_else(|e| {
+            error = Some(e);
+            None
+        }));
+        self.driver = driver;
+        match error {
+            Some(e) => {
+                self.error = Some(e.clone());
+                Err(e)
+            }
+            None => Ok(input)
+        }
+    }
+}
+
+impl Drop for TbpBot {
+    fn drop(&mut self) {
+        let _ = self.send(&FrontendMessage::Quit);
+        if let Some(child) = &mut self.child {
+            // Give it a moment to exit by itself.
+            for _ in 0..100 {
+                if let Ok(Some(_)) = child.try_wait() {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
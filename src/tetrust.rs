@@ -1,494 +1,507 @@
-pub mod tetrust {
-    use rand::{
-        distributions::{Distribution, Standard},
-        Rng,
-    };
-    use std::ops::Index;
-    use sdl2::video::*;
-    use sdl2::render::Texture;
-    use sdl2::surface::Surface;
-    use sdl2::rect::Rect;
+use std::collections::VecDeque;
+use rand::{
+    distributions::{Distribution, Standard},
+    rngs::StdRng,
+    Rng, SeedableRng,
+};
+use serde::{Deserialize, Serialize};
+
+pub const TILE_CANVAS_WIDTH: u16 = 16;
+pub const TILE_CANVAS_HEIGHT: u16 = 19;
+pub const PREVIEW_LENGTH: usize = 5;
+// Every piece needs a 4x4 box to spawn and turn in; smaller boards can lock
+// tiles outside the canvas.
+pub const MIN_BOARD_SIZE: u16 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TileColor {
+    Empty, Red, Green, Blue, Purple, Cyan, Yellow, Orange, Gray
+}
+
+// A tile's color and the frame on which it was locked into the canvas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile(pub TileColor, pub u64);
 
-    pub const TILE_CANVAS_WIDTH: u16 = 20; 
-    pub const TILE_CANVAS_HEIGHT: u16 = 20; 
+const EMPTY_TILE: Tile = Tile(TileColor::Empty, 0);
 
-    pub const BACKGROUND_COLOR: sdl2::pixels::Color = sdl2::pixels::Color::RGB(102, 102, 153);
-    
-    #[derive(Clone, Copy, Debug, PartialEq)]
-    pub enum TileColor {
-        Empty, Red, Green, Blue, Purple, Cyan, Yellow, Orange, Gray
+#[derive(Clone, Debug)]
+pub struct TileCanvas {
+    width: u16,
+    height: u16,
+    rows: Vec<Vec<Tile>>
+}
+
+// Errors for boards too small to play on.
+pub fn check_board_size(width: u16, height: u16) -> Result<(), String> {
+    if width < MIN_BOARD_SIZE || height < MIN_BOARD_SIZE {
+        return Err(format!("unsupported board size {}x{}: must be at least {}x{}", width, height, MIN_BOARD_SIZE, MIN_BOARD_SIZE));
     }
+    Ok(())
+}
 
-    impl TileColor {
-        fn get_asset_path(color: &TileColor) -> String {
-            match color { 
-                TileColor::Empty => "assets/empty.bmp".to_string(), TileColor::Red => "assets/red.bmp".to_string(),
-                TileColor::Green => "assets/green.bmp".to_string(), TileColor::Blue => "assets/blue.bmp".to_string(),
-                TileColor::Cyan => "assets/cyan.bmp".to_string(), TileColor::Purple => "assets/purple.bmp".to_string(),
-                TileColor::Yellow => "assets/yellow.bmp".to_string(), TileColor::Orange => "assets/orange.bmp".to_string(),
-                TileColor::Gray => "assets/gray.bmp".to_string()
-            }
+impl TileCanvas {
+    pub fn new(width: u16, height: u16) -> TileCanvas {
+        TileCanvas { width, height, rows: vec![vec![EMPTY_TILE; width as usize]; height as usize] }
+    }
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+    pub fn get_tile(&self, x: u16, y: u16) -> TileColor {
+        self.rows[y as usize][x as usize].0
+    }
+    pub fn get_tile_override(&self, x: u16, y: u16) -> Tile {
+        self.rows[y as usize][x as usize]
+    }
+    pub fn set_tile(&mut self, x: u16, y: u16, color: TileColor) {
+        self.rows[y as usize][x as usize] = Tile(color, 0);
+    }
+    pub fn set_tile_override(&mut self, x: u16, y: u16, tile: Tile) {
+        self.rows[y as usize][x as usize] = tile;
+    }
+    // Cells above the top of the canvas are free so pieces can spawn partially hidden.
+    pub fn is_free(&self, x: i16, y: i16) -> bool {
+        if x < 0 || x >= self.width as i16 || y >= self.height as i16 {
+            return false;
         }
+        y < 0 || self.rows[y as usize][x as usize].0 == TileColor::Empty
     }
-
-    pub struct TileTexture<'t> {
-        empty: Texture<'t>, red: Texture<'t>,
-        green: Texture<'t>, blue: Texture<'t>,
-        purple: Texture<'t>, cyan: Texture<'t>,
-        yellow: Texture<'t>, orange: Texture<'t>,
-        gray: Texture<'t>
+    pub fn is_row_full(&self, y: u16) -> bool {
+        self.rows[y as usize].iter().all(|t| t.0 != TileColor::Empty)
     }
-
-    impl<'t> TileTexture<'t> { 
-        pub fn load_from_path(texture_creator: &'t sdl2::render::TextureCreator<WindowContext>, color: TileColor) -> Result<Texture<'t>, String> {
-            let surface = Surface::load_bmp(TileColor::get_asset_path(&color))?;
-            let texture = texture_creator.create_texture_from_surface(surface).map_err(|err| {err.to_string()})?;
-            Ok(texture)
+    pub fn clear_rows(&mut self) -> u16 {
+        let width = self.width as usize;
+        let before = self.rows.len();
+        self.rows.retain(|row| row.iter().any(|t| t.0 == TileColor::Empty));
+        let cleared = before - self.rows.len();
+        for _ in 0..cleared {
+            self.rows.insert(0, vec![EMPTY_TILE; width]);
         }
-
-        pub fn new(texture_creator: &'t sdl2::render::TextureCreator<WindowContext>) -> Result<TileTexture<'t>, String> {
-            Ok(TileTexture { empty: TileTexture::load_from_path(texture_creator, TileColor::Empty)?,
-                              red: TileTexture::load_from_path(texture_creator, TileColor::Red)?,
-                              green: TileTexture::load_from_path(texture_creator, TileColor::Green)?,
-                              blue: TileTexture::load_from_path(texture_creator, TileColor::Blue)?,
-                              purple: TileTexture::load_from_path(texture_creator, TileColor::Purple)?,
-                              cyan: TileTexture::load_from_path(texture_creator, TileColor::Cyan)?,
-                              yellow: TileTexture::load_from_path(texture_creator, TileColor::Yellow)?,
-                              orange: TileTexture::load_from_path(texture_creator, TileColor::Orange)?,
-                              gray: TileTexture::load_from_path(texture_creator, TileColor::Gray)?,
-                            })
-                }
+        cleared as u16
     }
-
-    impl<'t> Index<TileColor> for TileTexture<'t> {
-        type Output = Texture<'t>; 
-
-        fn index(&self, color: TileColor) -> &Self::Output {
-            match color {
-                TileColor::Empty => &self.empty,
-                TileColor::Red => &self.red,
-                TileColor::Green => &self.green,
-                TileColor::Blue => &self.blue,
-                TileColor::Purple => &self.purple,
-                TileColor::Cyan => &self.cyan,
-                TileColor::Yellow => &self.yellow,
-                TileColor::Orange => &self.orange,
-                TileColor::Gray => &self.gray,
-            }
+    // Pushes the stack up and appends `rows` at the bottom, locked on `frame`.
+    // Returns true if any tile was pushed out of the top of the canvas. Every
+    // row must be as wide as the canvas.
+    pub fn insert_rows(&mut self, rows: Vec<Vec<TileColor>>, frame: u64) -> bool {
+        if let Some(row) = rows.iter().find(|row| row.len() != self.width as usize) {
+            panic!("garbage row of {} tiles on a board {} wide", row.len(), self.width);
         }
+        let mut overflow = false;
+        for row in rows {
+            let top = self.rows.remove(0);
+            overflow |= top.iter().any(|t| t.0 != TileColor::Empty);
+            self.rows.push(row.into_iter().map(|color| Tile(color, frame)).collect());
+        }
+        overflow
     }
-
-    #[derive(Clone, Copy, Debug)]
-    pub struct Tile(TileColor, bool);
-
-    pub struct TileCanvas<'t> { 
-        canvas: sdl2::render::Canvas<Window>,
-        textures: TileTexture<'t>,
-        tiles: [[Tile; TILE_CANVAS_HEIGHT as usize]; TILE_CANVAS_WIDTH as usize]
+    pub fn is_empty(&self) -> bool {
+        self.rows.iter().all(|row| row.iter().all(|t| t.0 == TileColor::Empty))
     }
-
-    #[derive(Clone, Copy, Debug, PartialEq)]
-    pub enum PieceEnum {
-        O, S, Z, T, L, J, I 
+    pub fn count_rows(&self, color: TileColor) -> u16 {
+        self.rows.iter().filter(|row| row.iter().any(|t| t.0 == color)).count() as u16
     }
-
-    pub struct Piece(pub Vec<Vec<u8>>, pub TileColor); 
-
-    impl PieceEnum { 
-        pub fn buf(state: u8, piece: &PieceEnum) -> (u16, u16){ 
-            match piece {
-                PieceEnum::O => (0, 2),
-                PieceEnum::I => {
-                    match state % 2 {
-                        0 => (0, 0),
-                        1 => (1, 2),
-                        _ => (0, 0)
-                    }
-                }
-                PieceEnum::S => {
-                    match state % 2 {
-                        0 => (0, 1),
-                        1 => (0, 2),
-                        _ => (0, 1)
-                    }
-                }
-                PieceEnum::Z => {
-                    match state % 2 {
-                        0 => (0, 1),
-                        1 => (1, 1),
-                        _ => (0, 1)
-                    }
-                }
-                _ => {
-                    match state { 
-                        1 => (1, 1),
-                        3 => (0, 2),
-                        _ => (0, 1)
-                    }
-                }
-            }
-        }
-        pub fn rot(state: u8, piece: &PieceEnum) -> (Vec<(u8, u8)>, Vec<(u8, u8)>) {
-            match piece {
-                PieceEnum::O => (Vec::new(), Vec::new()),
-                PieceEnum::I => match state % 2 { 
-                    0 => (vec![(0, 1), (2, 1), (3, 1)], vec![(1, 0), (1, 2), (1, 3)]),
-                    1 => (vec![(1, 0), (1, 2), (1, 3)], vec![(0, 1), (2, 1), (3, 1)]),
-                    _ => (vec![(0, 1), (2, 1), (3, 1)], vec![(1, 0), (1, 2), (1, 3)]),
-                },
-                PieceEnum::S => match state % 2 {
-                    0 => (vec![(0, 0), (2, 1)], vec![(0, 1), (0, 2)]),
-                    1 => (vec![(0, 1), (0, 2)], vec![(0, 0), (2, 1)]),
-                    _ => (vec![(0, 0), (3, 1)], vec![(0, 2), (0, 3)]),
-                },
-                PieceEnum::Z => match state % 2 {
-                    0 => (vec![(0, 2), (2, 1)], vec![(0, 0), (0, 1)]),
-                    1 => (vec![(0, 0), (0, 1)], vec![(0, 2), (2, 1)]),
-                    _ => (vec![(0, 2), (2, 1)], vec![(0, 0), (0, 1)]),
-                },
-                PieceEnum::T => match state {
-                    0 => (vec![(2, 1)], vec![(1, 0)]),
-                    1 => (vec![(1, 0)], vec![(0, 1)]),
-                    2 => (vec![(0, 1)], vec![(1, 2)]),
-                    3 => (vec![(1, 2)], vec![(2, 1)]),
-                    _ => (vec![(2, 1)], vec![(0, 0)]),
-                },
-                PieceEnum::L => match state {
-                    0 => (vec![(0, 1), (2, 1), (2, 2)], vec![(1, 0), (0, 2), (1, 2)]),
-                    1 => (vec![(1, 0), (2, 0), (1, 2)], vec![(0, 1), (2, 1), (2, 2)]),
-                    2 => (vec![(0, 0), (0, 1), (2, 1)], vec![(1, 0), (2, 0), (1, 2)]),
-                    3 => (vec![(1, 0), (0, 2), (1, 2)], vec![(0, 0), (0, 1), (2, 1)]),
-                    _ => (vec![(0, 1), (2, 1), (2, 2)], vec![(1, 0), (0, 2), (1, 2)]),
-                },
-                PieceEnum::J => match state {
-                    0 => (vec![(0, 1), (0, 2), (2, 1)], vec![(0, 0), (1, 0), (1, 2)]),
-                    1 => (vec![(1, 0), (1, 2), (2, 2)], vec![(0, 1), (0, 2), (2, 1)]),
-                    2 => (vec![(0, 1), (2, 0), (2, 1)], vec![(1, 0), (1, 2), (2, 2)]),
-                    3 => (vec![(0, 0), (1, 0), (1, 2)], vec![(0, 1), (2, 0), (2, 1)]),
-                    _ => (vec![(0, 1), (0, 2), (2, 1)], vec![(0, 0), (1, 0), (1, 2)]),
+    // How far each column's highest tile is from the floor, 0 for an empty one.
+    pub fn column_heights(&self) -> Vec<u16> {
+        (0..self.width)
+            .map(|x| (0..self.height).find(|&y| self.get_tile(x, y) != TileColor::Empty).map(|y| self.height - y).unwrap_or(0))
+            .collect()
+    }
+    // Empty cells with a tile somewhere above them in their column.
+    pub fn holes(&self) -> usize {
+        self.column_heights().iter().enumerate()
+            .map(|(x, &h)| (self.height - h..self.height).filter(|&y| self.get_tile(x as u16, y) == TileColor::Empty).count())
+            .sum()
+    }
+    // The cells whose color differs in `other`, with their new color. Both
+    // canvases must be the same size.
+    pub fn diff(&self, other: &TileCanvas) -> Vec<(u16, u16, TileColor)> {
+        let mut cells = Vec::new();
+        for (y, (row, other_row)) in self.rows.iter().zip(other.rows.iter()).enumerate() {
+            for (x, (tile, other_tile)) in row.iter().zip(other_row.iter()).enumerate() {
+                if tile.0 != other_tile.0 {
+                    cells.push((x as u16, y as u16, other_tile.0));
                 }
             }
         }
+        cells
     }
+}
 
-    pub struct Pieces { 
-        pub o: Piece, 
-        pub s: Piece, 
-        pub z: Piece, 
-        pub t: Piece, 
-        pub l: Piece, 
-        pub j: Piece, 
-        pub i: Piece, 
-    }
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PieceEnum {
+    O, S, Z, T, L, J, I
+}
 
-    impl Distribution<PieceEnum> for Standard {
-        fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> PieceEnum {
-            match rng.gen_range(0..=6) {
-                0 => PieceEnum::O,
-                1 => PieceEnum::S,
-                2 => PieceEnum::Z,
-                3 => PieceEnum::T,
-                4 => PieceEnum::L,
-                5 => PieceEnum::J,
-                _ => PieceEnum::I,
+impl PieceEnum {
+    pub const ALL: [PieceEnum; 7] = [PieceEnum::O, PieceEnum::S, PieceEnum::Z, PieceEnum::T, PieceEnum::L, PieceEnum::J, PieceEnum::I];
+
+    pub fn color(&self) -> TileColor {
+        match self {
+            PieceEnum::O => TileColor::Yellow,
+            PieceEnum::S => TileColor::Green,
+            PieceEnum::Z => TileColor::Red,
+            PieceEnum::T => TileColor::Purple,
+            PieceEnum::L => TileColor::Orange,
+            PieceEnum::J => TileColor::Blue,
+            PieceEnum::I => TileColor::Cyan,
+        }
+    }
+    pub fn cells(&self, state: u8) -> [(i16, i16); 4] {
+        let (cells, size) = match self {
+            PieceEnum::O => return [(1, 0), (2, 0), (1, 1), (2, 1)],
+            PieceEnum::S => ([(1, 0), (2, 0), (0, 1), (1, 1)], 3),
+            PieceEnum::Z => ([(0, 0), (1, 0), (1, 1), (2, 1)], 3),
+            PieceEnum::T => ([(1, 0), (0, 1), (1, 1), (2, 1)], 3),
+            PieceEnum::L => ([(2, 0), (0, 1), (1, 1), (2, 1)], 3),
+            PieceEnum::J => ([(0, 0), (0, 1), (1, 1), (2, 1)], 3),
+            PieceEnum::I => ([(0, 1), (1, 1), (2, 1), (3, 1)], 4),
+        };
+        let mut rotated = cells;
+        for _ in 0..state % 4 {
+            for cell in rotated.iter_mut() {
+                *cell = (size - 1 - cell.1, cell.0);
             }
         }
+        rotated
     }
-
-    impl Pieces { 
-        pub fn new() -> Pieces { 
-            Pieces {
-                o: Piece(vec![ vec![0, 1], vec![0, 1], vec![]], TileColor::Yellow),
-                s: Piece(vec![ vec![1, 2], vec![0, 1], vec![]], TileColor::Green),
-                z: Piece(vec![ vec![0, 1], vec![1, 2], vec![]], TileColor::Red),
-                t: Piece(vec![ vec![1], vec![0, 1, 2], vec![]], TileColor::Purple),
-                l: Piece(vec![ vec![2], vec![0, 1, 2], vec![]], TileColor::Orange),
-                j: Piece(vec![ vec![0], vec![0, 1, 2], vec![]], TileColor::Blue),
-                i: Piece(vec![ vec![], vec![0, 1, 2, 3], vec![]], TileColor::Cyan),
+    // SRS wall kicks for rotating from state `from` to `to`, with y pointing down.
+    pub fn kicks(piece: &PieceEnum, from: u8, to: u8) -> [(i16, i16); 5] {
+        match piece {
+            PieceEnum::O => [(0, 0); 5],
+            PieceEnum::I => match (from, to) {
+                (0, 1) => [(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)],
+                (1, 0) => [(0, 0), (2, 0), (-1, 0), (2, -1), (-1, 2)],
+                (1, 2) => [(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)],
+                (2, 1) => [(0, 0), (1, 0), (-2, 0), (1, 2), (-2, -1)],
+                (2, 3) => [(0, 0), (2, 0), (-1, 0), (2, -1), (-1, 2)],
+                (3, 2) => [(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)],
+                (3, 0) => [(0, 0), (1, 0), (-2, 0), (1, 2), (-2, -1)],
+                _ => [(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)],
+            },
+            _ => match (from, to) {
+                (0, 1) | (2, 1) => [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+                (1, 0) | (1, 2) => [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+                (2, 3) | (0, 3) => [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+                _ => [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
             }
         }
     }
+}
 
-    impl Index<PieceEnum> for Pieces {
-        type Output = Piece; 
-
-        fn index(&self, piece: PieceEnum) -> &Self::Output {
-            match piece {
-                PieceEnum::O => &self.o,
-                PieceEnum::S => &self.s,
-                PieceEnum::Z => &self.z,
-                PieceEnum::T => &self.t,
-                PieceEnum::L => &self.l,
-                PieceEnum::J => &self.j,
-                PieceEnum::I => &self.i,
-            }
+impl Distribution<PieceEnum> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> PieceEnum {
+        match rng.gen_range(0..=6) {
+            0 => PieceEnum::O,
+            1 => PieceEnum::S,
+            2 => PieceEnum::Z,
+            3 => PieceEnum::T,
+            4 => PieceEnum::L,
+            5 => PieceEnum::J,
+            _ => PieceEnum::I,
         }
     }
+}
 
-    impl<'t> TileCanvas<'t> { 
-        pub fn new(tile_canvas: sdl2::render::Canvas<Window>, tile_textures: TileTexture<'t>) -> TileCanvas {
-            let mut canvas = TileCanvas { canvas: tile_canvas, tiles: [[Tile(TileColor::Empty, true); TILE_CANVAS_HEIGHT as usize]; TILE_CANVAS_WIDTH as usize], textures: tile_textures};
-            for x in 2..(TILE_CANVAS_WIDTH-2){
-                for y in 0..TILE_CANVAS_HEIGHT { 
-                    canvas.set_tile(x, y, TileColor::Empty).unwrap();
-                    }
-            }
-            canvas
+#[derive(Clone, Debug)]
+pub struct PieceQueue {
+    rng: StdRng,
+    next: VecDeque<PieceEnum>,
+    fixed: bool
+}
 
+impl PieceQueue {
+    pub fn new(seed: u64) -> PieceQueue {
+        let mut queue = PieceQueue { rng: StdRng::seed_from_u64(seed), next: VecDeque::new(), fixed: false };
+        queue.fill();
+        queue
+    }
+    // A queue that hands out exactly `pieces` and then runs dry.
+    pub fn fixed(pieces: Vec<PieceEnum>) -> PieceQueue {
+        PieceQueue { rng: StdRng::seed_from_u64(0), next: pieces.into(), fixed: true }
+    }
+    pub fn pop(&mut self) -> Option<PieceEnum> {
+        let piece = self.next.pop_front();
+        self.fill();
+        piece
+    }
+    pub fn peek(&self) -> impl Iterator<Item = &PieceEnum> {
+        self.next.iter().take(PREVIEW_LENGTH)
+    }
+    // Pieces waiting, including any beyond the preview.
+    pub fn len(&self) -> usize {
+        self.next.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.next.is_empty()
+    }
+    // Replaces the piece that comes out of the queue next.
+    pub fn set_next(&mut self, piece: PieceEnum) {
+        match self.next.front_mut() {
+            Some(next) => *next = piece,
+            None => self.next.push_back(piece)
         }
-        pub fn update(&mut self) {
-            self.canvas.present();
-        }
-        pub fn set_tile(&mut self, x: u16, y: u16, color: TileColor) -> Result<(), String>{ 
-            let x_c = std::cmp::min(x, TILE_CANVAS_WIDTH - 2) as usize; 
-            let y_c = y as usize;
-            if self.tiles[x_c][y_c].1 == true {
-                self.tiles[x_c][y_c].0 = color; 
-                self.canvas.copy(&self.textures[color], None, Rect::new(x_c as i32 * 32, y_c as i32 * 32, 32, 32))?;
-            }
-            Ok(())
-        }
-        pub fn set_tile_override(&mut self, x: u16, y: u16, color: TileColor) -> Result<(), String>{ 
-            let x_c = x as usize; 
-            let y_c = y as usize;
-            self.tiles[x_c][y_c].0 = color; 
-            self.canvas.copy(&self.textures[color], None, Rect::new(x_c as i32 * 32, y_c as i32 * 32, 32, 32))?;
-            Ok(())
-        }
-        pub fn set_tile_state(&mut self, x: u16, y: u16, active: bool) {
-            self.tiles[x as usize][(y % TILE_CANVAS_HEIGHT) as usize].1 = active
-        }
-        pub fn get_tile(&self, x: u16, y: u16) -> Option<Tile> { 
-            let tile = self.tiles[(x % TILE_CANVAS_WIDTH) as usize][(y % TILE_CANVAS_HEIGHT) as usize];
-            match tile.0 { 
-                TileColor::Empty => None,
-                TileColor::Gray => if y < TILE_CANVAS_HEIGHT - 1 {
-                    None 
-                    }
-                else { 
-                    Some(Tile(TileColor::Empty, false))
-                }
-                _ => match tile.1 {
-                    true => Some(tile),
-                    false => Some(Tile(TileColor::Empty, false))
-                }
-            }
-        
-        }
-        pub fn get_tile_override(&self, x: u16, y: u16) -> Tile { 
-            self.tiles[(x % TILE_CANVAS_WIDTH) as usize][(y % TILE_CANVAS_HEIGHT) as usize]
+    }
+
+    fn fill(&mut self) {
+        while !self.fixed && self.next.len() < PREVIEW_LENGTH {
+            let piece = self.rng.gen();
+            self.next.push_back(piece);
         }
-        pub fn draw_piece(&mut self, x: u16, y: u16, piece: &Piece) -> Result<(), String>{
-            for i in 0..piece.0.len() {
-                for t in piece.0[i].iter() {
-                    self.set_tile(x + *t as u16, y + i as u16, piece.1)?;
-                }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TetrisPiece {
+    pub x: i16,
+    pub y: i16,
+    pub piece: PieceEnum,
+    pub state: u8
+}
+
+impl TetrisPiece {
+    pub fn cells(&self) -> [(i16, i16); 4] {
+        let (x, y) = (self.x, self.y);
+        self.piece.cells(self.state).map(|(cx, cy)| (x + cx, y + cy))
+    }
+    // Rotates using the first wall kick that `fits` allows, like `Tetris::try_rotate`.
+    pub fn rotated(&self, clockwise: bool, fits: impl Fn(&TetrisPiece) -> bool) -> Option<(TetrisPiece, u8)> {
+        let from = self.state;
+        let to = if clockwise { (from + 1) % 4 } else { (from + 3) % 4 };
+        for (i, &(x, y)) in PieceEnum::kicks(&self.piece, from, to).iter().enumerate() {
+            let rotated = TetrisPiece { x: self.x + x, y: self.y + y, state: to, ..*self };
+            if fits(&rotated) {
+                return Some((rotated, i as u8));
             }
-            self.update();
-            Ok(())
         }
+        None
     }
+}
 
-    #[derive(Copy, Clone, Debug)]
-    pub struct TetrisPiece { 
-        pub x: u16, 
-        pub y: u16,
-        pub piece: PieceEnum,
-        pub buf: (u16, u16),
-        pub state: u8
-    }
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TSpin {
+    None, Mini, Full
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Clear {
+    pub lines: u16,
+    pub tspin: TSpin,
+    pub perfect: bool
+}
 
-    pub struct Tetris<'t> {
-        pub tile_canvas: TileCanvas<'t>,
-        active_piece: TetrisPiece,
-        pieces: Pieces,
-        score: u16,
-        font: sdl2::ttf::Font<'t, 't>
+impl Clear {
+    // Like "T-spin mini double"; empty when nothing happened.
+    pub fn name(&self) -> String {
+        let lines = ["", "single", "double", "triple", "tetris"].get(self.lines as usize).copied().unwrap_or("clear");
+        let name = match self.tspin {
+            TSpin::Full => format!("T-spin {}", lines),
+            TSpin::Mini => format!("T-spin mini {}", lines),
+            TSpin::None => lines.to_string()
+        };
+        let name = name.trim_end().to_string();
+        if self.perfect { format!("{}, perfect clear", name) } else { name }
     }
+}
 
-    impl<'t> Tetris<'t> { 
-        pub fn set_piece(&mut self, x_c: u16, y_c: u16, piece_enum: PieceEnum) -> Result<(u16, u16), String> {
-            self.active_piece = TetrisPiece { x: x_c, y: y_c, piece: piece_enum, buf: PieceEnum::buf(0, &piece_enum), state: 0};
-            self.tile_canvas.draw_piece(x_c, y_c, &self.pieces[piece_enum])?;
-        
-            Ok((x_c, y_c))
-        }
-        pub fn new(canvas: sdl2::render::Canvas<Window>, tile_textures: TileTexture<'t>, tetris_piece: TetrisPiece, tetris_pieces: Pieces, game_font: sdl2::ttf::Font<'t, 't>) -> Tetris<'t> { 
-            let mut tetris = Tetris { tile_canvas: TileCanvas::new(canvas, tile_textures), active_piece: tetris_piece, pieces: tetris_pieces, score: 0, font: game_font};
-            tetris.set_piece(tetris_piece.x, tetris_piece.y, tetris_piece.piece).unwrap();
-            tetris
-        }
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HoldMode {
+    Disabled, Once, Infinite
+}
 
-        pub fn rotate_piece(&mut self) -> Result<bool, String> {
-            let x_c = self.active_piece.x;
-            let y_c = self.active_piece.y;
-            let mut collision = false;
-            if x_c >= 2 && (x_c + self.active_piece.buf.1 + if self.active_piece.piece == PieceEnum::I { 1 } else {0} < TILE_CANVAS_WIDTH - 2) {
+#[derive(Clone, Debug)]
+pub struct Tetris {
+    pub tile_canvas: TileCanvas,
+    pub queue: PieceQueue,
+    pub hold_mode: HoldMode,
+    // Frame counter used to timestamp locked tiles, advanced by the game loop.
+    pub clock: u64,
+    active_piece: TetrisPiece,
+    // Index of the wall kick used if the last successful action was a rotation.
+    last_kick: Option<u8>,
+    hold: Option<PieceEnum>,
+    can_hold: bool,
+    score: u32,
+    lost: bool
+}
 
-                let rots = PieceEnum::rot(self.active_piece.state, &self.active_piece.piece);
-                if rots.0.iter().all(|&x| self.tile_canvas.get_tile(x_c + x.1 as u16, y_c + x.0 as u16).is_none()){
-                    for r in rots.1 {
-                        self.tile_canvas.set_tile(x_c + r.1 as u16, y_c + r.0 as u16, TileColor::Empty)?;
-                    }
-                    for r in rots.0 {
-                        self.tile_canvas.set_tile(x_c + r.1 as u16, y_c + r.0 as u16, self.pieces[self.active_piece.piece].1)?;
-                        collision |= match self.tile_canvas.get_tile(x_c + r.1 as u16, y_c + r.0 as u16 + 1) {
-                                    Some(t) => !t.1, 
-                                    None => false
-                                };
-                    }
-                    self.tile_canvas.update();
-                    self.active_piece.state += 1;
-                    self.active_piece.state %= 4;
-                
-                    self.active_piece.buf = PieceEnum::buf(self.active_piece.state, &self.active_piece.piece);
-                }
+impl Tetris {
+    pub fn new(tile_canvas: TileCanvas, seed: u64) -> Tetris {
+        Tetris::with_queue(tile_canvas, PieceQueue::new(seed))
+    }
+    pub fn with_queue(tile_canvas: TileCanvas, queue: PieceQueue) -> Tetris {
+        let active_piece = TetrisPiece { x: 0, y: 0, piece: PieceEnum::O, state: 0 };
+        let mut tetris = Tetris { tile_canvas, queue, hold_mode: HoldMode::Once, clock: 0, active_piece, last_kick: None, hold: None,
+                                  can_hold: true, score: 0, lost: false };
+        tetris.spawn_next();
+        tetris
+    }
+    pub fn active_piece(&self) -> TetrisPiece {
+        self.active_piece
+    }
+    pub fn last_kick(&self) -> Option<u8> {
+        self.last_kick
+    }
+    // Puts the active piece somewhere directly, e.g. a placement found by a search.
+    pub fn place_piece(&mut self, piece: TetrisPiece, last_kick: Option<u8>) {
+        self.active_piece = piece;
+        self.last_kick = last_kick;
+    }
+    pub fn hold(&self) -> Option<PieceEnum> {
+        self.hold
+    }
+    pub fn set_hold(&mut self, piece: Option<PieceEnum>) {
+        self.hold = piece;
+    }
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+    pub fn fits(&self, piece: &TetrisPiece) -> bool {
+        piece.cells().iter().all(|&(x, y)| self.tile_canvas.is_free(x, y))
+    }
+    pub fn spawn_position(&self, piece: PieceEnum) -> TetrisPiece {
+        let x = (self.tile_canvas.width() as i16 - if piece == PieceEnum::I { 4 } else { 3 }) / 2;
+        TetrisPiece { x, y: 0, piece, state: 0 }
+    }
+    pub fn set_piece(&mut self, piece: PieceEnum) -> bool {
+        self.active_piece = self.spawn_position(piece);
+        self.last_kick = None;
+        self.lost |= !self.fits(&self.active_piece);
+        !self.lost
+    }
+    // Spawns the next piece from the queue. Returns false if the spawn is blocked
+    // or the queue ran out of pieces.
+    pub fn spawn_next(&mut self) -> bool {
+        self.can_hold = true;
+        match self.queue.pop() {
+            Some(piece) => self.set_piece(piece),
+            None => {
+                self.lost = true;
+                false
             }
-
-            Ok(collision)
         }
-        pub fn tile_check(tile: Option<Tile>, o_tile: Option<Tile>) -> bool {
-            if tile.is_some() && tile.unwrap().0 != TileColor::Empty && o_tile.is_some() && o_tile.unwrap().0 == TileColor::Empty {
-                true 
+    }
+    // Swaps the active piece with the held one. Returns false if holding is not
+    // allowed right now; check `check_loss` for a blocked spawn.
+    pub fn hold_piece(&mut self) -> bool {
+        if self.hold_mode == HoldMode::Disabled || (!self.can_hold && self.hold_mode == HoldMode::Once) {
+            return false;
+        }
+        let current = self.active_piece.piece;
+        let next = match self.hold {
+            Some(piece) => piece,
+            None => match self.queue.pop() {
+                Some(piece) => piece,
+                None => return false
             }
-            else {
-                false
+        };
+        self.hold = Some(current);
+        self.can_hold = false;
+        self.set_piece(next);
+        true
+    }
+    // Rotates `piece` using the first wall kick that fits. Returns the rotated
+    // piece and the index of the kick used.
+    pub fn try_rotate(&self, piece: &TetrisPiece, clockwise: bool) -> Option<(TetrisPiece, u8)> {
+        piece.rotated(clockwise, |p| self.fits(p))
+    }
+    pub fn rotate_piece(&mut self, clockwise: bool) -> bool {
+        match self.try_rotate(&self.active_piece, clockwise) {
+            Some((rotated, kick)) => {
+                self.active_piece = rotated;
+                self.last_kick = Some(kick);
+                true
             }
+            None => false
         }
-
-        pub fn check_horizontal_collision(&mut self, x: i16) -> bool {
-            let mut collision = false;
-            if x != 0 {
-                let y_c = self.active_piece.y;
-                match x > 0 {
-                    true => {
-                        for i in 0..4 { 
-                            for j in [1, 2, 3] {
-                                let x_c = self.active_piece.x + j;
-                                let tile = self.tile_canvas.get_tile(x_c, (y_c + i) % (TILE_CANVAS_WIDTH - 1));
-                                let o_tile = self.tile_canvas.get_tile(x_c + 1, (y_c + i) % (TILE_CANVAS_HEIGHT - 1));
-                                collision |= Tetris::tile_check(tile, o_tile);
-                                
-                            }
-                        }
-                    }
-                    false => {
-                        for i in 0..4 { 
-                            for j in [0, 1, 2] {
-                                let x_c = self.active_piece.x + j;
-                                let tile = self.tile_canvas.get_tile(x_c, (y_c + i) % (TILE_CANVAS_HEIGHT - 1));
-                                let o_tile = self.tile_canvas.get_tile(x_c - 1, (y_c + i) % (TILE_CANVAS_HEIGHT - 1));
-                                collision |= Tetris::tile_check(tile, o_tile);
-                            }
-                        }
-                    }
-                }
-            }
-            //println!("{}", collision);
-            collision
+    }
+    pub fn move_piece(&mut self, x: i16, y: i16) -> bool {
+        let mut moved = self.active_piece;
+        moved.x += x;
+        moved.y += y;
+        if self.fits(&moved) {
+            self.active_piece = moved;
+            self.last_kick = None;
+            return true;
         }
-
-        pub fn move_piece(&mut self, x: i16, y: i16) -> Result<bool, String>  { 
-            let mut collision = false;
-            let p = self.active_piece;
-            if !(x > 0 && p.x + (4 - p.buf.1) > TILE_CANVAS_WIDTH - 3) && !(x < 0 && p.x + p.buf.0 < 3) && !(self.check_horizontal_collision(x)){
-                for i in match x < 0 {
-                    false => [3, 2, 1, 0],
-                    true => [0, 1, 2, 3]
-                    }
-                    { 
-                    for j in (0..4).rev() { 
-                        if let Some(Tile(tile, true)) = self.tile_canvas.get_tile(p.x + i, p.y + j) {
-                                let x_i = p.x + i;
-                                let y_j = p.y + j;
-                                collision |= match self.tile_canvas.get_tile(x_i.wrapping_add(x as u16), (y_j + 1).wrapping_add(y as u16)) {
-                                    Some(t) => !t.1, 
-                                    None => false
-                                };
-                                println!("{}", collision);
-
-                                self.tile_canvas.set_tile(x_i, y_j, TileColor::Empty)?;
-                                self.tile_canvas.set_tile(x_i.wrapping_add(x as u16), (y_j).wrapping_add(y as u16), tile)?;
-                                self.tile_canvas.update();
-                            }
-                        }
-                    }
-                self.active_piece.x = self.active_piece.x.wrapping_add(x as u16);
-                self.active_piece.y = self.active_piece.y.wrapping_add(y as u16);
-
-                self.tile_canvas.update();
-            }
-            Ok(collision)
+        false
+    }
+    pub fn on_ground(&self) -> bool {
+        let mut below = self.active_piece;
+        below.y += 1;
+        !self.fits(&below)
+    }
+    // Where the active piece would land if it were dropped now.
+    pub fn ghost_piece(&self) -> TetrisPiece {
+        let mut ghost = self.active_piece;
+        while self.fits(&TetrisPiece { y: ghost.y + 1, ..ghost }) {
+            ghost.y += 1;
         }
-
-        pub fn clear_row(&mut self) -> Result<(), String> {
-            let y_c = self.active_piece.y;
-            for i in 0..4 {
-                if (2..TILE_CANVAS_WIDTH - 2).all(|x| self.tile_canvas.get_tile(x, (y_c + i) % (TILE_CANVAS_HEIGHT - 1)).is_some()) {
-                    println!("Tetris!");
-                    for n in 2..TILE_CANVAS_WIDTH - 2 {
-                        let mut m = y_c + i;
-                        while m > 0 {
-                            while self.tile_canvas.get_tile(n, m).is_some() {
-                                self.tile_canvas.set_tile_override(n, m, self.tile_canvas.get_tile_override(n, m - 1).0)?;
-                                m -= 1;
-                            }
-                            self.tile_canvas.set_tile_state(n, m + 1, true);
-                            m -= 1;
-                        }
-                    }
-                self.update_score()?;
-                }
-            }
-            Ok(())
+        ghost
+    }
+    pub fn hard_drop(&mut self) -> u16 {
+        let mut rows = 0;
+        while self.move_piece(0, 1) {
+            rows += 1;
         }
-
-        pub fn disable_piece(&mut self) -> Result<(), String> {
-            let p = self.active_piece; 
-            self.clear_row()?;
-            for i in 0..5 {
-                for j in 0..5 {
-                    if let Some(..) = self.tile_canvas.get_tile(p.x + i, p.y + j) {
-                        self.tile_canvas.set_tile_state(p.x + i, p.y + j, false);
-                    }
-                }
-            }
-            Ok(())
+        rows
+    }
+    // Moves the active piece down one row. Returns true if it landed and has to be
+    // locked with `disable_piece`.
+    pub fn update_screen(&mut self) -> bool {
+        !self.move_piece(0, 1)
+    }
+    // Three corner rule: a T piece that got into place by rotating and has at
+    // least three of its corners blocked is a T-spin. It is a mini unless both
+    // corners it points at are blocked or it used the last wall kick.
+    pub fn tspin_for(&self, piece: &TetrisPiece, last_kick: Option<u8>) -> TSpin {
+        let kick = match last_kick {
+            Some(kick) if piece.piece == PieceEnum::T => kick,
+            _ => return TSpin::None
+        };
+        let blocked = |(x, y): (i16, i16)| !self.tile_canvas.is_free(piece.x + x, piece.y + y);
+        let corners = [(0, 0), (2, 0), (2, 2), (0, 2)];
+        if corners.iter().filter(|&&c| blocked(c)).count() < 3 {
+            return TSpin::None;
         }
-
-        pub fn check_loss(&mut self) -> bool {
-        let mut loss = false;
-            for y in 0..3 {
-                for x in 2..TILE_CANVAS_WIDTH - 2 { 
-                    if let Some(tile) = self.tile_canvas.get_tile(x, y) {
-                        if tile.0 == TileColor::Empty { 
-                            loss = true 
-                        }
-                    }
-                }
+        let front = [corners[piece.state as usize % 4], corners[(piece.state as usize + 1) % 4]];
+        if kick == 4 || front.iter().all(|&c| blocked(c)) { TSpin::Full } else { TSpin::Mini }
+    }
+    pub fn tspin(&self) -> TSpin {
+        self.tspin_for(&self.active_piece, self.last_kick)
+    }
+    pub fn disable_piece(&mut self) -> Clear {
+        let tspin = self.tspin();
+        let color = self.active_piece.piece.color();
+        for &(x, y) in self.active_piece.cells().iter() {
+            if y < 0 {
+                self.lost = true;
+            } else {
+                self.tile_canvas.set_tile_override(x as u16, y as u16, Tile(color, self.clock));
             }
-        loss
-        }
-
-        pub fn update_screen(&mut self) -> Result<bool, String> {
-            Ok(self.move_piece(0, 1)?)
         }
-
-        pub fn update_score(&mut self) -> Result<(), String> {
-            self.tile_canvas.canvas.set_draw_color(BACKGROUND_COLOR);
-            self.tile_canvas.canvas.fill_rect(Rect::new(64, 640, 192, 64))?;
-
-            self.score += 1; 
-            let font_surface = self.font.render(&(String::from("Score: ") + &self.score.to_string())).blended(sdl2::pixels::Color::RGB(255, 255, 255)).map_err(|e| e.to_string()).unwrap(); 
-            let texture_creator = self.tile_canvas.canvas.texture_creator();
-            let texture = texture_creator.create_texture_from_surface(font_surface).map_err(|e| e.to_string()).unwrap();
-
-            self.tile_canvas.canvas.copy(&texture, None, Rect::new(64, 640, 192, 64))?;
-            self.tile_canvas.canvas.present();
-
-            Ok(())
+        let lines = self.tile_canvas.clear_rows();
+        self.score += lines as u32;
+        Clear { lines, tspin, perfect: lines > 0 && self.tile_canvas.is_empty() }
+    }
+    pub fn check_loss(&self) -> bool {
+        self.lost
+    }
+    // Empties the canvas after a top out and respawns the active piece.
+    pub fn clear_canvas(&mut self) {
+        self.tile_canvas = TileCanvas::new(self.tile_canvas.width(), self.tile_canvas.height());
+        self.lost = false;
+        self.set_piece(self.active_piece.piece);
+    }
+    // Adds garbage at the bottom of the canvas, lifting the active piece with the
+    // stack if it would otherwise overlap.
+    pub fn add_garbage(&mut self, rows: Vec<Vec<TileColor>>) {
+        let count = rows.len() as i16;
+        self.lost |= self.tile_canvas.insert_rows(rows, self.clock);
+        if !self.fits(&self.active_piece) {
+            self.active_piece.y -= count;
+            self.lost |= !self.fits(&self.active_piece);
         }
     }
-
 }
-
@@ -0,0 +1,33 @@
+// Challenge modifiers that hide locked tiles. Hidden tiles still collide as
+// usual; only the renderer treats them differently.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StackVisibility {
+    Visible,
+    Invisible,
+    // Tiles fade out linearly over the given number of frames after locking.
+    Fade(u32)
+}
+
+// How long the whole stack is shown after a game ends.
+pub const REVEAL_FRAMES: u32 = 180;
+pub const DEFAULT_FADE_FRAMES: u32 = 300;
+
+impl StackVisibility {
+    // Opacity (0 to 255) of a tile locked on `locked_at` as seen on frame `now`.
+    pub fn alpha(&self, locked_at: u64, now: u64) -> u8 {
+        match *self {
+            StackVisibility::Visible => 255,
+            StackVisibility::Invisible => 0,
+            StackVisibility::Fade(frames) => {
+                let age = now.saturating_sub(locked_at);
+                if age >= frames as u64 { 0 } else { (255 - age * 255 / frames as u64) as u8 }
+            }
+        }
+    }
+
+    // Whether the stack has been revealed for long enough `frames` frames after
+    // the game ended. A visible stack has nothing to reveal.
+    pub fn revealed(&self, frames: u32) -> bool {
+        *self == StackVisibility::Visible || frames >= REVEAL_FRAMES
+    }
+}
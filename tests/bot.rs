@@ -0,0 +1,104 @@
+use tetrust::bot::{Bot, Difficulty, Weights};
+use tetrust::game::*;
+use tetrust::modes::versus::Versus;
+use tetrust::notation::parse_board;
+use tetrust::tetrust::*;
+
+const WELL: &str = "\
+XXXX.XXXXX
+XXXX.XXXXX
+XXXX.XXXXX
+XXXX.XXXXX
+";
+
+fn marathon(seed: u64) -> Game {
+    Game::new(Tetris::new(TileCanvas::new(10, 20), seed), GRAVITY_UNIT / 19, Timings::default())
+}
+
+// Plays until the game ends or `pieces` pieces are down, returning the lines cleared.
+fn play(bot: &mut Bot, game: &mut Game, pieces: u32) -> u32 {
+    let (mut locked, mut lines) = (0, 0);
+    while !game.is_over() && locked < pieces {
+        if let Some(lock) = game.step(bot.input(game)).locked {
+            locked += 1;
+            lines += lock.clear.lines as u32;
+        }
+        assert!(game.frames() < pieces as u64 * 200, "bot stalled");
+    }
+    lines
+}
+
+#[test]
+fn evaluation_prefers_flat_boards() {
+    let weights = Weights::default();
+    let none = Clear { lines: 0, tspin: TSpin::None, perfect: false };
+    let flat = parse_board("XXXXXXXXX.", 10, 20).unwrap();
+    let bumpy = parse_board("X.X.X.X.X.\nXXXXXXXXX.", 10, 20).unwrap();
+    let holes = parse_board("XXXXXXXXX.\nX.X.X.X.X.", 10, 20).unwrap();
+    assert!(weights.evaluate(&flat, &none) > weights.evaluate(&bumpy, &none));
+    assert!(weights.evaluate(&bumpy, &none) > weights.evaluate(&holes, &none));
+}
+
+#[test]
+fn holds_for_the_tetris() {
+    let queue = PieceQueue::fixed(vec![PieceEnum::O, PieceEnum::I, PieceEnum::O]);
+    let mut game = Game::new(Tetris::with_queue(parse_board(WELL, 10, 20).unwrap(), queue), 0, Timings::default());
+    let bot = Bot::new(Difficulty::Hard, 1);
+    let best = &bot.plans(&game.tetris)[0];
+    assert!(best.hold);
+    assert_eq!(best.placement.piece.piece, PieceEnum::I);
+
+    let mut bot = Bot::new(Difficulty::Hard, 1);
+    let mut lines = 0;
+    while lines == 0 && game.frames() < 100 {
+        if let Some(lock) = game.step(bot.input(&game)).locked {
+            lines = lock.clear.lines;
+        }
+    }
+    assert_eq!(lines, 4);
+}
+
+#[test]
+fn hard_bot_survives_marathon() {
+    let mut game = marathon(5);
+    let lines = play(&mut Bot::new(Difficulty::Hard, 5), &mut game, 300);
+    assert!(!game.is_over());
+    assert!(lines >= 90, "only {} lines", lines);
+}
+
+#[test]
+fn bot_plays_versus() {
+    let mut versus = Versus::new(marathon(9), 0.5, 9);
+    let mut bots = [Bot::new(Difficulty::Hard, 1), Bot::new(Difficulty::Easy, 2)];
+    while versus.outcome().is_none() {
+        let inputs: Vec<Input> = bots.iter_mut().zip(versus.players.iter()).map(|(bot, p)| bot.input(&p.game)).collect();
+        versus.step(&inputs);
+        assert!(versus.players[0].game.frames() < 200_000, "no winner");
+    }
+    assert!(versus.players[0].sent() > 0);
+}
+
+// Thousands of short games at every difficulty and speed, checking the engine
+// never panics and always lets the bot finish its pieces. Run with
+// `cargo test --release -- --ignored`.
+#[test]
+#[ignore]
+fn soak() {
+    let gravities = [0, GRAVITY_UNIT / 19, GRAVITY_UNIT, GRAVITY_20G];
+    for seed in 0..2000 {
+        let difficulty = Difficulty::ALL[seed as usize % 3];
+        let mut game = marathon(seed);
+        game.gravity = gravities[seed as usize / 3 % 4];
+        play(&mut Bot::new(difficulty, seed), &mut game, 100);
+    }
+}
+
+#[test]
+fn short_soak() {
+    let gravities = [0, GRAVITY_UNIT, GRAVITY_20G];
+    for seed in 0..12 {
+        let mut game = marathon(seed);
+        game.gravity = gravities[seed as usize % 3];
+        play(&mut Bot::new(Difficulty::ALL[seed as usize / 3 % 3], seed), &mut game, 60);
+    }
+}
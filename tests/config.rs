@@ -0,0 +1,230 @@
+use tetrust::config::{self, Action, Config, KeySection, DEFAULT_CONFIG};
+use tetrust::game::*;
+use tetrust::tetrust::*;
+
+// The file the first run wrote before there was a restart key or gamepads.
+const OLD_DEFAULT_CONFIG: &str = r#"# tetrust settings. Delete this file to get the defaults back.
+
+# Frames are 1/60 of a second.
+[handling]
+# Delayed auto shift: frames a direction is held before the piece slides.
+das = 10
+# Auto repeat rate: frames between moves while sliding, 0 to slide at once.
+arr = 2
+# Soft drop speed in rows per frame.
+sdf = 1.0
+
+[display]
+ghost = true
+# Width and height of a tile in pixels, which sets the window size.
+tile_size = 32
+# Size the font is rendered at before it is scaled to a tile.
+font_size = 128
+
+# The board of the solo modes that don't have one of their own.
+[board]
+width = 16
+height = 19
+
+[theme]
+# Directory with a .bmp per tile colour and font.ttf.
+assets = "assets"
+background = [102, 102, 153]
+
+[sound]
+# From 0 to 100.
+volume = 80
+
+# Each action takes a list of keys, any of which triggers it.
+[keys]
+left = ["Left"]
+right = ["Right"]
+soft_drop = ["Down"]
+hard_drop = ["Space"]
+hold = ["C", "Left Shift"]
+rotate_cw = ["Up", "X"]
+rotate_ccw = ["Z"]
+quit = ["Escape"]
+
+# Local versus; quit comes from [keys].
+[versus.player1]
+left = ["A"]
+right = ["D"]
+soft_drop = ["S"]
+hard_drop = ["W"]
+hold = ["Left Shift"]
+rotate_cw = ["G"]
+rotate_ccw = ["F"]
+
+[versus.player2]
+left = ["Left"]
+right = ["Right"]
+soft_drop = ["Down"]
+hard_drop = ["Up"]
+hold = ["Right Shift"]
+rotate_cw = ["/"]
+rotate_ccw = ["."]
+"#;
+
+fn error(text: &str) -> String {
+    Config::parse(text).unwrap_err()
+}
+
+#[test]
+fn default_file_matches_the_defaults() {
+    let config = Config::parse(DEFAULT_CONFIG).unwrap();
+    assert_eq!(config, Config::default());
+    assert_eq!(config.keys[&Action::Hold], vec!["C", "Left Shift"]);
+    assert_eq!(config.keys.len(), Action::ALL.len());
+}
+
+#[test]
+fn missing_settings_keep_their_defaults() {
+    let config = Config::parse("[handling]\ndas = 7\n\n[keys]\nleft = [\"J\"]\nright = [\"L\"]\nsoft_drop = [\"K\"]\nhard_drop = [\"I\"]\n\
+                                hold = [\"A\"]\nrotate_cw = [\"S\", \"Up\"]\nrotate_ccw = [\"D\"]\nquit = [\"Q\"]\n\
+                                restart = [\"F1\"]\n").unwrap();
+    assert_eq!(config.handling.das, 7);
+    assert_eq!(config.handling.arr, Timings::default().arr);
+    assert_eq!(config.keys[&Action::RotateCw], vec!["S", "Up"]);
+    assert_eq!(config.board, Config::default().board);
+    assert_eq!(config.versus, Config::default().versus);
+    assert_eq!(Config::parse("").unwrap(), Config::default());
+}
+
+#[test]
+fn actions_added_since_get_their_default_keys() {
+    let config = Config::parse(OLD_DEFAULT_CONFIG).unwrap();
+    assert_eq!(config, Config::default());
+    assert_eq!(config.keys[&Action::Restart], vec!["R"]);
+    assert_eq!(config.gamepad.buttons[&Action::Restart], vec!["y"]);
+    assert_eq!(config.keys[&Action::Undo], vec!["Backspace"]);
+    assert_eq!(config.keys[&Action::Pick7], vec!["7"]);
+    // Gamepads have no buttons to spare for the mode actions.
+    assert_eq!(config.gamepad.buttons.get(&Action::Next), None);
+    // Only keys nothing else uses are taken.
+    let config = Config::parse("[keys]\nleft = [\"J\"]\nhold = [\"r\"]\n\n[gamepad.controllers.\"Pad\"]\nhold = [\"y\"]\n").unwrap();
+    assert_eq!(config.keys[&Action::Left], vec!["J"]);
+    assert_eq!(config.keys[&Action::Right], vec!["Right"]);
+    assert_eq!(config.keys.get(&Action::Restart), None);
+    assert_eq!(config.gamepad.controllers["Pad"][&Action::Quit], vec!["back", "start"]);
+    assert_eq!(config.gamepad.controllers["Pad"].get(&Action::Restart), None);
+}
+
+#[test]
+fn errors_name_the_setting() {
+    assert_eq!(error("[handling]\ndas = 200\n"), "handling.das = 200 is out of range, expected 0 to 60");
+    assert_eq!(error("[handling]\nsdf = nan\n"), "handling.sdf = NaN is out of range, expected 0.1 to 40");
+    assert_eq!(error("[handling]\ncountdown = 11\n"), "handling.countdown = 11 is out of range, expected 0 to 10");
+    assert_eq!(error("[sound]\nvolume = 101\n"), "sound.volume = 101 is out of range, expected 0 to 100");
+    assert_eq!(error("[board]\nwidth = 2\n"), "board.width = 2 is out of range, expected 4 to 40");
+    assert_eq!(error("[theme]\nassets = \"no such dir\"\n"), "theme.assets: no such dir is not a directory");
+    assert_eq!(error(&DEFAULT_CONFIG.replace("hold = [\"C\", \"Left Shift\"]", "hold = []")), "keys.hold: needs at least one key");
+    assert_eq!(error(&DEFAULT_CONFIG.replace("rotate_cw = [\"Up\", \"X\"]", "rotate_cw = [\"Up\", \" \"]")), "keys.rotate_cw: key 2 is empty");
+    assert_eq!(error(&DEFAULT_CONFIG.replace("rotate_ccw = [\".\"]", "rotate_ccw = [\".\"]\nquit = [\"Q\"]")),
+               "versus.player2: quit can only be bound in [keys]");
+    // Mistakes the TOML parser finds come with the line.
+    let unknown = error("[display]\ngohst = true\n");
+    assert!(unknown.contains("line 2") && unknown.contains("unknown field `gohst`"), "{}", unknown);
+    let sound = error("[sound]\nvolme = 80\n");
+    assert!(sound.contains("unknown field `volme`"), "{}", sound);
+    let action = error("[keys]\nspin = [\"X\"]\n");
+    assert!(action.contains("line 2") && action.contains("unknown variant `spin`"), "{}", action);
+    let wrong_type = error("[display]\nghost = \"yes\"\n");
+    assert!(wrong_type.contains("line 2") && wrong_type.contains("expected a boolean"), "{}", wrong_type);
+}
+
+#[test]
+fn first_run_writes_the_default_file() {
+    let dir = std::env::temp_dir().join(format!("tetrust-config-{}", std::process::id()));
+    let path = dir.join("tetrust").join(config::CONFIG_FILE);
+    assert_eq!(Config::load_or_create(&path).unwrap(), Config::default());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), DEFAULT_CONFIG);
+    std::fs::write(&path, "[handling]\narr = 0\n").unwrap();
+    assert_eq!(Config::load_or_create(&path).unwrap().handling.arr, 0);
+    std::fs::write(&path, "[handling]\narr = -1\n").unwrap();
+    assert!(Config::load(&path).unwrap_err().starts_with(&format!("{}: ", path.display())));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn handling_sets_up_games() {
+    let config = Config::parse("[handling]\ndas = 6\narr = 0\nsdf = 2.5\n").unwrap();
+    let mut game = Game::new(Tetris::new(TileCanvas::new(10, 20), 0), 0, Timings::default());
+    config.handling.apply(&mut game);
+    assert_eq!((game.timings.das, game.timings.arr), (6, 0));
+    assert_eq!(game.soft_drop_gravity, GRAVITY_UNIT * 5 / 2);
+    // The ghost sits on the floor of the empty board.
+    let ghost = game.tetris.ghost_piece();
+    assert_eq!(ghost.cells().iter().map(|&(_, y)| y).max(), Some(19));
+    assert_eq!(ghost.x, game.tetris.active_piece().x);
+}
+
+fn keys(config: &Config, section: KeySection, action: Action) -> Vec<String> {
+    config.bindings(section)[&action].clone()
+}
+
+#[test]
+fn binding_a_key_takes_it_off_clashing_actions() {
+    let mut config = Config::default();
+    // X moves from rotate_cw, which still has Up.
+    assert_eq!(config.bind(KeySection::Solo, Action::Hold, "X", false), Ok(vec![(KeySection::Solo, Action::RotateCw)]));
+    assert_eq!(keys(&config, KeySection::Solo, Action::Hold), vec!["X"]);
+    assert_eq!(keys(&config, KeySection::Solo, Action::RotateCw), vec!["Up"]);
+    // Z was rotate_ccw's only key, so the two actions swap.
+    assert_eq!(config.bind(KeySection::Solo, Action::Hold, "Z", false), Ok(vec![(KeySection::Solo, Action::RotateCcw)]));
+    assert_eq!(keys(&config, KeySection::Solo, Action::RotateCcw), vec!["X"]);
+    // Adding can't swap anything back, so it is refused.
+    assert_eq!(config.bind(KeySection::Solo, Action::Left, "X", true), Err(String::from("X is the only key for keys.rotate_ccw")));
+    assert_eq!(config.bind(KeySection::Solo, Action::Left, "J", true), Ok(vec![]));
+    assert_eq!(keys(&config, KeySection::Solo, Action::Left), vec!["Left", "J"]);
+    config.validate().unwrap();
+    // Mode actions are bound like the rest and can be left without keys.
+    assert_eq!(config.bind(KeySection::Solo, Action::Undo, "N", false), Ok(vec![(KeySection::Solo, Action::Next)]));
+    assert_eq!(keys(&config, KeySection::Solo, Action::Next), vec!["Backspace"]);
+    assert_eq!(config.bind(KeySection::Solo, Action::Next, "R", false), Ok(vec![(KeySection::Solo, Action::Restart)]));
+    assert_eq!(keys(&config, KeySection::Solo, Action::Restart), vec!["Backspace"]);
+    config.keys.remove(&Action::Pick1);
+    config.validate().unwrap();
+    assert!(config.bind(KeySection::Player1, Action::Undo, "U", false).is_err());
+
+    // The versus players share the keyboard with each other and the quit key,
+    // but not with the solo keys.
+    let mut config = Config::default();
+    assert_eq!(config.bind(KeySection::Player1, Action::Left, "Right", false), Ok(vec![(KeySection::Player2, Action::Right)]));
+    assert_eq!(keys(&config, KeySection::Player2, Action::Right), vec!["A"]);
+    assert_eq!(keys(&config, KeySection::Solo, Action::Right), vec!["Right"]);
+    assert_eq!(config.bind(KeySection::Player2, Action::Hold, "Escape", true), Err(String::from("Escape is the only key for keys.quit")));
+    assert!(config.bind(KeySection::Player1, Action::Quit, "Q", false).is_err());
+    config.validate().unwrap();
+
+    config.reset_keys();
+    assert_eq!(config, Config::default());
+}
+
+#[test]
+fn saved_bindings_load_back() {
+    let path = std::env::temp_dir().join(format!("tetrust-saved-{}.toml", std::process::id()));
+    let mut config = Config::default();
+    config.handling.arr = 0;
+    config.bind(KeySection::Solo, Action::HardDrop, "Up", true).unwrap();
+    config.save(&path).unwrap();
+    let loaded = Config::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, config);
+    assert_eq!(keys(&loaded, KeySection::Solo, Action::HardDrop), vec!["Space", "Up"]);
+}
+
+#[test]
+fn gamepads_have_their_own_bindings() {
+    let config = Config::parse(&format!("{}\n[gamepad.controllers.\"Arcade Stick\"]\nleft = [\"dpleft\"]\nright = [\"dpright\"]\nsoft_drop = [\"dpdown\"]\n\
+                                         hard_drop = [\"dpup\"]\nhold = [\"x\"]\nrotate_cw = [\"a\"]\nrotate_ccw = [\"b\"]\nquit = [\"start\"]\nrestart = [\"y\"]\n",
+                                        DEFAULT_CONFIG)).unwrap();
+    assert_eq!(config.gamepad.buttons_for("Arcade Stick")[&Action::Hold], vec!["x"]);
+    assert_eq!(config.gamepad.buttons_for("Xbox Controller")[&Action::Left], vec!["dpleft", "leftx-"]);
+
+    assert_eq!(error("[gamepad]\ndeadzone = 1.5\n"), "gamepad.deadzone = 1.5 is out of range, expected 0.05 to 0.95");
+    assert_eq!(error("[gamepad]\nmappings = [\"030000005e040000,Xbox,a:b0\"]\n"),
+               "gamepad.mappings: entry 1 doesn't start with a 32 digit GUID and a name");
+    Config::parse("[gamepad]\nmappings = [\"030000005e0400008e02000014010000,Xbox 360,a:b0,b:b1,platform:Linux,\"]\n").unwrap();
+    assert_eq!(error("[gamepad.controllers.Pad]\nleft = []\n"), "gamepad.controllers.\"Pad\".left: needs at least one key");
+}
@@ -0,0 +1,157 @@
+use tetrust::game::*;
+use tetrust::garbage::GarbageGenerator;
+use tetrust::modes::dig::Dig;
+use tetrust::tetrust::*;
+
+fn hole(row: &[TileColor]) -> usize {
+    assert_eq!(row.iter().filter(|&&t| t == TileColor::Empty).count(), 1);
+    row.iter().position(|&t| t == TileColor::Empty).unwrap()
+}
+
+fn garbage(width: u16, count: u16) -> Vec<Vec<TileColor>> {
+    GarbageGenerator::new(width, 0.0, 0).rows(count)
+}
+
+// A board with only `count` garbage rows on it, as if the rest were cleared.
+fn cleared_to(count: u16) -> TileCanvas {
+    let mut canvas = TileCanvas::new(10, 20);
+    canvas.insert_rows(garbage(10, count), 0);
+    canvas
+}
+
+#[test]
+fn rows_go_in_at_the_bottom() {
+    let mut canvas = TileCanvas::new(4, 4);
+    canvas.set_tile(0, 3, TileColor::Red);
+    let rows = vec![vec![TileColor::Gray, TileColor::Empty, TileColor::Gray, TileColor::Gray]; 2];
+    assert!(!canvas.insert_rows(rows, 5));
+    assert_eq!(canvas.get_tile(0, 1), TileColor::Red);
+    assert_eq!(canvas.get_tile(1, 3), TileColor::Empty);
+    assert_eq!(canvas.get_tile_override(2, 3), Tile(TileColor::Gray, 5));
+    assert_eq!(canvas.count_rows(TileColor::Gray), 2);
+
+    // The red tile is pushed out of the top.
+    assert!(canvas.insert_rows(garbage(4, 2), 6));
+    assert_eq!(canvas.count_rows(TileColor::Red), 0);
+    assert_eq!(canvas.count_rows(TileColor::Gray), 4);
+}
+
+#[test]
+#[should_panic(expected = "garbage row of 9 tiles on a board 10 wide")]
+fn rows_must_fit_the_board() {
+    let mut canvas = TileCanvas::new(10, 20);
+    canvas.insert_rows(garbage(9, 1), 0);
+}
+
+#[test]
+fn garbage_lifts_the_active_piece() {
+    let queue = PieceQueue::fixed(vec![PieceEnum::I; 2]);
+    let mut tetris = Tetris::with_queue(TileCanvas::new(10, 20), queue);
+    tetris.add_garbage(garbage(10, 18));
+    assert_eq!(tetris.active_piece().y, 0);
+
+    // The I fills the second row, so the next row pushes it up with the stack.
+    tetris.add_garbage(garbage(10, 1));
+    assert_eq!(tetris.active_piece().y, -1);
+    assert!(!tetris.check_loss());
+    tetris.add_garbage(garbage(10, 1));
+    assert_eq!(tetris.active_piece().y, -2);
+    assert!(!tetris.check_loss());
+
+    tetris.add_garbage(garbage(10, 1));
+    assert!(tetris.check_loss());
+}
+
+#[test]
+fn game_tops_out_from_garbage() {
+    let mut game = Game::new(Tetris::new(TileCanvas::new(10, 20), 0), 0, Timings::default());
+    game.add_garbage(garbage(10, 4));
+    assert_eq!(game.events(), &[Event::Garbage(4)]);
+    assert!(!game.is_over());
+    game.add_garbage(garbage(10, 20));
+    assert!(game.is_over());
+}
+
+#[test]
+fn generator_is_seeded() {
+    let rows = GarbageGenerator::new(10, 0.5, 42).rows(20);
+    assert_eq!(rows, GarbageGenerator::new(10, 0.5, 42).rows(20));
+    for row in &rows {
+        assert_eq!(row.len(), 10);
+        assert!(row.iter().all(|&t| t == TileColor::Gray || t == TileColor::Empty));
+        hole(row);
+    }
+}
+
+#[test]
+fn messiness_moves_the_hole() {
+    let rows = GarbageGenerator::new(10, 0.0, 3).rows(20);
+    assert!(rows.iter().all(|row| hole(row) == hole(&rows[0])));
+    let rows = GarbageGenerator::new(10, 1.0, 3).rows(20);
+    assert!(rows.windows(2).all(|pair| hole(&pair[0]) != hole(&pair[1])));
+
+    // Out of range messiness is clamped, and NaN keeps the hole still.
+    let rows = GarbageGenerator::new(10, 5.0, 3).rows(20);
+    assert!(rows.windows(2).all(|pair| hole(&pair[0]) != hole(&pair[1])));
+    let rows = GarbageGenerator::new(10, f64::NAN, 3).rows(20);
+    assert!(rows.iter().all(|row| hole(row) == hole(&rows[0])));
+
+    // A one-wide board has nowhere else to put the hole.
+    assert!(GarbageGenerator::new(1, 1.0, 3).rows(5).iter().all(|row| hole(row) == 0));
+}
+
+#[test]
+fn chunks_share_a_hole() {
+    let mut generator = GarbageGenerator::new(10, 1.0, 9);
+    let chunk = generator.chunk(3);
+    assert_eq!(chunk.len(), 3);
+    assert!(chunk.iter().all(|row| hole(row) == hole(&chunk[0])));
+    assert_ne!(hole(&generator.chunk(1)[0]), hole(&chunk[0]));
+}
+
+#[test]
+fn dig_refills_until_every_line_is_sent() {
+    let mut tetris = Tetris::new(TileCanvas::new(10, 20), 0);
+    let mut dig = Dig::new(10, 5, 3, 0.5, 1);
+    dig.setup(&mut tetris);
+    assert_eq!(tetris.tile_canvas.count_rows(TileColor::Gray), 3);
+    assert_eq!(dig.remaining(), 5);
+
+    // Two rows cleared: the last two are sent.
+    tetris.tile_canvas = cleared_to(1);
+    dig.update(&mut tetris);
+    assert_eq!(tetris.tile_canvas.count_rows(TileColor::Gray), 3);
+    assert_eq!(dig.remaining(), 3);
+
+    // Everything is sent, so only the rows left on the board remain.
+    tetris.tile_canvas = cleared_to(1);
+    dig.update(&mut tetris);
+    assert_eq!(tetris.tile_canvas.count_rows(TileColor::Gray), 1);
+    assert_eq!(dig.remaining(), 1);
+    assert!(!dig.is_cleared());
+
+    tetris.tile_canvas = cleared_to(0);
+    dig.update(&mut tetris);
+    assert_eq!(tetris.tile_canvas.count_rows(TileColor::Gray), 0);
+    assert!(dig.is_cleared());
+}
+
+#[test]
+fn dig_keeps_the_visible_rows_without_refilling() {
+    let mut tetris = Tetris::new(TileCanvas::new(10, 20), 0);
+    let mut dig = Dig::new(10, 10, 4, 0.0, 1);
+    dig.setup(&mut tetris);
+    let board = tetris.tile_canvas.clone();
+
+    // No garbage cleared, so nothing is added.
+    dig.update(&mut tetris);
+    assert!(tetris.tile_canvas.diff(&board).is_empty());
+    assert_eq!(dig.remaining(), 10);
+
+    // Fewer lines than can be visible are all sent at once.
+    let mut tetris = Tetris::new(TileCanvas::new(10, 20), 0);
+    let mut dig = Dig::new(10, 2, 4, 0.0, 1);
+    dig.setup(&mut tetris);
+    assert_eq!(tetris.tile_canvas.count_rows(TileColor::Gray), 2);
+    assert_eq!(dig.remaining(), 2);
+}
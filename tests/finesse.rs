@@ -0,0 +1,85 @@
+use tetrust::finesse::{self, Finesse};
+use tetrust::game::*;
+use tetrust::movegen::Action;
+use tetrust::tetrust::*;
+
+fn game(pieces: Vec<PieceEnum>) -> Game {
+    Game::new(Tetris::with_queue(TileCanvas::new(10, 20), PieceQueue::fixed(pieces)), 0, Timings::default())
+}
+
+// Presses each action for `frames` frames, letting go in between.
+fn press(game: &mut Game, finesse: &mut Finesse, actions: &[(Action, u32)]) {
+    for &(action, frames) in actions {
+        for _ in 0..frames {
+            game.step(action.input());
+            finesse.update(game, action.input());
+        }
+        game.step(Input::default());
+        finesse.update(game, Input::default());
+    }
+}
+
+#[test]
+fn optimal_placements_are_clean() {
+    let mut game = game(vec![PieceEnum::T, PieceEnum::T, PieceEnum::O]);
+    let mut finesse = Finesse::new();
+    // Far left, then right wall standing up.
+    press(&mut game, &mut finesse, &[(Action::DasLeft, 20), (Action::HardDrop, 1)]);
+    press(&mut game, &mut finesse, &[(Action::RotateCcw, 1), (Action::DasRight, 20), (Action::HardDrop, 1)]);
+    assert_eq!(finesse.pieces(), 2);
+    assert_eq!(finesse.faults(), 0);
+    assert_eq!(finesse.log()[0].inputs, vec![Action::DasLeft, Action::HardDrop]);
+    assert_eq!(finesse.log()[1].inputs, finesse.log()[1].optimal);
+    assert_eq!(finesse.accuracy(), 100.0);
+}
+
+#[test]
+fn extra_presses_are_faults() {
+    let mut game = game(vec![PieceEnum::T, PieceEnum::O]);
+    let mut finesse = Finesse::new();
+    // Tapping to the wall instead of holding, plus a spin back and forth.
+    let taps = [(Action::Left, 1), (Action::Left, 1), (Action::Left, 1), (Action::Left, 1), (Action::RotateCw, 1), (Action::RotateCcw, 1), (Action::HardDrop, 1)];
+    press(&mut game, &mut finesse, &taps);
+    let log = &finesse.log()[0];
+    assert_eq!(log.piece, PieceEnum::T);
+    assert_eq!(log.inputs.len(), 7);
+    assert_eq!(log.optimal, vec![Action::DasLeft, Action::HardDrop]);
+    assert!(log.is_fault());
+    assert_eq!(finesse.extra_presses(), 5);
+    assert!(finesse.recent_fault(game.frames()).is_some());
+    assert!(finesse.recent_fault(game.frames() + finesse::FAULT_FRAMES).is_none());
+    assert_eq!(finesse::format_inputs(&log.optimal), "DL HD");
+}
+
+#[test]
+fn hold_starts_the_piece_over() {
+    let mut game = game(vec![PieceEnum::T, PieceEnum::I, PieceEnum::O]);
+    let mut finesse = Finesse::new();
+    press(&mut game, &mut finesse, &[(Action::Right, 1), (Action::Hold, 1), (Action::DasLeft, 20), (Action::HardDrop, 1)]);
+    let log = &finesse.log()[0];
+    assert_eq!(log.piece, PieceEnum::I);
+    assert_eq!(log.inputs, vec![Action::DasLeft, Action::HardDrop]);
+    assert!(!log.is_fault());
+}
+
+#[test]
+fn presses_during_are_are_not_charged_to_the_next_piece() {
+    let mut game = Game::new(Tetris::with_queue(TileCanvas::new(10, 20), PieceQueue::fixed(vec![PieceEnum::T, PieceEnum::T, PieceEnum::T, PieceEnum::O])), 0,
+                             Timings { are: 30, ..Timings::default() });
+    let mut finesse = Finesse::new();
+    press(&mut game, &mut finesse, &[(Action::DasLeft, 20), (Action::HardDrop, 1)]);
+    // Spinning while waiting for the next piece, which then goes straight down.
+    press(&mut game, &mut finesse, &[(Action::RotateCw, 1), (Action::RotateCcw, 1)]);
+    while !game.is_falling() {
+        game.step(Input::default());
+        finesse.update(&game, Input::default());
+    }
+    press(&mut game, &mut finesse, &[(Action::HardDrop, 1)]);
+    assert_eq!(finesse.log()[1].inputs, vec![Action::HardDrop]);
+    assert_eq!(finesse.faults(), 0);
+
+    // A shift held from before the spawn moves the piece, and counts once.
+    press(&mut game, &mut finesse, &[(Action::DasRight, 40), (Action::HardDrop, 1)]);
+    assert_eq!(finesse.log()[2].inputs, vec![Action::DasRight, Action::HardDrop]);
+    assert_eq!(finesse.faults(), 0);
+}
@@ -0,0 +1,93 @@
+use tetrust::fumen::{self, Page};
+use tetrust::notation::{format_board, parse_board};
+use tetrust::tetrust::*;
+
+const BOARD: &str = "\
+IIII......
+ZZ....OOXX
+TZZ...OOXX
+TTSSLJJJXX
+TSSLLLJXX.
+";
+
+fn board() -> TileCanvas {
+    parse_board(BOARD, 10, 20).unwrap()
+}
+
+fn same_board(a: &TileCanvas, b: &TileCanvas) -> bool {
+    format_board(a) == format_board(b) && a.height() == b.height()
+}
+
+#[test]
+fn ascii_round_trip() {
+    assert_eq!(format_board(&board()), BOARD);
+    assert_eq!(format_board(&TileCanvas::new(10, 20)), "");
+    assert!(parse_board("XX.", 10, 20).is_err());
+    assert!(parse_board("XXXXXXXXX?", 10, 20).is_err());
+}
+
+#[test]
+fn empty_fumen() {
+    assert_eq!(fumen::encode(&[Page::new(TileCanvas::new(10, 20))]).unwrap(), "v115@vhAAgH");
+    let pages = fumen::decode("https://harddrop.com/fumen/?v115@vhAAgH", 20).unwrap();
+    assert_eq!(pages.len(), 1);
+    assert!(pages[0].board.is_empty() && pages[0].piece.is_none());
+}
+
+#[test]
+fn single_page_round_trip() {
+    let code = fumen::encode(&[Page::new(board())]).unwrap();
+    let pages = fumen::decode(&code, 20).unwrap();
+    assert_eq!(pages.len(), 1);
+    assert!(same_board(&pages[0].board, &board()));
+}
+
+#[test]
+fn every_piece_and_rotation_round_trips() {
+    let pieces = [PieceEnum::O, PieceEnum::S, PieceEnum::Z, PieceEnum::T, PieceEnum::L, PieceEnum::J, PieceEnum::I];
+    for &piece in pieces.iter() {
+        for state in 0..4 {
+            let piece = TetrisPiece { x: 3, y: 10, piece, state };
+            let page = Page { board: board(), piece: Some(piece), comment: String::new() };
+            let pages = fumen::decode(&fumen::encode(&[page]).unwrap(), 20).unwrap();
+            assert_eq!(pages[0].piece, Some(piece));
+        }
+    }
+}
+
+#[test]
+fn multi_page_round_trip() {
+    let mut tetris = Tetris::with_queue(board(), PieceQueue::fixed(vec![PieceEnum::I, PieceEnum::O, PieceEnum::T, PieceEnum::L]));
+    let mut pages = Vec::new();
+    while tetris.spawn_next() {
+        tetris.rotate_piece(true);
+        tetris.move_piece(2, 0);
+        tetris.hard_drop();
+        pages.push(Page { board: tetris.tile_canvas.clone(), piece: Some(tetris.active_piece()), comment: String::new() });
+        tetris.disable_piece();
+    }
+    pages.push(Page::new(tetris.tile_canvas.clone()));
+    // Repeated fields and comments, including ones that need escaping.
+    pages.push(Page::new(tetris.tile_canvas.clone()));
+    pages[0].comment = String::from("TSD setup: 100% ⚡");
+    pages[2].comment = String::from("next");
+
+    let code = fumen::encode(&pages).unwrap();
+    let decoded = fumen::decode(&code, 20).unwrap();
+    assert_eq!(decoded.len(), pages.len());
+    for (a, b) in pages.iter().zip(decoded.iter()) {
+        assert!(same_board(&a.board, &b.board));
+        assert_eq!(a.piece, b.piece);
+        assert_eq!(a.comment, b.comment);
+    }
+    assert_eq!(fumen::encode(&decoded).unwrap(), code);
+}
+
+#[test]
+fn rejects_boards_that_do_not_fit() {
+    assert!(fumen::encode(&[Page::new(TileCanvas::new(16, 19))]).is_err());
+    let tall = fumen::encode(&[Page::new(board())]).unwrap();
+    assert!(fumen::decode(&tall, 4).is_err());
+    assert!(fumen::decode("v115@!!", 20).is_err());
+    assert!(fumen::decode("not a fumen", 20).is_err());
+}
@@ -0,0 +1,110 @@
+use tetrust::game::*;
+use tetrust::notation::parse_board;
+use tetrust::tetrust::*;
+
+// Four rows with a well for a vertical I after one clockwise turn.
+const WELL: &str = "\
+XXXXX.XXXX
+XXXXX.XXXX
+XXXXX.XXXX
+XXXXX.XXXX
+";
+
+const DELAYS: Timings = Timings { are: 5, line_are: 8, das: 10, arr: 2, lock_delay: 30, line_clear: 10 };
+
+fn press(f: impl FnOnce(&mut Input)) -> Input {
+    let mut input = Input::default();
+    f(&mut input);
+    input
+}
+
+fn game(board: &str, gravity: u32, timings: Timings) -> Game {
+    let queue = PieceQueue::fixed(vec![PieceEnum::I; 4]);
+    Game::new(Tetris::with_queue(parse_board(board, 10, 20).unwrap(), queue), gravity, timings)
+}
+
+// Steps with nothing held until a piece spawns and returns the frame it did on.
+fn wait_for_spawn(game: &mut Game) -> u64 {
+    for _ in 0..100 {
+        let step = game.step(Input::default());
+        assert!(step.locked.is_none());
+        if step.spawned.is_some() {
+            return game.frames();
+        }
+        assert!(!game.is_falling());
+    }
+    panic!("nothing spawned");
+}
+
+#[test]
+fn are_follows_a_lock() {
+    let mut game = game("", 0, DELAYS);
+    let step = game.step(press(|i| i.hard_drop = true));
+    assert_eq!(step.locked.unwrap().clear.lines, 0);
+    assert!(!game.is_falling());
+    // Buttons do nothing while waiting.
+    let piece = game.tetris.active_piece();
+    game.step(press(|i| i.left = true));
+    assert_eq!(game.tetris.active_piece(), piece);
+    assert_eq!(wait_for_spawn(&mut game), 1 + DELAYS.are as u64);
+    assert!(game.is_falling());
+    assert_eq!(game.events(), &[Event::Spawned(PieceEnum::I)]);
+}
+
+#[test]
+fn line_clears_wait_for_the_clear_and_line_are() {
+    let mut game = game(WELL, 0, DELAYS);
+    game.step(press(|i| i.rotate_cw = true));
+    let step = game.step(press(|i| i.hard_drop = true));
+    assert_eq!(step.locked.unwrap().clear.lines, 4);
+    assert_eq!(wait_for_spawn(&mut game), 2 + (DELAYS.line_clear + DELAYS.line_are) as u64);
+
+    // Without a clear delay line ARE starts at once, and without that the next
+    // piece appears on the frame of the lock.
+    let mut game = self::game(WELL, 0, Timings { line_clear: 0, ..DELAYS });
+    game.step(press(|i| i.rotate_cw = true));
+    game.step(press(|i| i.hard_drop = true));
+    assert_eq!(wait_for_spawn(&mut game), 2 + DELAYS.line_are as u64);
+    let mut game = self::game(WELL, 0, Timings { line_clear: 0, line_are: 0, ..DELAYS });
+    game.step(press(|i| i.rotate_cw = true));
+    let step = game.step(press(|i| i.hard_drop = true));
+    assert!(step.locked.is_some() && step.spawned.is_some());
+    assert!(game.is_falling());
+}
+
+#[test]
+fn pieces_lock_after_the_lock_delay() {
+    let mut game = game("", GRAVITY_20G, DELAYS);
+    for frame in 1..DELAYS.lock_delay {
+        let step = game.step(Input::default());
+        assert!(step.locked.is_none(), "locked on frame {}", frame);
+        assert!(game.tetris.on_ground());
+    }
+    assert!(game.step(Input::default()).locked.is_some());
+    assert_eq!(game.frames(), DELAYS.lock_delay as u64);
+}
+
+#[test]
+fn falling_resets_the_lock_delay() {
+    // One row per frame: the piece only touches down on the floor.
+    let mut game = game("", GRAVITY_UNIT, Timings { lock_delay: 3, ..DELAYS });
+    let mut frames = 0;
+    while !game.tetris.on_ground() {
+        assert!(game.step(Input::default()).locked.is_none());
+        frames += 1;
+    }
+    assert_eq!(frames, 18);
+    assert!(game.step(Input::default()).locked.is_none());
+    assert!(game.step(Input::default()).locked.is_some());
+}
+
+#[test]
+fn soft_drop_is_counted_for_the_lock() {
+    let mut game = game("", 0, DELAYS);
+    for _ in 0..5 {
+        game.step(press(|i| i.soft_drop = true));
+    }
+    assert_eq!(game.tetris.active_piece().y, 5);
+    let lock = game.step(press(|i| i.hard_drop = true)).locked.unwrap();
+    assert_eq!(lock.soft_drop, 5);
+}
@@ -0,0 +1,116 @@
+use tetrust::bot::Weights;
+use tetrust::game::*;
+use tetrust::gym::{self, Action, Env};
+
+// Index of the placement the bot's weights like best.
+fn best(env: &mut Env) -> usize {
+    let tetris = env.game().tetris.clone();
+    let weights = Weights::default();
+    let scores: Vec<f64> = env.placements().iter().map(|m| {
+        let mut after = tetris.clone();
+        if m.hold {
+            after.hold_piece();
+        }
+        after.place_piece(m.placement.piece, m.placement.kick);
+        let clear = after.disable_piece();
+        weights.evaluate(&after.tile_canvas, &clear)
+    }).collect();
+    (0..scores.len()).max_by(|&a, &b| scores[a].total_cmp(&scores[b])).expect("no placements")
+}
+
+#[test]
+fn reset_is_deterministic() {
+    let mut env = Env::new(10, 20).unwrap();
+    let first = env.reset(4);
+    env.step(Action::from_bits(Input { hard_drop: true, ..Input::default() }.bits()));
+    assert_eq!(env.reset(4), first);
+    assert_ne!(env.reset(5).queue, first.queue);
+    assert_eq!(first.board, vec![0; 20]);
+    assert_eq!(first.hold, gym::NO_PIECE);
+    assert!(Env::new(65, 20).is_err());
+    assert!(Env::new(3, 20).is_err());
+    assert!(Env::new(10, 0).is_err());
+}
+
+#[test]
+fn inputs_play_frame_by_frame() {
+    let mut env = Env::new(10, 20).unwrap();
+    let start = env.reset(1);
+    let (obs, _, done, info) = env.step(Action::Input(Input { left: true, ..Input::default() }));
+    assert_eq!((obs.x, info.frames, done), (start.x - 1, 1, false));
+    let (obs, reward, _, info) = env.step(Action::Input(Input { hard_drop: true, ..Input::default() }));
+    assert_eq!((info.pieces, reward), (1, 0.0));
+    assert_eq!(obs.board.iter().map(|row| row.count_ones()).sum::<u32>(), 4);
+    assert_eq!(obs.board[..18], [0; 18]);
+    // Holding hard drop doesn't drop the next piece too.
+    let (_, _, _, info) = env.step(Action::Input(Input { hard_drop: true, ..Input::default() }));
+    assert_eq!(info.pieces, 1);
+    let (obs, _, _, _) = env.step(Action::Input(Input { hold: true, ..Input::default() }));
+    assert_eq!(obs.hold, start.queue[0]);
+}
+
+#[test]
+fn placements_play_whole_pieces() {
+    let mut env = Env::new(10, 20).unwrap();
+    env.max_pieces = Some(100);
+    env.reset(2);
+    let (mut total, mut done, mut steps) = (0.0, false, 0);
+    let mut last = gym::Info::default();
+    while !done {
+        let action = Action::Placement(best(&mut env));
+        let (_, reward, d, info) = env.step(action);
+        assert!(!info.invalid);
+        total += reward;
+        done = d;
+        last = info;
+        steps += 1;
+    }
+    assert_eq!((steps, last.pieces), (100, 100));
+    assert!(last.lines >= 30, "only {} lines", last.lines);
+    assert!(total >= last.lines as f64);
+    assert!(!env.game().is_over());
+
+    env.reset(2);
+    let (_, reward, done, info) = env.step(Action::Placement(10_000));
+    assert!(info.invalid && !done && reward == 0.0);
+    assert_eq!(info.pieces, 0);
+}
+
+#[test]
+fn topping_out_ends_the_episode() {
+    let mut env = Env::new(10, 20).unwrap();
+    env.reset(3);
+    let mut total = 0.0;
+    for _ in 0..100 {
+        // The spawn position, dropped straight down.
+        let (_, reward, done, _) = env.step(Action::Placement(0));
+        total += reward;
+        if done {
+            break;
+        }
+    }
+    assert!(env.is_done() && env.game().is_over());
+    assert_eq!(total, env.rewards.top_out);
+    let (_, reward, done, _) = env.step(Action::Placement(0));
+    assert!(done && reward == 0.0);
+}
+
+#[test]
+fn placements_follow_the_piece() {
+    let mut env = Env::new(10, 20).unwrap();
+    env.reset(6);
+    let check = |env: &mut Env| {
+        let moves = gym::moves(env.game());
+        assert_eq!(env.placements(), moves.as_slice());
+    };
+    check(&mut env);
+    for input in [Input { left: true, ..Input::default() }, Input::default(), Input { rotate_cw: true, ..Input::default() },
+                  Input { hold: true, ..Input::default() }, Input { hard_drop: true, ..Input::default() }] {
+        env.step(Action::Input(input));
+        check(&mut env);
+    }
+    for _ in 0..20 {
+        env.step(Action::Input(Input::default()));
+        check(&mut env);
+    }
+}
@@ -0,0 +1,233 @@
+use tetrust::game::*;
+use tetrust::modes::master::{self, Master};
+use tetrust::tetrust::*;
+
+fn game() -> Game {
+    Game::new(Tetris::new(TileCanvas::new(master::MASTER_WIDTH, master::MASTER_HEIGHT), 0), 0, Timings::default())
+}
+
+fn clear(lines: u16, soft_drop: u32) -> Step {
+    Step { spawned: None, locked: Some(Lock { clear: Clear { lines, tspin: TSpin::None, perfect: false }, soft_drop }) }
+}
+
+fn spawn() -> Step {
+    Step { spawned: Some(PieceEnum::T), locked: None }
+}
+
+// Lets `frames` frames pass without the piece landing.
+fn wait(game: &mut Game, frames: u64) {
+    let gravity = game.gravity;
+    game.gravity = 0;
+    for _ in 0..frames {
+        game.step(Input::default());
+    }
+    game.gravity = gravity;
+}
+
+// Clears singles until `master` reaches `level`.
+fn singles_to(master: &mut Master, game: &mut Game, level: u16) {
+    while master.level() < level {
+        master.update(game, &clear(1, 0));
+    }
+}
+
+#[test]
+fn gravity_table() {
+    assert_eq!(master::gravity_for(0), 4);
+    assert_eq!(master::gravity_for(29), 4);
+    assert_eq!(master::gravity_for(30), 6);
+    assert_eq!(master::gravity_for(199), 144);
+    // The drop back down at 200.
+    assert_eq!(master::gravity_for(200), 4);
+    assert_eq!(master::gravity_for(251), 256);
+    assert_eq!(master::gravity_for(499), 768);
+    assert_eq!(master::gravity_for(500), GRAVITY_20G);
+    assert_eq!(master::gravity_for(999), GRAVITY_20G);
+}
+
+#[test]
+fn timing_table() {
+    assert_eq!(master::timings_for(0), Timings { are: 25, line_are: 25, das: 14, arr: 1, lock_delay: 30, line_clear: 40 });
+    assert_eq!(master::timings_for(499), master::timings_for(0));
+    assert_eq!(master::timings_for(500), Timings { are: 25, line_are: 25, das: 8, arr: 1, lock_delay: 30, line_clear: 25 });
+    assert_eq!(master::timings_for(899), Timings { are: 12, line_are: 6, das: 8, arr: 1, lock_delay: 30, line_clear: 6 });
+    assert_eq!(master::timings_for(900), Timings { are: 12, line_are: 6, das: 6, arr: 1, lock_delay: 17, line_clear: 6 });
+    assert_eq!(master::timings_for(999), master::timings_for(900));
+}
+
+#[test]
+fn times_are_minutes_seconds_and_hundredths() {
+    assert_eq!(master::format_time(0), "00:00.00");
+    assert_eq!(master::format_time(30), "00:00.50");
+    assert_eq!(master::format_time(61 * 60 + 6), "01:01.10");
+    assert_eq!(master::format_time((13 * 60 + 30) * 60), "13:30.00");
+}
+
+#[test]
+fn levels_stop_at_the_end_of_each_section() {
+    let mut game = game();
+    let mut master = Master::new();
+    master.setup(&mut game);
+    assert_eq!((game.gravity, game.timings), (4, master::timings_for(0)));
+    for _ in 0..150 {
+        master.update(&mut game, &spawn());
+    }
+    assert_eq!((master.level(), master.level_stop()), (99, 100));
+    // Only a clear gets past the stop.
+    master.update(&mut game, &clear(2, 0));
+    assert_eq!((master.level(), master.level_stop()), (101, 200));
+    master.update(&mut game, &spawn());
+    assert_eq!(master.level(), 102);
+    // A lock without a clear isn't a spawn.
+    master.update(&mut game, &clear(0, 0));
+    assert_eq!(master.level(), 102);
+
+    singles_to(&mut master, &mut game, 900);
+    assert_eq!(master.level_stop(), 999);
+    for _ in 0..150 {
+        master.update(&mut game, &spawn());
+    }
+    assert_eq!(master.level(), 998);
+}
+
+#[test]
+fn levels_set_the_speed() {
+    let mut game = game();
+    let mut master = Master::new();
+    master.setup(&mut game);
+    singles_to(&mut master, &mut game, 200);
+    assert_eq!(game.gravity, 4);
+    singles_to(&mut master, &mut game, 500);
+    assert_eq!((game.gravity, game.timings), (GRAVITY_20G, master::timings_for(500)));
+    singles_to(&mut master, &mut game, 900);
+    assert_eq!(game.timings.lock_delay, 17);
+}
+
+#[test]
+fn sections_are_timed() {
+    let mut game = game();
+    let mut master = Master::new();
+    master.setup(&mut game);
+    wait(&mut game, 600);
+    singles_to(&mut master, &mut game, 99);
+    assert!(master.section_times().is_empty());
+    wait(&mut game, 100);
+    master.update(&mut game, &clear(1, 0));
+    assert_eq!(master.section_times(), &[700]);
+    wait(&mut game, 50);
+    singles_to(&mut master, &mut game, 200);
+    assert_eq!(master.section_times(), &[700, 50]);
+}
+
+#[test]
+fn clears_score_grade_points() {
+    let mut game = game();
+    let mut master = Master::new();
+    master.setup(&mut game);
+    // (level + lines) / 4 rounded up, plus soft drop frames, times lines.
+    master.update(&mut game, &clear(1, 0));
+    assert_eq!(master.score(), 1);
+    master.update(&mut game, &clear(0, 0));
+    master.update(&mut game, &clear(2, 3));
+    assert_eq!(master.score(), 1 + (1 + 3) * 2 * 3);
+    // Clears in a row multiply by the combo, and perfect clears by four.
+    let step = Step { spawned: None, locked: Some(Lock { clear: Clear { lines: 4, tspin: TSpin::None, perfect: true }, soft_drop: 0 }) };
+    master.update(&mut game, &step);
+    assert_eq!(master.score(), 25 + 2 * 4 * 9 * 4);
+    assert_eq!(master.level(), 7);
+}
+
+#[test]
+fn grades_change_at_the_table_boundaries() {
+    let mut game = game();
+    let mut master = Master::new();
+    master.setup(&mut game);
+    assert_eq!(master.grade(), "9");
+    master.update(&mut game, &clear(1, 398));
+    assert_eq!((master.score(), master.grade()), (399, "9"));
+    master.update(&mut game, &clear(1, 0));
+    assert_eq!((master.score(), master.grade()), (400, "8"));
+    master.update(&mut game, &clear(1, 11_998));
+    assert_eq!((master.score(), master.grade()), (12_399, "1"));
+    master.update(&mut game, &clear(1, 3_600));
+    assert_eq!((master.score(), master.grade()), (16_000, "S1"));
+    master.update(&mut game, &clear(1, 103_997));
+    assert_eq!((master.score(), master.grade()), (119_999, "S8"));
+    master.update(&mut game, &clear(1, 0));
+    assert_eq!((master.score(), master.grade()), (120_001, "S9"));
+}
+
+// Clears singles up to `level`, the last with enough soft drop to leave the
+// score at exactly `score`.
+fn single_to_score(master: &mut Master, game: &mut Game, level: u16, score: u32) {
+    singles_to(master, game, level - 1);
+    let points = (master.level() as u32 + 1).div_ceil(4);
+    master.update(game, &clear(1, score - master.score() - points));
+    assert_eq!((master.level(), master.score()), (level, score));
+}
+
+// Tetrises with enough soft drop to pass every grade requirement.
+fn tetrises_to_the_end(master: &mut Master, game: &mut Game) {
+    while !master.is_finished() {
+        master.update(game, &clear(4, 1_000));
+    }
+}
+
+#[test]
+fn fast_strong_games_reach_grand_master() {
+    let mut game = game();
+    let mut master = Master::new();
+    master.setup(&mut game);
+    tetrises_to_the_end(&mut master, &mut game);
+    assert_eq!(master.level(), 999);
+    assert_eq!(master.section_times().len(), 10);
+    assert_eq!(master.grade(), "GM");
+    // Nothing counts once the game is over.
+    let score = master.score();
+    master.update(&mut game, &clear(4, 0));
+    assert_eq!(master.score(), score);
+}
+
+#[test]
+fn checkpoints_need_the_grade_in_time() {
+    // Too weak at 300.
+    let mut game = game();
+    let mut master = Master::new();
+    master.setup(&mut game);
+    singles_to(&mut master, &mut game, 300);
+    tetrises_to_the_end(&mut master, &mut game);
+    assert_eq!(master.grade(), "S9");
+
+    // Strong enough but too slow at 500.
+    let mut game = self::game();
+    let mut master = Master::new();
+    master.setup(&mut game);
+    while master.level() < 480 {
+        master.update(&mut game, &clear(4, 1_000));
+    }
+    wait(&mut game, (7 * 60 + 31) * 60);
+    tetrises_to_the_end(&mut master, &mut game);
+    assert_eq!(master.grade(), "S9");
+
+    // Just in time.
+    let mut game = self::game();
+    let mut master = Master::new();
+    master.setup(&mut game);
+    while master.level() < 480 {
+        master.update(&mut game, &clear(4, 1_000));
+    }
+    wait(&mut game, (7 * 60 + 30) * 60);
+    tetrises_to_the_end(&mut master, &mut game);
+    assert_eq!(master.grade(), "GM");
+
+    // Grade 1 at 300 and S4 at 500 are just enough, one point less isn't.
+    for (at_300, at_500, grade) in [(12_000, 40_000, "GM"), (11_999, 40_000, "S9"), (12_000, 39_999, "S9")] {
+        let mut game = self::game();
+        let mut master = Master::new();
+        master.setup(&mut game);
+        single_to_score(&mut master, &mut game, 300, at_300);
+        single_to_score(&mut master, &mut game, 500, at_500);
+        tetrises_to_the_end(&mut master, &mut game);
+        assert_eq!(master.grade(), grade, "{} at 300, {} at 500", at_300, at_500);
+    }
+}
@@ -0,0 +1,46 @@
+use tetrust::menu::{Item, Menu, Nav};
+
+#[test]
+fn selection_wraps_and_survives_new_items() {
+    let mut menu = Menu::new("Title", vec![Item::new(1, "One"), Item::with_value(2, "Two", String::from("2")), Item::new(3, "Three")]);
+    assert_eq!(menu.selected(), Some(1));
+    menu.up();
+    assert_eq!(menu.selected(), Some(3));
+    menu.down();
+    menu.down();
+    assert_eq!(menu.selected(), Some(2));
+    assert_eq!(menu.lines(), vec!["  One", "> Two: 2", "  Three"]);
+
+    menu.set_items(vec![Item::new(0, "Zero"), Item::new(2, "Two")]);
+    assert_eq!(menu.selected_index(), 1);
+    menu.set_items(vec![Item::new(5, "Five")]);
+    assert_eq!(menu.selected(), Some(5));
+    menu.select(7);
+    assert_eq!(menu.selected(), Some(5));
+
+    let mut empty: Menu<u8> = Menu::new("Empty", Vec::new());
+    empty.down();
+    assert_eq!(empty.selected(), None);
+}
+
+#[test]
+fn long_menus_scroll_to_the_selection() {
+    let mut menu = Menu::new("Keys", (0..10).map(|i| Item::new(i, &i.to_string())).collect());
+    assert_eq!(menu.visible_lines(20).len(), 10);
+    assert_eq!(menu.visible_lines(4), vec!["> 0", "  1", "  2", "  3"]);
+    menu.select(5);
+    assert_eq!(menu.visible_lines(4), vec!["  3", "  4", "> 5", "  6"]);
+    menu.select(9);
+    assert_eq!(menu.visible_lines(4), vec!["  6", "  7", "  8", "> 9"]);
+    assert!(menu.visible_lines(0).is_empty());
+}
+
+#[test]
+fn navigating_chooses_the_selected_item() {
+    let mut menu = Menu::new("Paused", vec![Item::new('r', "Resume"), Item::new('m', "Main menu"), Item::new('q', "Quit")]);
+    assert_eq!(menu.navigate(Nav::Up), None);
+    assert_eq!(menu.navigate(Nav::Back), None);
+    assert_eq!(menu.navigate(Nav::Select), Some('q'));
+    menu.navigate(Nav::Down);
+    assert_eq!(menu.navigate(Nav::Select), Some('r'));
+}
@@ -0,0 +1,149 @@
+use tetrust::game::*;
+use tetrust::movegen::{self, Action, MoveFinder, Placement};
+use tetrust::notation::{format_board, parse_board};
+use tetrust::tetrust::*;
+
+const TSD: &str = "\
+XXX.......
+XX...XXXXX
+XXX.XXXXXX
+";
+
+const TST: &str = "\
+...XXXXXXX
+....XXXXXX
+XXX.XXXXXX
+XX..XXXXXX
+XXX.XXXXXX
+";
+
+// An overhang with room for an O under its left end.
+const TUCK: &str = "\
+XXXXXXX...
+..........
+..........
+";
+
+fn tetris(board: &str, piece: PieceEnum) -> Tetris {
+    Tetris::with_queue(parse_board(board, 10, 20).unwrap(), PieceQueue::fixed(vec![piece]))
+}
+
+fn find(tetris: &Tetris, cells: &[(i16, i16)], tspin: TSpin) -> Placement {
+    let mut cells = cells.to_vec();
+    cells.sort_unstable();
+    movegen::placements(tetris).into_iter()
+        .find(|p| {
+            let mut found = p.piece.cells();
+            found.sort_unstable();
+            found.to_vec() == cells && p.tspin == tspin
+        })
+        .unwrap_or_else(|| panic!("no placement at {:?}", cells))
+}
+
+// Presses the inputs through a game the way a player would and returns the
+// board and the clear once the piece locks.
+fn play(tetris: &Tetris, inputs: &[Action]) -> (TileCanvas, Clear) {
+    let mut game = Game::new(tetris.clone(), 0, Timings { lock_delay: u32::MAX, ..Timings::default() });
+    for &action in inputs {
+        let held = match action {
+            Action::DasLeft | Action::DasRight | Action::SonicDrop => 40,
+            _ => 1
+        };
+        for _ in 0..held {
+            if let Some(lock) = game.step(action.input()).locked {
+                return (game.tetris.tile_canvas, lock.clear);
+            }
+        }
+        game.step(Input::default());
+    }
+    panic!("the piece never locked");
+}
+
+fn check(tetris: &Tetris, placement: &Placement) -> Clear {
+    let mut expected = tetris.clone();
+    expected.place_piece(placement.piece, placement.kick);
+    let clear = expected.disable_piece();
+    let (board, played) = play(tetris, &placement.inputs);
+    assert_eq!(format_board(&board), format_board(&expected.tile_canvas));
+    assert_eq!(played, clear);
+    clear
+}
+
+#[test]
+fn empty_board() {
+    let counts = [(PieceEnum::O, 9), (PieceEnum::I, 17), (PieceEnum::S, 17), (PieceEnum::T, 34), (PieceEnum::L, 34)];
+    for &(piece, count) in counts.iter() {
+        let tetris = tetris("", piece);
+        let placements = movegen::placements(&tetris);
+        assert_eq!(placements.len(), count, "{:?}", piece);
+        for placement in placements.iter() {
+            check(&tetris, placement);
+        }
+    }
+
+    let tetris = tetris("", PieceEnum::T);
+    let left = find(&tetris, &[(1, 18), (0, 19), (1, 19), (2, 19)], TSpin::None);
+    assert_eq!(left.inputs, vec![Action::DasLeft, Action::HardDrop]);
+    let right = find(&tetris, &[(9, 17), (9, 18), (9, 19), (8, 18)], TSpin::None);
+    assert_eq!(right.inputs, vec![Action::RotateCcw, Action::DasRight, Action::HardDrop]);
+    let placements = movegen::placements(&tetris);
+    assert!(placements.windows(2).all(|w| w[0].inputs.len() <= w[1].inputs.len()));
+}
+
+#[test]
+fn finder_reuses_its_buffers() {
+    let mut finder = MoveFinder::new();
+    let boards = [(TSD, 10, 20), (TST, 10, 20), (TUCK, 10, 20), ("", 10, 40), (TSD, 10, 20), ("", 6, 8)];
+    for &(board, width, height) in boards.iter() {
+        for &piece in PieceEnum::ALL.iter() {
+            let tetris = Tetris::with_queue(parse_board(board, width, height).unwrap(), PieceQueue::fixed(vec![piece]));
+            assert_eq!(finder.placements(&tetris), movegen::placements(&tetris), "{:?} on\n{}", piece, board);
+        }
+    }
+}
+
+#[test]
+fn tuck() {
+    let tetris = tetris(TUCK, PieceEnum::O);
+    let placement = find(&tetris, &[(0, 18), (1, 18), (0, 19), (1, 19)], TSpin::None);
+    assert_eq!(placement.inputs, vec![Action::DasRight, Action::SonicDrop, Action::DasLeft, Action::HardDrop]);
+    check(&tetris, &placement);
+}
+
+#[test]
+fn tspin_double() {
+    let tetris = tetris(TSD, PieceEnum::T);
+    let placement = find(&tetris, &[(2, 18), (3, 18), (4, 18), (3, 19)], TSpin::Full);
+    assert_eq!(placement.inputs.len(), 5);
+    assert_eq!(placement.inputs[3], Action::RotateCw);
+    let clear = check(&tetris, &placement);
+    assert_eq!((clear.lines, clear.tspin), (2, TSpin::Full));
+
+    // The same cells reached without a spin are a separate placement.
+    let mini = find(&tetris, &[(3, 17), (2, 18), (3, 18), (4, 18)], TSpin::Mini);
+    assert_eq!(check(&tetris, &mini).lines, 1);
+}
+
+#[test]
+fn tspin_triple() {
+    let tetris = tetris(TST, PieceEnum::T);
+    let placement = find(&tetris, &[(3, 17), (2, 18), (3, 18), (3, 19)], TSpin::Full);
+    assert_eq!(placement.inputs, vec![Action::DasLeft, Action::SonicDrop, Action::Right, Action::RotateCcw, Action::HardDrop]);
+    let clear = check(&tetris, &placement);
+    assert_eq!((clear.lines, clear.tspin), (3, TSpin::Full));
+}
+
+#[test]
+fn steps_follow_the_inputs() {
+    let tetris = tetris(TUCK, PieceEnum::O);
+    let placement = find(&tetris, &[(0, 18), (1, 18), (0, 19), (1, 19)], TSpin::None);
+    let steps = movegen::steps(&tetris, tetris.active_piece(), &placement.inputs);
+    assert_eq!(steps.first().map(|s| s.0), Some(Action::Right));
+    assert!(steps.iter().all(|s| ![Action::DasLeft, Action::DasRight, Action::SonicDrop].contains(&s.0)));
+    assert_eq!(steps.last(), Some(&(Action::HardDrop, placement.piece)));
+    let mut piece = tetris.active_piece();
+    for &(action, after) in steps.iter() {
+        piece = movegen::apply(&tetris, &piece, action).map(|m| m.0).unwrap_or(piece);
+        assert_eq!(piece, after);
+    }
+}
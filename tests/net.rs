@@ -0,0 +1,83 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use tetrust::game::*;
+use tetrust::modes::versus::Versus;
+use tetrust::net::{self, Client};
+use tetrust::tetrust::*;
+
+const FRAMES: u64 = 600;
+
+fn start_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || net::serve(listener, net::DEFAULT_INPUT_DELAY));
+    addr
+}
+
+fn template() -> Game {
+    Game::new(Tetris::new(TileCanvas::new(10, 20), 0), GRAVITY_UNIT / 4, Timings::default())
+}
+
+// A different button pattern for each player that drops a piece now and then.
+fn scripted(player: usize, frame: u64) -> Input {
+    let mut input = Input::from_bits(((frame * 7 + player as u64 * 3) % 64) as u8 & !8);
+    input.hard_drop = frame.is_multiple_of(13 + player as u64 * 4);
+    input
+}
+
+// Plays until `FRAMES` frames have passed, returning the final state hash or
+// the error that ended the match. `tamper` corrupts the board on that frame.
+fn play(addr: String, tamper: Option<u64>) -> std::thread::JoinHandle<Result<u64, String>> {
+    std::thread::spawn(move || {
+        let mut client = Client::connect(&addr, template())?;
+        while client.frame() < FRAMES {
+            if Some(client.frame()) == tamper {
+                client.versus.players[0].game.tetris.tile_canvas.set_tile(0, 19, TileColor::Gray);
+            }
+            let input = scripted(client.player(), client.frame());
+            client.step(input)?;
+        }
+        let hash = net::state_hash(&client.versus);
+        client.close();
+        Ok(hash)
+    })
+}
+
+#[test]
+fn clients_stay_in_sync() {
+    let addr = start_server();
+    let first = play(addr.clone(), None);
+    let second = play(addr, None);
+    let first = first.join().unwrap().unwrap();
+    let second = second.join().unwrap().unwrap();
+    assert_eq!(first, second);
+}
+
+#[test]
+fn desync_is_detected() {
+    let addr = start_server();
+    let first = play(addr.clone(), Some(100));
+    let second = play(addr, None);
+    let error = first.join().unwrap().unwrap_err();
+    assert!(error.contains("desync"), "{}", error);
+    assert!(second.join().unwrap().is_err());
+}
+
+#[test]
+fn other_protocol_versions_are_rejected() {
+    let addr = start_server();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    writeln!(stream, "hello {}", net::PROTOCOL_VERSION + 1).unwrap();
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).unwrap();
+    assert!(line.starts_with("error"), "{}", line);
+}
+
+#[test]
+fn state_hashes_are_fixed() {
+    // Clients built with any compiler must agree, so the value never changes.
+    let mut versus = Versus::new(template(), 0.0, 0);
+    assert_eq!(net::state_hash(&versus), 4_427_567_457_209_816_709);
+    versus.players[1].game.tetris.tile_canvas.set_tile(3, 19, TileColor::Gray);
+    assert_eq!(net::state_hash(&versus), 12_077_292_147_353_357_101);
+}
@@ -0,0 +1,197 @@
+use std::time::{Duration, Instant};
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use tetrust::game::*;
+use tetrust::modes::opener::{self, Opener, OPENER_HEIGHT, OPENER_WIDTH};
+use tetrust::movegen::{self, Action};
+use tetrust::notation::{format_board, parse_board};
+use tetrust::perfect::{self, Move, Planner, Search};
+use tetrust::tetrust::*;
+use PieceEnum::*;
+
+// An opener the solver finds in a handful of boards.
+const OPENER: [PieceEnum; 14] = [L, T, I, J, O, Z, S, J, O, T, I, Z, L, S];
+
+fn tetris(board: &str, pieces: &[PieceEnum]) -> Tetris {
+    Tetris::with_queue(parse_board(board, 10, 20).unwrap(), PieceQueue::fixed(pieces.to_vec()))
+}
+
+// Plays the moves straight onto the engine and returns the last clear.
+fn replay(tetris: &Tetris, moves: &[Move]) -> Clear {
+    let mut tetris = tetris.clone();
+    let mut clear = None;
+    for m in moves {
+        if m.hold {
+            assert!(tetris.hold_piece());
+        }
+        assert_eq!(tetris.active_piece().piece, m.placement.piece.piece);
+        tetris.place_piece(m.placement.piece, m.placement.kick);
+        clear = Some(tetris.disable_piece());
+        tetris.spawn_next();
+    }
+    clear.expect("no moves")
+}
+
+// The rest of `OPENER`, past the preview.
+fn queue(game: &Game) -> Vec<PieceEnum> {
+    OPENER[OPENER.len() - game.tetris.queue.len()..].to_vec()
+}
+
+// Presses the move's keys the way a player would, until the piece locks.
+fn press(game: &mut Game, m: &Move) -> Lock {
+    let mut actions = m.placement.inputs.clone();
+    if m.hold {
+        actions.insert(0, Action::Hold);
+    }
+    // Let go of the last piece's hard drop.
+    game.step(Input::default());
+    for action in actions {
+        let held = match action {
+            Action::DasLeft | Action::DasRight | Action::SonicDrop => 40,
+            _ => 1
+        };
+        for _ in 0..held {
+            if let Some(lock) = game.step(action.input()).locked {
+                return lock;
+            }
+        }
+        game.step(Input::default());
+    }
+    panic!("the piece never locked: {:?} {:?}", m, game.tetris.active_piece());
+}
+
+fn wait_for_hint(planner: &mut Planner, game: &Game) -> Move {
+    let start = Instant::now();
+    loop {
+        if let Some(m) = planner.update(&game.tetris, &queue(game)) {
+            return m.clone();
+        }
+        assert!(start.elapsed() < Duration::from_secs(60), "no hint");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn two_line_clear() {
+    let tetris = tetris("XXXX......\nXXXX......", &[O, I, L, J, T]);
+    let moves = match perfect::solve(&tetris, &[I, L, J, T], 2) {
+        Search::Found(moves) => moves,
+        search => panic!("{:?}", search)
+    };
+    assert_eq!(moves.len(), 3);
+    assert!(moves.iter().all(|m| m.placement.piece.cells().iter().all(|&(_, y)| y >= 18)));
+    assert!(replay(&tetris, &moves).perfect);
+}
+
+#[test]
+fn clears_below_the_line_limit_count() {
+    let tetris = tetris("XXXX......\nXXXX......", &[O, I, L, J, T]);
+    let two_lines = perfect::solve(&tetris, &[I, L, J, T], 2);
+    assert_eq!(perfect::solve(&tetris, &[I, L, J, T], 4), two_lines);
+    assert_eq!(two_lines.solution().map(|moves| moves.len()), Some(3));
+}
+
+#[test]
+fn impossible_boards() {
+    // Seven empty cells can't take whole pieces.
+    assert_eq!(perfect::solve(&tetris("XXXXXXXXX.\nXXXXXXXXX.\nXXXXXXXXX.\nXXXXXXXXX.\nXXXXXXX...", &[I, I]), &[I], 5), Search::Impossible);
+    // Twelve empty cells, but walled off into two lots of six.
+    assert_eq!(perfect::solve(&tetris("...XX...XX\n...XX...XX", &[L, J, I, O]), &[J, I, O], 2), Search::Impossible);
+    // Too few pieces.
+    assert_eq!(perfect::solve(&tetris("XXXX......\nXXXX......", &[O, I]), &[I], 2), Search::Impossible);
+    // Stack above the lines to clear.
+    assert_eq!(perfect::solve(&tetris("X.........\nXXXXXXXXX.", &[I, I]), &[I], 1), Search::Impossible);
+    assert_eq!(perfect::solve_limited(&tetris("", &OPENER), &OPENER[1..], 4, 1), Search::GaveUp);
+}
+
+#[test]
+fn opener_from_an_empty_board() {
+    let tetris = tetris("", &OPENER);
+    let moves = perfect::solve(&tetris, &OPENER[1..], 4).solution().expect("no perfect clear").to_vec();
+    assert_eq!(moves.len(), 10);
+    assert!(replay(&tetris, &moves).perfect);
+}
+
+#[test]
+fn planner_follows_the_player() {
+    let mut game = Game::new(tetris("", &OPENER), 0, Timings { lock_delay: u32::MAX, ..Timings::default() });
+    let mut planner = Planner::new(4);
+    let first = wait_for_hint(&mut planner, &game);
+    assert_eq!(planner.status().and_then(|s| s.solution()).map(|m| m.len()), Some(10));
+    let mut lock = press(&mut game, &first);
+    for _ in 1..10 {
+        // Following the plan never needs another search.
+        let next = planner.update(&game.tetris, &queue(&game)).cloned().expect("lost the plan");
+        lock = press(&mut game, &next);
+    }
+    assert!(lock.clear.perfect);
+}
+
+#[test]
+fn opener_mode_counts_perfect_clears() {
+    let mut game = Game::new(Tetris::new(TileCanvas::new(OPENER_WIDTH, OPENER_HEIGHT), 0), 0, Timings::default());
+    let mut opener = Opener::new(OPENER_WIDTH, OPENER_HEIGHT, 5, 0);
+    opener.setup(&mut game);
+    let start = Instant::now();
+    while opener.perfects() == 0 {
+        assert!(!opener.failed());
+        assert!(start.elapsed() < Duration::from_secs(60), "no perfect clear");
+        match opener.hint(&game).cloned() {
+            Some(m) => {
+                let lock = press(&mut game, &m);
+                opener.update(&mut game, &Step { locked: Some(lock), ..Step::default() });
+            }
+            None => std::thread::sleep(Duration::from_millis(5))
+        }
+    }
+    assert_eq!(opener.rounds(), 2);
+    assert!(game.tetris.tile_canvas.is_empty());
+
+    // Stacking anywhere but the setup ends the round.
+    let mut lock = None;
+    for _ in 0..3 {
+        let placement = movegen::placements(&game.tetris).remove(0);
+        lock = Some(press(&mut game, &Move { hold: false, placement }));
+    }
+    let start = Instant::now();
+    while !opener.failed() {
+        opener.update(&mut game, &Step { locked: lock, ..Step::default() });
+        assert!(start.elapsed() < Duration::from_secs(60), "never failed");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    opener.retry(&mut game);
+    assert!(!opener.failed());
+    assert!(game.tetris.tile_canvas.is_empty());
+}
+
+#[test]
+fn opener_setups_lead_to_perfect_clears() {
+    assert_eq!(opener::find_setup("pco-MIRROR"), Ok(1));
+    assert_eq!(opener::find_setup("tki"), Err(String::from("unknown opener 'tki', expected one of PCO, PCO-mirror")));
+    let mut rng = StdRng::seed_from_u64(1);
+    for setup in opener::SETUPS.iter() {
+        let target = parse_board(setup.board, 10, 20).unwrap();
+        let (mut built, mut finished) = (0, 0);
+        for _ in 0..20 {
+            let mut pieces = Vec::new();
+            for _ in 0..2 {
+                let mut bag = PieceEnum::ALL.to_vec();
+                bag.shuffle(&mut rng);
+                pieces.extend(bag);
+            }
+            // Some orders can't build it, even with hold, and some can't clear it.
+            let mut tetris = tetris("", &pieces);
+            let Some(moves) = opener::build(&tetris, &target) else { continue };
+            built += 1;
+            assert_eq!(moves.len(), 6, "{}", setup.name);
+            opener::play(&mut tetris, &moves);
+            assert_eq!(format_board(&tetris.tile_canvas), setup.board.to_string() + "\n", "{}", setup.name);
+            let queue: Vec<PieceEnum> = tetris.queue.peek().copied().collect();
+            if perfect::solve(&tetris, &queue, 4).solution().is_some() {
+                finished += 1;
+            }
+        }
+        assert!(built >= 5 && finished >= 2, "{}: built {}, finished {}", setup.name, built, finished);
+    }
+}
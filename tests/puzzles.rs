@@ -0,0 +1,45 @@
+use std::path::Path;
+use tetrust::modes::puzzle::{Goal, Puzzle, PUZZLE_DIR};
+
+#[test]
+fn bundled_puzzles_are_solvable() {
+    let puzzles = Puzzle::load_pack(Path::new(PUZZLE_DIR)).unwrap();
+    assert!(!puzzles.is_empty());
+    for puzzle in puzzles {
+        assert!(puzzle.solve().is_some(), "puzzle '{}' has no solution", puzzle.name);
+    }
+}
+
+#[test]
+fn unsolvable_puzzle_is_rejected() {
+    let puzzle = Puzzle::parse("pieces: O\ngoal: pc\nboard:\nXXXXXXX...\nXXXXXXX...\n").unwrap();
+    assert!(puzzle.solve().is_none());
+}
+
+// Bundled puzzle files are numbered and named after their goals, e.g.
+// 04-tsd-tetris.txt.
+#[test]
+fn bundled_puzzles_are_named_after_their_goals() {
+    let mut paths: Vec<_> = std::fs::read_dir(PUZZLE_DIR).unwrap().map(|e| e.unwrap().path()).collect();
+    paths.sort();
+    for (i, path) in paths.iter().enumerate() {
+        let puzzle = Puzzle::load(path).unwrap();
+        let tags: Vec<&str> = puzzle.goals.iter().filter_map(|goal| match goal {
+            Goal::TSpin { lines: 1, .. } => Some("tss"),
+            Goal::TSpin { lines: 2, .. } => Some("tsd"),
+            Goal::TSpin { .. } => Some("tst"),
+            Goal::Tetris(_) => Some("tetris"),
+            Goal::PerfectClear => Some("perfect-clear"),
+            Goal::Lines(_) => None
+        }).collect();
+        let name = format!("{:02}-{}.txt", i + 1, tags.join("-"));
+        assert_eq!(path.file_name().unwrap().to_str().unwrap(), name, "puzzle '{}'", puzzle.name);
+    }
+}
+
+#[test]
+fn tetris_goals_need_four_lines_at_once() {
+    let board = "board:\nXXXXXXXX..\nXXXXXXXX..\nXXXXXXXX..\nXXXXXXXX..\n";
+    assert!(Puzzle::parse(&format!("pieces: O O\ngoal: lines 4\n{}", board)).unwrap().solve().is_some());
+    assert!(Puzzle::parse(&format!("pieces: O O\ngoal: tetris\n{}", board)).unwrap().solve().is_none());
+}
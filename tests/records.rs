@@ -0,0 +1,56 @@
+use tetrust::records::{self, Record, Records, TABLE_SIZE};
+
+fn record(mode: &str, score: u32, frames: u64, cleared: bool) -> Record {
+    Record { mode: mode.to_string(), score, lines: score, pieces: 10, frames, pps: 1.0, cleared, grade: None, date: 0, seed: 1, replay: None }
+}
+
+#[test]
+fn tables_rank_each_mode_apart() {
+    let mut records = Records::default();
+    assert_eq!(records.add(record("marathon", 40, 3000, false)), Some(1));
+    assert_eq!(records.add(record("marathon", 90, 5000, false)), Some(1));
+    // Ties go to the faster game, then to the one that was there first.
+    assert_eq!(records.add(record("marathon", 40, 2000, false)), Some(2));
+    assert_eq!(records.add(record("marathon", 90, 5000, false)), Some(2));
+    // Cleared dig games go by time, ahead of any that weren't cleared.
+    assert_eq!(records.add(record("dig", 30, 900, false)), Some(1));
+    assert_eq!(records.add(record("dig", 18, 4000, true)), Some(1));
+    assert_eq!(records.add(record("dig", 12, 3000, true)), Some(1));
+    // Different settings are a different table.
+    assert_eq!(records.add(record("dig 5", 5, 6000, true)), Some(1));
+
+    let scores: Vec<(u32, u64)> = records.table("marathon").iter().map(|r| (r.score, r.frames)).collect();
+    assert_eq!(scores, vec![(90, 5000), (90, 5000), (40, 2000), (40, 3000)]);
+    let dig: Vec<String> = records.table("dig").iter().map(|r| r.headline()).collect();
+    assert_eq!(dig, vec!["Cleared", "Cleared", "30"]);
+    assert_eq!(records.modes(), vec!["marathon", "dig", "dig 5"]);
+
+    for score in 0..TABLE_SIZE as u32 {
+        records.add(record("master", 1000 + score, 100, false));
+    }
+    assert_eq!(records.table("master").len(), TABLE_SIZE);
+    assert_eq!(records.rank(&record("master", 500, 100, false)), None);
+    assert_eq!(records.rank(&record("master", 1005, 99, false)), Some(5));
+}
+
+#[test]
+fn records_survive_a_restart() {
+    let path = std::env::temp_dir().join(format!("tetrust-records-{}", std::process::id())).join(records::RECORDS_FILE);
+    assert_eq!(Records::load(&path).unwrap(), Records::default());
+    let mut saved = Records::default();
+    // More than a u16 can hold.
+    saved.add(Record { grade: Some(String::from("S4")), replay: Some(path.with_extension("replay")), ..record("master", 70_000, 36_000, true) });
+    saved.save(&path).unwrap();
+    let loaded = Records::load(&path);
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    let loaded = loaded.unwrap();
+    assert_eq!(loaded, saved);
+    assert_eq!(loaded.table("master")[0].headline(), "S4");
+}
+
+#[test]
+fn dates_are_shown_as_utc_days() {
+    assert_eq!(records::format_date(0), "1970-01-01");
+    assert_eq!(records::format_date(951_782_400), "2000-02-29");
+    assert_eq!(records::format_date(1_792_367_999), "2026-10-18");
+}
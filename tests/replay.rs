@@ -0,0 +1,126 @@
+use tetrust::bot::{Bot, Difficulty};
+use tetrust::game::*;
+use tetrust::modes::dig::Dig;
+use tetrust::modes::master::{self, Master};
+use tetrust::notation::format_board;
+use tetrust::replay::{Replay, ReplayMode, REPLAY_VERSION};
+use tetrust::tetrust::*;
+
+fn path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("tetrust-{}-{}.json", name, std::process::id()))
+}
+
+#[test]
+fn replays_play_out_the_same() {
+    let mut game = Game::new(Tetris::new(TileCanvas::new(10, 20), 42), GRAVITY_UNIT / 19, Timings::default());
+    let mut replay = Replay::new(&game, 42);
+    let mut bot = Bot::new(Difficulty::Medium, 1);
+    while game.frames() < 3000 && !game.is_over() {
+        let input = bot.input(&game);
+        game.step(input);
+        replay.record(input);
+    }
+    let path = path("replay");
+    replay.save(&path).unwrap();
+    let loaded = Replay::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, replay);
+
+    let played = loaded.play();
+    assert_eq!(played.frames(), game.frames());
+    assert_eq!(played.tetris.score(), game.tetris.score());
+    assert_eq!(format_board(&played.tetris.tile_canvas), format_board(&game.tetris.tile_canvas));
+    assert_eq!(played.tetris.active_piece(), game.tetris.active_piece());
+    assert_eq!(loaded.seek(0).frames(), 0);
+}
+
+#[test]
+fn newer_replays_are_refused() {
+    let game = Game::new(Tetris::new(TileCanvas::new(10, 20), 0), 0, Timings::default());
+    let mut replay = Replay::new(&game, 0);
+    replay.version = REPLAY_VERSION + 1;
+    let path = path("newer");
+    replay.save(&path).unwrap();
+    let error = Replay::load(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert!(error.contains("newer"), "{}", error);
+    assert!(Replay::load(&path).is_err());
+}
+
+#[test]
+fn replays_of_unplayable_boards_are_refused() {
+    let game = Game::new(Tetris::new(TileCanvas::new(10, 20), 0), 0, Timings::default());
+    let mut replay = Replay::new(&game, 0);
+    replay.width = 1;
+    let path = path("narrow");
+    replay.save(&path).unwrap();
+    let error = Replay::load(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert!(error.contains("board size 1x20"), "{}", error);
+}
+
+#[test]
+fn das_charged_in_the_countdown_is_replayed() {
+    let left = Input { left: true, ..Input::default() };
+    let mut game = Game::new(Tetris::new(TileCanvas::new(10, 20), 7), GRAVITY_UNIT / 19, Timings { arr: 0, ..Timings::default() });
+    let start_x = game.tetris.active_piece().x;
+    let mut replay = Replay::new(&game, 7);
+    for _ in 0..game.timings.das {
+        game.charge(left);
+        replay.record_countdown(left);
+    }
+    assert_eq!(game.frames(), 0);
+    assert_eq!(game.tetris.active_piece().x, start_x);
+    // The piece goes straight to the wall on the first frame.
+    game.step(left);
+    replay.record(left);
+    let wall_x = game.tetris.active_piece().x;
+    assert!(wall_x < start_x - 1);
+    assert!(!game.tetris.clone().move_piece(-1, 0));
+    assert_eq!(replay.play().tetris.active_piece(), game.tetris.active_piece());
+
+    // A tap in the last frame of the countdown still moves the piece once.
+    let mut tapped = Game::new(Tetris::new(TileCanvas::new(10, 20), 7), GRAVITY_UNIT / 19, Timings::default());
+    tapped.charge(left);
+    tapped.step(left);
+    assert_eq!(tapped.tetris.active_piece().x, start_x - 1);
+}
+
+#[test]
+fn dig_and_master_are_replayed() {
+    let mut dig = Dig::new(10, 12, 6, 0.4, 3);
+    let mut game = Game::new(Tetris::new(TileCanvas::new(10, 20), 3), GRAVITY_UNIT / 19, Timings::default());
+    let replay_mode = dig.replay_mode();
+    let mut replay = Replay::with_mode(&game, 3, replay_mode);
+    dig.setup(&mut game.tetris);
+    let mut bot = Bot::new(Difficulty::Hard, 3);
+    while game.frames() < 3000 && !game.is_over() && !dig.is_cleared() {
+        let input = bot.input(&game);
+        let step = game.step(input);
+        replay.record(input);
+        if step.locked.is_some() {
+            dig.update(&mut game.tetris);
+        }
+    }
+    assert_eq!(replay.mode, ReplayMode::Dig { lines: 12, visible: 6, messiness: 0.4, seed: 3 });
+    assert_eq!(format_board(&replay.play().tetris.tile_canvas), format_board(&game.tetris.tile_canvas));
+
+    let mut master = Master::new();
+    let mut game = Game::new(Tetris::new(TileCanvas::new(master::MASTER_WIDTH, master::MASTER_HEIGHT), 4), 0, Timings::default());
+    let mut replay = Replay::with_mode(&game, 4, ReplayMode::Master);
+    master.setup(&mut game);
+    let mut bot = Bot::new(Difficulty::Hard, 4);
+    while game.frames() < 3000 && !game.is_over() {
+        let input = bot.input(&game);
+        let step = game.step(input);
+        replay.record(input);
+        master.update(&mut game, &step);
+    }
+    assert!(master.level() > 0);
+    let path = path("master");
+    replay.save(&path).unwrap();
+    let played = Replay::load(&path).unwrap().play();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(format_board(&played.tetris.tile_canvas), format_board(&game.tetris.tile_canvas));
+    assert_eq!((played.gravity, played.timings), (game.gravity, game.timings));
+}
@@ -0,0 +1,83 @@
+use tetrust::game::*;
+use tetrust::notation::parse_board;
+use tetrust::report::Report;
+use tetrust::stats::Stats;
+use tetrust::tetrust::*;
+
+// An overhang over an empty row, with a covered well under it.
+const COVERED: &str = "\
+XXXXXX.XXX
+X.........
+XXXXX.XXXX
+XXXXX.XXXX
+XXXXX.XXXX
+XXXXX.XXXX
+";
+
+// A tetris for a vertical I, leaving one tile on the floor.
+const WELL: &str = "\
+X.........
+XXXXX.XXXX
+XXXXX.XXXX
+XXXXX.XXXX
+XXXXX.XXXX
+";
+
+fn play(report: &mut Report, game: &mut Game, stats: &mut Stats, inputs: &[Input]) {
+    for &input in inputs {
+        game.step(input);
+        stats.update(game);
+        report.update(game, stats);
+    }
+}
+
+#[test]
+fn stack_shape() {
+    let board = parse_board(COVERED, 10, 20).unwrap();
+    assert_eq!(board.column_heights(), vec![6, 6, 6, 6, 6, 6, 4, 6, 6, 6]);
+    assert_eq!(board.holes(), 12);
+}
+
+#[test]
+fn report_follows_the_game() {
+    let queue = PieceQueue::fixed(vec![PieceEnum::I; 8]);
+    let mut game = Game::new(Tetris::with_queue(parse_board(WELL, 10, 20).unwrap(), queue), 0, Timings::default());
+    let mut stats = Stats::new();
+    let mut report = Report::new("marathon", 7, 1_792_367_999);
+    let tetris = [Input { rotate_cw: true, ..Input::default() }, Input { hard_drop: true, ..Input::default() }, Input::default()];
+    play(&mut report, &mut game, &mut stats, &tetris);
+    play(&mut report, &mut game, &mut stats, &vec![Input::default(); 60]);
+    play(&mut report, &mut game, &mut stats, &[Input { hard_drop: true, ..Input::default() }]);
+    report.finish(&game);
+
+    assert_eq!(report.placements.len(), 2);
+    let first = &report.placements[0];
+    assert_eq!((first.frame, first.piece, first.lines, first.tspin), (2, PieceEnum::I, 4, TSpin::None));
+    assert_eq!((first.height, first.holes), (1, 0));
+    // The flat I lands beside the tile that was left.
+    assert_eq!((report.placements[1].height, report.placements[1].holes), (1, 0));
+
+    let frames: Vec<u64> = report.timeline.iter().map(|s| s.frame).collect();
+    assert_eq!(frames, vec![60, 64]);
+    assert_eq!(report.timeline[1].pieces, 2);
+    assert_eq!(report.timeline[1].lines, 4);
+    assert_eq!(report.stats, stats);
+
+    let json = serde_json::to_string(&report).unwrap();
+    assert_eq!(serde_json::from_str::<Report>(&json).unwrap(), report);
+}
+
+#[test]
+fn html_has_the_charts_and_placements() {
+    let mut report = Report::new("dig <10>", 1, 0);
+    let mut game = Game::new(Tetris::new(TileCanvas::new(10, 20), 1), 0, Timings::default());
+    let mut stats = Stats::new();
+    play(&mut report, &mut game, &mut stats, &[Input { hard_drop: true, ..Input::default() }, Input::default()]);
+    report.finish(&game);
+    let html = report.to_html();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("dig &lt;10&gt; on 1970-01-01"));
+    assert_eq!(html.matches("<svg").count(), 5);
+    assert!(html.contains("<polyline"));
+    assert_eq!(html.matches("<tr><td>").count(), 1);
+}
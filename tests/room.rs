@@ -0,0 +1,118 @@
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tetrust::game::*;
+use tetrust::room::{self, Room, RoomClient, Targeting, MAX_ROOM_SIZE};
+use tetrust::tetrust::*;
+
+fn template() -> Game {
+    Game::new(Tetris::new(TileCanvas::new(10, 20), 0), GRAVITY_UNIT, Timings::default())
+}
+
+#[test]
+fn targeting_strategies() {
+    let mut room = Room::new(4, 1);
+    room.players[1].height = 12;
+    room.players[2].badges = 3;
+    room.players[3].alive = false;
+
+    room.players[0].targeting = Targeting::Kos;
+    assert_eq!(room.targets(0), vec![1]);
+    room.players[0].targeting = Targeting::Badges;
+    assert_eq!(room.targets(0), vec![2]);
+    for _ in 0..20 {
+        room.players[0].targeting = Targeting::Random;
+        let targets = room.targets(0);
+        assert!(targets == vec![1] || targets == vec![2]);
+    }
+
+    room.players[1].targeting = Targeting::Badges;
+    room.players[2].targeting = Targeting::Kos;
+    room.targets(1);
+    room.players[0].targeting = Targeting::Attackers;
+    room.players[0].height = 15;
+    assert_eq!(room.targets(2), vec![0]);
+    assert_eq!(room.targets(0), vec![2]);
+}
+
+#[test]
+fn eliminations_credit_the_last_attacker() {
+    let mut room = Room::new(3, 1);
+    room.players[2].targeting = Targeting::Kos;
+    room.players[1].height = 20;
+    assert_eq!(room.attack(2, 4), vec![(1, 4)]);
+    assert_eq!(room.eliminate(1), Some((Some(2), 3)));
+    assert_eq!(room.eliminate(1), None);
+    assert_eq!(room.players[2].badges, 1);
+    assert_eq!(room.winner(), None);
+    assert_eq!(room.eliminate(0), Some((None, 2)));
+    assert_eq!(room.winner(), Some(2));
+}
+
+// A full room of headless clients mashing buttons until one is left.
+#[test]
+fn full_room_plays_to_a_winner() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || room::serve_room(&listener, MAX_ROOM_SIZE, 7));
+
+    let clients: Vec<_> = (0..MAX_ROOM_SIZE).map(|i| {
+        let addr = addr.clone();
+        std::thread::spawn(move || -> Result<RoomClient, String> {
+            let mut client = RoomClient::join(&addr, template())?;
+            client.set_targeting(Targeting::ALL[i % 4])?;
+            let mut rng = StdRng::seed_from_u64(i as u64);
+            while client.winner().is_none() {
+                let input = Input::from_bits(rng.gen::<u8>() & 0x7f);
+                client.step(input)?;
+                if client.place().is_some() {
+                    std::thread::yield_now();
+                }
+            }
+            Ok(client)
+        })
+    }).collect();
+
+    let clients: Vec<RoomClient> = clients.into_iter().map(|c| c.join().unwrap().unwrap_or_else(|e| panic!("{}", e))).collect();
+    let winner = clients[0].winner().unwrap();
+    let mut places: Vec<usize> = clients.iter().map(|c| c.place().unwrap()).collect();
+    places.sort_unstable();
+    assert_eq!(places, (1..=MAX_ROOM_SIZE).collect::<Vec<_>>());
+    for client in clients.iter() {
+        assert_eq!(client.winner(), Some(winner));
+        assert_eq!(client.eliminations().len(), MAX_ROOM_SIZE - 1);
+        assert_eq!(client.alive_count(), 1);
+    }
+    let winner = clients.iter().find(|c| c.id() == winner).unwrap();
+    assert_eq!(winner.place(), Some(1));
+}
+
+// Clients that connect without joining or leave before the room starts don't
+// stop the server.
+#[test]
+fn rooms_survive_silent_and_departed_clients() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || room::serve_rooms(listener, 2));
+
+    // One never says anything, the other joins and hangs up at once.
+    let silent = TcpStream::connect(&addr).unwrap();
+    let mut departed = TcpStream::connect(&addr).unwrap();
+    writeln!(departed, "join {}", room::ROOM_PROTOCOL_VERSION).unwrap();
+    drop(departed);
+    let clients: Vec<_> = (0..3).map(|i| {
+        let addr = addr.clone();
+        std::thread::spawn(move || -> Result<RoomClient, String> {
+            let mut client = RoomClient::join(&addr, template())?;
+            let mut rng = StdRng::seed_from_u64(i);
+            while client.winner().is_none() {
+                client.step(Input::from_bits(rng.gen::<u8>() & 0x7f))?;
+            }
+            Ok(client)
+        })
+    }).collect();
+    for client in clients {
+        assert!(client.join().unwrap().unwrap_or_else(|e| panic!("{}", e)).winner().is_some());
+    }
+    drop(silent);
+}
@@ -0,0 +1,110 @@
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tetrust::game::*;
+use tetrust::net::Wire;
+use tetrust::spectate::{self, Broadcaster, SpectateMessage, Spectator};
+use tetrust::tetrust::*;
+
+fn game(seed: u64) -> Game {
+    Game::new(Tetris::new(TileCanvas::new(10, 20), seed), GRAVITY_UNIT / 4, Timings::default())
+}
+
+#[test]
+fn messages_round_trip() {
+    let piece = TetrisPiece { x: -1, y: 17, piece: PieceEnum::T, state: 3 };
+    let messages = vec![
+        SpectateMessage::Hello { version: 1, width: 10, height: 20 },
+        SpectateMessage::Cells(vec![(0, 19, TileColor::Gray), (4, 3, TileColor::Empty), (9, 0, TileColor::Cyan)]),
+        SpectateMessage::Piece(piece),
+        SpectateMessage::Queue { hold: None, next: vec![PieceEnum::S, PieceEnum::I] },
+        SpectateMessage::Queue { hold: Some(PieceEnum::O), next: Vec::new() },
+        SpectateMessage::Score(65535),
+        SpectateMessage::Event(Event::Locked(piece, Clear { lines: 2, tspin: TSpin::Full, perfect: false })),
+        SpectateMessage::Event(Event::Held(PieceEnum::J)),
+        SpectateMessage::Event(Event::Pressed(Input { left: true, hard_drop: true, ..Input::default() })),
+        SpectateMessage::Event(Event::Garbage(4)),
+        SpectateMessage::Event(Event::ToppedOut),
+        SpectateMessage::Bye,
+    ];
+    for message in messages {
+        assert_eq!(SpectateMessage::parse(&message.encode()), Ok(message));
+    }
+    assert!(SpectateMessage::parse("cells 1,2").is_err());
+    assert!(SpectateMessage::parse("event lock Q 0 0 0 1 none 0").is_err());
+}
+
+#[test]
+fn events_follow_the_game() {
+    let mut game = Game::new(Tetris::with_queue(TileCanvas::new(10, 20), PieceQueue::fixed(vec![PieceEnum::I, PieceEnum::O, PieceEnum::T])), 0,
+                             Timings::default());
+    let hold = Input { hold: true, ..Input::default() };
+    game.step(hold);
+    assert_eq!(game.events(), &[Event::Pressed(hold), Event::Held(PieceEnum::I)]);
+    // Only the newly pressed buttons count.
+    game.step(Input { hold: true, hard_drop: true, ..Input::default() });
+    match game.events() {
+        [Event::Pressed(Input { hard_drop: true, hold: false, .. }), Event::Locked(piece, clear), Event::Spawned(PieceEnum::T)] => {
+            assert_eq!(piece.piece, PieceEnum::O);
+            assert_eq!(clear.lines, 0);
+        }
+        events => panic!("unexpected events {:?}", events)
+    }
+    game.step(Input::default());
+    assert!(game.events().is_empty());
+    game.add_garbage(vec![vec![TileColor::Gray; 10]; 2]);
+    assert_eq!(game.events(), &[Event::Garbage(2)]);
+}
+
+#[test]
+fn spectator_mirrors_the_broadcast() {
+    let mut broadcaster = Broadcaster::bind("127.0.0.1:0").unwrap();
+    let addr = broadcaster.local_addr().unwrap();
+    let spectator = std::thread::spawn(move || Spectator::connect(&addr));
+
+    let mut game = game(3);
+    let mut rng = StdRng::seed_from_u64(3);
+    for frame in 0..3000 {
+        // Mash buttons, but let go every other frame so presses register.
+        let input = if frame % 2 == 0 { Input::from_bits(rng.gen::<u8>() & 0x7f) } else { Input::default() };
+        game.step(input);
+        if game.is_over() {
+            game = self::game(frame);
+        }
+        broadcaster.update(&game).unwrap();
+    }
+    assert_eq!(broadcaster.spectators(), 1);
+    broadcaster.close();
+
+    let mut spectator = spectator.join().unwrap().unwrap();
+    while spectator.update().is_ok() {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let tetris = &game.tetris;
+    assert!(spectator.tetris.tile_canvas.diff(&tetris.tile_canvas).is_empty());
+    assert_eq!(spectator.tetris.active_piece(), tetris.active_piece());
+    assert_eq!(spectator.tetris.hold(), tetris.hold());
+    assert!(spectator.tetris.queue.peek().eq(tetris.queue.peek()));
+    assert_eq!(spectator.score(), tetris.score());
+    assert!(!spectator.events().is_empty());
+}
+
+#[test]
+fn stalled_spectators_are_dropped_without_holding_up_the_game() {
+    let mut broadcaster = Broadcaster::bind("127.0.0.1:0").unwrap();
+    // Connects but never reads.
+    let _stalled = TcpStream::connect(broadcaster.local_addr().unwrap()).unwrap();
+    let start = Instant::now();
+    let mut game = game(4);
+    let mut frames = 0;
+    while frames <= spectate::SPECTATOR_BACKLOG || broadcaster.spectators() > 0 {
+        // A board of another size every frame, which is sent over from scratch.
+        game.set_tetris(Tetris::new(TileCanvas::new(10 + frames as u16 % 2, 20), frames as u64));
+        let frame = Instant::now();
+        broadcaster.update(&game).unwrap();
+        assert!(frame.elapsed() < Duration::from_secs(1), "a frame took {:?}", frame.elapsed());
+        assert!(start.elapsed() < Duration::from_secs(60), "the spectator was never dropped");
+        frames += 1;
+    }
+    broadcaster.close();
+}
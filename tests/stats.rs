@@ -0,0 +1,68 @@
+use tetrust::game::*;
+use tetrust::modes::versus::AttackTable;
+use tetrust::notation::parse_board;
+use tetrust::stats::Stats;
+use tetrust::tetrust::*;
+
+// Two tetrises' worth of rows with a well in the column a vertical I lands in
+// after one rotation, under a row that keeps them from being perfect clears.
+const WELLS: &str = "\
+X.........
+XXXXX.XXXX
+XXXXX.XXXX
+XXXXX.XXXX
+XXXXX.XXXX
+XXXXX.XXXX
+XXXXX.XXXX
+XXXXX.XXXX
+XXXXX.XXXX
+";
+
+fn step(game: &mut Game, stats: &mut Stats, input: Input) {
+    game.step(input);
+    stats.update(game);
+}
+
+#[test]
+fn stats_follow_the_game() {
+    let queue = PieceQueue::fixed(vec![PieceEnum::I; 8]);
+    let mut game = Game::new(Tetris::with_queue(parse_board(WELLS, 10, 20).unwrap(), queue), 0, Timings::default());
+    let mut stats = Stats::new();
+    for _ in 0..2 {
+        step(&mut game, &mut stats, Input { rotate_cw: true, ..Input::default() });
+        step(&mut game, &mut stats, Input { hard_drop: true, ..Input::default() });
+        step(&mut game, &mut stats, Input::default());
+    }
+    assert_eq!(stats.clears, [0, 0, 0, 0, 2]);
+    assert_eq!(stats.lines, 8);
+    assert_eq!(stats.combo, Some(1));
+    assert!(stats.back_to_back);
+    let table = AttackTable::default();
+    let tetris = Clear { lines: 4, tspin: TSpin::None, perfect: false };
+    assert_eq!(stats.attack, (table.attack(&tetris, 0, false) + table.attack(&tetris, 1, true)) as u32);
+
+    // A piece that clears nothing ends the combo but not the best one.
+    step(&mut game, &mut stats, Input { hard_drop: true, ..Input::default() });
+    assert_eq!(stats.combo, None);
+    assert_eq!(stats.max_combo, 1);
+    assert_eq!(stats.pieces, 3);
+    assert_eq!(stats.pieces_by_type, [0, 0, 0, 0, 0, 0, 3]);
+    assert_eq!(stats.keys, 5);
+    assert_eq!(stats.frames, 7);
+    assert!((stats.kpp() - 5.0 / 3.0).abs() < 1e-9);
+    assert!((stats.pps() - 3.0 * 60.0 / 7.0).abs() < 1e-9);
+    assert!((stats.apm() - stats.attack as f64 * 3600.0 / 7.0).abs() < 1e-9);
+}
+
+#[test]
+fn held_buttons_count_once() {
+    let mut game = Game::new(Tetris::new(TileCanvas::new(10, 20), 1), 0, Timings::default());
+    let mut stats = Stats::new();
+    assert_eq!((stats.pps(), stats.apm(), stats.kpp()), (0.0, 0.0, 0.0));
+    for _ in 0..10 {
+        step(&mut game, &mut stats, Input { left: true, ..Input::default() });
+    }
+    step(&mut game, &mut stats, Input { left: true, rotate_ccw: true, ..Input::default() });
+    assert_eq!(stats.keys, 2);
+    assert_eq!(stats.pieces, 0);
+}
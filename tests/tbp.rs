@@ -0,0 +1,186 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tetrust::bot::{Bot, Controller, Difficulty};
+use tetrust::game::*;
+use tetrust::garbage::GarbageGenerator;
+use tetrust::tbp::{self, BotMessage, FrontendMessage, Location, Move, Orientation, Spin, Start, TbpBot};
+use tetrust::tetrust::*;
+
+const HEIGHT: u16 = 20;
+
+// A bot that keeps its own copy of the game from what the frontend tells it and
+// suggests the heuristic bot's best placements. Every message it gets is logged.
+fn fake_bot(log: Arc<Mutex<Vec<FrontendMessage>>>) -> TbpBot {
+    let (reader, to_bot) = std::io::pipe().unwrap();
+    let (from_bot, mut writer) = std::io::pipe().unwrap();
+    std::thread::spawn(move || {
+        let mut send = |message: BotMessage| writeln!(writer, "{}", tbp::encode(&message).unwrap()).unwrap();
+        send(BotMessage::Info { name: String::from("Fake"), version: String::from("1"), author: String::from("tests"), features: Vec::new() });
+        let bot = Bot::new(Difficulty::Hard, 0);
+        let mut state: Option<Start> = None;
+        for message in tbp::forward::<FrontendMessage>(reader) {
+            let message = match message {
+                Ok(message) => message,
+                Err(_) => return
+            };
+            log.lock().unwrap().push(message.clone());
+            match message {
+                FrontendMessage::Rules => send(BotMessage::Ready),
+                FrontendMessage::Start(start) => state = Some(start),
+                FrontendMessage::Stop => state = None,
+                FrontendMessage::NewPiece { piece } => state.as_mut().unwrap().queue.push(piece),
+                FrontendMessage::Suggest => {
+                    let moves = bot.plans(&tetris(state.as_ref().unwrap())).iter().take(3).map(|plan| Move {
+                        location: tbp::location(&plan.placement.piece, HEIGHT),
+                        spin: match plan.placement.tspin { TSpin::None => Spin::None, TSpin::Mini => Spin::Mini, TSpin::Full => Spin::Full }
+                    }).collect();
+                    send(BotMessage::Suggestion { moves });
+                }
+                FrontendMessage::Play { mv } => {
+                    let start = state.as_mut().unwrap();
+                    let mut tetris = tetris(start);
+                    if tetris.active_piece().piece != mv.location.piece {
+                        assert!(tetris.hold_piece());
+                    }
+                    tetris.place_piece(tbp::piece_at(&mv.location, HEIGHT), None);
+                    tetris.disable_piece();
+                    tetris.spawn_next();
+                    let mut queue = vec![tetris.active_piece().piece];
+                    queue.extend(tetris.queue.peek());
+                    *start = Start { hold: tetris.hold(), queue, board: tbp::board(&tetris.tile_canvas), ..start.clone() };
+                }
+                FrontendMessage::Quit => return
+            }
+        }
+    });
+    TbpBot::new(from_bot, to_bot).unwrap()
+}
+
+fn tetris(start: &Start) -> Tetris {
+    let mut canvas = TileCanvas::new(10, HEIGHT);
+    for (row, cells) in start.board.iter().take(HEIGHT as usize).enumerate() {
+        for (x, cell) in cells.iter().enumerate() {
+            if cell.is_some() {
+                canvas.set_tile(x as u16, HEIGHT - 1 - row as u16, TileColor::Gray);
+            }
+        }
+    }
+    let mut tetris = Tetris::with_queue(canvas, PieceQueue::fixed(start.queue.clone()));
+    tetris.set_hold(start.hold);
+    tetris
+}
+
+// Plays until `pieces` pieces have locked and returns the lines cleared. There
+// is no gravity, so the frames spent waiting for the bot don't matter.
+fn play(game: &mut Game, bot: &mut TbpBot, pieces: usize) -> u16 {
+    let (mut locked, mut lines) = (0, 0);
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(30) {
+        std::thread::sleep(Duration::from_micros(50));
+        let input = bot.input(game).unwrap();
+        if let Some(lock) = game.step(input).locked {
+            locked += 1;
+            lines += lock.clear.lines;
+            if locked == pieces {
+                return lines;
+            }
+        }
+        assert!(!game.is_over());
+    }
+    panic!("only {} pieces locked", locked);
+}
+
+fn count(log: &Arc<Mutex<Vec<FrontendMessage>>>, matches: fn(&FrontendMessage) -> bool) -> usize {
+    log.lock().unwrap().iter().filter(|m| matches(m)).count()
+}
+
+#[test]
+fn locations_match_the_engine() {
+    // T flat on the floor against the left wall.
+    let piece = TetrisPiece { x: 0, y: HEIGHT as i16 - 2, piece: PieceEnum::T, state: 0 };
+    let location = tbp::location(&piece, HEIGHT);
+    assert_eq!(location, Location { piece: PieceEnum::T, orientation: Orientation::North, x: 1, y: 0 });
+    // I standing up in the right column, centred on its second cell from the top.
+    let piece = TetrisPiece { x: 7, y: HEIGHT as i16 - 4, piece: PieceEnum::I, state: 1 };
+    assert_eq!(tbp::location(&piece, HEIGHT), Location { piece: PieceEnum::I, orientation: Orientation::East, x: 9, y: 2 });
+    for &piece in &[PieceEnum::O, PieceEnum::S, PieceEnum::Z, PieceEnum::T, PieceEnum::L, PieceEnum::J, PieceEnum::I] {
+        for state in 0..4 {
+            let piece = TetrisPiece { x: 3, y: 10, piece, state };
+            let mut cells = tbp::piece_at(&tbp::location(&piece, HEIGHT), HEIGHT).cells();
+            let mut expected = piece.cells();
+            cells.sort_unstable();
+            expected.sort_unstable();
+            assert_eq!(cells, expected);
+        }
+    }
+}
+
+#[test]
+fn messages_use_the_protocol_format() {
+    let mv = Move { location: Location { piece: PieceEnum::T, orientation: Orientation::South, x: 4, y: 1 }, spin: Spin::Full };
+    assert_eq!(tbp::encode(&FrontendMessage::Play { mv }).unwrap(),
+               r#"{"type":"play","move":{"location":{"type":"T","orientation":"south","x":4,"y":1},"spin":"full"}}"#);
+    assert_eq!(tbp::encode(&FrontendMessage::NewPiece { piece: PieceEnum::I }).unwrap(), r#"{"type":"new_piece","piece":"I"}"#);
+    let suggestion: BotMessage = serde_json::from_str(r#"{"type":"suggestion","moves":[{"location":{"type":"T","orientation":"south","x":4,"y":1},"spin":"full"}],"move_info":{"nodes":1}}"#).unwrap();
+    assert_eq!(suggestion, BotMessage::Suggestion { moves: vec![mv] });
+    let unknown: BotMessage = serde_json::from_str(r#"{"type":"analysis","depth":3}"#).unwrap();
+    assert_eq!(unknown, BotMessage::Unknown);
+
+    let tetris = Tetris::with_queue(TileCanvas::new(10, HEIGHT), PieceQueue::fixed(vec![PieceEnum::S; 7]));
+    let start = tbp::start(&tetris);
+    assert_eq!(start.queue.len(), 6);
+    assert_eq!(start.board.len(), tbp::BOARD_HEIGHT);
+    assert!(start.board.iter().all(|row| row.len() == 10 && row.iter().all(|c| c.is_none())));
+}
+
+#[test]
+fn fake_bot_plays_a_game() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut bot = fake_bot(log.clone());
+    assert_eq!(bot.name, "Fake 1");
+    let mut game = Game::new(Tetris::new(TileCanvas::new(10, HEIGHT), 7), 0, Timings::default());
+    assert!(play(&mut game, &mut bot, 40) >= 4);
+    // The bot stayed in step with the game the whole time.
+    assert_eq!(count(&log, |m| matches!(m, FrontendMessage::Start(_))), 1);
+    assert_eq!(count(&log, |m| matches!(m, FrontendMessage::Play { .. })), 40);
+    assert!(count(&log, |m| matches!(m, FrontendMessage::NewPiece { .. })) >= 39);
+}
+
+#[test]
+fn garbage_restarts_the_bot() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut bot = fake_bot(log.clone());
+    let mut game = Game::new(Tetris::new(TileCanvas::new(10, HEIGHT), 3), 0, Timings::default());
+    play(&mut game, &mut bot, 5);
+    game.add_garbage(GarbageGenerator::new(10, 0.0, 1).rows(2));
+    play(&mut game, &mut bot, 5);
+    let log = log.lock().unwrap();
+    let restarts: Vec<usize> = log.iter().enumerate().filter(|(_, m)| matches!(m, FrontendMessage::Start(_))).map(|(i, _)| i).collect();
+    assert_eq!(restarts.len(), 2);
+    assert_eq!(log[restarts[1] - 1], FrontendMessage::Stop);
+    match &log[restarts[1]] {
+        FrontendMessage::Start(start) => assert!(start.board[0].contains(&Some(tbp::Cell::G))),
+        _ => unreachable!()
+    }
+}
+
+#[test]
+fn bots_only_play_on_the_standard_board() {
+    assert!(tbp::check_board(&TileCanvas::new(10, HEIGHT)).is_ok());
+    assert!(tbp::check_board(&TileCanvas::new(10, 40)).is_ok());
+    assert!(tbp::check_board(&TileCanvas::new(10, 19)).is_err());
+    assert!(tbp::check_board(&TileCanvas::new(10, 41)).is_err());
+    assert_eq!(tbp::check_board(&TileCanvas::new(16, HEIGHT)), Err(String::from("TBP bots need a board 10 wide and 20 to 40 tall, not 16x20")));
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut bot = fake_bot(log.clone());
+    let mut game = Game::new(Tetris::new(TileCanvas::new(16, HEIGHT), 7), 0, Timings::default());
+    let error = (0..10).find_map(|_| bot.input(&game).err().or_else(|| {
+        game.step(Input::default());
+        None
+    }));
+    assert!(error.unwrap().contains("16x20"));
+    assert_eq!(count(&log, |m| matches!(m, FrontendMessage::Start(_))), 0);
+}
@@ -0,0 +1,76 @@
+use tetrust::game::*;
+use tetrust::modes::versus::{AttackTable, Outcome, Versus};
+use tetrust::notation::parse_board;
+use tetrust::tetrust::*;
+
+// Four rows with a well in the column a vertical I lands in after one rotation,
+// under a row that keeps the tetris from being a perfect clear.
+const WELL: &str = "\
+X.........
+XXXXX.XXXX
+XXXXX.XXXX
+XXXXX.XXXX
+XXXXX.XXXX
+";
+
+fn clear(lines: u16, tspin: TSpin) -> Clear {
+    Clear { lines, tspin, perfect: false }
+}
+
+fn game(board: &str) -> Game {
+    let queue = PieceQueue::fixed(vec![PieceEnum::I; 8]);
+    Game::new(Tetris::with_queue(parse_board(board, 10, 20).unwrap(), queue), 0, Timings::default())
+}
+
+fn drop_vertical_i(versus: &mut Versus, player: usize) {
+    let mut inputs = [Input::default(); 2];
+    inputs[player].rotate_cw = true;
+    versus.step(&inputs);
+    inputs[player] = Input { hard_drop: true, ..Input::default() };
+    versus.step(&inputs);
+    versus.step(&[Input::default(); 2]);
+}
+
+#[test]
+fn attack_table() {
+    let table = AttackTable::default();
+    assert_eq!(table.attack(&clear(1, TSpin::None), 0, false), 0);
+    assert_eq!(table.attack(&clear(4, TSpin::None), 0, false), 4);
+    assert_eq!(table.attack(&clear(2, TSpin::Full), 0, false), 4);
+    assert_eq!(table.attack(&clear(4, TSpin::None), 0, true), 5);
+    assert_eq!(table.attack(&clear(2, TSpin::None), 4, false), 2);
+    assert_eq!(table.attack(&clear(1, TSpin::None), 50, false), 5);
+    assert_eq!(table.attack(&Clear { lines: 1, tspin: TSpin::None, perfect: true }, 0, false), 10);
+}
+
+#[test]
+fn garbage_is_sent_and_received() {
+    let mut versus = Versus::new(game(WELL), 0.0, 1);
+    versus.players[1].game = game("");
+    drop_vertical_i(&mut versus, 0);
+    assert_eq!(versus.players[0].sent(), 4);
+    assert_eq!(versus.players[1].incoming(), 4);
+
+    drop_vertical_i(&mut versus, 1);
+    assert_eq!(versus.players[1].incoming(), 0);
+    assert_eq!(versus.players[1].game.tetris.tile_canvas.count_rows(TileColor::Gray), 4);
+}
+
+#[test]
+fn clears_cancel_incoming_garbage() {
+    let mut versus = Versus::new(game(WELL), 0.0, 1);
+    versus.players[0].receive(3);
+    versus.players[0].receive(3);
+    drop_vertical_i(&mut versus, 0);
+    assert_eq!(versus.players[0].sent(), 0);
+    assert_eq!(versus.players[0].incoming(), 2);
+    assert_eq!(versus.players[1].incoming(), 0);
+}
+
+#[test]
+fn topping_out_loses() {
+    let mut versus = Versus::new(game(""), 0.0, 1);
+    versus.players[1].receive(19);
+    drop_vertical_i(&mut versus, 1);
+    assert_eq!(versus.outcome(), Some(Outcome::Winner(0)));
+}
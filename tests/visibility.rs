@@ -0,0 +1,36 @@
+use tetrust::visibility::{self, StackVisibility};
+
+#[test]
+fn visible_and_invisible_stacks_never_change() {
+    for now in [100, 101, 1_000, u64::MAX] {
+        assert_eq!(StackVisibility::Visible.alpha(100, now), 255);
+        assert_eq!(StackVisibility::Invisible.alpha(100, now), 0);
+    }
+}
+
+#[test]
+fn tiles_fade_out_after_locking() {
+    let fade = StackVisibility::Fade(60);
+    assert_eq!(fade.alpha(100, 100), 255);
+    assert_eq!(fade.alpha(100, 115), 192);
+    assert_eq!(fade.alpha(100, 130), 128);
+    assert_eq!(fade.alpha(100, 159), 5);
+    assert_eq!(fade.alpha(100, 160), 0);
+    assert_eq!(fade.alpha(100, 1_000), 0);
+    // A tile from the future, e.g. after an undo, is still fully shown.
+    assert_eq!(fade.alpha(100, 50), 255);
+    // Fading over no frames hides tiles at once.
+    assert_eq!(StackVisibility::Fade(0).alpha(100, 100), 0);
+}
+
+#[test]
+fn the_stack_is_revealed_when_the_game_ends() {
+    for hidden in [StackVisibility::Invisible, StackVisibility::Fade(visibility::DEFAULT_FADE_FRAMES)] {
+        assert!(!hidden.revealed(0));
+        assert!(!hidden.revealed(visibility::REVEAL_FRAMES - 1));
+        assert!(hidden.revealed(visibility::REVEAL_FRAMES));
+    }
+    assert_eq!(visibility::REVEAL_FRAMES, 180);
+    // Nothing to wait for when the stack was never hidden.
+    assert!(StackVisibility::Visible.revealed(0));
+}
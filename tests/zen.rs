@@ -0,0 +1,106 @@
+use tetrust::game::*;
+use tetrust::modes::zen::{self, Zen};
+use tetrust::tetrust::*;
+
+fn start(seed: u64) -> (Zen, Game) {
+    let mut game = Game::new(Tetris::new(TileCanvas::new(10, 20), seed), GRAVITY_UNIT, Timings::default());
+    let mut zen = Zen::new();
+    zen.setup(&mut game);
+    (zen, game)
+}
+
+// Hard drops the active piece where it is, then lets go of the button.
+// Returns whether a piece spawned.
+fn drop(zen: &mut Zen, game: &mut Game, shift: i16) -> bool {
+    game.tetris.move_piece(shift, 0);
+    let step = game.step(Input { hard_drop: true, ..Input::default() });
+    zen.update(game, &step);
+    let released = game.step(Input::default());
+    zen.update(game, &released);
+    step.spawned.is_some() || released.spawned.is_some()
+}
+
+#[test]
+fn zen_has_no_gravity() {
+    let (mut zen, mut game) = start(1);
+    let piece = game.tetris.active_piece();
+    for _ in 0..600 {
+        let step = game.step(Input::default());
+        zen.update(&mut game, &step);
+    }
+    assert_eq!(game.tetris.active_piece(), piece);
+    assert_eq!(game.tetris.hold_mode, HoldMode::Infinite);
+}
+
+#[test]
+fn undo_restores_the_last_spawn() {
+    let (mut zen, mut game) = start(2);
+    assert_eq!(zen.undo_count(), 0);
+    assert!(!zen.undo(&mut game));
+
+    let first = game.tetris.active_piece();
+    assert!(drop(&mut zen, &mut game, -3));
+    let second = game.tetris.active_piece();
+    let board = game.tetris.tile_canvas.clone();
+    assert!(drop(&mut zen, &mut game, 3));
+    assert_eq!(zen.undo_count(), 2);
+
+    assert!(zen.undo(&mut game));
+    assert_eq!(game.tetris.active_piece(), second);
+    assert!(game.tetris.tile_canvas.diff(&board).is_empty());
+    assert!(zen.undo(&mut game));
+    assert_eq!(game.tetris.active_piece(), first);
+    assert!(game.tetris.tile_canvas.is_empty());
+    assert!(!zen.undo(&mut game));
+    assert!(game.is_falling());
+}
+
+#[test]
+fn undo_keeps_the_last_hundred_placements() {
+    let (mut zen, mut game) = start(3);
+    let mut spawns = 0;
+    for i in 0..150 {
+        if drop(&mut zen, &mut game, [-4, -2, 0, 2, 4][i % 5]) {
+            spawns += 1;
+        }
+    }
+    assert!(spawns > zen::MAX_UNDO);
+    assert_eq!(zen.undo_count(), zen::MAX_UNDO);
+    assert_eq!(zen.snapshots().len(), zen::MAX_UNDO + 1);
+    for _ in 0..zen::MAX_UNDO {
+        assert!(zen.undo(&mut game));
+    }
+    assert!(!zen.undo(&mut game));
+}
+
+#[test]
+fn pick_next_chooses_the_following_piece() {
+    let (mut zen, mut game) = start(4);
+    for &piece in PieceEnum::ALL.iter() {
+        zen.pick_next(&mut game, piece);
+        assert_eq!(game.tetris.queue.peek().next(), Some(&piece));
+        drop(&mut zen, &mut game, 0);
+        assert_eq!(game.tetris.active_piece().piece, piece);
+        zen.undo(&mut game);
+    }
+}
+
+#[test]
+fn topping_out_clears_the_board() {
+    let (mut zen, mut game) = start(5);
+    let mut cleared = false;
+    for _ in 0..40 {
+        drop(&mut zen, &mut game, 0);
+        assert!(!game.is_over());
+        if game.tetris.tile_canvas.is_empty() {
+            cleared = true;
+            break;
+        }
+    }
+    assert!(cleared);
+    assert!(game.is_falling());
+    // The placements from before the clear can still be undone.
+    assert!(zen.undo_count() > 0);
+    assert!(zen.undo(&mut game));
+    assert!(!game.tetris.tile_canvas.is_empty());
+}